@@ -9,6 +9,7 @@ pub mod mzml;
 #[cfg(feature = "mzmlb")]
 pub mod mzmlb;
 mod offset_index;
+mod partition;
 mod shorthand;
 pub(crate) mod traits;
 mod utils;
@@ -26,14 +27,19 @@ pub use crate::io::mzml::{MzMLParserError, MzMLReader, MzMLWriter};
 #[cfg(feature = "mzmlb")]
 pub use crate::io::mzmlb::{MzMLbError, MzMLbReader};
 pub use crate::io::offset_index::OffsetIndex;
+pub use crate::io::partition::{
+    PartitionBy, RunPartitionKey, SpectrumPartition, SpectrumPartitioner,
+};
 pub use crate::io::traits::{
     BorrowedGeneric3DIonMobilityFrameSource, ChromatogramIterator, ChromatogramSource,
-    Generic3DIonMobilityFrameSource, IonMobilityFrameAccessError, IonMobilityFrameGrouping,
-    IonMobilityFrameIterator, IonMobilityFrameSource, MZFileReader, MemorySpectrumSource,
+    ChromatogramWriter, CollapsedIonMobilitySpectrumSource, DeferredChromatogramWriter,
+    Generic3DIonMobilityFrameSource,
+    IonMobilityFrameAccessError, IonMobilityFrameGrouping, IonMobilityFrameIterator,
+    IonMobilityFrameSource, MZFileReader, MemorySpectrumSource,
     RandomAccessIonMobilityFrameIterator, RandomAccessSpectrumGroupingIterator,
     RandomAccessSpectrumIterator, RandomAccessSpectrumSource, SpectrumAccessError,
     SpectrumGrouping, SpectrumIterator, SpectrumReceiver, SpectrumSource,
-    SpectrumSourceWithMetadata, SpectrumWriter, StreamingSpectrumIterator,
+    SpectrumSourceWithMetadata, SpectrumTypeFilter, SpectrumWriter, StreamingSpectrumIterator,
 };
 pub use crate::io::utils::{checksum_file, DetailLevel, PreBufferedStream};
 pub use compression::RestartableGzDecoder;
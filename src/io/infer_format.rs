@@ -18,10 +18,13 @@ pub use crate::io::mzmlb::{MzMLbReaderType, MzMLbWriterBuilder};
 use crate::io::compression::{is_gzipped, is_gzipped_extension, RestartableGzDecoder};
 use crate::io::mgf::{is_mgf, MGFReaderType, MGFWriterType};
 use crate::io::mzml::{is_mzml, MzMLReaderType, MzMLWriterType};
-use crate::io::traits::{RandomAccessSpectrumIterator, SpectrumSource, SpectrumWriter, MZFileReader};
+use crate::io::traits::{
+    DeferredChromatogramWriter, MZFileReader, RandomAccessSpectrumIterator, SpectrumSource,
+    SpectrumWriter,
+};
 use crate::meta::{FormatConversion, MSDataFileMetadata};
 use crate::spectrum::bindata::{BuildArrayMapFrom, BuildFromArrayMap};
-use crate::spectrum::MultiLayerSpectrum;
+use crate::spectrum::{Chromatogram, ChromatogramLike, ChromatogramType, MultiLayerSpectrum};
 use crate::Param;
 
 #[cfg(feature = "thermo")]
@@ -776,6 +779,25 @@ pub enum Sink<C: CentroidLike
     Writer(Box<dyn io::Write + Send>, MassSpectrometryFormat)
 }
 
+impl<C: CentroidLike + Default + From<CentroidPeak> + BuildArrayMapFrom + BuildFromArrayMap + Clone + 'static + Sync + Send,
+     D: DeconvolutedCentroidLike + Default + From<DeconvolutedPeak> + BuildArrayMapFrom + BuildFromArrayMap + Clone + Sync + 'static + Send>
+     Sink<C, D> {
+    /// Whether the writer opened for this sink can store [`Chromatogram`]s
+    fn stores_chromatograms(&self) -> bool {
+        let format = match self {
+            Self::PathLike(path) => infer_from_path(path).0,
+            Self::Writer(_, format) => *format,
+            Self::Sender(_) | Self::SyncSender(_) => return false,
+        };
+        match format {
+            MassSpectrometryFormat::MzML => true,
+            #[cfg(feature = "mzmlb")]
+            MassSpectrometryFormat::MzMLb => true,
+            _ => false,
+        }
+    }
+}
+
 impl<C: CentroidLike + Default + From<CentroidPeak> + BuildArrayMapFrom + BuildFromArrayMap + Clone + 'static + Sync + Send,
      D: DeconvolutedCentroidLike + Default + From<DeconvolutedPeak> + BuildArrayMapFrom + BuildFromArrayMap + Clone + Sync + 'static + Send>
      From<(Box<dyn io::Write + Send>, MassSpectrometryFormat)> for Sink<C, D> {
@@ -867,6 +889,8 @@ pub trait MassSpectrometryReadWriteProcess<
         write_path: Q,
     ) -> Result<(), Self::ErrorType> {
         let read_path = read_path.into();
+        let write_path = write_path.into();
+        let copy_chromatograms = write_path.stores_chromatograms();
         match read_path {
             Source::PathLike(read_path) => {
                 let (format, is_gzipped) = infer_format(&read_path)?;
@@ -889,22 +913,35 @@ pub trait MassSpectrometryReadWriteProcess<
                         let handle = fs::File::open(read_path)?;
 
                         if is_gzipped {
+                            if copy_chromatograms {
+                                log::warn!("Chromatograms are not copied from gzipped mzML files, which cannot be read out of order");
+                            }
                             let fh = RestartableGzDecoder::new(io::BufReader::new(handle));
                             let reader = StreamingSpectrumIterator::new(MzMLReaderType::new(fh));
                             let reader = self.transform_reader(reader, format)?;
                             self.open_writer(reader, format, write_path)?;
                         } else {
-                            let reader = MzMLReaderType::new_indexed(handle);
+                            let mut reader = MzMLReaderType::new_indexed(handle);
+                            let chromatograms = if copy_chromatograms {
+                                self.read_chromatograms(&mut reader, format)?
+                            } else {
+                                Vec::new()
+                            };
                             let reader = self.transform_reader(reader, format)?;
-                            self.open_writer(reader, format, write_path)?;
+                            self.open_writer_with_chromatograms(reader, format, write_path, chromatograms)?;
                         };
                         Ok(())
                     }
                     #[cfg(feature = "mzmlb")]
                     MassSpectrometryFormat::MzMLb => {
-                        let reader = MzMLbReaderType::new(&read_path)?;
+                        let mut reader = MzMLbReaderType::new(&read_path)?;
+                        let chromatograms = if copy_chromatograms {
+                            self.read_chromatograms(&mut reader, format)?
+                        } else {
+                            Vec::new()
+                        };
                         let reader = self.transform_reader(reader, format)?;
-                        self.open_writer(reader, format, write_path)?;
+                        self.open_writer_with_chromatograms(reader, format, write_path, chromatograms)?;
                         Ok(())
                     },
                     #[cfg(feature = "thermo")]
@@ -941,9 +978,14 @@ pub trait MassSpectrometryReadWriteProcess<
                     MassSpectrometryFormat::MzML => {
                         let handle = io::BufReader::new(handle);
 
-                        let reader = MzMLReaderType::new_indexed(handle);
+                        let mut reader = MzMLReaderType::new_indexed(handle);
+                        let chromatograms = if copy_chromatograms {
+                            self.read_chromatograms(&mut reader, format)?
+                        } else {
+                            Vec::new()
+                        };
                         let reader = self.transform_reader(reader, format)?;
-                        self.open_writer(reader, format, write_path)?;
+                        self.open_writer_with_chromatograms(reader, format, write_path, chromatograms)?;
 
                         Ok(())
                     },
@@ -984,6 +1026,9 @@ pub trait MassSpectrometryReadWriteProcess<
                         Ok(())
                     }
                     MassSpectrometryFormat::MzML => {
+                        if copy_chromatograms {
+                            log::warn!("Chromatograms are not copied from mzML read over STDIN, which cannot be read out of order");
+                        }
                         if compressed {
                             let reader = StreamingSpectrumIterator::new(MzMLReaderType::new(
                                 RestartableGzDecoder::new(io::BufReader::new(buffered)),
@@ -1008,6 +1053,36 @@ pub trait MassSpectrometryReadWriteProcess<
         }
     }
 
+    /// Read the [`Chromatogram`]s from `reader` that should be copied to the writer. The format is
+    /// passed along to allow each format to be customized explicitly.
+    ///
+    /// By default, all chromatograms except the total ion current and base peak chromatograms
+    /// are read, as the writers that support chromatograms generate those from the spectra they
+    /// write. The reader is reset afterwards.
+    ///
+    /// The chromatograms are held in memory until every spectrum has been written, which may be
+    /// a lot for files with many chromatograms. This is only called when the output format can
+    /// store chromatograms.
+    #[allow(unused)]
+    fn read_chromatograms<R: ChromatogramSource + SpectrumSource<C, D>>(
+        &self,
+        reader: &mut R,
+        format: MassSpectrometryFormat,
+    ) -> Result<Vec<Chromatogram>, Self::ErrorType> {
+        let chromatograms = reader
+            .iter_chromatograms()
+            .filter(|chrom| {
+                !matches!(
+                    chrom.chromatogram_typ(),
+                    ChromatogramType::TotalIonCurrentChromatogram
+                        | ChromatogramType::BasePeakChromatogram
+                )
+            })
+            .collect();
+        reader.reset();
+        Ok(chromatograms)
+    }
+
     /// Opens the writer, transforms it with [`MassSpectrometryReadWriteProcess::transform_writer`], and then passes control to [`MassSpectrometryReadWriteProcess::task`]
     fn open_writer<
         Q: Into<Sink<C, D>>,
@@ -1017,6 +1092,26 @@ pub trait MassSpectrometryReadWriteProcess<
         reader: R,
         reader_format: MassSpectrometryFormat,
         write_path: Q,
+    ) -> Result<(), Self::ErrorType> {
+        self.open_writer_with_chromatograms(reader, reader_format, write_path, Vec::new())
+    }
+
+    /// Like [`MassSpectrometryReadWriteProcess::open_writer`], but when the writer supports chromatograms, it is wrapped
+    /// in a [`DeferredChromatogramWriter`] which writes `chromatograms` after all spectra have been written.
+    ///
+    /// Formats which cannot store chromatograms discard them. Chromatograms are only read
+    /// from sources which can be read out of order, plain mzML files and readers and mzMLb files,
+    /// as they are stored after the spectra. Gzipped mzML files and mzML read over STDIN are streamed
+    /// once, so their chromatograms are not copied.
+    fn open_writer_with_chromatograms<
+        Q: Into<Sink<C, D>>,
+        R: RandomAccessSpectrumIterator<C, D> + MSDataFileMetadata + SpectrumSource<C, D> + Send + 'static,
+    >(
+        &self,
+        reader: R,
+        reader_format: MassSpectrometryFormat,
+        write_path: Q,
+        chromatograms: Vec<Chromatogram>,
     ) -> Result<(), Self::ErrorType> {
         let write_path = write_path.into();
 
@@ -1060,16 +1155,20 @@ pub trait MassSpectrometryReadWriteProcess<
                                 handle,
                             );
                             writer.copy_metadata_from(&reader);
+                            writer.chromatogram_count += chromatograms.len() as u64;
                             let (reader, writer) =
                                 self.transform_writer(reader, reader_format, writer, writer_format)?;
+                            let writer = DeferredChromatogramWriter::new(writer, chromatograms);
                             self.task(reader, writer)?;
                         } else {
                             let mut writer = MzMLWriterType::new(
                                 handle,
                             );
                             writer.copy_metadata_from(&reader);
+                            writer.chromatogram_count += chromatograms.len() as u64;
                             let (reader, writer) =
                                 self.transform_writer(reader, reader_format, writer, writer_format)?;
+                            let writer = DeferredChromatogramWriter::new(writer, chromatograms);
                             self.task(reader, writer)?;
                         }
                         Ok(())
@@ -1080,8 +1179,10 @@ pub trait MassSpectrometryReadWriteProcess<
                             .with_zlib_compression(9)
                             .create()?;
                         writer.copy_metadata_from(&reader);
+                        *writer.chromatogram_count_mut() += chromatograms.len() as u64;
                         let (reader, writer) =
                             self.transform_writer(reader, reader_format, writer, writer_format)?;
+                        let writer = DeferredChromatogramWriter::new(writer, chromatograms);
                         self.task(reader, writer)?;
                         Ok(())
                     }
@@ -1115,8 +1216,10 @@ pub trait MassSpectrometryReadWriteProcess<
                             handle,
                         );
                         writer.copy_metadata_from(&reader);
+                        writer.chromatogram_count += chromatograms.len() as u64;
                         let (reader, writer) =
                             self.transform_writer(reader, reader_format, writer, writer_format)?;
+                        let writer = DeferredChromatogramWriter::new(writer, chromatograms);
                         self.task(reader, writer)?;
                         Ok(())
                     }
@@ -1259,4 +1362,92 @@ mod test {
         assert_eq!(n, n_ms1 + n_msn);
         Ok(())
     }
+
+    struct CopyProcess;
+
+    impl MassSpectrometryReadWriteProcess for CopyProcess {
+        type ErrorType = io::Error;
+
+        fn task<
+            R: RandomAccessSpectrumIterator + MSDataFileMetadata + SpectrumSource + Send + 'static,
+            W: SpectrumWriter + Send + 'static,
+        >(
+            &self,
+            reader: R,
+            mut writer: W,
+        ) -> Result<(), Self::ErrorType> {
+            writer.write_all_owned(reader)?;
+            writer.close()
+        }
+    }
+
+    #[test]
+    fn test_process_copies_chromatograms() -> io::Result<()> {
+        use crate::spectrum::{
+            ArrayType, BinaryArrayMap, BinaryDataArrayType, ChromatogramDescription, DataArray,
+            SRMTransition,
+        };
+
+        let tmpdir = tempfile::tempdir()?;
+        let source_path = tmpdir.path().join("source.mzML");
+        let dest_path = tmpdir.path().join("dest.mzML");
+
+        let mut descr = ChromatogramDescription {
+            id: "SRM SIC Q1=500.25 Q3=300.1".to_string(),
+            chromatogram_type: ChromatogramType::SelectedReactionMonitoringChromatogram,
+            ..Default::default()
+        };
+        descr.set_transition(SRMTransition::new(500.25, 300.1));
+        let mut arrays = BinaryArrayMap::default();
+        let mut time_array =
+            DataArray::from_name_and_type(&ArrayType::TimeArray, BinaryDataArrayType::Float64);
+        time_array.extend(&[0.1f64, 0.2, 0.3]).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.extend(&[10.0f32, 50.0, 20.0]).unwrap();
+        arrays.add(time_array);
+        arrays.add(intensity_array);
+        let chrom = Chromatogram::new(descr, arrays);
+
+        let mut reader = MzMLReaderType::<_, CentroidPeak, DeconvolutedPeak>::open_path(
+            "./test/data/three_test_scans.mzML",
+        )?;
+        let mut writer = MzMLWriterType::<_, CentroidPeak, DeconvolutedPeak>::new(
+            io::BufWriter::new(fs::File::create(&source_path)?),
+        );
+        writer.copy_metadata_from(&reader);
+        writer.chromatogram_count += 1;
+        let mut writer = DeferredChromatogramWriter::new(writer, vec![chrom.clone()]);
+        writer.write_all_owned(reader.iter())?;
+        writer.close()?;
+
+        CopyProcess.main(source_path.as_path(), dest_path.as_path())?;
+
+        let mut reader = crate::MzMLReader::open_path(dest_path)?;
+        assert_eq!(reader.len(), 3);
+        let chroms: Vec<_> = reader.iter_chromatograms().collect();
+        assert_eq!(chroms.len(), 3);
+        assert_eq!(
+            chroms
+                .iter()
+                .filter(|c| c.chromatogram_typ() == ChromatogramType::TotalIonCurrentChromatogram)
+                .count(),
+            1
+        );
+        let chrom2 = reader.get_chromatogram_by_id(chrom.id()).unwrap();
+        assert_eq!(chrom2.transition(), chrom.transition());
+        assert_eq!(chrom2.time().unwrap(), chrom.time().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_stores_chromatograms() {
+        let sink: Sink = Path::new("out.mzML.gz").into();
+        assert!(sink.stores_chromatograms());
+        let sink: Sink = Path::new("out.mgf").into();
+        assert!(!sink.stores_chromatograms());
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let sink: Sink = sender.into();
+        assert!(!sink.stores_chromatograms());
+    }
 }
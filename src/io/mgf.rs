@@ -1,6 +1,14 @@
 /*!
 Read and write [MGF](https://www.matrixscience.com/help/data_file_help.html#GEN) files.
 Supports random access when reading from a source that supports [`io::Seek`].
+
+# Additional precursors
+MGF has no standard way to describe more than one precursor. When a spectrum has several,
+such as the synchronous precursor selection notches of an MS3 spectrum, mzdata writes the m/z
+of every precursor after the first as a comma-separated `ADDITIONAL_PRECURSOR_MZ` entry and reads
+it back into [`SpectrumDescription::precursors`](crate::spectrum::SpectrumDescription::precursors).
+This entry is an mzdata extension that other tools will ignore or reject, see
+[`MGFHeaderStyle::write_additional_precursors`] to leave it out.
 */
 
 use std::collections::HashMap;
@@ -353,7 +361,7 @@ impl<R: io::Read, C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting> MGFReade
                         },
                         None => None,
                     };
-                    builder.description.precursor = Some(Precursor {
+                    let primary = Precursor {
                         ions: vec![SelectedIon {
                             mz,
                             intensity,
@@ -361,46 +369,67 @@ impl<R: io::Read, C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting> MGFReade
                             ..Default::default()
                         }],
                         ..Default::default()
-                    });
-                }
-                "CHARGE" => {
-                    let (sign, value, tail_sign) = if let Some(stripped) = value.strip_suffix('+') {
-                        (1, stripped, true)
-                    } else if let Some(stripped) = value.strip_suffix('-') {
-                        (-1, stripped, true)
-                    } else {
-                        (1, value, false)
                     };
-
-                    if tail_sign && (value.starts_with('-') || value.starts_with('+')) {
-                        self.state = MGFParserState::Error;
-                        self.error = Some(MGFError::MalformedHeaderLine(format!(
-                            "Could not parse CHARGE header {value}"
-                        )));
-                        return false;
+                    let precursors = &mut builder.description.precursors;
+                    if precursors.is_empty() {
+                        precursors.push(primary);
+                    } else {
+                        precursors[0] = primary;
                     }
-
-                    match value.parse::<i32>() {
-                        Ok(z) => {
-                            if let Some(ion) = builder
-                                .description
-                                .precursor
-                                .get_or_insert_with(Precursor::default)
-                                .iter_mut()
-                                .last()
-                            {
-                                ion.charge = Some(sign * z);
+                }
+                "ADDITIONAL_PRECURSOR_MZ" => {
+                    let mzs: Result<Vec<f64>, _> =
+                        value.split(',').map(|v| v.trim().parse::<f64>()).collect();
+                    match mzs {
+                        Ok(mzs) => {
+                            let precursors = &mut builder.description.precursors;
+                            if precursors.is_empty() {
+                                precursors.push(Precursor::default());
                             }
+                            precursors.extend(mzs.into_iter().map(|mz| Precursor {
+                                ions: vec![SelectedIon {
+                                    mz,
+                                    ..Default::default()
+                                }],
+                                ..Default::default()
+                            }));
                         }
                         Err(e) => {
                             self.state = MGFParserState::Error;
                             self.error = Some(MGFError::MalformedHeaderLine(format!(
-                                "Could not parse CHARGE header {value} : {e}"
+                                "Malformed m/z value in ADDITIONAL_PRECURSOR_MZ header {value}: {e}"
                             )));
                             return false;
                         }
                     }
                 }
+                "CHARGE" => {
+                    let charges: Result<Vec<i32>, String> = value
+                        .split(" and ")
+                        .map(|v| parse_charge(v.trim()))
+                        .collect();
+                    match charges {
+                        Ok(charges) => {
+                            let precursors = &mut builder.description.precursors;
+                            if precursors.is_empty() {
+                                precursors.push(Precursor::default());
+                            }
+                            if let Some(ion) = precursors[0].iter_mut().last() {
+                                // Several charges are candidates for an ion of unknown charge
+                                if let [z] = charges[..] {
+                                    ion.charge = Some(z);
+                                } else {
+                                    ion.set_possible_charges(&charges);
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            self.state = MGFParserState::Error;
+                            self.error = Some(MGFError::MalformedHeaderLine(e));
+                            return false;
+                        }
+                    }
+                }
                 &_ => {
                     builder
                         .description
@@ -797,6 +826,32 @@ impl<R: Read, C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting> Chromatogram
 
 pub type MGFReader<R> = MGFReaderType<R, CentroidPeak, DeconvolutedPeak>;
 
+/// Parse a single charge state from a `CHARGE` header, like `2+`, `3-` or `2`
+fn parse_charge(value: &str) -> Result<i32, String> {
+    let (sign, value, tail_sign) = if let Some(stripped) = value.strip_suffix('+') {
+        (1, stripped, true)
+    } else if let Some(stripped) = value.strip_suffix('-') {
+        (-1, stripped, true)
+    } else {
+        (1, value, false)
+    };
+
+    if tail_sign && (value.starts_with('-') || value.starts_with('+')) {
+        return Err(format!("Could not parse CHARGE header {value}"));
+    }
+
+    value
+        .parse::<i32>()
+        .map(|z| sign * z)
+        .map_err(|e| format!("Could not parse CHARGE header {value} : {e}"))
+}
+
+/// Format a charge state the way a `CHARGE` header expects, like `2+` or `3-`
+fn format_charge(charge: i32) -> String {
+    let sign = if charge < 0 { '-' } else { '+' };
+    format!("{}{sign}", charge.abs())
+}
+
 pub(crate) fn is_mgf(buf: &[u8]) -> bool {
     let needle = b"BEGIN IONS";
     buf.windows(needle.len()).any(|window| window == needle)
@@ -805,6 +860,7 @@ pub(crate) fn is_mgf(buf: &[u8]) -> bool {
 const TITLE_CV: CURIE = ControlledVocabulary::MS.curie(1000796);
 const MS_LEVEL_CV: CURIE = ControlledVocabulary::MS.curie(1000511);
 const MSN_SPECTRUM_CV: CURIE = ControlledVocabulary::MS.curie(1000580);
+const POSSIBLE_CHARGE_STATE_CV: CURIE = ControlledVocabulary::MS.curie(1000633);
 
 /// A trait that controls what additional descriptive entries
 /// are written in the spectrum header of an MGF file, not including
@@ -841,11 +897,20 @@ pub trait MGFHeaderStyle: Sized {
         }
         writer.handle.write_all(b"\n")?;
 
+        let possible_charges = ion.possible_charges();
+        if ion.charge.is_none() && !possible_charges.is_empty() {
+            let charges: Vec<String> = possible_charges.into_iter().map(format_charge).collect();
+            writer.write_kv("CHARGE", &charges.join(" and "))?;
+        }
+
+        let activation_params = precursor.activation.structured_params();
         for param in precursor
             .ion()
             .params()
             .iter()
+            .filter(|p| POSSIBLE_CHARGE_STATE_CV != **p)
             .chain(precursor.activation.params())
+            .chain(activation_params.iter())
         {
             writer.write_param(param)?;
         }
@@ -856,6 +921,31 @@ pub trait MGFHeaderStyle: Sized {
         }
         Ok(())
     }
+
+    /// Write the precursors after the primary one, such as the synchronous precursor selection
+    /// notches of an MS3 spectrum. MGF has no dedicated entry for these, so by default their
+    /// m/z values are written as a comma-separated `ADDITIONAL_PRECURSOR_MZ` entry.
+    ///
+    /// `ADDITIONAL_PRECURSOR_MZ` is not part of the MGF format but an mzdata extension. Styles
+    /// that must stay readable by other tools can override this method to write nothing.
+    fn write_additional_precursors<
+        W: io::Write,
+        C: CentroidPeakAdapting,
+        D: DeconvolutedPeakAdapting,
+    >(
+        writer: &mut MGFWriterType<W, C, D, Self>,
+        precursors: &[Precursor],
+    ) -> io::Result<()> {
+        let mzs: Vec<String> = precursors
+            .iter()
+            .filter(|p| !p.ions.is_empty())
+            .map(|p| p.ion().mz.to_string())
+            .collect();
+        if !mzs.is_empty() {
+            writer.write_kv("ADDITIONAL_PRECURSOR_MZ", &mzs.join(","))?;
+        }
+        Ok(())
+    }
 }
 
 /// An MGF style that writes the `SCANS` entry, but no additional
@@ -1005,11 +1095,11 @@ impl<W: io::Write, C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting, Y: MGFH
         self.handle
             .write_all((spectrum.start_time() * 60.0).to_string().as_bytes())?;
         self.handle.write_all(b"\n")?;
-        match &desc.precursor {
-            Some(precursor) => {
-                self.write_precursor(precursor)?;
+        if let Some((precursor, rest)) = desc.precursors.split_first() {
+            self.write_precursor(precursor)?;
+            if !rest.is_empty() {
+                Y::write_additional_precursors(self, rest)?;
             }
-            None => {}
         }
 
         Y::write_header(self, spectrum)?;
@@ -1249,4 +1339,34 @@ mod test {
         // Not including platform-specific line endings
         Ok(())
     }
+
+    #[test]
+    fn test_additional_precursors() -> io::Result<()> {
+        let path = path::Path::new("./test/data/small.mgf");
+        let file = fs::File::open(path).expect("Test file doesn't exist");
+        let mut reader = MGFReader::new(file);
+        let mut scan = reader.next().unwrap();
+        for mz in [450.5, 612.25] {
+            scan.add_precursor(Precursor {
+                ions: vec![SelectedIon {
+                    mz,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            });
+        }
+
+        let mut writer = MGFWriter::new(io::Cursor::new(Vec::new()));
+        writer.write(&scan)?;
+        writer.flush()?;
+        let buffer = writer.handle.into_inner()?.into_inner();
+        let mut reader2 = MGFReader::new(io::Cursor::new(buffer));
+        let scan2 = reader2.next().unwrap();
+
+        let mzs: Vec<f64> = scan2.precursors().iter().map(|p| p.ion().mz).collect();
+        assert_eq!(mzs.len(), 3);
+        assert!((mzs[0] - scan.precursor().unwrap().ion().mz).abs() < 1e-6);
+        assert_eq!(mzs[1..], [450.5, 612.25]);
+        Ok(())
+    }
 }
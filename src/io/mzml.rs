@@ -38,7 +38,9 @@ pub use crate::io::mzml::reader::{
 
 pub(crate) use crate::io::mzml::reader::is_mzml;
 
-pub use crate::io::mzml::writer::{MzMLWriter, MzMLWriterState, MzMLWriterType, MzMLWriterError};
+pub use crate::io::mzml::writer::{
+    IonMobilityFrameLayout, MzMLWriter, MzMLWriterError, MzMLWriterState, MzMLWriterType,
+};
 
 #[cfg(feature = "async")]
 pub use crate::io::mzml::r#async::{
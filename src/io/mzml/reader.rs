@@ -34,7 +34,7 @@ use crate::params::{Param, ParamList, Unit};
 use crate::prelude::ParamLike;
 use crate::spectrum::bindata::{
     ArrayType, BinaryArrayMap, BinaryCompressionType, BinaryDataArrayType, BuildArrayMapFrom,
-    BuildFromArrayMap, DataArray,
+    BuildFromArrayMap, DataArray, CCS_ARRAY_NAME,
 };
 use crate::spectrum::chromatogram::{Chromatogram, ChromatogramLike};
 use crate::spectrum::scan_properties::*;
@@ -128,13 +128,33 @@ pub trait SpectrumBuilding<
 
                 // Array types
                 1000514 => self.current_array_mut().name = ArrayType::MZArray,
-                1000515 => self.current_array_mut().name = ArrayType::IntensityArray,
+                1000515 => {
+                    self.current_array_mut().name = ArrayType::IntensityArray;
+                    if param.unit() == Unit::AbsorbanceUnit {
+                        self.current_array_mut().unit = Unit::AbsorbanceUnit;
+                    }
+                }
                 1000516 => self.current_array_mut().name = ArrayType::ChargeArray,
+                1000617 => {
+                    self.current_array_mut().name = ArrayType::WavelengthArray;
+                    self.current_array_mut().unit = match param.unit() {
+                        Unit::Unknown => Unit::Nanometer,
+                        unit => unit,
+                    };
+                }
                 1000517 => self.current_array_mut().name = ArrayType::SignalToNoiseArray,
+                1002530 => self.current_array_mut().name = ArrayType::BaselineArray,
+                1002742 => self.current_array_mut().name = ArrayType::NoiseArray,
                 1000786 => {
-                    self.current_array_mut().name = ArrayType::NonStandardDataArray {
-                        name: Box::new(param.value().to_string()),
-                    };
+                    let name = param.value().to_string();
+                    if name == CCS_ARRAY_NAME {
+                        self.current_array_mut().name = ArrayType::CollisionalCrossSectionArray;
+                        self.current_array_mut().unit = param.unit();
+                    } else {
+                        self.current_array_mut().name = ArrayType::NonStandardDataArray {
+                            name: Box::new(name),
+                        };
+                    }
                 }
                 1000595 => {
                     self.current_array_mut().name = ArrayType::TimeArray;
@@ -152,6 +172,10 @@ pub trait SpectrumBuilding<
                     self.current_array_mut().name = ArrayType::MeanIonMobilityArray;
                     self.current_array_mut().unit = Unit::Millisecond;
                 }
+                1002816 => {
+                    self.current_array_mut().name = ArrayType::MeanIonMobilityArray;
+                    self.current_array_mut().unit = param.unit();
+                }
                 1003006 => {
                     self.current_array_mut().name = ArrayType::MeanIonMobilityArray;
                     self.current_array_mut().unit = Unit::VoltSecondPerSquareCentimeter;
@@ -318,7 +342,11 @@ pub struct MzMLSpectrumBuilder<
 > {
     pub params: ParamList,
     pub acquisition: Acquisition,
+    /// The precursor currently being read
     pub precursor: Precursor,
+    /// The precursors that have been read completely, in document order
+    pub precursors: Vec<Precursor>,
+    pub product: Product,
 
     pub arrays: BinaryArrayMap,
     pub current_array: DataArray,
@@ -328,7 +356,9 @@ pub struct MzMLSpectrumBuilder<
     pub ms_level: u8,
     pub polarity: ScanPolarity,
     pub signal_continuity: SignalContinuity,
+    pub spectrum_type: SpectrumType,
     pub has_precursor: bool,
+    pub has_product: bool,
     pub detail_level: DetailLevel,
     pub instrument_id_map: Option<&'a mut IncrementingIdMap>,
     entry_type: EntryType,
@@ -355,7 +385,13 @@ impl<
     for MzMLSpectrumBuilder<'inner, C, D>
 {
     fn isolation_window_mut(&mut self) -> &mut IsolationWindow {
-        &mut self.precursor.isolation_window
+        // A chromatogram's `<product>` always follows its `<precursor>`, so once it has
+        // been opened, any further isolation window belongs to the product.
+        if self.has_product {
+            &mut self.product.isolation_window
+        } else {
+            &mut self.precursor.isolation_window
+        }
     }
 
     fn scan_window_mut(&mut self) -> &mut ScanWindow {
@@ -394,16 +430,13 @@ impl<
         description.id = self.entry_id;
         description.index = self.index;
         description.signal_continuity = self.signal_continuity;
+        description.spectrum_type = self.spectrum_type;
         description.ms_level = self.ms_level;
         description.polarity = self.polarity;
 
         description.params = self.params;
         description.acquisition = self.acquisition;
-        if self.has_precursor {
-            description.precursor = Some(self.precursor);
-        } else {
-            description.precursor = None;
-        }
+        description.precursors = self.precursors;
 
         spectrum.arrays = Some(self.arrays);
     }
@@ -425,6 +458,15 @@ impl<
             "centroid spectrum" => {
                 self.signal_continuity = SignalContinuity::Centroid;
             }
+            "electromagnetic radiation spectrum" => {
+                self.spectrum_type = SpectrumType::ElectromagneticRadiationSpectrum;
+            }
+            "absorption spectrum" => {
+                self.spectrum_type = SpectrumType::AbsorptionSpectrum;
+            }
+            "emission spectrum" => {
+                self.spectrum_type = SpectrumType::EmissionSpectrum;
+            }
             &_ => {
                 self.params.push(param.into());
             }
@@ -471,10 +513,11 @@ impl<
         }
 
         description.params = params;
-        if self.has_precursor {
-            description.precursor = Some(self.precursor);
+        description.precursor = self.precursors.into_iter().next();
+        if self.has_product {
+            description.product = Some(self.product);
         } else {
-            description.precursor = None;
+            description.product = None;
         }
 
         chromatogram.arrays = self.arrays;
@@ -517,9 +560,13 @@ impl<
         self.entry_id.clear();
 
         self.precursor = Precursor::default();
+        self.precursors.clear();
+        self.product = Product::default();
         self.index = 0;
         self.has_precursor = false;
+        self.has_product = false;
         self.signal_continuity = SignalContinuity::Unknown;
+        self.spectrum_type = SpectrumType::MassSpectrum;
         self.polarity = ScanPolarity::Unknown;
     }
 
@@ -577,10 +624,15 @@ impl<
                         event.start_time = value;
                     }
                     b"ion injection time" => {
-                        event.injection_time = param
+                        let value = param
                             .to_f64()
-                            .expect("Expected floating point number for injection time")
-                            as f32;
+                            .expect("Expected floating point number for injection time");
+                        let value = match &param.unit {
+                            Unit::Second => value * 1000.0,
+                            Unit::Minute => value * 60000.0,
+                            _ => value,
+                        };
+                        event.injection_time = value as f32;
                     }
                     _ => event.add_param(param),
                 }
@@ -618,7 +670,9 @@ impl<
             MzMLParserState::BinaryDataArray => {
                 self.fill_binary_data_array(param);
             }
-            MzMLParserState::Precursor | MzMLParserState::PrecursorList => {
+            MzMLParserState::Precursor
+            | MzMLParserState::PrecursorList
+            | MzMLParserState::Product => {
                 warn!("cvParam found for {:?} where none are allowed", &state);
             }
             _ => {}
@@ -730,6 +784,10 @@ impl<
                 }
                 return Ok(MzMLParserState::Precursor);
             }
+            b"product" => {
+                self.has_product = true;
+                return Ok(MzMLParserState::Product);
+            }
             b"isolationWindow" => {
                 return Ok(MzMLParserState::IsolationWindow);
             }
@@ -833,9 +891,16 @@ impl<
                                     self.acquisition.scans.last_mut().unwrap().start_time = value;
                                 }
                                 b"ion injection time" => {
-                                    self.acquisition.scans.last_mut().unwrap().injection_time = param.to_f32().unwrap_or_else(
+                                    let value = param.to_f64().unwrap_or_else(
                                             |e| panic!("Expected floating point number for injection time: {e} for {}", self.warning_context())
                                         );
+                                    let value = match &param.unit {
+                                        Unit::Second => value * 1000.0,
+                                        Unit::Minute => value * 60000.0,
+                                        _ => value,
+                                    };
+                                    self.acquisition.scans.last_mut().unwrap().injection_time =
+                                        value as f32;
                                 }
                                 _ => self
                                     .acquisition
@@ -893,7 +958,9 @@ impl<
                             MzMLParserState::BinaryDataArray => {
                                 self.fill_binary_data_array(param);
                             }
-                            MzMLParserState::Precursor | MzMLParserState::PrecursorList => {
+                            MzMLParserState::Precursor
+                            | MzMLParserState::PrecursorList
+                            | MzMLParserState::Product => {
                                 warn!("cvParam found for {:?} where none are allowed", &state);
                             }
                             _ => {}
@@ -917,11 +984,23 @@ impl<
             b"scanWindow" => return Ok(MzMLParserState::ScanWindowList),
             b"scanWindowList" => return Ok(MzMLParserState::Scan),
             b"precursorList" => return Ok(MzMLParserState::Spectrum),
-            b"precursor" => return Ok(MzMLParserState::PrecursorList),
-            b"isolationWindow" => return Ok(MzMLParserState::Precursor),
+            b"precursor" => {
+                self.precursors.push(mem::take(&mut self.precursor));
+                return Ok(MzMLParserState::PrecursorList);
+            }
+            b"isolationWindow" => {
+                if self.has_product {
+                    return Ok(MzMLParserState::Product);
+                }
+                return Ok(MzMLParserState::Precursor);
+            }
+            b"product" => return Ok(MzMLParserState::Chromatogram),
             b"selectedIonList" => return Ok(MzMLParserState::Precursor),
             b"selectedIon" => return Ok(MzMLParserState::SelectedIonList),
-            b"activation" => return Ok(MzMLParserState::Precursor),
+            b"activation" => {
+                self.precursor.activation._extract_energies_from_params();
+                return Ok(MzMLParserState::Precursor);
+            }
             b"binaryDataArrayList" => {
                 return Ok(MzMLParserState::Spectrum);
             }
@@ -2007,7 +2086,7 @@ impl<
     type Item = Chromatogram;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.reader.chromatogram_index.len() <= self.index {
+        if self.index < self.reader.chromatogram_index.len() {
             let result = self.reader.get_chromatogram_by_index(self.index);
             self.index += 1;
             result
@@ -82,6 +82,7 @@ pub enum MzMLParserState {
     SelectedIonList,
     SelectedIon,
     Activation,
+    Product,
 
     SpectrumDone,
     SpectrumListDone,
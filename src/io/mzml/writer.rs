@@ -16,7 +16,7 @@ use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::{Error as XMLError, Writer};
 
 use super::super::offset_index::OffsetIndex;
-use super::super::traits::SpectrumWriter;
+use super::super::traits::{ChromatogramWriter, SpectrumWriter};
 use super::super::utils::MD5HashingStream;
 
 use mzpeaks::{CentroidPeak, DeconvolutedPeak};
@@ -29,12 +29,14 @@ use crate::params::{
     ControlledVocabulary, Param, ParamCow, ParamDescribed, ParamLike, ParamValue, Unit, ValueRef,
 };
 use crate::spectrum::bindata::{
-    to_bytes, ArrayRetrievalError, ArrayType, BinaryArrayMap, BinaryCompressionType,
-    BinaryDataArrayType, BuildArrayMap3DFrom, BuildArrayMapFrom, ByteArrayView, DataArray,
+    to_bytes, ArrayRetrievalError, ArrayType, BinaryArrayMap, BinaryArrayMap3D,
+    BinaryCompressionType, BinaryDataArrayType, BuildArrayMap3DFrom, BuildArrayMapFrom,
+    ByteArrayView, DataArray,
 };
+use crate::spectrum::frame::RefFeatureDataLevel;
 use crate::spectrum::spectrum_types::SpectrumLike;
 use crate::spectrum::{scan_properties::*, Chromatogram, ChromatogramLike, RefPeakDataLevel};
-use crate::{curie, impl_param_described, RawSpectrum};
+use crate::{impl_param_described, RawSpectrum};
 
 const BUFFER_SIZE: usize = 10000;
 
@@ -503,9 +505,15 @@ pub struct MzMLWriterType<
     pub bic_collector: ChromatogramCollector,
     pub wrote_summaries: bool,
 
+    /// How ion mobility frames written with [`IonMobilityFrameWriter`] are laid out
+    pub ion_mobility_frame_layout: IonMobilityFrameLayout,
+
     pub run: MassSpectrometryRun,
 
     handle: InnerXMLWriter<W>,
+    /// The id of the first spectrum written for each frame split by
+    /// [`IonMobilityFrameLayout::SpectrumPerIonMobility`]
+    split_frame_ids: HashMap<String, String>,
     centroid_type: PhantomData<C>,
     deconvoluted_type: PhantomData<D>,
     ms_cv: ControlledVocabulary,
@@ -542,6 +550,127 @@ impl<
     }
 }
 
+impl<
+        W: Write,
+        C: CentroidLike + Default + BuildArrayMapFrom,
+        D: DeconvolutedCentroidLike + Default + BuildArrayMapFrom,
+    > ChromatogramWriter for MzMLWriterType<W, C, D>
+{
+    fn write_chromatogram(&mut self, chromatogram: &Chromatogram) -> io::Result<usize> {
+        match MzMLWriterType::write_chromatogram(self, chromatogram) {
+            Ok(()) => {
+                let pos = self.stream_position()?;
+                Ok(pos as usize)
+            }
+            Err(err) => {
+                let msg = err.to_string();
+                Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+            }
+        }
+    }
+}
+
+/// How an ion mobility frame is laid out when it is written as mzML spectra
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IonMobilityFrameLayout {
+    /// Write each frame as a single spectrum whose data arrays include an ion mobility
+    /// array, one value per point.
+    #[default]
+    IonMobilityArray,
+    /// Write each point along the ion mobility dimension that has any signal as a
+    /// separate spectrum, recording the ion mobility value on its scan.
+    ///
+    /// Each spectrum's id is the frame's id followed by `scan=` and the point's 1-based
+    /// position. Because no spectrum has the frame's id, precursors isolated from a frame
+    /// written earlier are pointed at the first spectrum written for that frame.
+    SpectrumPerIonMobility,
+}
+
+impl IonMobilityFrameLayout {
+    /// Convert an ion mobility frame's description and feature data into the spectra
+    /// to write according to this layout.
+    pub fn frame_to_spectra<
+        CF: FeatureLike<MZ, IonMobility> + BuildArrayMap3DFrom,
+        DF: FeatureLike<Mass, IonMobility> + KnownCharge + BuildArrayMap3DFrom,
+    >(
+        &self,
+        description: SpectrumDescription,
+        features: RefFeatureDataLevel<'_, CF, DF>,
+    ) -> Result<Vec<RawSpectrum>, ArrayRetrievalError> {
+        match self {
+            Self::IonMobilityArray => {
+                let peak_data = match features {
+                    RefFeatureDataLevel::Missing => BinaryArrayMap::default(),
+                    RefFeatureDataLevel::RawData(a) => a.unstack()?,
+                    RefFeatureDataLevel::Centroid(c) => CF::as_arrays(&c[..]),
+                    RefFeatureDataLevel::Deconvoluted(d) => DF::as_arrays(&d[..]),
+                };
+                Ok(vec![RawSpectrum::new(description, peak_data)])
+            }
+            Self::SpectrumPerIonMobility => match features {
+                RefFeatureDataLevel::Missing => Ok(vec![RawSpectrum::new(
+                    description,
+                    BinaryArrayMap::default(),
+                )]),
+                RefFeatureDataLevel::RawData(a) => Ok(split_ion_mobility(&description, a)),
+                RefFeatureDataLevel::Centroid(c) => {
+                    Ok(split_ion_mobility(&description, &CF::as_arrays_3d(&c[..])))
+                }
+                RefFeatureDataLevel::Deconvoluted(d) => {
+                    Ok(split_ion_mobility(&description, &DF::as_arrays_3d(&d[..])))
+                }
+            },
+        }
+    }
+}
+
+/// Select the scan-level term for an ion mobility value from its unit
+fn ion_mobility_scan_param(value: f64, unit: Unit) -> Param {
+    const CV: ControlledVocabulary = ControlledVocabulary::MS;
+    let mut param: Param = match unit {
+        Unit::VoltSecondPerSquareCentimeter => CV
+            .const_param_ident("inverse reduced ion mobility drift time", 1002815)
+            .into(),
+        Unit::Volt => CV
+            .const_param_ident("FAIMS compensation voltage", 1001581)
+            .into(),
+        _ => CV
+            .const_param_ident("ion mobility drift time", 1002476)
+            .into(),
+    };
+    param.value = value.into();
+    param.unit = unit;
+    param
+}
+
+fn split_ion_mobility(
+    description: &SpectrumDescription,
+    arrays: &BinaryArrayMap3D,
+) -> Vec<RawSpectrum> {
+    arrays
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, point_arrays))| {
+            point_arrays
+                .iter()
+                .any(|(_, array)| array.data_len().map(|n| n > 0).unwrap_or(true))
+        })
+        .map(|(i, (im, point_arrays))| {
+            let mut descr = description.clone();
+            descr.id = format!("{} scan={}", description.id, i + 1);
+            let param = ion_mobility_scan_param(im, arrays.ion_mobility_unit);
+            if let Some(scan) = descr.acquisition.first_scan_mut() {
+                scan.add_param(param);
+            } else {
+                let mut scan = ScanEvent::default();
+                scan.add_param(param);
+                descr.acquisition.scans.push(scan);
+            }
+            RawSpectrum::new(descr, point_arrays.clone())
+        })
+        .collect()
+}
+
 impl<
         W: Write,
         C: CentroidLike + Default + BuildArrayMapFrom,
@@ -555,20 +684,32 @@ impl<
         frame: &S,
     ) -> io::Result<usize> {
         let state = frame.description().clone().into();
-        let peak_data = match frame.features() {
-            crate::spectrum::frame::RefFeatureDataLevel::Missing => BinaryArrayMap::default(),
-            crate::spectrum::frame::RefFeatureDataLevel::RawData(a) => a.unstack()?,
-            crate::spectrum::frame::RefFeatureDataLevel::Centroid(c) => CF::as_arrays(&c[..]),
-            crate::spectrum::frame::RefFeatureDataLevel::Deconvoluted(d) => DF::as_arrays(&d[..]),
-        };
-        let spectrum = RawSpectrum::new(state, peak_data);
-        self.write_owned(spectrum)
+        let mut spectra = self
+            .ion_mobility_frame_layout
+            .frame_to_spectra(state, frame.features())?;
+        if matches!(
+            self.ion_mobility_frame_layout,
+            IonMobilityFrameLayout::SpectrumPerIonMobility
+        ) {
+            self.link_split_precursors(frame.id(), &mut spectra);
+        }
+        let mut n = 0;
+        for spectrum in spectra {
+            n = self.write_owned(spectrum)?;
+        }
+        Ok(n)
     }
 
     fn write_frame_owned<S: crate::spectrum::IonMobilityFrameLike<CF, DF> + 'static>(
         &mut self,
         frame: S,
     ) -> io::Result<usize> {
+        if matches!(
+            self.ion_mobility_frame_layout,
+            IonMobilityFrameLayout::SpectrumPerIonMobility
+        ) {
+            return self.write_frame(&frame);
+        }
         let (features, state) = frame.into_features_and_parts();
         let peak_data = match features {
             crate::spectrum::frame::FeatureDataLevel::Missing => BinaryArrayMap::default(),
@@ -745,11 +886,36 @@ where
             ms_cv: ControlledVocabulary::MS,
             data_array_compression,
             wrote_summaries: false,
+            ion_mobility_frame_layout: IonMobilityFrameLayout::default(),
+            split_frame_ids: HashMap::new(),
             run: MassSpectrometryRun::default(),
             param_groups: Vec::default(),
         }
     }
 
+    /// Point the precursors of the spectra split from the frame `frame_id` at the first spectrum
+    /// split from their precursor frame, and remember the first spectrum split from this one
+    fn link_split_precursors(&mut self, frame_id: &str, spectra: &mut [RawSpectrum]) {
+        for spectrum in spectra.iter_mut() {
+            for precursor in spectrum.description.precursors.iter_mut() {
+                let Some(precursor_id) = precursor.precursor_id.as_mut() else {
+                    continue;
+                };
+                match self.split_frame_ids.get(precursor_id.as_str()) {
+                    Some(first_id) => *precursor_id = first_id.clone(),
+                    None => warn!(
+                        "The precursor frame {precursor_id} of {} was not written by this writer, its id may not refer to any spectrum",
+                        spectrum.description.id
+                    ),
+                }
+            }
+        }
+        if let Some(first) = spectra.first() {
+            self.split_frame_ids
+                .insert(frame_id.to_string(), first.description.id.clone());
+        }
+    }
+
     pub fn new_with_index(file: W, write_index: bool) -> MzMLWriterType<W, C, D> {
         Self::new_with_index_and_compression(file, write_index, BinaryCompressionType::Zlib)
     }
@@ -1218,7 +1384,6 @@ where
                 self.close_spectrum_list()?;
             }
             state if state < MzMLWriterState::SpectrumList => {
-                self.start_run()?;
                 self.start_spectrum_list()?;
                 self.close_spectrum_list()?;
             }
@@ -1397,7 +1562,7 @@ where
                 .param_val(
                     "MS:1000828",
                     "isolation window lower offset",
-                    (iw.target - iw.lower_bound).to_string(),
+                    iw.lower_offset().to_string(),
                 )
                 .with_unit("MS:1000040", "m/z"),
         )?;
@@ -1407,7 +1572,7 @@ where
                 .param_val(
                     "MS:1000829",
                     "isolation window upper offset",
-                    (iw.upper_bound - iw.target).to_string(),
+                    iw.upper_offset().to_string(),
                 )
                 .with_unit("MS:1000040", "m/z"),
         )?;
@@ -1448,11 +1613,13 @@ where
         let act = precursor.activation();
         let tag = bstart!("activation");
         start_event!(self, tag);
-        if let Some(meth) = act.method() {
-            let meth_param: Param = meth.clone().into();
+        for meth in act.methods() {
+            let meth_param: Param = (*meth).into();
             self.handle.write_param(&meth_param)?;
         }
         self.handle.write_param_list(act.params().iter())?;
+        self.handle
+            .write_param_list(act.structured_params().iter())?;
         self.handle.write_param(
             &self
                 .ms_cv
@@ -1467,7 +1634,34 @@ where
         let mut precursor_list_tag = bstart!("precursorList");
         attrib!("count", "1", precursor_list_tag);
         start_event!(self, precursor_list_tag);
+        self.write_precursor_element(precursor)?;
+        end_event!(self, precursor_list_tag);
+        Ok(())
+    }
 
+    /// Write a `<precursorList>` containing each of `precursors` in order. Nothing is
+    /// written if `precursors` is empty.
+    pub fn write_precursor_list<P: PrecursorSelection>(
+        &mut self,
+        precursors: &[P],
+    ) -> WriterResult {
+        if precursors.is_empty() {
+            return Ok(());
+        }
+        let mut precursor_list_tag = bstart!("precursorList");
+        let count = precursors.len().to_string();
+        attrib!("count", count, precursor_list_tag);
+        start_event!(self, precursor_list_tag);
+        for precursor in precursors {
+            self.write_precursor_element(precursor)?;
+        }
+        end_event!(self, precursor_list_tag);
+        Ok(())
+    }
+
+    /// Write a single `<precursor>` element without the enclosing `<precursorList>`, as
+    /// used by chromatograms.
+    pub fn write_precursor_element(&mut self, precursor: &impl PrecursorSelection) -> WriterResult {
         let mut precursor_tag = bstart!("precursor");
         if let Some(prec_id) = precursor.precursor_id() {
             attrib!("spectrumRef", prec_id, precursor_tag);
@@ -1477,10 +1671,40 @@ where
 
         let iw = precursor.isolation_window();
         self.write_isolation_window(iw)?;
-        self.write_selected_ions(precursor)?;
+        // The selected ion list is optional, as for SRM chromatograms
+        if precursor.iter().next().is_some() {
+            self.write_selected_ions(precursor)?;
+        }
         self.write_activation(precursor)?;
         end_event!(self, precursor_tag);
-        end_event!(self, precursor_list_tag);
+        Ok(())
+    }
+
+    pub fn write_product(&mut self, product: &Product) -> WriterResult {
+        let product_tag = bstart!("product");
+        start_event!(self, product_tag);
+        self.write_isolation_window(&product.isolation_window)?;
+        end_event!(self, product_tag);
+        Ok(())
+    }
+
+    /// Write the chromatogram type, if it is not already among `chromatogram`'s parameters,
+    /// followed by the chromatogram's `<precursor>` and `<product>`, if present.
+    pub fn write_chromatogram_descriptors(&mut self, chromatogram: &Chromatogram) -> WriterResult {
+        let chromatogram_type = chromatogram.chromatogram_typ();
+        if !matches!(chromatogram_type, ChromatogramType::Unknown)
+            && chromatogram
+                .get_param_by_curie(&chromatogram_type.to_curie())
+                .is_none()
+        {
+            self.write_param(&chromatogram_type.to_param())?;
+        }
+        if let Some(precursor) = chromatogram.precursor() {
+            self.write_precursor_element(precursor)?;
+        }
+        if let Some(product) = chromatogram.product() {
+            self.write_product(product)?;
+        }
         Ok(())
     }
 
@@ -1489,6 +1713,10 @@ where
         D1: DeconvolutedCentroidLike + Default + BuildArrayMapFrom,
         S: SpectrumLike<C1, D1> + 'static
     >(&mut self, spectrum: &S) -> WriterResult {
+        if let Some(param) = spectrum.spectrum_type().to_param() {
+            // Non-mass spectra are described by their spectrum type and have no MS level
+            return self.handle.write_param(&param);
+        }
         let ms_level = spectrum.ms_level();
         if ms_level == 1 {
             self.handle.write_param(&MS1_SPECTRUM)?;
@@ -1512,6 +1740,9 @@ where
         match spectrum.polarity() {
             ScanPolarity::Negative => self.handle.write_param(&NEGATIVE_SCAN),
             ScanPolarity::Positive => self.handle.write_param(&POSITIVE_SCAN),
+            ScanPolarity::Unknown if spectrum.spectrum_type().is_electromagnetic_radiation() => {
+                Ok(())
+            }
             ScanPolarity::Unknown => {
                 warn!(
                     "Could not determine scan polarity for {}, assuming positive",
@@ -1634,15 +1865,29 @@ where
         )?;
 
         match &array.name {
-            ArrayType::MZArray | ArrayType::IntensityArray | ArrayType::ChargeArray => {
-                self.handle.write_param(&array.name.as_param_const())?
-            }
+            ArrayType::IntensityArray if array.unit == Unit::AbsorbanceUnit => self
+                .handle
+                .write_param(&array.name.as_param_with_unit_const(array.unit))?,
+            ArrayType::MZArray
+            | ArrayType::IntensityArray
+            | ArrayType::ChargeArray
+            | ArrayType::SignalToNoiseArray
+            | ArrayType::BaselineArray
+            | ArrayType::NoiseArray => self.handle.write_param(&array.name.as_param_const())?,
+            ArrayType::WavelengthArray => self.handle.write_param(
+                &array
+                    .name
+                    .as_param((array.unit != Unit::Unknown).then_some(array.unit)),
+            )?,
             ArrayType::TimeArray
             | ArrayType::RawIonMobilityArray
             | ArrayType::MeanIonMobilityArray
             | ArrayType::DeconvolutedIonMobilityArray => self
                 .handle
                 .write_param(&array.name.as_param_with_unit_const(array.unit))?,
+            ArrayType::CollisionalCrossSectionArray => self
+                .handle
+                .write_param(&array.name.as_param(Some(array.unit)))?,
             ArrayType::NonStandardDataArray { name } => {
                 let mut p =
                     self.ms_cv
@@ -1775,9 +2020,14 @@ where
         spectrum: &S,
     ) -> SpectrumHasSummary {
         let peaks = spectrum.peaks();
+        let coordinate_array = if spectrum.spectrum_type().is_electromagnetic_radiation() {
+            ArrayType::WavelengthArray
+        } else {
+            ArrayType::MZArray
+        };
         let peak_count = match peaks {
             RefPeakDataLevel::RawData(arrays) => {
-                if let Some(arr) = arrays.get(&ArrayType::MZArray) {
+                if let Some(arr) = arrays.get(&coordinate_array) {
                     if let Ok(count) = arr.data_len() {
                         count
                     } else {
@@ -1822,9 +2072,7 @@ where
         self.write_signal_properties(spectrum)?;
 
         self.write_scan_list(spectrum.acquisition())?;
-        for precursor in spectrum.precursor_iter() {
-            self.write_precursor(precursor)?;
-        }
+        self.write_precursor_list(spectrum.precursors())?;
         Ok(())
     }
 
@@ -1872,22 +2120,25 @@ where
 
         self.write_spectrum_descriptors(spectrum, &summary_metrics)?;
 
-        let tic = spectrum
-            .params()
-            .get_param_by_curie(&curie!(MS:1000285))
-            .map(|p| p.to_f32().unwrap())
-            .unwrap_or_else(|| spectrum.peaks().tic());
+        // Non-mass spectra do not contribute to the ion current summary chromatograms
+        if spectrum.spectrum_type().is_mass_spectrum() {
+            let tic = spectrum
+                .description()
+                .total_ion_current()
+                .map(|v| v as f32)
+                .unwrap_or_else(|| spectrum.peaks().tic());
 
-        let bpi = spectrum
-            .params()
-            .get_param_by_curie(&curie!(MS:1000505))
-            .map(|p| p.to_f32().unwrap())
-            .unwrap_or_else(|| spectrum.peaks().base_peak().intensity);
+            let bpi = spectrum
+                .description()
+                .base_peak_intensity()
+                .map(|v| v as f32)
+                .unwrap_or_else(|| spectrum.peaks().base_peak().intensity);
 
-        let time = spectrum.start_time();
+            let time = spectrum.start_time();
 
-        self.tic_collector.add(time, tic);
-        self.bic_collector.add(time, bpi);
+            self.tic_collector.add(time, tic);
+            self.bic_collector.add(time, bpi);
+        }
 
         match spectrum.peaks() {
             RefPeakDataLevel::RawData(arrays) => {
@@ -1976,10 +2227,7 @@ where
         let mut outer = bstart!("chromatogram");
         let default_array_len = self.start_chromatogram(chromatogram, &mut outer)?;
         self.write_param_list(chromatogram.params().iter())?;
-
-        if let Some(precursor) = chromatogram.precursor() {
-            self.write_precursor(precursor)?;
-        }
+        self.write_chromatogram_descriptors(chromatogram)?;
 
         self.write_binary_data_arrays(&chromatogram.arrays, default_array_len)?;
         end_event!(self, outer);
@@ -2085,6 +2333,7 @@ pub type MzMLWriter<W> = MzMLWriterType<W, CentroidPeak, DeconvolutedPeak>;
 mod test {
     use super::super::reader::MzMLReader;
     use super::*;
+    use crate::meta::DissociationMethodTerm;
     use crate::prelude::*;
     use std::fs;
     use std::path;
@@ -2148,4 +2397,376 @@ mod test {
 
         Ok(())
     }
+
+    #[test_log::test]
+    fn write_srm_chromatogram_test() -> WriterResult {
+        let tmpdir = tempfile::tempdir()?;
+        let dest_path = tmpdir.path().join("srm.mzML");
+
+        let mut descr = ChromatogramDescription {
+            id: "SRM SIC Q1=500.25 Q3=300.1".to_string(),
+            chromatogram_type: ChromatogramType::SelectedReactionMonitoringChromatogram,
+            ..Default::default()
+        };
+        descr.set_transition(SRMTransition::new(500.25, 300.1));
+        let mut arrays = BinaryArrayMap::default();
+        let mut time_array =
+            DataArray::from_name_and_type(&ArrayType::TimeArray, BinaryDataArrayType::Float64);
+        time_array.extend(&[0.1f64, 0.2, 0.3]).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.extend(&[10.0f32, 50.0, 20.0]).unwrap();
+        arrays.add(time_array);
+        arrays.add(intensity_array);
+        let chrom = Chromatogram::new(descr, arrays);
+
+        let dest = fs::File::create(dest_path.clone())?;
+        let mut writer = MzMLWriterType::<_, CentroidPeak, DeconvolutedPeak>::new(dest);
+        writer.write_chromatogram(&chrom)?;
+        writer.close()?;
+
+        let mut reader = MzMLReader::open_path(dest_path)?;
+        let chrom2 = reader
+            .get_chromatogram_by_id("SRM SIC Q1=500.25 Q3=300.1")
+            .unwrap();
+        assert!(chrom2.description().is_srm());
+        assert_eq!(chrom2.transition(), chrom.transition());
+        assert_eq!(chrom2.time().unwrap(), chrom.time().unwrap());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn write_chromatograms_without_spectra_test() -> WriterResult {
+        let tmpdir = tempfile::tempdir()?;
+        let dest_path = tmpdir.path().join("chromatograms_only.mzML");
+
+        let mut arrays = BinaryArrayMap::default();
+        let mut time_array =
+            DataArray::from_name_and_type(&ArrayType::TimeArray, BinaryDataArrayType::Float64);
+        time_array.extend(&[0.1f64, 0.2]).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.extend(&[10.0f32, 20.0]).unwrap();
+        arrays.add(time_array);
+        arrays.add(intensity_array);
+        let descr = ChromatogramDescription {
+            id: "SIC 500.25".to_string(),
+            chromatogram_type: ChromatogramType::SelectedIonCurrentChromatogram,
+            ..Default::default()
+        };
+        let chrom = Chromatogram::new(descr, arrays);
+
+        // Opening the chromatogram list before any spectrum must write the run only once
+        let dest = fs::File::create(dest_path.clone())?;
+        let mut writer = MzMLWriterType::<_, CentroidPeak, DeconvolutedPeak>::new(dest);
+        writer.start_chromatogram_list()?;
+        writer.write_chromatogram(&chrom)?;
+        writer.close()?;
+
+        let mut reader = MzMLReader::open_path(dest_path)?;
+        assert_eq!(reader.len(), 0);
+        let chrom2 = reader.get_chromatogram_by_id("SIC 500.25").unwrap();
+        assert_eq!(chrom2.time().unwrap(), chrom.time().unwrap());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn write_multiple_precursors_test() -> WriterResult {
+        let tmpdir = tempfile::tempdir()?;
+        let dest_path = tmpdir.path().join("sps.mzML");
+
+        let mut descr = SpectrumDescription {
+            id: "scan=3".to_string(),
+            ms_level: 3,
+            signal_continuity: SignalContinuity::Centroid,
+            ..Default::default()
+        };
+        descr.acquisition.scans.push(ScanEvent::default());
+        for (i, mz) in [500.25f64, 612.8, 733.4].iter().copied().enumerate() {
+            let mut precursor = Precursor::default();
+            precursor.add_ion(SelectedIon {
+                mz,
+                charge: Some(2),
+                ..Default::default()
+            });
+            *precursor.isolation_window_mut() = IsolationWindow::around(mz as f32, 1.0);
+            precursor.precursor_id = Some(format!("scan={}", i + 1));
+            descr.precursors.push(precursor);
+        }
+        let mut arrays = BinaryArrayMap::default();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array.extend(&[126.1f64, 127.1, 128.1]).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.extend(&[10.0f32, 50.0, 20.0]).unwrap();
+        arrays.add(mz_array);
+        arrays.add(intensity_array);
+        let spec = RawSpectrum::new(descr, arrays);
+
+        let dest = fs::File::create(dest_path.clone())?;
+        let mut writer = MzMLWriterType::<_, CentroidPeak, DeconvolutedPeak>::new(dest);
+        writer.write(&spec)?;
+        writer.close()?;
+
+        let mut reader = MzMLReader::open_path(dest_path)?;
+        let spec2 = reader.get_spectrum_by_index(0).unwrap();
+        assert_eq!(spec2.precursors().len(), 3);
+        assert_eq!(spec2.precursor().unwrap().ion().mz, 500.25);
+        let precursors = &spec.description.precursors;
+        for (a, b) in precursors.iter().zip(spec2.precursor_iter()) {
+            assert_eq!(a.ion().mz, b.ion().mz);
+            assert_eq!(a.precursor_id, b.precursor_id);
+            assert_eq!(a.isolation_window, b.isolation_window);
+        }
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn write_combined_activation_test() -> WriterResult {
+        let tmpdir = tempfile::tempdir()?;
+        let dest_path = tmpdir.path().join("ethcd.mzML");
+
+        let mut descr = SpectrumDescription {
+            id: "scan=2".to_string(),
+            ms_level: 2,
+            signal_continuity: SignalContinuity::Centroid,
+            ..Default::default()
+        };
+        descr.acquisition.scans.push(ScanEvent::default());
+        let mut precursor = Precursor::default();
+        precursor.add_ion(SelectedIon {
+            mz: 712.37,
+            charge: Some(3),
+            ..Default::default()
+        });
+        let activation = &mut precursor.activation;
+        activation
+            .methods_mut()
+            .push(DissociationMethodTerm::ElectronTransferDissociation);
+        activation
+            .methods_mut()
+            .push(DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation);
+        activation.energy = 30.0;
+        activation.supplemental_energy = Some(25.0);
+        activation.stepped_energies = vec![25.0, 30.0, 35.0];
+        activation.reaction_time = Some(12.5);
+        descr.precursors.push(precursor);
+
+        let mut arrays = BinaryArrayMap::default();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array.extend(&[126.1f64, 127.1]).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.extend(&[10.0f32, 50.0]).unwrap();
+        arrays.add(mz_array);
+        arrays.add(intensity_array);
+        let spec = RawSpectrum::new(descr, arrays);
+
+        let dest = fs::File::create(dest_path.clone())?;
+        let mut writer = MzMLWriterType::<_, CentroidPeak, DeconvolutedPeak>::new(dest);
+        writer.write(&spec)?;
+        writer.close()?;
+
+        let mut reader = MzMLReader::open_path(dest_path)?;
+        let spec2 = reader.get_spectrum_by_index(0).unwrap();
+        let act = &spec2.precursor().unwrap().activation;
+        assert!(act.is_ethcd());
+        assert!(!act.is_etcid());
+        assert_eq!(act, &spec.description.precursors[0].activation);
+        assert_eq!(
+            act.reaction_time_of(&DissociationMethodTerm::ElectronTransferDissociation),
+            Some(12.5)
+        );
+        assert_eq!(
+            act.energy_of(&DissociationMethodTerm::ElectronTransferDissociation),
+            None
+        );
+        assert_eq!(
+            act.energy_of(
+                &DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation
+            ),
+            Some(25.0)
+        );
+        assert!(act.params().is_empty());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn write_absorption_spectrum_test() -> WriterResult {
+        let tmpdir = tempfile::tempdir()?;
+        let dest_path = tmpdir.path().join("pda.mzML");
+
+        let mut descr = SpectrumDescription {
+            id: "pda=1".to_string(),
+            signal_continuity: SignalContinuity::Profile,
+            spectrum_type: SpectrumType::AbsorptionSpectrum,
+            ..Default::default()
+        };
+        descr.acquisition.scans.push(ScanEvent::default());
+        let mut arrays = BinaryArrayMap::default();
+        let mut wavelength_array = DataArray::from_name_and_type(
+            &ArrayType::WavelengthArray,
+            BinaryDataArrayType::Float64,
+        );
+        wavelength_array.extend(&[200.0f64, 210.0, 220.0]).unwrap();
+        let mut absorbance_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        absorbance_array.extend(&[0.5f32, 1.25, 0.75]).unwrap();
+        absorbance_array.unit = Unit::AbsorbanceUnit;
+        arrays.add(wavelength_array);
+        arrays.add(absorbance_array);
+        let pda = RawSpectrum::new(descr, arrays);
+
+        let mut descr = SpectrumDescription {
+            id: "scan=1".to_string(),
+            index: 1,
+            ms_level: 1,
+            polarity: ScanPolarity::Positive,
+            signal_continuity: SignalContinuity::Centroid,
+            ..Default::default()
+        };
+        descr.acquisition.scans.push(ScanEvent::default());
+        let mut arrays = BinaryArrayMap::default();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array.extend(&[126.1f64, 127.1]).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.extend(&[10.0f32, 50.0]).unwrap();
+        arrays.add(mz_array);
+        arrays.add(intensity_array);
+        let ms1 = RawSpectrum::new(descr, arrays);
+
+        let dest = fs::File::create(dest_path.clone())?;
+        let mut writer = MzMLWriterType::<_, CentroidPeak, DeconvolutedPeak>::new(dest);
+        writer.write(&pda)?;
+        writer.write(&ms1)?;
+        writer.close()?;
+
+        let mut reader = MzMLReader::open_path(dest_path)?;
+        let pda2 = reader.get_spectrum_by_index(0).unwrap();
+        assert_eq!(pda2.spectrum_type(), SpectrumType::AbsorptionSpectrum);
+        assert!(pda2.description().is_electromagnetic_radiation());
+        assert_eq!(pda2.ms_level(), 0);
+        let arrays = pda2.arrays.as_ref().unwrap();
+        assert_eq!(&*arrays.wavelengths().unwrap(), &[200.0, 210.0, 220.0]);
+        assert_eq!(&*arrays.absorbances().unwrap(), &[0.5, 1.25, 0.75]);
+        assert_eq!(
+            arrays.get(&ArrayType::IntensityArray).unwrap().unit,
+            Unit::AbsorbanceUnit
+        );
+
+        let ms1_2 = reader.get_spectrum_by_index(1).unwrap();
+        assert_eq!(ms1_2.spectrum_type(), SpectrumType::MassSpectrum);
+        assert_eq!(ms1_2.ms_level(), 1);
+
+        reader.reset();
+        let ids: Vec<_> = reader.mass_spectra().map(|s| s.id().to_string()).collect();
+        assert_eq!(ids, vec!["scan=1".to_string()]);
+        reader.reset();
+        let ids: Vec<_> = reader
+            .electromagnetic_radiation_spectra()
+            .map(|s| s.id().to_string())
+            .collect();
+        assert_eq!(ids, vec!["pda=1".to_string()]);
+        Ok(())
+    }
+
+    fn make_test_frame() -> crate::spectrum::MultiLayerIonMobilityFrame {
+        let mut arrays = BinaryArrayMap::default();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array
+            .extend(&[200.0f64, 300.0, 200.5, 400.0, 500.0])
+            .unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array
+            .extend(&[10.0f32, 20.0, 30.0, 40.0, 50.0])
+            .unwrap();
+        let mut im_array = DataArray::from_name_and_type(
+            &ArrayType::MeanIonMobilityArray,
+            BinaryDataArrayType::Float64,
+        );
+        im_array.unit = Unit::VoltSecondPerSquareCentimeter;
+        im_array.extend(&[0.8f64, 0.8, 0.9, 1.0, 1.0]).unwrap();
+        arrays.add(mz_array);
+        arrays.add(intensity_array);
+        arrays.add(im_array);
+        let mut arrays = BinaryArrayMap3D::stack(&arrays).unwrap();
+        arrays.ion_mobility_unit = Unit::VoltSecondPerSquareCentimeter;
+
+        let mut descr = crate::spectrum::frame::IonMobilityFrameDescription {
+            id: "frame=1".to_string(),
+            ms_level: 1,
+            ..Default::default()
+        };
+        descr.acquisition.scans.push(ScanEvent::default());
+        crate::spectrum::MultiLayerIonMobilityFrame::new(Some(arrays), None, None, descr)
+    }
+
+    #[test_log::test]
+    fn write_frame_layouts_test() -> WriterResult {
+        let tmpdir = tempfile::tempdir()?;
+        let frame = make_test_frame();
+
+        let dest_path = tmpdir.path().join("frame_stacked.mzML");
+        let dest = fs::File::create(dest_path.clone())?;
+        let mut writer = MzMLWriterType::<_, CentroidPeak, DeconvolutedPeak>::new(dest);
+        writer.write_frame(&frame)?;
+        writer.close()?;
+
+        let mut reader = MzMLReader::open_path(&dest_path)?;
+        assert_eq!(reader.len(), 1);
+        let spec = reader.get_spectrum_by_index(0).unwrap();
+        assert_eq!(spec.id(), "frame=1");
+        let arrays = spec.arrays.as_ref().unwrap();
+        assert!(arrays.has_ion_mobility());
+        assert_eq!(arrays.mzs().unwrap().len(), 5);
+
+        let dest_path = tmpdir.path().join("frame_unstacked.mzML");
+        let dest = fs::File::create(dest_path.clone())?;
+        let mut writer = MzMLWriterType::<_, CentroidPeak, DeconvolutedPeak>::new(dest);
+        writer.ion_mobility_frame_layout = IonMobilityFrameLayout::SpectrumPerIonMobility;
+        let mut msn = make_test_frame();
+        let descr = msn.description_mut();
+        descr.id = "frame=2".to_string();
+        descr.ms_level = 2;
+        descr.precursors.push(Precursor {
+            precursor_id: Some("frame=1".to_string()),
+            ..Default::default()
+        });
+        writer.write_frame_owned(frame)?;
+        writer.write_frame_owned(msn)?;
+        writer.close()?;
+
+        let reader = MzMLReader::open_path(&dest_path)?;
+        let mut spectra: Vec<_> = reader.collect();
+        assert_eq!(spectra.len(), 6);
+        // The precursors of the split MSn frame name a spectrum that exists
+        for msn in spectra.drain(3..) {
+            assert_eq!(
+                msn.precursor().unwrap().precursor_id.as_deref(),
+                Some("frame=1 scan=1")
+            );
+        }
+        let ims: Vec<_> = spectra
+            .iter()
+            .map(|s| {
+                s.acquisition()
+                    .first_scan()
+                    .unwrap()
+                    .ion_mobility()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(ims, vec![0.8, 0.9, 1.0]);
+        assert_eq!(spectra[0].id(), "frame=1 scan=1");
+        assert!(!spectra[0].arrays.as_ref().unwrap().has_ion_mobility());
+        assert_eq!(spectra[0].arrays.as_ref().unwrap().mzs().unwrap().len(), 2);
+        assert_eq!(spectra[2].arrays.as_ref().unwrap().mzs().unwrap().len(), 2);
+        Ok(())
+    }
 }
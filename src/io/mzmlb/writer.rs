@@ -24,7 +24,7 @@ use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, Deconvoluted
 use quick_xml::events::{BytesStart, Event};
 use thiserror::Error;
 
-use crate::io::traits::{IonMobilityFrameWriter, SpectrumWriter};
+use crate::io::traits::{ChromatogramWriter, IonMobilityFrameWriter, SpectrumWriter};
 use crate::meta::{
     DataProcessing, FileDescription, InstrumentConfiguration, MassSpectrometryRun, Software,
 };
@@ -35,7 +35,7 @@ use crate::spectrum::bindata::{
 };
 use crate::spectrum::{ArrayType, BinaryArrayMap, Chromatogram, ChromatogramLike, RefPeakDataLevel};
 
-use crate::io::mzml::{MzMLWriterError, MzMLWriterState, MzMLWriterType};
+use crate::io::mzml::{IonMobilityFrameLayout, MzMLWriterError, MzMLWriterState, MzMLWriterType};
 use crate::RawSpectrum;
 
 macro_rules! bstart {
@@ -182,12 +182,15 @@ impl ToString for BufferName {
             ArrayType::IntensityArray => Cow::Borrowed("intensity"),
             ArrayType::ChargeArray => Cow::Borrowed("charge"),
             ArrayType::SignalToNoiseArray => Cow::Borrowed("snr"),
+            ArrayType::BaselineArray => Cow::Borrowed("baseline"),
+            ArrayType::NoiseArray => Cow::Borrowed("noise"),
             ArrayType::TimeArray => Cow::Borrowed("time"),
             ArrayType::WavelengthArray => Cow::Borrowed("wavelength"),
             ArrayType::IonMobilityArray => Cow::Borrowed("ion_mobility"),
             ArrayType::MeanIonMobilityArray => Cow::Borrowed("mean_ion_mobility"),
             ArrayType::RawIonMobilityArray => Cow::Borrowed("raw_ion_mobility"),
             ArrayType::DeconvolutedIonMobilityArray => Cow::Borrowed("deconvoluted_ion_mobility"),
+            ArrayType::CollisionalCrossSectionArray => Cow::Borrowed("collisional_cross_section"),
             ArrayType::NonStandardDataArray { name } => Cow::Owned(name.replace(['/', ' '], "_")),
         };
         let dtype = match self.dtype {
@@ -419,6 +422,23 @@ where
     }
 }
 
+impl<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default> ChromatogramWriter
+    for MzMLbWriterType<C, D>
+where
+    C: BuildArrayMapFrom,
+    D: BuildArrayMapFrom,
+{
+    fn write_chromatogram(&mut self, chromatogram: &Chromatogram) -> io::Result<usize> {
+        match MzMLbWriterType::write_chromatogram(self, chromatogram) {
+            Ok(()) => {
+                let pos = self.mzml_writer.stream_position()?;
+                Ok(pos as usize)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct MzMLbWriterBuilder<
     C: CentroidLike + Default + 'static,
@@ -603,9 +623,12 @@ where
             ArrayType::DeconvolutedIonMobilityArray => self
                 .mzml_writer
                 .write_param(&array.name.as_param_with_unit_const(array.unit))?,
-            ArrayType::SignalToNoiseArray => {
+            ArrayType::SignalToNoiseArray | ArrayType::BaselineArray | ArrayType::NoiseArray => {
                 self.mzml_writer.write_param(&array.name.as_param_const())?
             }
+            ArrayType::CollisionalCrossSectionArray => self
+                .mzml_writer
+                .write_param(&array.name.as_param(Some(array.unit)))?,
             ArrayType::NonStandardDataArray { name } => {
                 let mut p =
                     self.get_ms_cv()
@@ -762,10 +785,9 @@ where
             .start_chromatogram(chromatogram, &mut outer)?;
         self.mzml_writer
             .write_param_list(chromatogram.params().iter())?;
+        self.mzml_writer
+            .write_chromatogram_descriptors(chromatogram)?;
 
-        if let Some(precursor) = chromatogram.precursor() {
-            self.mzml_writer.write_precursor(precursor)?;
-        }
         self.write_binary_data_arrays(
             &chromatogram.arrays,
             BufferContext::Chromatogram,
@@ -815,6 +837,16 @@ where
         &mut self.mzml_writer.chromatogram_count
     }
 
+    /// How ion mobility frames written with [`IonMobilityFrameWriter`] are laid out
+    pub fn ion_mobility_frame_layout(&self) -> IonMobilityFrameLayout {
+        self.mzml_writer.ion_mobility_frame_layout
+    }
+
+    /// Set how ion mobility frames are laid out when written
+    pub fn set_ion_mobility_frame_layout(&mut self, layout: IonMobilityFrameLayout) {
+        self.mzml_writer.ion_mobility_frame_layout = layout;
+    }
+
     fn make_mzml_buffer(handle: &mut hdf5::File, chunk_size: usize, filters: &[hdf5::filters::Filter]) -> Result<hdf5::Dataset, MzMLbWriterError> {
         let mzml_buffer = handle
             .new_dataset_builder()
@@ -968,17 +1000,23 @@ impl<
 {
     fn write_frame<S: crate::spectrum::IonMobilityFrameLike<CF, DF> + 'static>(&mut self, frame: &S) -> io::Result<usize> {
         let state = frame.description().clone().into();
-        let peak_data = match frame.features() {
-            crate::spectrum::frame::RefFeatureDataLevel::Missing => BinaryArrayMap::default(),
-            crate::spectrum::frame::RefFeatureDataLevel::RawData(a) => a.unstack()?,
-            crate::spectrum::frame::RefFeatureDataLevel::Centroid(c) => CF::as_arrays(&c[..]),
-            crate::spectrum::frame::RefFeatureDataLevel::Deconvoluted(d) => DF::as_arrays(&d[..]),
-        };
-        let spectrum = RawSpectrum::new(state, peak_data);
-        self.write_owned(spectrum)
+        let spectra = self
+            .ion_mobility_frame_layout()
+            .frame_to_spectra(state, frame.features())?;
+        let mut n = 0;
+        for spectrum in spectra {
+            n = self.write_owned(spectrum)?;
+        }
+        Ok(n)
     }
 
     fn write_frame_owned<S: crate::spectrum::IonMobilityFrameLike<CF, DF> + 'static>(&mut self, frame: S) -> io::Result<usize> {
+        if matches!(
+            self.ion_mobility_frame_layout(),
+            IonMobilityFrameLayout::SpectrumPerIonMobility
+        ) {
+            return self.write_frame(&frame);
+        }
         let (features, state) = frame.into_features_and_parts();
         let peak_data = match features {
             crate::spectrum::frame::FeatureDataLevel::Missing => BinaryArrayMap::default(),
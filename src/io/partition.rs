@@ -0,0 +1,724 @@
+//! Split a run into independent streams of spectra by FAIMS compensation voltage and/or
+//! scan polarity.
+//!
+//! FAIMS-stepping and polarity-switching acquisitions interleave several distinct experiments
+//! in a single run. [`SpectrumPartitioner`] lazily routes each spectrum from a source into a
+//! per-[`RunPartitionKey`] stream, renumbering spectra and re-linking precursor references
+//! so that each stream can be treated like a run of its own, e.g. by a
+//! [`SpectrumGroupingIterator`](crate::spectrum::SpectrumGroupingIterator).
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::io;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
+
+use crate::io::infer_format::{MassSpectrometryFormat, MassSpectrometryReadWriteProcess, Sink};
+use crate::io::traits::{
+    RandomAccessSpectrumIterator, SpectrumReceiver, SpectrumSource, SpectrumWriter,
+    StreamingSpectrumIterator,
+};
+use crate::meta::MSDataFileMetadata;
+use crate::spectrum::bindata::{BuildArrayMapFrom, BuildFromArrayMap};
+use crate::spectrum::{IonMobilityMeasure, MultiLayerSpectrum, ScanPolarity, SpectrumLike};
+
+/// Which properties of a spectrum to partition a run by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PartitionBy {
+    /// Partition by FAIMS compensation voltage only
+    CompensationVoltage,
+    /// Partition by scan polarity only
+    Polarity,
+    /// Partition by both FAIMS compensation voltage and scan polarity
+    #[default]
+    CompensationVoltageAndPolarity,
+}
+
+impl PartitionBy {
+    pub fn uses_compensation_voltage(&self) -> bool {
+        matches!(
+            self,
+            Self::CompensationVoltage | Self::CompensationVoltageAndPolarity
+        )
+    }
+
+    pub fn uses_polarity(&self) -> bool {
+        matches!(self, Self::Polarity | Self::CompensationVoltageAndPolarity)
+    }
+}
+
+/// Identifies one partition of a run.
+///
+/// Compensation voltages are compared at a precision of 0.01 V. A property that the run
+/// is not being partitioned by, or which a spectrum does not carry, is `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct RunPartitionKey {
+    compensation_voltage: Option<i64>,
+    polarity: Option<ScanPolarity>,
+}
+
+impl RunPartitionKey {
+    pub fn new(compensation_voltage: Option<f64>, polarity: Option<ScanPolarity>) -> Self {
+        Self {
+            compensation_voltage: compensation_voltage.map(|cv| (cv * 100.0).round() as i64),
+            polarity,
+        }
+    }
+
+    /// Build the key for `spectrum`, reading the compensation voltage from the
+    /// first scan event that has one.
+    pub fn from_spectrum<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    >(
+        spectrum: &S,
+        by: PartitionBy,
+    ) -> Self {
+        let compensation_voltage = if by.uses_compensation_voltage() {
+            spectrum
+                .acquisition()
+                .iter()
+                .find_map(|scan| scan.compensation_voltage())
+        } else {
+            None
+        };
+        let polarity = if by.uses_polarity() {
+            Some(spectrum.polarity())
+        } else {
+            None
+        };
+        Self::new(compensation_voltage, polarity)
+    }
+
+    pub fn compensation_voltage(&self) -> Option<f64> {
+        self.compensation_voltage.map(|cv| cv as f64 / 100.0)
+    }
+
+    pub fn polarity(&self) -> Option<ScanPolarity> {
+        self.polarity
+    }
+}
+
+/// Formats the key as a short label suitable for use in a file name, e.g. `CV-45_positive`
+impl Display for RunPartitionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(cv) = self.compensation_voltage() {
+            parts.push(format!("CV{cv}"));
+        }
+        if let Some(polarity) = self.polarity {
+            parts.push(polarity.to_string().to_lowercase());
+        }
+        if parts.is_empty() {
+            f.write_str("all")
+        } else {
+            f.write_str(&parts.join("_"))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PartitionBuffer<S> {
+    queue: VecDeque<S>,
+    next_index: usize,
+    seen_ids: HashSet<String>,
+    last_id_by_level: HashMap<u8, String>,
+    handles: usize,
+    closed: bool,
+}
+
+impl<S> Default for PartitionBuffer<S> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            next_index: 0,
+            seen_ids: HashSet::new(),
+            last_id_by_level: HashMap::new(),
+            handles: 0,
+            closed: false,
+        }
+    }
+}
+
+impl<S> PartitionBuffer<S> {
+    /// Renumber `spectrum` within this partition and point any precursor reference that
+    /// does not resolve within this partition at the most recent spectrum of the level above.
+    fn relink<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>(
+        &mut self,
+        spectrum: &mut S,
+    ) where
+        S: SpectrumLike<C, D>,
+    {
+        let ms_level = spectrum.ms_level();
+        let parent_id = if ms_level > 1 {
+            self.last_id_by_level.get(&(ms_level - 1)).cloned()
+        } else {
+            None
+        };
+        let descr = spectrum.description_mut();
+        descr.index = self.next_index;
+        self.next_index += 1;
+        for precursor in descr.precursors.iter_mut() {
+            let resolved = precursor
+                .precursor_id
+                .as_ref()
+                .is_some_and(|id| self.seen_ids.contains(id));
+            if !resolved {
+                precursor.precursor_id.clone_from(&parent_id);
+            }
+        }
+        self.seen_ids.insert(descr.id.clone());
+        self.last_id_by_level.insert(ms_level, descr.id.clone());
+    }
+}
+
+#[derive(Debug)]
+struct PartitionerState<
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+> {
+    source: R,
+    by: PartitionBy,
+    partitions: HashMap<RunPartitionKey, PartitionBuffer<S>>,
+    order: Vec<RunPartitionKey>,
+    exhausted: bool,
+    _c: PhantomData<C>,
+    _d: PhantomData<D>,
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > PartitionerState<R, C, D, S>
+{
+    fn buffer_mut(&mut self, key: RunPartitionKey) -> &mut PartitionBuffer<S> {
+        if !self.partitions.contains_key(&key) {
+            self.order.push(key);
+        }
+        self.partitions.entry(key).or_default()
+    }
+
+    /// Read the next spectrum from the source and assign it to its partition without
+    /// buffering it
+    fn read_next(&mut self) -> Option<(RunPartitionKey, S)> {
+        if self.exhausted {
+            return None;
+        }
+        match self.source.next() {
+            Some(mut spectrum) => {
+                let key = RunPartitionKey::from_spectrum(&spectrum, self.by);
+                self.buffer_mut(key).relink(&mut spectrum);
+                Some((key, spectrum))
+            }
+            None => {
+                self.exhausted = true;
+                None
+            }
+        }
+    }
+
+    fn pop_buffered(&mut self) -> Option<(RunPartitionKey, S)> {
+        for key in self.order.iter() {
+            if let Some(spectrum) = self
+                .partitions
+                .get_mut(key)
+                .and_then(|buf| buf.queue.pop_front())
+            {
+                return Some((*key, spectrum));
+            }
+        }
+        None
+    }
+
+    fn next_for(&mut self, key: &RunPartitionKey) -> Option<S> {
+        if let Some(spectrum) = self
+            .partitions
+            .get_mut(key)
+            .and_then(|buf| buf.queue.pop_front())
+        {
+            return Some(spectrum);
+        }
+        loop {
+            let (next_key, spectrum) = self.read_next()?;
+            if next_key == *key {
+                return Some(spectrum);
+            }
+            let buf = self.buffer_mut(next_key);
+            if !buf.closed {
+                buf.queue.push_back(spectrum);
+            }
+        }
+    }
+}
+
+/// Partition the spectra of a run by FAIMS compensation voltage and/or [`ScanPolarity`].
+///
+/// Each partition is read through a [`SpectrumPartition`] obtained from [`SpectrumPartitioner::partition`].
+/// The source is only read on demand, and spectra belonging to partitions other than the one
+/// being read are buffered until their partition is read. Once every [`SpectrumPartition`] for a
+/// key has been dropped, further spectra for that key are discarded, so partitions that are not
+/// of interest should be dropped rather than left unread.
+///
+/// As spectra are routed, their index is renumbered within their partition, and precursor
+/// references to spectra that are not part of the same partition are re-linked to the most
+/// recent spectrum of the next lowest MS level within the partition.
+#[derive(Debug)]
+pub struct SpectrumPartitioner<
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+> {
+    state: Rc<RefCell<PartitionerState<R, C, D, S>>>,
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > SpectrumPartitioner<R, C, D, S>
+{
+    pub fn new(source: R, by: PartitionBy) -> Self {
+        let state = PartitionerState {
+            source,
+            by,
+            partitions: HashMap::new(),
+            order: Vec::new(),
+            exhausted: false,
+            _c: PhantomData,
+            _d: PhantomData,
+        };
+        Self {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    pub fn partition_by(&self) -> PartitionBy {
+        self.state.borrow().by
+    }
+
+    /// The partition keys encountered so far, in the order they were first seen
+    pub fn partition_keys(&self) -> Vec<RunPartitionKey> {
+        self.state.borrow().order.clone()
+    }
+
+    /// Read from the source until `count` distinct partitions have been seen or the source
+    /// is exhausted, returning the keys encountered. The spectra read are buffered.
+    pub fn discover_partitions(&mut self, count: usize) -> Vec<RunPartitionKey> {
+        let mut state = self.state.borrow_mut();
+        while state.order.len() < count {
+            match state.read_next() {
+                Some((key, spectrum)) => {
+                    let buf = state.buffer_mut(key);
+                    if !buf.closed {
+                        buf.queue.push_back(spectrum);
+                    }
+                }
+                None => break,
+            }
+        }
+        state.order.clone()
+    }
+
+    /// Open a lazily-evaluated stream over the spectra of the partition identified by `key`
+    pub fn partition(&self, key: RunPartitionKey) -> SpectrumPartition<R, C, D, S> {
+        {
+            let mut state = self.state.borrow_mut();
+            let buf = state.buffer_mut(key);
+            buf.handles += 1;
+            buf.closed = false;
+        }
+        SpectrumPartition {
+            key,
+            state: Rc::clone(&self.state),
+        }
+    }
+
+    /// Get the next spectrum from any partition along with its partition key, taking
+    /// buffered spectra first.
+    pub fn next_partitioned(&mut self) -> Option<(RunPartitionKey, S)> {
+        let mut state = self.state.borrow_mut();
+        state.pop_buffered().or_else(|| state.read_next())
+    }
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>> + MSDataFileMetadata,
+        C: CentroidLike
+            + Default
+            + From<CentroidPeak>
+            + BuildArrayMapFrom
+            + BuildFromArrayMap
+            + Clone
+            + 'static
+            + Sync
+            + Send,
+        D: DeconvolutedCentroidLike
+            + Default
+            + From<DeconvolutedPeak>
+            + BuildArrayMapFrom
+            + BuildFromArrayMap
+            + Clone
+            + Sync
+            + 'static
+            + Send,
+    > SpectrumPartitioner<R, C, D, MultiLayerSpectrum<C, D>>
+{
+    /// Write each partition to its own [`Sink`], created by calling `sink_for` the first time
+    /// a partition is encountered. This reads the remainder of the source in a single pass.
+    ///
+    /// Each partition is written on its own thread by the writer that
+    /// [`MassSpectrometryReadWriteProcess::open_writer`] opens for its [`Sink`], with the metadata
+    /// of the source copied to it. Because the size of each partition is not known until the source
+    /// is exhausted, no [`MSDataFileMetadata::spectrum_count_hint`] is given to the writers, so
+    /// those which declare the number of spectra up front, like mzML, do not state it.
+    ///
+    /// Returns the number of spectra written to each partition.
+    pub fn write_partitions<F: FnMut(&RunPartitionKey) -> Sink<C, D>>(
+        &mut self,
+        mut sink_for: F,
+    ) -> io::Result<HashMap<RunPartitionKey, usize>> {
+        let mut counts = HashMap::new();
+        thread::scope(|scope| {
+            let mut writers = HashMap::new();
+            let mut result = Ok(());
+            while let Some((key, spectrum)) = self.next_partitioned() {
+                let (sender, _) = match writers.entry(key) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let (sender, receiver) = sync_channel(PARTITION_BUFFER_SIZE);
+                        let state = self.state.borrow();
+                        let source = &state.source;
+                        let reader: SpectrumReceiver<C, D, MultiLayerSpectrum<C, D>> =
+                            SpectrumReceiver::new(
+                                receiver,
+                                source.file_description().clone(),
+                                source.instrument_configurations().clone(),
+                                source.softwares().clone(),
+                                source.samples().clone(),
+                                source.data_processings().clone(),
+                                source.run_description().cloned().unwrap_or_default(),
+                                None,
+                            );
+                        let sink = sink_for(&key);
+                        let handle = scope.spawn(move || {
+                            PartitionWriteProcess.open_writer(
+                                StreamingSpectrumIterator::new(reader),
+                                MassSpectrometryFormat::Unknown,
+                                sink,
+                            )
+                        });
+                        entry.insert((sender, handle))
+                    }
+                };
+                if sender.send(spectrum).is_err() {
+                    // The writer stopped early, so its error is reported when it is joined
+                    break;
+                }
+                *counts.entry(key).or_default() += 1;
+            }
+            for (_, (sender, handle)) in writers {
+                drop(sender);
+                let status = handle
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e));
+                if result.is_ok() {
+                    result = status;
+                }
+            }
+            result
+        })?;
+        Ok(counts)
+    }
+}
+
+/// The number of spectra that may be queued for each partition's writer
+const PARTITION_BUFFER_SIZE: usize = 1024;
+
+/// Copies each partition's spectra to the writer opened for its [`Sink`]
+struct PartitionWriteProcess;
+
+impl<
+        C: CentroidLike
+            + Default
+            + From<CentroidPeak>
+            + BuildArrayMapFrom
+            + BuildFromArrayMap
+            + Clone
+            + 'static
+            + Sync
+            + Send,
+        D: DeconvolutedCentroidLike
+            + Default
+            + From<DeconvolutedPeak>
+            + BuildArrayMapFrom
+            + BuildFromArrayMap
+            + Clone
+            + Sync
+            + 'static
+            + Send,
+    > MassSpectrometryReadWriteProcess<C, D> for PartitionWriteProcess
+{
+    type ErrorType = io::Error;
+
+    fn task<
+        R: RandomAccessSpectrumIterator<C, D>
+            + MSDataFileMetadata
+            + SpectrumSource<C, D>
+            + Send
+            + 'static,
+        W: SpectrumWriter<C, D> + Send + 'static,
+    >(
+        &self,
+        reader: R,
+        mut writer: W,
+    ) -> Result<(), Self::ErrorType> {
+        writer.write_all_owned(reader)?;
+        writer.close()
+    }
+}
+
+/// A lazily-evaluated stream over a single partition of a [`SpectrumPartitioner`].
+///
+/// This is an [`Iterator`] over spectra, and so can be wrapped in a
+/// [`SpectrumGroupingIterator`](crate::spectrum::SpectrumGroupingIterator).
+#[derive(Debug)]
+pub struct SpectrumPartition<
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+> {
+    key: RunPartitionKey,
+    state: Rc<RefCell<PartitionerState<R, C, D, S>>>,
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > SpectrumPartition<R, C, D, S>
+{
+    pub fn key(&self) -> &RunPartitionKey {
+        &self.key
+    }
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > Iterator for SpectrumPartition<R, C, D, S>
+{
+    type Item = S;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.state.borrow_mut().next_for(&self.key)
+    }
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > Drop for SpectrumPartition<R, C, D, S>
+{
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            if let Some(buf) = state.partitions.get_mut(&self.key) {
+                buf.handles = buf.handles.saturating_sub(1);
+                if buf.handles == 0 {
+                    buf.closed = true;
+                    buf.queue.clear();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+    use crate::io::SpectrumReceiver;
+    use crate::params::ControlledVocabulary;
+    use crate::prelude::*;
+    use crate::spectrum::group::{SpectrumGroup, SpectrumGroupingIterator};
+    use crate::spectrum::{Precursor, ScanEvent, SpectrumDescription};
+
+    fn make_spectrum(
+        index: usize,
+        ms_level: u8,
+        cv: f64,
+        polarity: ScanPolarity,
+        precursor_id: Option<&str>,
+    ) -> MultiLayerSpectrum {
+        let mut descr = SpectrumDescription {
+            id: format!("scan={}", index + 1),
+            index,
+            ms_level,
+            polarity,
+            ..Default::default()
+        };
+        let mut event = ScanEvent {
+            start_time: index as f64 * 0.01,
+            ..Default::default()
+        };
+        event.add_param(ControlledVocabulary::MS.param_val(
+            1001581,
+            "FAIMS compensation voltage",
+            cv,
+        ));
+        descr.acquisition.scans.push(event);
+        if ms_level > 1 {
+            let prec = Precursor {
+                precursor_id: precursor_id.map(|s| s.to_string()),
+                ..Default::default()
+            };
+            descr.precursors.push(prec);
+        }
+        MultiLayerSpectrum::from_description(descr)
+    }
+
+    /// Two FAIMS CVs per cycle, where the instrument links every MS2 back to the first
+    /// MS1 of the cycle regardless of CV.
+    fn make_run(cycles: usize) -> Vec<MultiLayerSpectrum> {
+        let mut spectra = Vec::new();
+        for _ in 0..cycles {
+            let ms1_id = format!("scan={}", spectra.len() + 1);
+            for cv in [-45.0, -60.0] {
+                spectra.push(make_spectrum(
+                    spectra.len(),
+                    1,
+                    cv,
+                    ScanPolarity::Positive,
+                    None,
+                ));
+                for _ in 0..2 {
+                    spectra.push(make_spectrum(
+                        spectra.len(),
+                        2,
+                        cv,
+                        ScanPolarity::Positive,
+                        Some(&ms1_id),
+                    ));
+                }
+            }
+        }
+        spectra
+    }
+
+    #[test]
+    fn test_partition_faims() {
+        let spectra = make_run(3);
+        let mut partitioner: SpectrumPartitioner<_> =
+            SpectrumPartitioner::new(spectra.into_iter(), PartitionBy::CompensationVoltage);
+        let keys = partitioner.discover_partitions(2);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].compensation_voltage(), Some(-45.0));
+        assert_eq!(keys[1].compensation_voltage(), Some(-60.0));
+        assert_eq!(keys[1].polarity(), None);
+        assert_eq!(keys[1].to_string(), "CV-60");
+
+        let first = partitioner.partition(keys[0]);
+        let second = partitioner.partition(keys[1]);
+
+        let groups: Vec<SpectrumGroup> = SpectrumGroupingIterator::new(second).collect();
+        assert_eq!(groups.len(), 3);
+        for group in groups.iter() {
+            let precursor = group.precursor().unwrap();
+            assert_eq!(precursor.ms_level(), 1);
+            assert_eq!(group.products().len(), 2);
+            for product in group.products() {
+                assert_eq!(
+                    product.precursor().unwrap().precursor_id.as_deref(),
+                    Some(precursor.id())
+                );
+            }
+        }
+
+        let indices: Vec<_> = first.map(|s| s.index()).collect();
+        assert_eq!(indices, (0..9).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_write_partitions() {
+        let mut spectra = make_run(2);
+        for s in spectra.iter_mut().skip(3).step_by(6) {
+            s.description.polarity = ScanPolarity::Negative;
+        }
+        let (sender, receiver) = channel();
+        spectra.into_iter().for_each(|s| sender.send(s).unwrap());
+        drop(sender);
+        let mut partitioner: SpectrumPartitioner<
+            SpectrumReceiver<CentroidPeak, DeconvolutedPeak, MultiLayerSpectrum>,
+        > = SpectrumPartitioner::new(receiver.into(), PartitionBy::Polarity);
+
+        let mut receivers = HashMap::new();
+        let counts = partitioner
+            .write_partitions(|key| {
+                let (sender, receiver) = channel();
+                receivers.insert(*key, receiver);
+                Sink::Sender(sender)
+            })
+            .unwrap();
+        assert_eq!(counts.len(), 2);
+        let negative = RunPartitionKey::new(None, Some(ScanPolarity::Negative));
+        assert_eq!(counts[&negative], 2);
+        let received: Vec<_> = receivers[&negative].try_iter().collect();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].index(), 1);
+    }
+
+    #[test]
+    fn test_write_partitions_mzml() -> io::Result<()> {
+        let spectra = make_run(2);
+        let n = spectra.len() as u64;
+        let (sender, receiver) = channel();
+        spectra.into_iter().for_each(|s| sender.send(s).unwrap());
+        drop(sender);
+        let source = SpectrumReceiver::new(
+            receiver,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Some(n),
+        );
+        let mut partitioner: SpectrumPartitioner<
+            SpectrumReceiver<CentroidPeak, DeconvolutedPeak, MultiLayerSpectrum>,
+        > = SpectrumPartitioner::new(source, PartitionBy::CompensationVoltage);
+
+        let tmpdir = tempfile::tempdir()?;
+        let counts = partitioner
+            .write_partitions(|key| Sink::PathLike(tmpdir.path().join(format!("{key}.mzML"))))?;
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts.values().sum::<usize>() as u64, n);
+        for (key, count) in counts {
+            let path = tmpdir.path().join(format!("{key}.mzML"));
+            let content = std::fs::read_to_string(&path)?;
+            // The whole run's count is not claimed by each partition
+            assert!(!content.contains(&format!("<spectrumList count=\"{}\"", n)));
+            let reader = crate::io::MzMLReader::open_path(&path)?;
+            assert_eq!(reader.len(), count);
+        }
+        Ok(())
+    }
+}
@@ -579,7 +579,7 @@ impl From<&PROXISpectrum> for SpectrumDescription {
         }
 
         if has_precursor {
-            this.precursor = Some(precursor);
+            this.precursors = vec![precursor];
         }
         this
     }
@@ -535,9 +535,9 @@ impl<C: CentroidLike + Default + From<CentroidPeak>, D: DeconvolutedCentroidLike
                 activation
                     .methods_mut()
                     .push(DissociationMethodTerm::ElectronTransferDissociation);
-                activation.add_param(
-                    DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation.into(),
-                );
+                activation
+                    .methods_mut()
+                    .push(DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation);
             }
             DissociationMethod::ETCID => {
                 activation
@@ -545,7 +545,7 @@ impl<C: CentroidLike + Default + From<CentroidPeak>, D: DeconvolutedCentroidLike
                     .push(DissociationMethodTerm::ElectronTransferDissociation);
                 activation
                     .methods_mut()
-                    .push(DissociationMethodTerm::SupplementalCollisionInducedDissociation.into());
+                    .push(DissociationMethodTerm::SupplementalCollisionInducedDissociation);
             }
             DissociationMethod::NETD => {
                 activation
@@ -562,17 +562,17 @@ impl<C: CentroidLike + Default + From<CentroidPeak>, D: DeconvolutedCentroidLike
                 activation
                     .methods_mut()
                     .push(DissociationMethodTerm::ElectronCaptureDissociation);
-                activation.add_param(
-                    DissociationMethodTerm::SupplementalCollisionInducedDissociation.into(),
-                );
+                activation
+                    .methods_mut()
+                    .push(DissociationMethodTerm::SupplementalCollisionInducedDissociation);
             }
             DissociationMethod::ECHCD => {
                 activation
                     .methods_mut()
                     .push(DissociationMethodTerm::ElectronCaptureDissociation);
-                activation.add_param(
-                    DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation.into(),
-                )
+                activation
+                    .methods_mut()
+                    .push(DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation);
             }
             _ => {
                 activation
@@ -580,6 +580,14 @@ impl<C: CentroidLike + Default + From<CentroidPeak>, D: DeconvolutedCentroidLike
                     .push(DissociationMethodTerm::CollisionInducedDissociation);
             }
         }
+        // The energy reported for an electron-based method is not a collision energy. Its reaction
+        // time and any supplemental energy are read from the filter string instead.
+        if activation
+            .primary_method()
+            .is_some_and(|method| method.is_electronic())
+        {
+            activation.energy = 0.0;
+        }
 
         let iso_window = &mut precursor.isolation_window;
         let vwin = vprec.isolation_window();
@@ -693,7 +701,7 @@ impl<C: CentroidLike + Default + From<CentroidPeak>, D: DeconvolutedCentroidLike
         if let Some(vprec) = view.precursor() {
             let mut prec = Precursor::default();
             self.populate_precursor(vprec, &mut prec);
-            spec.description.precursor = Some(prec);
+            spec.description.precursors = vec![prec];
         }
 
         let event = spec.description.acquisition.first_scan_mut().unwrap();
@@ -706,6 +714,13 @@ impl<C: CentroidLike + Default + From<CentroidPeak>, D: DeconvolutedCentroidLike
                 event.add_param(p);
             }
         }
+        // Recover the reaction time and supplemental energy of combined activations
+        if let Some(Err(e)) = spec.description.backfill_from_filter_string() {
+            warn!(
+                "Failed to parse the filter string of {}: {e}",
+                spec.description.id
+            );
+        }
 
         if let Some(data) = view.data() {
             if spec.signal_continuity() == SignalContinuity::Centroid {
@@ -770,6 +785,17 @@ impl<C: CentroidLike + Default + From<CentroidPeak>, D: DeconvolutedCentroidLike
                             kv.value.parse::<Value>().unwrap(),
                         );
                         spec.params_mut().push(param);
+                        // Stepped collision energies are listed separated by commas
+                        let steps: Vec<f32> = kv
+                            .value
+                            .split(',')
+                            .filter_map(|v| v.trim().parse().ok())
+                            .collect();
+                        if steps.len() > 1 {
+                            if let Some(precursor) = spec.description.precursors.first_mut() {
+                                precursor.activation.stepped_energies = steps;
+                            }
+                        }
                     }
                 }
                 _ => {}
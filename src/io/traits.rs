@@ -7,17 +7,20 @@ pub use spectrum::{
     MZFileReader, MemorySpectrumSource, RandomAccessSpectrumGroupingIterator,
     RandomAccessSpectrumIterator, RandomAccessSpectrumSource, SpectrumAccessError,
     SpectrumGrouping, SpectrumIterator, SpectrumReceiver, SpectrumSource,
-    SpectrumSourceWithMetadata, SpectrumWriter, StreamingSpectrumIterator,
+    SpectrumSourceWithMetadata, SpectrumTypeFilter, SpectrumWriter, StreamingSpectrumIterator,
 };
 pub use util::SeekRead;
 
 pub use frame::{
-    BorrowedGeneric3DIonMobilityFrameSource, Generic3DIonMobilityFrameSource,
+    BorrowedGeneric3DIonMobilityFrameSource, CollapsedIonMobilitySpectrumSource,
+    Generic3DIonMobilityFrameSource,
     IonMobilityFrameAccessError, IonMobilityFrameGrouping, IonMobilityFrameIterator,
     IonMobilityFrameSource, IonMobilityFrameWriter, RandomAccessIonMobilityFrameIterator,
 };
 
-pub use chromatogram::{ChromatogramIterator, ChromatogramSource};
+pub use chromatogram::{
+    ChromatogramIterator, ChromatogramSource, ChromatogramWriter, DeferredChromatogramWriter,
+};
 
 #[cfg(test)]
 mod test {
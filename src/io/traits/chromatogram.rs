@@ -1,7 +1,12 @@
+use std::io;
 use std::iter::FusedIterator;
+use std::mem;
 
-use crate::spectrum::Chromatogram;
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike};
 
+use crate::spectrum::{Chromatogram, SpectrumLike};
+
+use super::SpectrumWriter;
 
 /// A trait that for retrieving [`Chromatogram`]s from a source.
 pub trait ChromatogramSource {
@@ -45,4 +50,146 @@ impl<'a, R: ChromatogramSource> Iterator for ChromatogramIterator<'a, R> {
     }
 }
 
-impl<'a, R: ChromatogramSource> FusedIterator for ChromatogramIterator<'a, R> {}
\ No newline at end of file
+impl<'a, R: ChromatogramSource> FusedIterator for ChromatogramIterator<'a, R> {}
+
+/// Common interface for chromatogram writing, the analog of [`SpectrumWriter`]
+pub trait ChromatogramWriter {
+    /// Write out a single chromatogram
+    fn write_chromatogram(&mut self, chromatogram: &Chromatogram) -> io::Result<usize>;
+
+    /// Write out a single owned chromatogram.
+    ///
+    /// This may produce fewer copies for some implementations.
+    fn write_chromatogram_owned(&mut self, chromatogram: Chromatogram) -> io::Result<usize> {
+        self.write_chromatogram(&chromatogram)
+    }
+
+    /// Consume an [`Iterator`] over [`Chromatogram`] references
+    fn write_all_chromatograms<'b, T: Iterator<Item = &'b Chromatogram>>(
+        &mut self,
+        iterator: T,
+    ) -> io::Result<usize> {
+        let mut n = 0;
+        for chromatogram in iterator {
+            n += self.write_chromatogram(chromatogram)?;
+        }
+        Ok(n)
+    }
+
+    /// Consume an [`Iterator`] over [`Chromatogram`]
+    fn write_all_chromatograms_owned<T: Iterator<Item = Chromatogram>>(
+        &mut self,
+        iterator: T,
+    ) -> io::Result<usize> {
+        let mut n = 0;
+        for chromatogram in iterator {
+            n += self.write_chromatogram_owned(chromatogram)?;
+        }
+        Ok(n)
+    }
+
+    /// Write every chromatogram from `source` in order
+    fn copy_chromatograms_from<R: ChromatogramSource>(
+        &mut self,
+        source: &mut R,
+    ) -> io::Result<usize>
+    where
+        Self: Sized,
+    {
+        let mut n = 0;
+        for chromatogram in source.iter_chromatograms() {
+            n += self.write_chromatogram_owned(chromatogram)?;
+        }
+        Ok(n)
+    }
+}
+
+/**
+A [`SpectrumWriter`] wrapper that holds on to a collection of [`Chromatogram`]s and writes
+them to the wrapped [`ChromatogramWriter`] once it is closed, after all spectra have been
+written, as formats like mzML require.
+
+If the wrapper is dropped without being closed, the chromatograms are written and the
+wrapped writer is closed then.
+*/
+#[derive(Debug)]
+pub struct DeferredChromatogramWriter<W: ChromatogramWriter> {
+    inner: W,
+    chromatograms: Vec<Chromatogram>,
+}
+
+impl<W: ChromatogramWriter> DeferredChromatogramWriter<W> {
+    pub fn new(inner: W, chromatograms: Vec<Chromatogram>) -> Self {
+        Self {
+            inner,
+            chromatograms,
+        }
+    }
+
+    /// Add another chromatogram to be written when this writer is closed
+    pub fn push(&mut self, chromatogram: Chromatogram) {
+        self.chromatograms.push(chromatogram);
+    }
+
+    pub fn chromatograms(&self) -> &[Chromatogram] {
+        &self.chromatograms
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    fn write_deferred(&mut self) -> io::Result<usize> {
+        let chromatograms = mem::take(&mut self.chromatograms);
+        self.inner
+            .write_all_chromatograms_owned(chromatograms.into_iter())
+    }
+}
+
+impl<W: ChromatogramWriter> ChromatogramWriter for DeferredChromatogramWriter<W> {
+    fn write_chromatogram(&mut self, chromatogram: &Chromatogram) -> io::Result<usize> {
+        self.inner.write_chromatogram(chromatogram)
+    }
+
+    fn write_chromatogram_owned(&mut self, chromatogram: Chromatogram) -> io::Result<usize> {
+        self.inner.write_chromatogram_owned(chromatogram)
+    }
+}
+
+impl<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        W: ChromatogramWriter + SpectrumWriter<C, D>,
+    > SpectrumWriter<C, D> for DeferredChromatogramWriter<W>
+{
+    fn write<S: SpectrumLike<C, D> + 'static>(&mut self, spectrum: &S) -> io::Result<usize> {
+        self.inner.write(spectrum)
+    }
+
+    fn write_owned<S: SpectrumLike<C, D> + 'static>(&mut self, spectrum: S) -> io::Result<usize> {
+        self.inner.write_owned(spectrum)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.write_deferred()?;
+        self.inner.close()
+    }
+}
+
+impl<W: ChromatogramWriter> Drop for DeferredChromatogramWriter<W> {
+    fn drop(&mut self) {
+        if !self.chromatograms.is_empty() {
+            if let Err(e) = self.write_deferred() {
+                log::error!("Failed to write deferred chromatograms: {e}");
+            }
+        }
+    }
+}
@@ -7,13 +7,16 @@ use log::warn;
 
 use mzpeaks::{
     IonMobility,
-    KnownCharge, Mass, MZ,
+    KnownCharge, Mass, MZ, Tolerance,
     feature::{ChargedFeature, Feature, FeatureLike}
 };
 
 use crate::{io::OffsetIndex, prelude::MSDataFileMetadata};
+use crate::params::Param;
+use crate::spectrum::bindata::{BuildArrayMapFrom, BuildFromArrayMap};
 use crate::spectrum::group::IonMobilityFrameGroupingIterator;
 use crate::spectrum::spectrum_types::MultiLayerSpectrum;
+use crate::spectrum::transforms::AdaptorMetadata;
 use crate::spectrum::{
     CentroidPeakAdapting, DeconvolutedPeakAdapting, IonMobilityFrameLike,
     MultiLayerIonMobilityFrame,
@@ -956,3 +959,216 @@ where
 }
 
 
+
+/// Adapt an iterator over ion mobility frames into an iterator over spectra by collapsing the
+/// ion mobility dimension of each frame with [`MultiLayerIonMobilityFrame::collapse`]. When the
+/// source is an [`IonMobilityFrameSource`], the adaptor is a [`SpectrumSource`].
+///
+/// The collapse is recorded in the adaptor's [`AdaptorMetadata`].
+#[derive(Debug)]
+pub struct CollapsedIonMobilitySpectrumSource<
+    CP: CentroidPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+    DP: DeconvolutedPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+    R: Iterator<Item = MultiLayerIonMobilityFrame<C, D>>,
+    C: FeatureLike<MZ, IonMobility> = Feature<MZ, IonMobility>,
+    D: FeatureLike<Mass, IonMobility> + KnownCharge = ChargedFeature<Mass, IonMobility>,
+> {
+    source: R,
+    ion_mobility_range: Option<(f64, f64)>,
+    mz_tolerance: Option<Tolerance>,
+    metadata: AdaptorMetadata,
+    _cp: PhantomData<CP>,
+    _dp: PhantomData<DP>,
+    _c: PhantomData<C>,
+    _d: PhantomData<D>,
+}
+
+impl<
+        CP: CentroidPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        DP: DeconvolutedPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        R: Iterator<Item = MultiLayerIonMobilityFrame<C, D>>,
+        C: FeatureLike<MZ, IonMobility>,
+        D: FeatureLike<Mass, IonMobility> + KnownCharge,
+    > CollapsedIonMobilitySpectrumSource<CP, DP, R, C, D>
+{
+    /// Create a new adapter over `source`, summing the signal within `ion_mobility_range` (inclusive,
+    /// or all of it when `None`), merging m/z values within `mz_tolerance` if given.
+    pub fn new(
+        source: R,
+        ion_mobility_range: Option<(f64, f64)>,
+        mz_tolerance: Option<Tolerance>,
+    ) -> Self
+    where
+        R: MSDataFileMetadata,
+    {
+        let mut metadata = AdaptorMetadata::new(&source);
+        let description = match ion_mobility_range {
+            Some((low, high)) => format!("summed ion mobility from {low} to {high}"),
+            None => "summed ion mobility".to_string(),
+        };
+        let description = match mz_tolerance {
+            Some(tol) => format!("{description} with m/z tolerance {}", tol.to_string()),
+            None => description,
+        };
+        let param = Param::new_key_value("collapse ion mobility dimension", description);
+        metadata.record_method("collapse_ion_mobility", vec![param]);
+        Self {
+            source,
+            ion_mobility_range,
+            mz_tolerance,
+            _cp: PhantomData,
+            _dp: PhantomData,
+            _c: PhantomData,
+            _d: PhantomData,
+            metadata,
+        }
+    }
+
+    /// The range of ion mobility values that are summed, if restricted
+    pub fn ion_mobility_range(&self) -> Option<(f64, f64)> {
+        self.ion_mobility_range
+    }
+
+    /// The tolerance used to merge m/z values, if any
+    pub fn mz_tolerance(&self) -> Option<Tolerance> {
+        self.mz_tolerance
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    fn collapse_frame(
+        &self,
+        frame: MultiLayerIonMobilityFrame<C, D>,
+    ) -> Option<MultiLayerSpectrum<CP, DP>> {
+        match frame.collapse(self.ion_mobility_range, self.mz_tolerance) {
+            Ok(spectrum) => Some(spectrum),
+            Err(e) => {
+                warn!("Failed to collapse frame {}: {e}", frame.id());
+                None
+            }
+        }
+    }
+}
+
+impl<
+        CP: CentroidPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        DP: DeconvolutedPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        R: Iterator<Item = MultiLayerIonMobilityFrame<C, D>>,
+        C: FeatureLike<MZ, IonMobility>,
+        D: FeatureLike<Mass, IonMobility> + KnownCharge,
+    > MSDataFileMetadata for CollapsedIonMobilitySpectrumSource<CP, DP, R, C, D>
+{
+    crate::delegate_impl_metadata_trait!(metadata);
+}
+
+impl<
+        CP: CentroidPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        DP: DeconvolutedPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        R: Iterator<Item = MultiLayerIonMobilityFrame<C, D>>,
+        C: FeatureLike<MZ, IonMobility>,
+        D: FeatureLike<Mass, IonMobility> + KnownCharge,
+    > Iterator for CollapsedIonMobilitySpectrumSource<CP, DP, R, C, D>
+{
+    type Item = MultiLayerSpectrum<CP, DP>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Frames which fail to collapse are skipped rather than ending the iteration
+        while let Some(frame) = self.source.next() {
+            if let Some(spectrum) = self.collapse_frame(frame) {
+                return Some(spectrum);
+            }
+        }
+        None
+    }
+}
+
+impl<
+        CP: CentroidPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        DP: DeconvolutedPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        R: IonMobilityFrameSource<C, D, MultiLayerIonMobilityFrame<C, D>>,
+        C: FeatureLike<MZ, IonMobility>,
+        D: FeatureLike<Mass, IonMobility> + KnownCharge,
+    > SpectrumSource<CP, DP, MultiLayerSpectrum<CP, DP>>
+    for CollapsedIonMobilitySpectrumSource<CP, DP, R, C, D>
+{
+    fn reset(&mut self) {
+        self.source.reset()
+    }
+
+    fn get_spectrum_by_id(&mut self, id: &str) -> Option<MultiLayerSpectrum<CP, DP>> {
+        let frame = self.source.get_frame_by_id(id)?;
+        self.collapse_frame(frame)
+    }
+
+    fn get_spectrum_by_index(&mut self, index: usize) -> Option<MultiLayerSpectrum<CP, DP>> {
+        let frame = self.source.get_frame_by_index(index)?;
+        self.collapse_frame(frame)
+    }
+
+    fn get_spectrum_by_time(&mut self, time: f64) -> Option<MultiLayerSpectrum<CP, DP>> {
+        let frame = self.source.get_frame_by_time(time)?;
+        self.collapse_frame(frame)
+    }
+
+    fn get_index(&self) -> &OffsetIndex {
+        self.source.get_index()
+    }
+
+    fn set_index(&mut self, index: OffsetIndex) {
+        self.source.set_index(index)
+    }
+}
+
+impl<
+        CP: CentroidPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        DP: DeconvolutedPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        R: IonMobilityFrameSource<C, D, MultiLayerIonMobilityFrame<C, D>>,
+        C: FeatureLike<MZ, IonMobility>,
+        D: FeatureLike<Mass, IonMobility> + KnownCharge,
+    > RandomAccessSpectrumIterator<CP, DP, MultiLayerSpectrum<CP, DP>>
+    for CollapsedIonMobilitySpectrumSource<CP, DP, R, C, D>
+where
+    R: RandomAccessIonMobilityFrameIterator<C, D, MultiLayerIonMobilityFrame<C, D>>,
+{
+    fn start_from_id(&mut self, id: &str) -> Result<&mut Self, SpectrumAccessError> {
+        match self.source.start_from_id(id) {
+            Ok(_) => Ok(self),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn start_from_index(&mut self, index: usize) -> Result<&mut Self, SpectrumAccessError> {
+        match self.source.start_from_index(index) {
+            Ok(_) => Ok(self),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn start_from_time(&mut self, time: f64) -> Result<&mut Self, SpectrumAccessError> {
+        match self.source.start_from_time(time) {
+            Ok(_) => Ok(self),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl From<IonMobilityFrameAccessError> for SpectrumAccessError {
+    fn from(value: IonMobilityFrameAccessError) -> Self {
+        match value {
+            IonMobilityFrameAccessError::FrameNotFound => SpectrumAccessError::SpectrumNotFound,
+            IonMobilityFrameAccessError::FrameIdNotFound(id) => {
+                SpectrumAccessError::SpectrumIdNotFound(id)
+            }
+            IonMobilityFrameAccessError::FrameIndexNotFound(i) => {
+                SpectrumAccessError::SpectrumIndexNotFound(i)
+            }
+            IonMobilityFrameAccessError::IOError(e) => SpectrumAccessError::IOError(e),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 
 use std::collections::{HashMap, VecDeque};
+use std::iter::Filter;
 use std::path::PathBuf;
 use std::sync::mpsc::Receiver;
 use std::{fs, io, path};
@@ -14,11 +15,20 @@ use thiserror::Error;
 
 use crate::io::utils::FileSource;
 use crate::io::OffsetIndex;
+use crate::io::partition::{PartitionBy, SpectrumPartitioner};
 use crate::meta::{DataProcessing, FileDescription, InstrumentConfiguration, MassSpectrometryRun, Sample, Software};
 use crate::prelude::MSDataFileMetadata;
-use crate::spectrum::group::{SpectrumGroup, SpectrumGroupingIterator};
+use crate::spectrum::group::{DIACycleIterator, SpectrumGroup, SpectrumGroupingIterator};
 use crate::spectrum::spectrum_types::{MultiLayerSpectrum, SpectrumLike};
+use crate::spectrum::transforms::{
+    TransformChain, TransformableSpectrum, TransformedSpectrumSource,
+};
+
 
+/// A [`SpectrumIterator`] which only yields spectra of a particular [`SpectrumType`](crate::spectrum::SpectrumType),
+/// created by [`SpectrumSource::mass_spectra`] or [`SpectrumSource::electromagnetic_radiation_spectra`]
+pub type SpectrumTypeFilter<'a, C, D, S, R> =
+    Filter<SpectrumIterator<'a, C, D, S, R>, fn(&S) -> bool>;
 
 /// A base trait defining the behaviors of a source of spectra.
 ///
@@ -143,6 +153,62 @@ pub trait SpectrumSource<
     {
         SpectrumGroupingIterator::new(self)
     }
+
+    /// Create a new `SpectrumIterator` over `self` and use that state to drive a
+    /// [`DIACycleIterator`], grouping the spectra of a data independent acquisition run by cycle
+    fn dia_cycles(&mut self) -> DIACycleIterator<SpectrumIterator<'_, C, D, S, Self>, C, D, S>
+    where
+        Self: Sized,
+    {
+        DIACycleIterator::new(self.iter())
+    }
+
+    /// Consume `self` to create a [`DIACycleIterator`]
+    fn into_dia_cycles(self) -> DIACycleIterator<Self, C, D, S>
+    where
+        Self: Sized,
+    {
+        DIACycleIterator::new(self)
+    }
+
+    /// Create a new `SpectrumIterator` over `self` which skips spectra that are not mass spectra,
+    /// like the UV/PDA traces recorded alongside an LC-MS run
+    fn mass_spectra(&mut self) -> SpectrumTypeFilter<'_, C, D, S, Self>
+    where
+        Self: Sized,
+    {
+        let predicate: fn(&S) -> bool = |s| s.spectrum_type().is_mass_spectrum();
+        self.iter().filter(predicate)
+    }
+
+    /// Create a new `SpectrumIterator` over `self` which only yields electromagnetic radiation
+    /// spectra, like UV absorption spectra from a photodiode array detector
+    fn electromagnetic_radiation_spectra(&mut self) -> SpectrumTypeFilter<'_, C, D, S, Self>
+    where
+        Self: Sized,
+    {
+        let predicate: fn(&S) -> bool = |s| s.spectrum_type().is_electromagnetic_radiation();
+        self.iter().filter(predicate)
+    }
+
+    /// Consume `self` to create a [`SpectrumPartitioner`], splitting the run into separate
+    /// streams by FAIMS compensation voltage and/or scan polarity
+    fn into_partitions(self, by: PartitionBy) -> SpectrumPartitioner<Self, C, D, S>
+    where
+        Self: Sized,
+    {
+        SpectrumPartitioner::new(self, by)
+    }
+
+    /// Consume `self` to create a [`TransformedSpectrumSource`] which applies `chain` to each
+    /// spectrum, recording the transforms in the copied [`MSDataFileMetadata`]
+    fn into_transformed(self, chain: TransformChain) -> TransformedSpectrumSource<Self, C, D, S>
+    where
+        Self: Sized + MSDataFileMetadata,
+        S: TransformableSpectrum,
+    {
+        TransformedSpectrumSource::new(self, chain)
+    }
 }
 
 /// A generic iterator over a [`SpectrumSource`] implementer that assumes the
@@ -11,7 +11,8 @@ use std::sync::mpsc::sync_channel;
 use mzdata::io::Source;
 use mzdata::prelude::*;
 use mzdata::spectrum::{
-    DeconvolutedSpectrum, MultiLayerSpectrum, RefPeakDataLevel, SignalContinuity, SpectrumLike,
+    AcquisitionSchemeAnalyzer, DeconvolutedSpectrum, MSConvertFilterChain, MultiLayerSpectrum,
+    RefPeakDataLevel, SignalContinuity, SpectrumLike,
 };
 
 struct MSDataFileSummary {
@@ -21,6 +22,7 @@ struct MSDataFileSummary {
     pub charge_table: HashMap<i32, usize>,
     pub peak_charge_table: HashMap<u8, HashMap<i32, usize>>,
     pub peak_mode_table: HashMap<SignalContinuity, usize>,
+    pub acquisition_scheme: AcquisitionSchemeAnalyzer,
 }
 
 impl Default for MSDataFileSummary {
@@ -32,12 +34,14 @@ impl Default for MSDataFileSummary {
             charge_table: Default::default(),
             peak_charge_table: Default::default(),
             peak_mode_table: Default::default(),
+            acquisition_scheme: AcquisitionSchemeAnalyzer::new(),
         }
     }
 }
 
 impl MSDataFileSummary {
     pub fn handle_scan(&mut self, scan: MultiLayerSpectrum) {
+        self.acquisition_scheme.observe(scan.description());
         let time = scan.start_time();
         self.start_time = self.start_time.min(time);
         self.end_time = self.end_time.max(time);
@@ -93,11 +97,17 @@ impl MSDataFileSummary {
         println!("{:0.3} seconds elapsed", elapsed.as_secs_f64());
     }
 
-    pub fn scan_file<R: SpectrumSource + Send + 'static>(&mut self, reader: R) {
+    pub fn scan_file<R: Iterator<Item = MultiLayerSpectrum> + Send + 'static>(
+        &mut self,
+        reader: R,
+    ) {
         self.scan_file_threaded(reader)
     }
 
-    pub fn scan_file_threaded<R: SpectrumSource + Send + 'static>(&mut self, reader: R) {
+    pub fn scan_file_threaded<R: Iterator<Item = MultiLayerSpectrum> + Send + 'static>(
+        &mut self,
+        reader: R,
+    ) {
         let start = time::Instant::now();
         let (sender, receiver) = sync_channel(2usize.pow(12));
         let read_handle = spawn(move || {
@@ -169,23 +179,53 @@ impl MSDataFileSummary {
                 SignalContinuity::Centroid => println!("Peaks: {}", count),
                 SignalContinuity::Profile => println!("Points: {}", count),
             });
+        print!("{}", self.acquisition_scheme.summary());
     }
 }
 
 fn main() -> io::Result<()> {
-    let path = path::PathBuf::from(env::args().nth(1).unwrap_or_else(|| {
+    let mut args = env::args().skip(1);
+    let mut path = None;
+    let mut filters = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--filter" {
+            filters.push(args.next().unwrap_or_else(|| {
+                eprintln!("--filter requires an msconvert filter expression");
+                process::exit(1)
+            }));
+        } else if path.is_none() {
+            path = Some(path::PathBuf::from(arg));
+        } else {
+            eprintln!("Unexpected argument {arg}");
+            process::exit(1)
+        }
+    }
+    let path = path.unwrap_or_else(|| {
         eprintln!("Please provide a path to an MS data file");
+        eprintln!("Usage: mzdata <path> [--filter <msconvert filter expression>]...");
         process::exit(1)
-    }));
+    });
+    let chain = MSConvertFilterChain::parse(&filters).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1)
+    });
     let mut summarizer = MSDataFileSummary::default();
 
     if path.as_os_str() == "-" {
         mzdata::mz_read!(Source::Stdin, reader => {
-            summarizer.scan_file(reader)
+            if chain.is_empty() {
+                summarizer.scan_file(reader)
+            } else {
+                summarizer.scan_file(chain.filter_source(reader))
+            }
         })?;
     } else {
         mzdata::mz_read!(path.as_ref(), reader => {
-            summarizer.scan_file(reader)
+            if chain.is_empty() {
+                summarizer.scan_file(reader)
+            } else {
+                summarizer.scan_file(chain.filter_source(reader))
+            }
         })?;
     };
 
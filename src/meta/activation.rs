@@ -113,6 +113,14 @@ impl DissociationMethodTerm {
             _ => false,
         }
     }
+
+    pub fn is_supplemental(&self) -> bool {
+        matches!(
+            self,
+            Self::SupplementalBeamTypeCollisionInducedDissociation
+                | Self::SupplementalCollisionInducedDissociation
+        )
+    }
 }
 
 crate::cvmap! {
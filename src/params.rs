@@ -1964,6 +1964,9 @@ pub enum Unit {
 
     Nanometer,
 
+    // Area
+    SquareAngstrom,
+
     // Time
     Minute,
     Second,
@@ -1996,6 +1999,10 @@ impl Unit {
             Self::MZ => ("MS:1000040", "m/z"),
             Self::Mass => ("UO:000221", "dalton"),
 
+            Self::SquareAngstrom => ("UO:0010001", "square angstrom"),
+
+            Self::Nanometer => ("UO:0000018", "nanometer"),
+
             Self::DetectorCounts => ("MS:1000131", "number of detector counts"),
             Self::PercentBasePeak => ("MS:1000132", "percent of base peak"),
             Self::PercentBasePeakTimes100 => ("MS:1000905", "percent of base peak times 100"),
@@ -2021,6 +2028,10 @@ impl Unit {
             b"m/z" => Self::MZ,
             b"dalton" => Self::Mass,
 
+            b"square angstrom" => Self::SquareAngstrom,
+
+            b"nanometer" => Self::Nanometer,
+
             b"number of detector counts" => Self::DetectorCounts,
             b"percent of base peak" => Self::PercentBasePeak,
             b"percent of base peak times 100" => Self::PercentBasePeakTimes100,
@@ -2047,6 +2058,10 @@ impl Unit {
             b"MS:1000040" => Self::MZ,
             b"UO:000221" => Self::Mass,
 
+            b"UO:0010001" => Self::SquareAngstrom,
+
+            b"UO:0000018" => Self::Nanometer,
+
             b"MS:1000131" => Self::DetectorCounts,
             b"MS:1000132" => Self::PercentBasePeak,
             b"MS:1000905" => Self::PercentBasePeakTimes100,
@@ -2088,6 +2103,16 @@ impl Unit {
                 accession: 221,
             } => Self::Mass,
 
+            CURIE {
+                controlled_vocabulary: ControlledVocabulary::UO,
+                accession: 10001,
+            } => Self::SquareAngstrom,
+
+            CURIE {
+                controlled_vocabulary: ControlledVocabulary::UO,
+                accession: 18,
+            } => Self::Nanometer,
+
             CURIE {
                 controlled_vocabulary: ControlledVocabulary::MS,
                 accession: 1000131,
@@ -2149,6 +2174,16 @@ impl Unit {
                 accession: 221,
             }),
 
+            Self::SquareAngstrom => Some(CURIE {
+                controlled_vocabulary: ControlledVocabulary::UO,
+                accession: 10001,
+            }),
+
+            Self::Nanometer => Some(CURIE {
+                controlled_vocabulary: ControlledVocabulary::UO,
+                accession: 18,
+            }),
+
             Self::DetectorCounts => Some(CURIE {
                 controlled_vocabulary: ControlledVocabulary::MS,
                 accession: 1000131,
@@ -4,7 +4,7 @@ pub use crate::io::traits::{
     RandomAccessSpectrumSource as _, SpectrumSourceWithMetadata as _, SpectrumSource,
     SpectrumWriter, SeekRead, SpectrumAccessError, SpectrumGrouping, IonMobilityFrameSource,
     IonMobilityFrameGrouping, RandomAccessIonMobilityFrameIterator, ChromatogramSource,
-    IonMobilityFrameWriter,
+    IonMobilityFrameWriter, ChromatogramWriter,
 };
 
 pub use crate::meta::MSDataFileMetadata;
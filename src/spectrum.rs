@@ -55,23 +55,81 @@ for spectrum in reader {
 More examples can be found in the [spectrum tutorial](crate::tutorial::spectrum).
 */
 
+pub(crate) mod acquisition_scheme;
 pub mod bindata;
+pub(crate) mod ccs;
+pub(crate) mod charge_inference;
 pub(crate) mod chromatogram;
+pub(crate) mod deconvolution;
+pub(crate) mod filter_string;
 pub(crate) mod frame;
 pub(crate) mod group;
+pub(crate) mod isolation_purity;
+pub(crate) mod msconvert_filter;
+pub(crate) mod noise;
 pub(crate) mod peaks;
+pub(crate) mod precursor_correction;
+pub(crate) mod recalibration;
+pub(crate) mod reporter_ions;
 pub(crate) mod scan_properties;
+pub(crate) mod similarity;
 pub(crate) mod spectrum_types;
+#[cfg(test)]
+pub(crate) mod test_utils;
+pub(crate) mod transforms;
 pub mod utils;
 
+pub use crate::spectrum::acquisition_scheme::{
+    AcquisitionMode, AcquisitionSchemeAnalyzer, AcquisitionSchemeSummary, MSLevelSummary,
+};
 pub use crate::spectrum::bindata::{ArrayType, BinaryArrayMap, BinaryDataArrayType, DataArray};
-pub use crate::spectrum::chromatogram::{Chromatogram, ChromatogramLike};
+pub use crate::spectrum::ccs::{
+    CCSCalculator, CCSConversionError, DriftGas, SingleFieldCalibration,
+};
+pub use crate::spectrum::charge_inference::{ChargeCandidate, ChargeInferrer};
+pub use crate::spectrum::chromatogram::{
+    Chromatogram, ChromatogramLike, ChromatogramPeak, PeakDetectionParameters,
+    SRMChromatogramCollector,
+};
+pub use crate::spectrum::chromatogram::processing as chromatogram_processing;
+pub use crate::spectrum::deconvolution::{
+    Averagine, DeconvolutingSpectrumSource, Deconvoluter, IsotopicFit,
+};
+pub use crate::spectrum::filter_string::{
+    FilterActivation, FilterMassAnalyzer, FilterPrecursor, FilterScanType, FilterString,
+    FilterStringParseError,
+};
+pub use crate::spectrum::isolation_purity::{
+    PurityAnnotatingSource, PurityEstimate, PurityEstimator,
+};
+pub use crate::spectrum::msconvert_filter::{
+    ActivationType, MSConvertFilter, MSConvertFilterChain, MSConvertFilterError,
+    MSConvertFilteredSource, MSLevelSet,
+};
+pub use crate::spectrum::noise::{signal_to_noise, NoiseEstimate, NoiseEstimator};
+pub use crate::spectrum::precursor_correction::{PrecursorCorrectingSource, PrecursorCorrector};
+pub use crate::spectrum::recalibration::{
+    CalibrationCurve, CalibrationError, CalibrationModel, CalibrationPoint, CalibrationStrategy,
+    MassRecalibrator, RecalibratedSpectrumSource,
+};
+pub use crate::spectrum::reporter_ions::{
+    ImpurityMatrix, IsobaricReagent, ReporterChannel, ReporterIntensities, ReporterMatching,
+    ReporterQuantificationError, ReporterQuantifier, ReporterRow, ReporterTable,
+};
 pub use crate::spectrum::scan_properties::*;
+pub use crate::spectrum::similarity::{
+    IntensityTransform, SimilarityMethod, SimilarityScore, SpectralSimilarity,
+};
 pub use crate::spectrum::spectrum_types::{
     CentroidPeakAdapting, CentroidSpectrum, CentroidSpectrumType, DeconvolutedPeakAdapting,
     DeconvolutedSpectrum, DeconvolutedSpectrumType, MultiLayerSpectrum, RawSpectrum, Spectrum,
     SpectrumConversionError, SpectrumLike, SpectrumProcessingError,
 };
+pub use crate::spectrum::transforms::{
+    AdaptorMetadata, ClipMzRange, IntensityThreshold, NormalizeIntensity, RemoveExtraZeros,
+    RemovePrecursor, SpectrumTransform, SqrtIntensity, TopNPerWindow, TransformChain,
+    TransformableSpectrum, TransformedSpectrumSource,
+};
 
 pub use crate::spectrum::peaks::{
     PeakDataIter, PeakDataIterDispatch, PeakDataLevel, RawIter, RefPeakDataIter, RefPeakDataLevel,
@@ -81,9 +139,10 @@ pub use crate::spectrum::peaks::{
 pub use frame::{IonMobilityFrameDescription, IonMobilityFrameLike, MultiLayerIonMobilityFrame};
 
 pub use group::{
-    IonMobilityFrameGroup, IonMobilityFrameGroupIntoIter, IonMobilityFrameGroupIter,
-    IonMobilityFrameGroupingIterator, SpectrumGroup, SpectrumGroupIntoIter, SpectrumGroupIter,
-    SpectrumGroupingIterator,
+    DIACycle, DIACycleIterator, DIAWindowTable, IonMobilityFrameGroup,
+    IonMobilityFrameGroupIntoIter, IonMobilityFrameGroupIter, IonMobilityFrameGroupingIterator,
+    SpectrumGroup, SpectrumGroupIntoIter, SpectrumGroupIter, SpectrumGroupingIterator,
+    DEFAULT_WINDOW_TOLERANCE,
 };
 
 #[cfg(feature = "mzsignal")]
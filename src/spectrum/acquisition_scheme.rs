@@ -0,0 +1,580 @@
+//! Infer how a run was acquired from the metadata of its spectra.
+//!
+//! The [`AcquisitionSchemeAnalyzer`] only looks at [`SpectrumDescription`]s, so it is
+//! cheapest to feed it from a reader using [`DetailLevel::MetadataOnly`](crate::io::DetailLevel::MetadataOnly).
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::io::traits::SpectrumSource;
+use crate::params::{ParamDescribed, ParamValue};
+
+use super::group::DIAWindowTable;
+use super::scan_properties::{
+    IonMobilityMeasure, ScanPolarity, SpectrumDescription, INVERSE_REDUCED_ION_MOBILITY,
+    ION_MOBILITY_DRIFT_TIME,
+};
+use super::spectrum_types::{CentroidPeakAdapting, DeconvolutedPeakAdapting, SpectrumLike};
+
+/// Once this many distinct isolation windows have been seen the run is assumed not to
+/// follow a fixed window scheme and windows stop being tracked.
+const MAX_TRACKED_WINDOWS: usize = 1000;
+
+/// The minimum average number of times each isolation window must be acquired for a
+/// run to be considered data independent.
+const DIA_MIN_WINDOW_REPEATS: f64 = 3.0;
+
+/// The broad strategy used to select precursors for fragmentation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AcquisitionMode {
+    /// No spectra were observed, or MSn spectra lacked isolation information
+    #[default]
+    Unknown,
+    /// Only MS1 spectra were acquired
+    MS1Only,
+    /// Precursors were selected on the fly from the preceding MS1 spectrum
+    DataDependent,
+    /// A fixed scheme of isolation windows was repeated every cycle
+    DataIndependent,
+}
+
+impl Display for AcquisitionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            AcquisitionMode::Unknown => "unknown",
+            AcquisitionMode::MS1Only => "MS1 only",
+            AcquisitionMode::DataDependent => "data dependent (DDA)",
+            AcquisitionMode::DataIndependent => "data independent (DIA)",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The acquisition settings observed for a single MS level
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MSLevelSummary {
+    pub ms_level: u8,
+    /// The number of spectra at this level
+    pub count: usize,
+    /// The distinct collision energies used
+    pub collision_energies: Vec<f32>,
+    /// The names of the distinct dissociation methods used
+    pub activation_methods: Vec<String>,
+    /// The distinct mass resolutions the spectra were acquired at
+    pub resolutions: Vec<f64>,
+    /// The distinct isolation window widths used
+    pub isolation_widths: Vec<f32>,
+    /// The largest number of precursors isolated for a single spectrum
+    pub max_precursors: usize,
+}
+
+/// A description of how a run was acquired, produced by [`AcquisitionSchemeAnalyzer`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AcquisitionSchemeSummary {
+    pub mode: AcquisitionMode,
+    /// The total number of spectra observed
+    pub spectrum_count: usize,
+    /// The number of acquisition cycles observed
+    pub cycle_count: usize,
+    /// The median time between the start of consecutive cycles, in minutes
+    pub cycle_time: Option<f64>,
+    /// The median number of MSn spectra per cycle
+    pub median_products_per_cycle: Option<f64>,
+    /// The largest number of MSn spectra in any cycle, the "N" of a top-N method
+    pub max_products_per_cycle: usize,
+    /// The settings for each MS level, in ascending order
+    pub levels: Vec<MSLevelSummary>,
+    /// The isolation window scheme, if the run is data independent
+    pub dia_windows: Option<DIAWindowTable>,
+    /// The distinct polarities observed
+    pub polarities: Vec<ScanPolarity>,
+    /// Whether the polarity changed during the run more than once
+    pub polarity_switching: bool,
+    /// The distinct FAIMS compensation voltages observed
+    pub faims_compensation_voltages: Vec<f64>,
+    /// Whether MSn precursors were selected by ion mobility as well as m/z, as with PASEF
+    pub ion_mobility_precursor_selection: bool,
+}
+
+impl AcquisitionSchemeSummary {
+    /// Get the summary for `ms_level`, if any spectra were acquired at that level
+    pub fn level(&self, ms_level: u8) -> Option<&MSLevelSummary> {
+        self.levels.iter().find(|l| l.ms_level == ms_level)
+    }
+
+    /// Whether MS3 spectra were acquired from multiple synchronously selected precursors
+    pub fn is_sps_ms3(&self) -> bool {
+        self.levels
+            .iter()
+            .any(|l| l.ms_level >= 3 && l.max_precursors > 1)
+    }
+
+    /// Whether more than one FAIMS compensation voltage was used
+    pub fn has_faims_stepping(&self) -> bool {
+        self.faims_compensation_voltages.len() > 1
+    }
+}
+
+fn write_list<T: Display>(
+    f: &mut std::fmt::Formatter<'_>,
+    label: &str,
+    values: &[T],
+) -> std::fmt::Result {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    writeln!(f, "\t\t{label}: {}", values.join(", "))
+}
+
+impl Display for AcquisitionSchemeSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Acquisition Mode: {}", self.mode)?;
+        writeln!(f, "Spectra: {}", self.spectrum_count)?;
+        writeln!(f, "Cycles: {}", self.cycle_count)?;
+        if let Some(cycle_time) = self.cycle_time {
+            writeln!(f, "Cycle Time: {:0.3} seconds", cycle_time * 60.0)?;
+        }
+        if let Some(n) = self.median_products_per_cycle {
+            writeln!(
+                f,
+                "MSn Per Cycle: {n:0.1} (max {})",
+                self.max_products_per_cycle
+            )?;
+        }
+        if let Some(table) = self.dia_windows.as_ref() {
+            let overlapping = if table.has_overlaps() {
+                ", overlapping"
+            } else {
+                ""
+            };
+            write!(f, "DIA Windows: {}{overlapping}", table.len())?;
+            if let Some((lo, hi)) = table.mz_range() {
+                write!(f, " covering {lo:0.2}-{hi:0.2}")?;
+            }
+            writeln!(f)?;
+        }
+        if self.is_sps_ms3() {
+            writeln!(f, "Synchronous Precursor Selection MS3")?;
+        }
+        let polarities: Vec<_> = self.polarities.iter().map(|p| format!("{p:?}")).collect();
+        writeln!(
+            f,
+            "Polarities: {}{}",
+            polarities.join(", "),
+            if self.polarity_switching {
+                " (switching)"
+            } else {
+                ""
+            }
+        )?;
+        if !self.faims_compensation_voltages.is_empty() {
+            let cvs: Vec<_> = self
+                .faims_compensation_voltages
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            writeln!(f, "FAIMS CVs: {}", cvs.join(", "))?;
+        }
+        if self.ion_mobility_precursor_selection {
+            writeln!(f, "Ion Mobility Precursor Selection (PASEF)")?;
+        }
+        for level in self.levels.iter() {
+            writeln!(f, "\tMS{}: {} spectra", level.ms_level, level.count)?;
+            write_list(f, "Resolutions", &level.resolutions)?;
+            write_list(f, "Isolation Widths", &level.isolation_widths)?;
+            write_list(f, "Activation", &level.activation_methods)?;
+            write_list(f, "Collision Energies", &level.collision_energies)?;
+            if level.max_precursors > 1 {
+                writeln!(f, "\t\tMax Precursors: {}", level.max_precursors)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn push_distinct<T: PartialEq>(values: &mut Vec<T>, value: T) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+fn round_to(value: f64, places: i32) -> f64 {
+    let scale = 10f64.powi(places);
+    (value * scale).round() / scale
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let n = values.len();
+    Some((values[(n - 1) / 2] + values[n / 2]) / 2.0)
+}
+
+/**
+Accumulates the metadata of a run's spectra in acquisition order to infer how the run was
+acquired, summarized in an [`AcquisitionSchemeSummary`].
+
+Cycles start at each MS1 spectrum. When MS1 spectra are missing, a new cycle also starts
+when an MS2 isolation window repeats within the current cycle, as in [`DIACycleIterator`](crate::spectrum::DIACycleIterator).
+*/
+#[derive(Debug, Clone)]
+pub struct AcquisitionSchemeAnalyzer {
+    spectrum_count: usize,
+    levels: BTreeMap<u8, MSLevelSummary>,
+    window_table: DIAWindowTable,
+    tracking_windows: bool,
+    windowed_ms2_count: usize,
+    current_windows: Vec<usize>,
+    cycle_starts: Vec<f64>,
+    cycle_sizes: Vec<usize>,
+    current_cycle_size: usize,
+    polarities: Vec<ScanPolarity>,
+    last_polarity: Option<ScanPolarity>,
+    polarity_changes: usize,
+    faims_compensation_voltages: Vec<f64>,
+    ion_mobility_precursor_selection: bool,
+}
+
+impl Default for AcquisitionSchemeAnalyzer {
+    fn default() -> Self {
+        Self {
+            spectrum_count: 0,
+            levels: BTreeMap::new(),
+            window_table: DIAWindowTable::default(),
+            tracking_windows: true,
+            windowed_ms2_count: 0,
+            current_windows: Vec::new(),
+            cycle_starts: Vec::new(),
+            cycle_sizes: Vec::new(),
+            current_cycle_size: 0,
+            polarities: Vec::new(),
+            last_polarity: None,
+            polarity_changes: 0,
+            faims_compensation_voltages: Vec::new(),
+            ion_mobility_precursor_selection: false,
+        }
+    }
+}
+
+impl AcquisitionSchemeAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read all of the spectra of `source` from the beginning, summarizing how they were acquired.
+    /// The source is reset afterwards.
+    pub fn analyze_source<
+        C: CentroidPeakAdapting,
+        D: DeconvolutedPeakAdapting,
+        S: SpectrumLike<C, D>,
+        R: SpectrumSource<C, D, S>,
+    >(
+        source: &mut R,
+    ) -> AcquisitionSchemeSummary {
+        let mut this = Self::new();
+        source.reset();
+        for spectrum in source.by_ref() {
+            this.observe(spectrum.description());
+        }
+        source.reset();
+        this.summary()
+    }
+
+    fn start_cycle(&mut self, time: f64) {
+        if !self.cycle_starts.is_empty() {
+            self.cycle_sizes.push(self.current_cycle_size);
+        }
+        self.current_cycle_size = 0;
+        self.current_windows.clear();
+        self.cycle_starts.push(time);
+    }
+
+    fn observe_window(&mut self, description: &SpectrumDescription, time: f64) {
+        let window = match description.precursors.first() {
+            Some(prec) if !prec.isolation_window.is_empty() => &prec.isolation_window,
+            _ => return,
+        };
+        self.windowed_ms2_count += 1;
+        if !self.tracking_windows {
+            return;
+        }
+        let i = self.window_table.find_or_insert(window);
+        if self.window_table.len() > MAX_TRACKED_WINDOWS {
+            self.tracking_windows = false;
+            self.current_windows.clear();
+            return;
+        }
+        if self.current_windows.contains(&i) {
+            self.start_cycle(time);
+        }
+        self.current_windows.push(i);
+    }
+
+    /// Add the metadata of the next spectrum in the run
+    pub fn observe(&mut self, description: &SpectrumDescription) {
+        self.spectrum_count += 1;
+        let time = description.acquisition.start_time();
+        let ms_level = description.ms_level;
+
+        if ms_level == 1 {
+            self.start_cycle(time);
+        } else {
+            if self.cycle_starts.is_empty() {
+                self.start_cycle(time);
+            }
+            if ms_level == 2 {
+                self.observe_window(description, time);
+            }
+            self.current_cycle_size += 1;
+        }
+
+        push_distinct(&mut self.polarities, description.polarity);
+        if let Some(last) = self.last_polarity {
+            if last != description.polarity {
+                self.polarity_changes += 1;
+            }
+        }
+        self.last_polarity = Some(description.polarity);
+
+        let level = self
+            .levels
+            .entry(ms_level)
+            .or_insert_with(|| MSLevelSummary {
+                ms_level,
+                ..Default::default()
+            });
+        level.count += 1;
+        level.max_precursors = level.max_precursors.max(description.precursors.len());
+
+        for scan in description.acquisition.iter() {
+            if let Some(resolution) = scan.resolution().and_then(|v| v.to_f64().ok()) {
+                push_distinct(&mut level.resolutions, resolution);
+            }
+            if let Some(cv) = scan.compensation_voltage() {
+                push_distinct(&mut self.faims_compensation_voltages, round_to(cv, 2));
+            }
+            if ms_level > 1
+                && (scan.get_param_by_curie(&ION_MOBILITY_DRIFT_TIME).is_some()
+                    || scan
+                        .get_param_by_curie(&INVERSE_REDUCED_ION_MOBILITY)
+                        .is_some())
+            {
+                self.ion_mobility_precursor_selection = true;
+            }
+        }
+
+        for precursor in description.precursors.iter() {
+            let iw = &precursor.isolation_window;
+            if !iw.is_empty() {
+                let width = ((iw.upper_bound - iw.lower_bound) * 100.0).round() / 100.0;
+                push_distinct(&mut level.isolation_widths, width);
+            }
+            let activation = &precursor.activation;
+            if activation.energy != 0.0 {
+                push_distinct(&mut level.collision_energies, activation.energy);
+            }
+            for method in activation.methods() {
+                push_distinct(&mut level.activation_methods, method.name().to_string());
+            }
+            if precursor.ions.iter().any(|ion| {
+                ion.get_param_by_curie(&INVERSE_REDUCED_ION_MOBILITY)
+                    .is_some()
+                    || ion.get_param_by_curie(&ION_MOBILITY_DRIFT_TIME).is_some()
+            }) {
+                self.ion_mobility_precursor_selection = true;
+            }
+        }
+    }
+
+    fn infer_mode(&self) -> AcquisitionMode {
+        let n_msn: usize = self
+            .levels
+            .values()
+            .filter(|l| l.ms_level > 1)
+            .map(|l| l.count)
+            .sum();
+        if n_msn == 0 {
+            if self.levels.contains_key(&1) {
+                AcquisitionMode::MS1Only
+            } else {
+                AcquisitionMode::Unknown
+            }
+        } else if self.windowed_ms2_count == 0 {
+            AcquisitionMode::Unknown
+        } else if self.tracking_windows
+            && !self.window_table.is_empty()
+            && (self.windowed_ms2_count as f64 / self.window_table.len() as f64)
+                >= DIA_MIN_WINDOW_REPEATS
+        {
+            AcquisitionMode::DataIndependent
+        } else {
+            AcquisitionMode::DataDependent
+        }
+    }
+
+    /// Summarize the spectra observed so far
+    pub fn summary(&self) -> AcquisitionSchemeSummary {
+        let mode = self.infer_mode();
+
+        let mut cycle_sizes: Vec<f64> = self.cycle_sizes.iter().map(|n| *n as f64).collect();
+        if !self.cycle_starts.is_empty() {
+            cycle_sizes.push(self.current_cycle_size as f64);
+        }
+        let max_products_per_cycle = cycle_sizes.iter().fold(0.0f64, |a, b| a.max(*b)) as usize;
+        let mut cycle_times: Vec<f64> = self
+            .cycle_starts
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect();
+
+        let mut levels: Vec<MSLevelSummary> = self.levels.values().cloned().collect();
+        for level in levels.iter_mut() {
+            level.collision_energies.sort_by(|a, b| a.total_cmp(b));
+            level.resolutions.sort_by(|a, b| a.total_cmp(b));
+            level.isolation_widths.sort_by(|a, b| a.total_cmp(b));
+        }
+        let mut faims_compensation_voltages = self.faims_compensation_voltages.clone();
+        faims_compensation_voltages.sort_by(|a, b| a.total_cmp(b));
+
+        AcquisitionSchemeSummary {
+            mode,
+            spectrum_count: self.spectrum_count,
+            cycle_count: self.cycle_starts.len(),
+            cycle_time: median(&mut cycle_times),
+            median_products_per_cycle: median(&mut cycle_sizes),
+            max_products_per_cycle,
+            levels,
+            dia_windows: if mode == AcquisitionMode::DataIndependent {
+                Some(self.window_table.clone())
+            } else {
+                None
+            },
+            polarities: self.polarities.clone(),
+            polarity_switching: self.polarity_changes > 1,
+            faims_compensation_voltages,
+            ion_mobility_precursor_selection: self.ion_mobility_precursor_selection,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::params::ControlledVocabulary;
+    use crate::spectrum::{IsolationWindow, Precursor, ScanEvent};
+
+    fn make_description(
+        time: f64,
+        ms_level: u8,
+        window: Option<(f32, f32)>,
+        polarity: ScanPolarity,
+    ) -> SpectrumDescription {
+        let mut descr = SpectrumDescription {
+            ms_level,
+            polarity,
+            ..Default::default()
+        };
+        descr.acquisition.scans.push(ScanEvent {
+            start_time: time,
+            ..Default::default()
+        });
+        if let Some((target, width)) = window {
+            let mut prec = Precursor {
+                isolation_window: IsolationWindow::around(target, width / 2.0),
+                ..Default::default()
+            };
+            prec.activation.energy = 30.0;
+            descr.precursors.push(prec);
+        }
+        descr
+    }
+
+    #[test]
+    fn test_dia_scheme() {
+        let mut analyzer = AcquisitionSchemeAnalyzer::new();
+        let mut time = 0.0;
+        for _ in 0..5 {
+            analyzer.observe(&make_description(time, 1, None, ScanPolarity::Positive));
+            time += 0.001;
+            for i in 0..10 {
+                let target = 412.5 + 25.0 * i as f32;
+                analyzer.observe(&make_description(
+                    time,
+                    2,
+                    Some((target, 25.0)),
+                    ScanPolarity::Positive,
+                ));
+                time += 0.001;
+            }
+        }
+        let summary = analyzer.summary();
+        assert_eq!(summary.mode, AcquisitionMode::DataIndependent);
+        assert_eq!(summary.cycle_count, 5);
+        assert_eq!(summary.max_products_per_cycle, 10);
+        assert!((summary.cycle_time.unwrap() - 0.011).abs() < 1e-6);
+        assert_eq!(summary.dia_windows.as_ref().unwrap().len(), 10);
+        assert_eq!(summary.level(2).unwrap().isolation_widths, vec![25.0]);
+        assert_eq!(summary.level(2).unwrap().collision_energies, vec![30.0]);
+        assert!(!summary.polarity_switching);
+        assert!(!summary.to_string().is_empty());
+    }
+
+    #[test]
+    fn test_default_dia_scheme_without_ms1() {
+        let mut analyzer = AcquisitionSchemeAnalyzer::default();
+        let mut time = 0.0;
+        for _ in 0..5 {
+            for i in 0..10 {
+                let target = 412.5 + 25.0 * i as f32;
+                analyzer.observe(&make_description(
+                    time,
+                    2,
+                    Some((target, 25.0)),
+                    ScanPolarity::Positive,
+                ));
+                time += 0.001;
+            }
+        }
+        let summary = analyzer.summary();
+        assert_eq!(summary.mode, AcquisitionMode::DataIndependent);
+        assert_eq!(summary.cycle_count, 5);
+        assert_eq!(summary.max_products_per_cycle, 10);
+        assert_eq!(summary.dia_windows.as_ref().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_dda_scheme() {
+        let mut analyzer = AcquisitionSchemeAnalyzer::new();
+        let mut time = 0.0;
+        for cycle in 0..6 {
+            let polarity = if cycle % 2 == 0 {
+                ScanPolarity::Positive
+            } else {
+                ScanPolarity::Negative
+            };
+            let mut ms1 = make_description(time, 1, None, polarity);
+            ms1.acquisition.scans[0].add_param(ControlledVocabulary::MS.param_val(
+                1001581,
+                "FAIMS compensation voltage",
+                if cycle % 3 == 0 { -45.0 } else { -60.0 },
+            ));
+            analyzer.observe(&ms1);
+            time += 0.01;
+            for i in 0..(cycle + 1) {
+                let target = 500.0 + 13.7 * (cycle * 10 + i) as f32;
+                analyzer.observe(&make_description(time, 2, Some((target, 1.6)), polarity));
+                time += 0.01;
+            }
+        }
+        let summary = analyzer.summary();
+        assert_eq!(summary.mode, AcquisitionMode::DataDependent);
+        assert_eq!(summary.cycle_count, 6);
+        assert_eq!(summary.max_products_per_cycle, 6);
+        assert!(summary.polarity_switching);
+        assert!(summary.dia_windows.is_none());
+        assert!(summary.has_faims_stepping());
+        assert_eq!(summary.faims_compensation_voltages, vec![-60.0, -45.0]);
+    }
+}
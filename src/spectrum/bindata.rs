@@ -11,7 +11,7 @@ pub use conversion::{
 pub use encodings::{
     as_bytes, delta_decoding, delta_encoding, linear_prediction_decoding,
     linear_prediction_encoding, to_bytes, vec_as_bytes, ArrayRetrievalError, ArrayType,
-    BinaryCompressionType, BinaryDataArrayType, Bytes,
+    BinaryCompressionType, BinaryDataArrayType, Bytes, CCS_ARRAY_NAME,
 };
 pub use map::{BinaryArrayMap, BinaryArrayMap3D};
 pub use traits::{ByteArrayView, ByteArrayViewMut};
@@ -379,6 +379,31 @@ impl<'transient, 'lifespan: 'transient> DataArray {
         }
     }
 
+    /// Decode the array and retain only those entries whose position in `keep` is `true`.
+    ///
+    /// Returns the number of entries remaining, or [`ArrayRetrievalError::DataTypeSizeMismatch`]
+    /// if `keep` is not the same length as the array.
+    pub fn retain_by_mask(&mut self, keep: &[bool]) -> Result<usize, ArrayRetrievalError> {
+        if !matches!(self.compression, BinaryCompressionType::Decoded) {
+            self.decode_and_store()?;
+        }
+        let size = self.dtype.size_of();
+        if self.data.len() != keep.len() * size {
+            return Err(ArrayRetrievalError::DataTypeSizeMismatch);
+        }
+        let data: Bytes = self
+            .data
+            .chunks_exact(size)
+            .zip(keep.iter())
+            .filter(|(_, k)| **k)
+            .flat_map(|(chunk, _)| chunk.iter().copied())
+            .collect();
+        let n = data.len() / size;
+        self.data = data;
+        self.item_count = Some(n);
+        Ok(n)
+    }
+
     pub fn clear(&mut self) {
         self.data.clear();
         self.params = None;
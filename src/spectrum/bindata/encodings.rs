@@ -39,12 +39,20 @@ pub enum ArrayType {
     IntensityArray,
     ChargeArray,
     SignalToNoiseArray,
+    /// The signal baseline, the intensity in the absence of analytes, at each point
+    BaselineArray,
+    /// The noise level at each point
+    NoiseArray,
     TimeArray,
     WavelengthArray,
     IonMobilityArray,
     MeanIonMobilityArray,
     RawIonMobilityArray,
     DeconvolutedIonMobilityArray,
+    /// Collisional cross sections derived from an ion mobility dimension. There is no
+    /// controlled vocabulary term for this array, so it is stored as a non-standard data array
+    /// named [`CCS_ARRAY_NAME`].
+    CollisionalCrossSectionArray,
     NonStandardDataArray {
         name: Box<String>,
     },
@@ -98,9 +106,33 @@ impl ArrayType {
                 )
                 .into(),
             ArrayType::ChargeArray => CV.const_param_ident("charge array", 1000516).into(),
+            ArrayType::SignalToNoiseArray => CV
+                .const_param_ident("signal to noise array", 1000517)
+                .into(),
+            ArrayType::BaselineArray => CV
+                .const_param_ident_unit(
+                    "baseline array",
+                    1002530,
+                    unit.unwrap_or(Unit::DetectorCounts),
+                )
+                .into(),
+            ArrayType::NoiseArray => CV
+                .const_param_ident_unit(
+                    "noise array",
+                    1002742,
+                    unit.unwrap_or(Unit::DetectorCounts),
+                )
+                .into(),
             ArrayType::TimeArray => CV
                 .const_param_ident_unit("time array", 1000595, unit.unwrap_or(Unit::Minute))
                 .into(),
+            ArrayType::WavelengthArray => CV
+                .const_param_ident_unit(
+                    "wavelength array",
+                    1000617,
+                    unit.unwrap_or(Unit::Nanometer),
+                )
+                .into(),
             ArrayType::RawIonMobilityArray => CV
                 .const_param_ident_unit("raw ion mobility array", 1003007, unit.unwrap_or_default())
                 .into(),
@@ -118,6 +150,11 @@ impl ArrayType {
                     unit.unwrap_or_default(),
                 )
                 .into(),
+            ArrayType::CollisionalCrossSectionArray => {
+                let mut p = CV.param_val(1000786, "non-standard data array", CCS_ARRAY_NAME);
+                p.unit = unit.unwrap_or(Unit::SquareAngstrom);
+                p
+            }
             ArrayType::NonStandardDataArray { name } => {
                 let mut p = CV.param_val(
                     1000786,
@@ -141,7 +178,17 @@ impl ArrayType {
                 CV.const_param_ident_unit("intensity array", 1000515, Unit::DetectorCounts)
             }
             ArrayType::ChargeArray => CV.const_param_ident("charge array", 1000516),
+            ArrayType::SignalToNoiseArray => CV.const_param_ident("signal to noise array", 1000517),
+            ArrayType::BaselineArray => {
+                CV.const_param_ident_unit("baseline array", 1002530, Unit::DetectorCounts)
+            }
+            ArrayType::NoiseArray => {
+                CV.const_param_ident_unit("noise array", 1002742, Unit::DetectorCounts)
+            }
             ArrayType::TimeArray => CV.const_param_ident_unit("time array", 1000595, Unit::Minute),
+            ArrayType::WavelengthArray => {
+                CV.const_param_ident_unit("wavelength array", 1000617, Unit::Nanometer)
+            }
             ArrayType::RawIonMobilityArray => {
                 CV.const_param_ident("raw ion mobility array", 1003007)
             }
@@ -168,7 +215,15 @@ impl ArrayType {
                 CV.const_param_ident_unit("intensity array", 1000515, unit)
             }
             ArrayType::ChargeArray => CV.const_param_ident_unit("charge array", 1000516, unit),
+            ArrayType::SignalToNoiseArray => {
+                CV.const_param_ident_unit("signal to noise array", 1000517, unit)
+            }
+            ArrayType::BaselineArray => CV.const_param_ident_unit("baseline array", 1002530, unit),
+            ArrayType::NoiseArray => CV.const_param_ident_unit("noise array", 1002742, unit),
             ArrayType::TimeArray => CV.const_param_ident_unit("time array", 1000595, unit),
+            ArrayType::WavelengthArray => {
+                CV.const_param_ident_unit("wavelength array", 1000617, unit)
+            }
             ArrayType::RawIonMobilityArray => {
                 CV.const_param_ident_unit("raw ion mobility array", 1003007, unit)
             }
@@ -188,6 +243,9 @@ impl ArrayType {
     }
 }
 
+/// The name of the non-standard data array used to store [`ArrayType::CollisionalCrossSectionArray`]
+pub const CCS_ARRAY_NAME: &str = "collisional cross sectional area array";
+
 /// The canonical primitive data types found in MS data file formats
 /// supported by the PSI-MS controlled vocabulary
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, Default)]
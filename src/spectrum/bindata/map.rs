@@ -7,7 +7,8 @@ use std::convert::TryFrom;
 #[cfg(feature = "parallelism")]
 use rayon::prelude::*;
 
-use mzpeaks::Tolerance;
+use mzpeaks::feature::Feature;
+use mzpeaks::{IonMobility, Tolerance, MZ};
 
 use crate::params::Unit;
 
@@ -239,6 +240,22 @@ impl BinaryArrayMap {
         }
     }
 
+    /// Get a reference to the wavelength array of an electromagnetic radiation spectrum
+    /// if it is present
+    pub fn wavelengths(&'_ self) -> Result<Cow<'_, [f64]>, ArrayRetrievalError> {
+        let wavelengths = self
+            .get(&ArrayType::WavelengthArray)
+            .ok_or(ArrayRetrievalError::NotFound(ArrayType::WavelengthArray))?
+            .to_f64()?;
+        Ok(wavelengths)
+    }
+
+    /// Get a reference to the absorbance of an absorption spectrum, which is stored in
+    /// the intensity array
+    pub fn absorbances(&'_ self) -> Result<Cow<'_, [f32]>, ArrayRetrievalError> {
+        self.intensities()
+    }
+
     /// Get a reference to the charge array if it is present
     pub fn charges(&'_ self) -> Result<Cow<'_, [i32]>, ArrayRetrievalError> {
         match self.get(&ArrayType::ChargeArray) {
@@ -258,6 +275,30 @@ impl BinaryArrayMap {
         }
     }
 
+    /// Get a reference to the signal-to-noise ratio array if it is present
+    pub fn signal_to_noise(&'_ self) -> Result<Cow<'_, [f32]>, ArrayRetrievalError> {
+        match self.get(&ArrayType::SignalToNoiseArray) {
+            Some(data_array) => data_array.to_f32(),
+            None => Err(ArrayRetrievalError::NotFound(ArrayType::SignalToNoiseArray)),
+        }
+    }
+
+    /// Get a reference to the baseline array if it is present
+    pub fn baseline(&'_ self) -> Result<Cow<'_, [f32]>, ArrayRetrievalError> {
+        match self.get(&ArrayType::BaselineArray) {
+            Some(data_array) => data_array.to_f32(),
+            None => Err(ArrayRetrievalError::NotFound(ArrayType::BaselineArray)),
+        }
+    }
+
+    /// Get a reference to the noise array if it is present
+    pub fn noise(&'_ self) -> Result<Cow<'_, [f32]>, ArrayRetrievalError> {
+        match self.get(&ArrayType::NoiseArray) {
+            Some(data_array) => data_array.to_f32(),
+            None => Err(ArrayRetrievalError::NotFound(ArrayType::NoiseArray)),
+        }
+    }
+
     /// Get a reference to the ion mobility array if it is present
     pub fn ion_mobility(&self) -> Result<(Cow<'_, [f64]>, ArrayType), ArrayRetrievalError> {
         if let Some((array_type, data_array)) = self
@@ -431,7 +472,7 @@ impl BinaryArrayMap3D {
 
         let mut im_dim = DataArray::from_name_and_type(
             &self.ion_mobility_type,
-            BinaryDataArrayType::Float32,
+            BinaryDataArrayType::Float64,
         );
         im_dim.unit = self.ion_mobility_unit;
 
@@ -502,6 +543,9 @@ impl BinaryArrayMap3D {
                                 }
                             }
                             indices[bin_i] += 1;
+                            if let Some(mz) = mz_axes[bin_i].get(i + 1).copied() {
+                                next_mz = mz.min(next_mz);
+                            }
                         } else if mz > current_mz {
                             next_mz = mz.min(next_mz);
                         }
@@ -514,6 +558,208 @@ impl BinaryArrayMap3D {
         Ok(destination)
     }
 
+    /// Sum the signal of every ion mobility point within `ion_mobility_range` (inclusive, or all points
+    /// when `None`) onto a common m/z axis, producing a [`BinaryArrayMap`] with only an m/z and an
+    /// intensity array.
+    ///
+    /// When `mz_tolerance` is given, consecutive m/z values within the tolerance of the first m/z of
+    /// a bin are merged into that bin, which is placed at the intensity-weighted mean m/z. Otherwise,
+    /// only identical m/z values are merged.
+    ///
+    /// # Errors
+    /// [`ArrayRetrievalError`] errors related to array decoding occur if the m/z or intensity
+    /// [`DataArray`] of any ion mobility point cannot be decoded.
+    pub fn collapse(
+        &self,
+        ion_mobility_range: Option<(f64, f64)>,
+        mz_tolerance: Option<Tolerance>,
+    ) -> Result<BinaryArrayMap, ArrayRetrievalError> {
+        let mut points: Vec<(f64, f32)> = Vec::new();
+        for (im, layer) in self.iter() {
+            if let Some((low, high)) = ion_mobility_range {
+                if im < low || im > high {
+                    continue;
+                }
+            }
+            if layer.is_empty() {
+                continue;
+            }
+            let mzs = layer.mzs()?;
+            let intensities = layer.intensities()?;
+            points.extend(mzs.iter().copied().zip(intensities.iter().copied()));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut mz_axis: Vec<f64> = Vec::with_capacity(points.len());
+        let mut intensity_axis: Vec<f32> = Vec::with_capacity(points.len());
+        let mut bin_start = f64::NAN;
+        let mut weighted_mz = 0.0;
+        for (mz, intensity) in points {
+            let same_bin = match mz_tolerance {
+                Some(tol) => !bin_start.is_nan() && tol.test(mz, bin_start),
+                None => mz == bin_start,
+            };
+            if same_bin {
+                let total = intensity_axis.last_mut().unwrap();
+                *total += intensity;
+                weighted_mz += mz * intensity as f64;
+                if *total > 0.0 {
+                    *mz_axis.last_mut().unwrap() = weighted_mz / *total as f64;
+                }
+            } else {
+                bin_start = mz;
+                weighted_mz = mz * intensity as f64;
+                mz_axis.push(mz);
+                intensity_axis.push(intensity);
+            }
+        }
+
+        let mut destination = BinaryArrayMap::new();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array.extend(&mz_axis)?;
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.extend(&intensity_axis)?;
+        destination.add(mz_array);
+        destination.add(intensity_array);
+        Ok(destination)
+    }
+
+    /// Extract the rectangular region of this map within `mz_range` and `ion_mobility_range`
+    /// (both inclusive, or unbounded when `None`) as a new [`BinaryArrayMap3D`].
+    ///
+    /// Only ion mobility points within `ion_mobility_range` are read. When `mz_range` is given, the
+    /// m/z array of each of those points is decoded to find the bounds of the region. Points lying
+    /// entirely within `mz_range` are copied without decoding their other arrays, but every array
+    /// of a point that is only partly within it is decoded to be sliced. Ion mobility points with no
+    /// signal within `mz_range` are omitted. [`BinaryArrayMap3D::additional_arrays`] are copied as-is.
+    ///
+    /// # Errors
+    /// [`ArrayRetrievalError`] errors related to array decoding occur if the m/z array of any
+    /// ion mobility point within range, or any array that must be sliced, cannot be decoded.
+    pub fn extract_region(
+        &self,
+        mz_range: Option<(f64, f64)>,
+        ion_mobility_range: Option<(f64, f64)>,
+    ) -> Result<Self, ArrayRetrievalError> {
+        let mut region = Self {
+            ion_mobility_type: self.ion_mobility_type.clone(),
+            ion_mobility_unit: self.ion_mobility_unit,
+            additional_arrays: self.additional_arrays.clone(),
+            ..Default::default()
+        };
+        for (im, layer) in self.iter() {
+            if let Some((low, high)) = ion_mobility_range {
+                if im < low || im > high {
+                    continue;
+                }
+            }
+            // The span of the layer's points within `mz_range`, or `None` if it is the whole layer
+            let selection = match mz_range {
+                Some((low, high)) => {
+                    let mzs = layer.mzs()?;
+                    let start = mzs.partition_point(|mz| *mz < low);
+                    let end = mzs.partition_point(|mz| *mz <= high);
+                    if start == 0 && end == mzs.len() {
+                        None
+                    } else {
+                        Some((start, end))
+                    }
+                }
+                None => None,
+            };
+            let layer = match selection {
+                None => layer.clone(),
+                Some((start, end)) if start >= end => continue,
+                Some((start, end)) => {
+                    let mut sliced = BinaryArrayMap::new();
+                    for (_, array) in layer.iter() {
+                        let width = array.dtype.size_of();
+                        let mut part = array.slice(start * width, end * width)?;
+                        part.unit = array.unit;
+                        part.params.clone_from(&array.params);
+                        sliced.add(part);
+                    }
+                    sliced
+                }
+            };
+            if layer.is_empty() {
+                continue;
+            }
+            region
+                .ion_mobility_index
+                .insert(NonNaNF64::from(im), region.arrays.len());
+            region.ion_mobility_dimension.push(im);
+            region.arrays.push(layer);
+        }
+        Ok(region)
+    }
+
+    /// Iterate over every point in this map as (ion mobility, m/z, intensity) triples, in
+    /// ascending ion mobility order and then in m/z order.
+    ///
+    /// # Errors
+    /// [`ArrayRetrievalError`] errors related to array decoding occur if the m/z or intensity
+    /// [`DataArray`] of any non-empty ion mobility point cannot be decoded.
+    pub fn iter_points(
+        &self,
+    ) -> Result<impl Iterator<Item = (f64, f64, f32)> + '_, ArrayRetrievalError> {
+        let mut layers = Vec::with_capacity(self.arrays.len());
+        for (im, layer) in self.iter() {
+            if layer.is_empty() {
+                continue;
+            }
+            layers.push((im, layer.mzs()?, layer.intensities()?));
+        }
+        Ok(layers.into_iter().flat_map(|(im, mzs, intensities)| {
+            let n = mzs.len().min(intensities.len());
+            (0..n).map(move |i| (im, mzs[i], intensities[i]))
+        }))
+    }
+
+    /// Extract the mobilogram of the signal within `error_tolerance` of `mz`.
+    ///
+    /// Each point of the returned feature is one ion mobility point with signal in range, at the
+    /// intensity-weighted mean m/z of that signal and with its summed intensity.
+    ///
+    /// # Errors
+    /// [`ArrayRetrievalError`] errors related to array decoding occur if the m/z or intensity
+    /// [`DataArray`] of any non-empty ion mobility point cannot be decoded.
+    pub fn extract_mobilogram(
+        &self,
+        mz: f64,
+        error_tolerance: Tolerance,
+    ) -> Result<Feature<MZ, IonMobility>, ArrayRetrievalError> {
+        let (low, high) = error_tolerance.bounds(mz);
+        let mut mobilogram = Feature::empty();
+        for (im, layer) in self.iter() {
+            if layer.is_empty() {
+                continue;
+            }
+            let mzs = layer.mzs()?;
+            let start = mzs.partition_point(|v| *v < low);
+            let end = mzs.partition_point(|v| *v <= high);
+            if start >= end {
+                continue;
+            }
+            let intensities = layer.intensities()?;
+            let mut total = 0.0f32;
+            let mut weighted_mz = 0.0;
+            for (mz_i, intensity) in mzs[start..end].iter().zip(&intensities[start..end]) {
+                total += *intensity;
+                weighted_mz += *mz_i * *intensity as f64;
+            }
+            let mean_mz = if total > 0.0 {
+                weighted_mz / total as f64
+            } else {
+                mzs[start..end].iter().sum::<f64>() / (end - start) as f64
+            };
+            mobilogram.push_raw(mean_mz, im, total);
+        }
+        Ok(mobilogram)
+    }
+
     /// Convert a [`BinaryArrayMap`] into a [`BinaryArrayMap3D`] if it has an ion mobility dimension.
     ///
     /// Any arrays that aren't the same length as the ion mobility dimension will be in
@@ -659,4 +905,92 @@ mod test {
         );
         Ok(())
     }
+
+    fn make_stacked() -> BinaryArrayMap3D {
+        let mut map = BinaryArrayMap::new();
+        let mut mzs =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mzs.extend(&[200.0f64, 300.0, 200.0, 300.001, 500.0])
+            .unwrap();
+        let mut intensities =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensities
+            .extend(&[10.0f32, 20.0, 30.0, 20.0, 50.0])
+            .unwrap();
+        let mut ims = DataArray::from_name_and_type(
+            &ArrayType::MeanIonMobilityArray,
+            BinaryDataArrayType::Float64,
+        );
+        ims.extend(&[0.8f64, 0.8, 0.9, 0.9, 1.0]).unwrap();
+        map.add(mzs);
+        map.add(intensities);
+        map.add(ims);
+        BinaryArrayMap3D::stack(&map).unwrap()
+    }
+
+    #[test]
+    fn test_collapse() -> Result<(), ArrayRetrievalError> {
+        let stacked = make_stacked();
+
+        let collapsed = stacked.collapse(None, None)?;
+        assert_eq!(&*collapsed.mzs()?, &[200.0, 300.0, 300.001, 500.0]);
+        assert_eq!(&*collapsed.intensities()?, &[40.0, 20.0, 20.0, 50.0]);
+
+        let collapsed = stacked.collapse(None, Some(Tolerance::PPM(10.0)))?;
+        let mzs = collapsed.mzs()?;
+        assert_eq!(mzs.len(), 3);
+        assert!((mzs[1] - 300.0005).abs() < 1e-6);
+        assert_eq!(&*collapsed.intensities()?, &[40.0, 40.0, 50.0]);
+
+        let collapsed = stacked.collapse(Some((0.85, 1.0)), None)?;
+        assert_eq!(&*collapsed.mzs()?, &[200.0, 300.001, 500.0]);
+        assert_eq!(&*collapsed.intensities()?, &[30.0, 20.0, 50.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_region() -> Result<(), ArrayRetrievalError> {
+        let stacked = make_stacked();
+
+        let region = stacked.extract_region(Some((250.0, 600.0)), Some((0.85, 1.0)))?;
+        assert_eq!(region.ion_mobility_dimension, vec![0.9, 1.0]);
+        assert_eq!(&*region.get_ion_mobility(0.9).unwrap().mzs()?, &[300.001]);
+        assert_eq!(
+            &*region.get_ion_mobility(0.9).unwrap().intensities()?,
+            &[20.0]
+        );
+        assert_eq!(&*region.get_ion_mobility(1.0).unwrap().mzs()?, &[500.0]);
+
+        let region = stacked.extract_region(Some((100.0, 250.0)), None)?;
+        assert_eq!(region.ion_mobility_dimension, vec![0.8, 0.9]);
+
+        let points: Vec<_> = stacked.iter_points()?.collect();
+        assert_eq!(
+            points,
+            vec![
+                (0.8, 200.0, 10.0),
+                (0.8, 300.0, 20.0),
+                (0.9, 200.0, 30.0),
+                (0.9, 300.001, 20.0),
+                (1.0, 500.0, 50.0)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_mobilogram() -> Result<(), ArrayRetrievalError> {
+        let stacked = make_stacked();
+        let mobilogram = stacked.extract_mobilogram(300.0, Tolerance::PPM(10.0))?;
+        assert_eq!(mobilogram.len(), 2);
+        let points: Vec<_> = mobilogram.iter().collect();
+        assert_eq!(*points[0].1, 0.8);
+        assert_eq!(*points[1].1, 0.9);
+        assert_eq!(*points[1].0, 300.001);
+        assert_eq!(*points[1].2, 20.0);
+
+        let mobilogram = stacked.extract_mobilogram(400.0, Tolerance::Da(0.5))?;
+        assert!(mobilogram.is_empty());
+        Ok(())
+    }
 }
@@ -0,0 +1,341 @@
+//! Convert ion mobility measurements to collisional cross sections (CCS).
+//!
+//! Inverse reduced mobilities (1/K0) from trapped ion mobility instruments are converted with
+//! the Mason–Schamp equation. Drift times from drift tube instruments are converted with a
+//! single-field calibration, `t_D = β γ CCS + t_fix`, where `γ = sqrt(m / (m + m_gas)) / z`.
+use std::f64::consts::PI;
+
+use thiserror::Error;
+
+use crate::params::{ParamDescribed, ParamValue, Unit};
+
+use super::bindata::{
+    ArrayRetrievalError, ArrayType, BinaryArrayMap3D, BinaryDataArrayType, DataArray,
+};
+use super::scan_properties::{SelectedIon, INVERSE_REDUCED_ION_MOBILITY, ION_MOBILITY_DRIFT_TIME};
+
+const ELEMENTARY_CHARGE: f64 = 1.602176634e-19;
+const BOLTZMANN: f64 = 1.380649e-23;
+/// The number density of an ideal gas at 273.15 K and 101.325 kPa, in m^-3
+const LOSCHMIDT: f64 = 2.6867811e25;
+const DALTON: f64 = 1.66053906660e-27;
+
+/// The neutral buffer gas the ions drift through
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DriftGas {
+    #[default]
+    Nitrogen,
+    Helium,
+    /// Any other gas, given by its mass in daltons
+    Other(f64),
+}
+
+impl DriftGas {
+    /// The mass of a single gas molecule in daltons
+    pub fn mass(&self) -> f64 {
+        match self {
+            DriftGas::Nitrogen => 28.006148,
+            DriftGas::Helium => 4.002603,
+            DriftGas::Other(mass) => *mass,
+        }
+    }
+}
+
+/// The coefficients of a single-field drift time calibration, with `t_fix` in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SingleFieldCalibration {
+    pub beta: f64,
+    pub t_fix: f64,
+}
+
+impl SingleFieldCalibration {
+    pub fn new(beta: f64, t_fix: f64) -> Self {
+        Self { beta, t_fix }
+    }
+}
+
+/// The ways a CCS conversion may fail
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum CCSConversionError {
+    #[error("Cannot convert ion mobility in {0} to a collisional cross section")]
+    UnsupportedUnit(Unit),
+    #[error("Converting drift times requires a single-field calibration")]
+    MissingCalibration,
+    #[error("No ion mobility measure was found")]
+    NoIonMobility,
+    #[error("An error occurred while accessing an array: {0}")]
+    ArrayRetrievalError(#[from] ArrayRetrievalError),
+}
+
+/// Converts ion mobility values to collisional cross sections in square angstroms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CCSCalculator {
+    /// The buffer gas
+    pub drift_gas: DriftGas,
+    /// The temperature of the buffer gas in Kelvin
+    pub temperature: f64,
+    /// The drift time calibration, required to convert drift times
+    pub calibration: Option<SingleFieldCalibration>,
+}
+
+impl Default for CCSCalculator {
+    fn default() -> Self {
+        Self {
+            drift_gas: DriftGas::Nitrogen,
+            temperature: 305.0,
+            calibration: None,
+        }
+    }
+}
+
+impl CCSCalculator {
+    pub fn new(drift_gas: DriftGas, temperature: f64) -> Self {
+        Self {
+            drift_gas,
+            temperature,
+            calibration: None,
+        }
+    }
+
+    pub fn with_calibration(mut self, calibration: SingleFieldCalibration) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// The reduced mass of an ion and a gas molecule in kilograms
+    fn reduced_mass(&self, mz: f64, charge: i32) -> f64 {
+        let ion_mass = mz * charge.unsigned_abs() as f64;
+        let gas_mass = self.drift_gas.mass();
+        ion_mass * gas_mass / (ion_mass + gas_mass) * DALTON
+    }
+
+    /// The Mason–Schamp factor relating CCS in Å² to 1/K0 in V·s/cm²
+    fn mason_schamp_factor(&self, mz: f64, charge: i32) -> f64 {
+        let mu = self.reduced_mass(mz, charge);
+        // The 1e4 converts 1/K0 from V·s/cm² to V·s/m², the 1e20 converts m² to Å²
+        3.0 * charge.unsigned_abs() as f64 * ELEMENTARY_CHARGE / (16.0 * LOSCHMIDT)
+            * (2.0 * PI / (mu * BOLTZMANN * self.temperature)).sqrt()
+            * 1e4
+            * 1e20
+    }
+
+    /// Convert an inverse reduced mobility in V·s/cm² to a CCS in Å²
+    pub fn ccs_from_inverse_reduced_mobility(&self, inverse_k0: f64, mz: f64, charge: i32) -> f64 {
+        self.mason_schamp_factor(mz, charge) * inverse_k0
+    }
+
+    /// Convert a CCS in Å² to an inverse reduced mobility in V·s/cm²
+    pub fn inverse_reduced_mobility_from_ccs(&self, ccs: f64, mz: f64, charge: i32) -> f64 {
+        ccs / self.mason_schamp_factor(mz, charge)
+    }
+
+    /// Convert a drift time in milliseconds to a CCS in Å² using the single-field calibration
+    pub fn ccs_from_drift_time(
+        &self,
+        drift_time: f64,
+        mz: f64,
+        charge: i32,
+    ) -> Result<f64, CCSConversionError> {
+        let calibration = self
+            .calibration
+            .ok_or(CCSConversionError::MissingCalibration)?;
+        let ion_mass = mz * charge.unsigned_abs() as f64;
+        let gamma =
+            (ion_mass / (ion_mass + self.drift_gas.mass())).sqrt() / charge.unsigned_abs() as f64;
+        Ok((drift_time - calibration.t_fix) / (calibration.beta * gamma))
+    }
+
+    /// Convert an ion mobility `value` measured in `unit` to a CCS in Å²
+    pub fn ccs_from_ion_mobility(
+        &self,
+        value: f64,
+        unit: Unit,
+        mz: f64,
+        charge: i32,
+    ) -> Result<f64, CCSConversionError> {
+        match unit {
+            Unit::VoltSecondPerSquareCentimeter => {
+                Ok(self.ccs_from_inverse_reduced_mobility(value, mz, charge))
+            }
+            Unit::Millisecond => self.ccs_from_drift_time(value, mz, charge),
+            Unit::Second => self.ccs_from_drift_time(value * 1000.0, mz, charge),
+            _ => Err(CCSConversionError::UnsupportedUnit(unit)),
+        }
+    }
+
+    /// Compute the CCS of a [`SelectedIon`] from its ion mobility drift time or inverse reduced
+    /// ion mobility. Ions without a charge are assumed to be singly charged.
+    pub fn selected_ion_ccs(&self, ion: &SelectedIon) -> Result<f64, CCSConversionError> {
+        let charge = ion.charge.unwrap_or(1);
+        if let Some(param) = ion.get_param_by_curie(&INVERSE_REDUCED_ION_MOBILITY) {
+            let value = param
+                .to_f64()
+                .map_err(|_| CCSConversionError::NoIonMobility)?;
+            Ok(self.ccs_from_inverse_reduced_mobility(value, ion.mz, charge))
+        } else if let Some(param) = ion.get_param_by_curie(&ION_MOBILITY_DRIFT_TIME) {
+            let value = param
+                .to_f64()
+                .map_err(|_| CCSConversionError::NoIonMobility)?;
+            let unit = match param.unit {
+                Unit::Unknown => Unit::Millisecond,
+                unit => unit,
+            };
+            self.ccs_from_ion_mobility(value, unit, ion.mz, charge)
+        } else {
+            Err(CCSConversionError::NoIonMobility)
+        }
+    }
+
+    /// Compute the CCS of a [`SelectedIon`] and store it as a `collisional cross sectional area`
+    /// parameter, replacing any existing value.
+    pub fn annotate_selected_ion(&self, ion: &mut SelectedIon) -> Result<f64, CCSConversionError> {
+        let ccs = self.selected_ion_ccs(ion)?;
+        ion.set_collisional_cross_section(ccs);
+        Ok(ccs)
+    }
+
+    /// Add an [`ArrayType::CollisionalCrossSectionArray`] to each ion mobility point of `arrays`,
+    /// with one CCS per m/z value.
+    ///
+    /// The charge of each point is read from the [`ArrayType::ChargeArray`] if present, and
+    /// `default_charge` is used for points without one or with a charge of zero.
+    pub fn add_ccs_arrays(
+        &self,
+        arrays: &mut BinaryArrayMap3D,
+        default_charge: i32,
+    ) -> Result<(), CCSConversionError> {
+        let unit = arrays.ion_mobility_unit;
+        if !matches!(
+            unit,
+            Unit::VoltSecondPerSquareCentimeter | Unit::Millisecond | Unit::Second
+        ) {
+            return Err(CCSConversionError::UnsupportedUnit(unit));
+        }
+        for (im, layer) in arrays
+            .ion_mobility_dimension
+            .iter()
+            .copied()
+            .zip(arrays.arrays.iter_mut())
+        {
+            if layer.is_empty() {
+                continue;
+            }
+            let ccs_values = {
+                let mzs = layer.mzs()?;
+                let charges = layer.charges().ok();
+                let mut ccs_values = Vec::with_capacity(mzs.len());
+                for (i, mz) in mzs.iter().copied().enumerate() {
+                    let charge = charges
+                        .as_ref()
+                        .and_then(|c| c.get(i).copied())
+                        .filter(|z| *z != 0)
+                        .unwrap_or(default_charge);
+                    ccs_values.push(self.ccs_from_ion_mobility(im, unit, mz, charge)?);
+                }
+                ccs_values
+            };
+            let mut array = DataArray::from_name_and_type(
+                &ArrayType::CollisionalCrossSectionArray,
+                BinaryDataArrayType::Float64,
+            );
+            array.unit = Unit::SquareAngstrom;
+            array.extend(&ccs_values)?;
+            layer.add(array);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrum::bindata::{BinaryArrayMap, ByteArrayView};
+    use crate::spectrum::IonMobilityMeasure;
+
+    #[test]
+    fn test_mason_schamp() {
+        let calc = CCSCalculator::default();
+        // Agilent tune mix ion, reference CCS in N2 ~202.9 Å²
+        let ccs = calc.ccs_from_inverse_reduced_mobility(0.992, 622.0290, 1);
+        assert!((ccs - 203.1).abs() < 0.1, "{}", ccs);
+        let inverse_k0 = calc.inverse_reduced_mobility_from_ccs(ccs, 622.0290, 1);
+        assert!((inverse_k0 - 0.992).abs() < 1e-9);
+
+        let mut ion = SelectedIon {
+            mz: 622.0290,
+            charge: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            calc.selected_ion_ccs(&ion),
+            Err(CCSConversionError::NoIonMobility)
+        );
+        ion.add_param(
+            INVERSE_REDUCED_ION_MOBILITY
+                .controlled_vocabulary
+                .param_val(
+                    INVERSE_REDUCED_ION_MOBILITY.accession,
+                    "inverse reduced ion mobility drift time",
+                    0.992,
+                )
+                .with_unit_t(&Unit::VoltSecondPerSquareCentimeter),
+        );
+        calc.annotate_selected_ion(&mut ion).unwrap();
+        calc.annotate_selected_ion(&mut ion).unwrap();
+        assert_eq!(ion.params().len(), 2);
+        assert!((ion.collisional_cross_section().unwrap() - ccs).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_drift_time_calibration() {
+        let calc = CCSCalculator::default();
+        assert_eq!(
+            calc.ccs_from_ion_mobility(20.0, Unit::Millisecond, 622.0290, 1),
+            Err(CCSConversionError::MissingCalibration)
+        );
+        let calc = calc.with_calibration(SingleFieldCalibration::new(0.1, 1.0));
+        let ccs = calc
+            .ccs_from_ion_mobility(20.0, Unit::Millisecond, 622.0290, 1)
+            .unwrap();
+        let gamma = (622.0290f64 / (622.0290 + DriftGas::Nitrogen.mass())).sqrt();
+        assert!((ccs - 19.0 / (0.1 * gamma)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ccs_arrays() -> Result<(), CCSConversionError> {
+        let mut map = BinaryArrayMap::new();
+        let mut mzs =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mzs.extend(&[622.0290f64, 922.0098, 622.0290])?;
+        let mut intensities =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensities.extend(&[10.0f32, 20.0, 30.0])?;
+        let mut ims = DataArray::from_name_and_type(
+            &ArrayType::MeanIonMobilityArray,
+            BinaryDataArrayType::Float64,
+        );
+        ims.unit = Unit::VoltSecondPerSquareCentimeter;
+        ims.extend(&[0.992f64, 0.992, 1.3])?;
+        map.add(mzs);
+        map.add(intensities);
+        map.add(ims);
+        let mut stacked = BinaryArrayMap3D::stack(&map)?;
+        stacked.ion_mobility_unit = Unit::VoltSecondPerSquareCentimeter;
+
+        let calc = CCSCalculator::default();
+        calc.add_ccs_arrays(&mut stacked, 1)?;
+        let layer = stacked.get_ion_mobility(0.992).unwrap();
+        let ccs = layer.get(&ArrayType::CollisionalCrossSectionArray).unwrap();
+        assert_eq!(ccs.unit, Unit::SquareAngstrom);
+        let ccs = ccs.to_f64()?;
+        assert_eq!(ccs.len(), 2);
+        assert!((ccs[0] - calc.ccs_from_inverse_reduced_mobility(0.992, 622.0290, 1)).abs() < 1e-9);
+
+        stacked.ion_mobility_unit = Unit::Unknown;
+        assert_eq!(
+            calc.add_ccs_arrays(&mut stacked, 1),
+            Err(CCSConversionError::UnsupportedUnit(Unit::Unknown))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,544 @@
+//! Infer the charge state of precursor ions which were not given one.
+//!
+//! Many data dependent acquisition spectra do not report the charge of their precursor,
+//! forcing search engines to try several. A [`ChargeInferrer`] scores each charge state in
+//! its range using evidence from the MSn spectrum and, when it is available, the precursor
+//! MS1 spectrum:
+//!
+//! 1. If nearly all fragment signal lies below the precursor m/z, the precursor is singly
+//!    charged. Otherwise it is multiply charged.
+//! 2. Fragments above the singly charged m/z of the precursor at charge `z` cannot come from
+//!    a precursor of charge `z`, and penalize it.
+//! 3. Pairs of singly charged fragments whose m/z sum to the precursor's mass plus two protons
+//!    at charge `z`, like complementary b and y ions, support it.
+//! 4. The [`Deconvoluter`] score of the isotopic envelope at each charge state in the MS1
+//!    spectrum is averaged with the fragment evidence. Envelopes scoring below
+//!    [`Deconvoluter::minimum_score`] count as no support.
+//!
+//! Every charge state scoring at least [`ChargeInferrer::relative_score`] times the best is kept.
+//! A single candidate is assigned as the ion's [`SelectedIon::charge`], while several are
+//! recorded as ranked [`SelectedIon::possible_charges`], which MGF files write as
+//! `CHARGE=2+ and 3+`.
+use mzpeaks::peak::MZPoint;
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike, Tolerance};
+
+use super::deconvolution::Deconvoluter;
+use super::group::SpectrumGroup;
+use super::precursor_correction::PrecursorCorrector;
+use super::scan_properties::{ScanPolarity, SelectedIon};
+use super::spectrum_types::{SpectrumLike, SpectrumProcessingError};
+use crate::meta::DataProcessingAction;
+use crate::params::Param;
+use crate::utils::{mass_charge_ratio, neutral_mass};
+
+/// A candidate charge state and the evidence supporting it, between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChargeCandidate {
+    pub charge: i32,
+    pub score: f32,
+}
+
+impl ChargeCandidate {
+    pub fn new(charge: i32, score: f32) -> Self {
+        Self { charge, score }
+    }
+}
+
+/// Infer precursor charge states from fragment and precursor isotopic peaks. See the
+/// [module documentation](self) for a description of the algorithm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeInferrer {
+    /// The inclusive range of absolute charge states to consider
+    pub charge_range: (i32, i32),
+    /// The mass accuracy to match fragment peaks with
+    pub fragment_tolerance: Tolerance,
+    /// The fraction of fragment signal below the precursor m/z above which the precursor
+    /// is considered singly charged
+    pub singly_charged_fraction: f32,
+    /// The m/z distance around the precursor m/z in which fragment peaks are ignored, as they
+    /// are likely to be the unfragmented precursor and its isotopic peaks
+    pub precursor_exclusion: f64,
+    /// The fraction of the best candidate's score other candidates must reach to be kept
+    pub relative_score: f32,
+    /// The deconvoluter used to score the precursor's isotopic envelope in the MS1 spectrum
+    pub deconvoluter: Deconvoluter,
+    /// The m/z distance around the precursor m/z to search in the MS1 spectrum
+    pub ms1_half_width: f64,
+}
+
+impl Default for ChargeInferrer {
+    fn default() -> Self {
+        Self {
+            charge_range: (1, 4),
+            fragment_tolerance: Tolerance::Da(0.02),
+            singly_charged_fraction: 0.9,
+            precursor_exclusion: 3.0,
+            relative_score: 0.5,
+            deconvoluter: Deconvoluter::default(),
+            ms1_half_width: 4.0,
+        }
+    }
+}
+
+impl ChargeInferrer {
+    pub fn new(low: i32, high: i32) -> Self {
+        Self::default().with_charge_range(low, high)
+    }
+
+    pub fn with_charge_range(mut self, low: i32, high: i32) -> Self {
+        self.charge_range = (low, high);
+        self
+    }
+
+    pub fn with_fragment_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.fragment_tolerance = tolerance;
+        self
+    }
+
+    pub fn with_relative_score(mut self, relative_score: f32) -> Self {
+        self.relative_score = relative_score;
+        self
+    }
+
+    pub fn with_deconvoluter(mut self, deconvoluter: Deconvoluter) -> Self {
+        self.deconvoluter = deconvoluter;
+        self
+    }
+
+    /// The fraction of `total` fragment intensity in singly charged pairs summing to `pair_sum`
+    fn complement_fraction(&self, fragments: &[(f64, f32)], pair_sum: f64, total: f64) -> f64 {
+        let mut paired = vec![false; fragments.len()];
+        for (i, (mz, _)) in fragments.iter().enumerate() {
+            let target = pair_sum - mz;
+            if target < *mz {
+                break;
+            }
+            let (low, high) = self.fragment_tolerance.bounds(target);
+            let start = fragments.partition_point(|p| p.0 < low);
+            let partner = (start..fragments.len())
+                .take_while(|j| fragments[*j].0 <= high)
+                .filter(|j| *j != i)
+                .max_by(|a, b| fragments[*a].1.total_cmp(&fragments[*b].1));
+            if let Some(j) = partner {
+                paired[i] = true;
+                paired[j] = true;
+            }
+        }
+        let explained: f64 = fragments
+            .iter()
+            .zip(paired)
+            .filter(|(_, p)| *p)
+            .map(|((_, i), _)| *i as f64)
+            .sum();
+        explained / total
+    }
+
+    /// Score the charge states of a precursor at `mz` from its `fragments` and the peaks of its
+    /// MS1 spectrum around `mz` if they are available, returning the candidates ranked from most
+    /// to least likely. The candidates' charges carry the sign of `charge_sign`.
+    pub fn infer_from_peaks(
+        &self,
+        mz: f64,
+        fragments: &[MZPoint],
+        ms1_peaks: Option<&[MZPoint]>,
+        charge_sign: i32,
+    ) -> Vec<ChargeCandidate> {
+        let sign = if charge_sign < 0 { -1 } else { 1 };
+        let charges: Vec<i32> = (self.charge_range.0.max(1)..=self.charge_range.1).collect();
+
+        let mut fragments: Vec<(f64, f32)> = fragments
+            .iter()
+            .filter(|p| p.intensity > 0.0 && (p.mz - mz).abs() > self.precursor_exclusion)
+            .map(|p| (p.mz, p.intensity))
+            .collect();
+        fragments.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let total: f64 = fragments.iter().map(|(_, i)| *i as f64).sum();
+
+        let mut scores = vec![0.0; charges.len()];
+        if total > 0.0 {
+            // The fraction of fragment signal above the m/z a singly charged fragment may reach
+            let beyond = |limit: f64| -> f64 {
+                let (_, high) = self.fragment_tolerance.bounds(limit);
+                let start = fragments.partition_point(|p| p.0 <= high);
+                fragments[start..]
+                    .iter()
+                    .map(|(_, i)| *i as f64)
+                    .sum::<f64>()
+                    / total
+            };
+            let singly_charged = 1.0 - beyond(mz) >= self.singly_charged_fraction as f64;
+            for (score, z) in scores.iter_mut().zip(charges.iter().copied()) {
+                *score = match (z, singly_charged) {
+                    (1, singly_charged) => singly_charged as u8 as f64,
+                    (_, true) => 0.0,
+                    (_, false) => {
+                        let mass = neutral_mass(mz, z * sign);
+                        let possible = 1.0 - beyond(mass_charge_ratio(mass, sign));
+                        let pair_sum = 2.0 * mass_charge_ratio(mass, 2 * sign);
+                        possible * (1.0 + self.complement_fraction(&fragments, pair_sum, total))
+                            / 2.0
+                    }
+                };
+            }
+        }
+
+        if let Some(peaks) = ms1_peaks {
+            let isotopic: Vec<f64> = charges
+                .iter()
+                .map(|z| {
+                    self.deconvoluter
+                        .clone()
+                        .with_charge_range(*z, *z)
+                        .fit_peak(peaks, mz, sign)
+                        .map(|fit| fit.score as f64)
+                        .unwrap_or_default()
+                })
+                .collect();
+            if isotopic.iter().any(|s| *s > 0.0) {
+                for (score, s) in scores.iter_mut().zip(isotopic) {
+                    *score = if total > 0.0 { (*score + s) / 2.0 } else { s };
+                }
+            }
+        }
+
+        let best = scores.iter().copied().fold(0.0, f64::max);
+        if best <= 0.0 {
+            return Vec::new();
+        }
+        let mut candidates: Vec<ChargeCandidate> = charges
+            .iter()
+            .zip(scores)
+            .filter(|(_, s)| *s > 0.0 && *s >= best * self.relative_score as f64)
+            .map(|(z, s)| ChargeCandidate::new(z * sign, s as f32))
+            .collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates
+    }
+
+    fn infer_impl<C, D, S, T>(
+        &self,
+        ion: &SelectedIon,
+        spectrum: &S,
+        precursor_spectrum: Option<&T>,
+    ) -> Result<Vec<ChargeCandidate>, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        T: SpectrumLike<C, D>,
+    {
+        let fragments = PrecursorCorrector::peaks_between(spectrum, 0.0, f64::MAX)?;
+        let ms1_peaks = precursor_spectrum
+            .map(|s| {
+                PrecursorCorrector::peaks_between(
+                    s,
+                    ion.mz - self.ms1_half_width,
+                    ion.mz + self.ms1_half_width,
+                )
+            })
+            .transpose()?;
+        let charge_sign = match spectrum.polarity() {
+            ScanPolarity::Negative => -1,
+            _ => 1,
+        };
+        Ok(self.infer_from_peaks(ion.mz, &fragments, ms1_peaks.as_deref(), charge_sign))
+    }
+
+    /// Rank the charge states of `ion`, a selected ion of `spectrum`, from the fragment peaks
+    /// of `spectrum` alone
+    pub fn infer<C, D, S>(
+        &self,
+        ion: &SelectedIon,
+        spectrum: &S,
+    ) -> Result<Vec<ChargeCandidate>, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        self.infer_impl::<C, D, S, S>(ion, spectrum, None)
+    }
+
+    /// Rank the charge states of `ion`, a selected ion of `spectrum`, from the fragment peaks
+    /// of `spectrum` and the isotopic peaks of `ion` in `precursor_spectrum`
+    pub fn infer_with_precursor<C, D, S, T>(
+        &self,
+        ion: &SelectedIon,
+        spectrum: &S,
+        precursor_spectrum: &T,
+    ) -> Result<Vec<ChargeCandidate>, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        T: SpectrumLike<C, D>,
+    {
+        self.infer_impl(ion, spectrum, Some(precursor_spectrum))
+    }
+
+    /// Assign `candidates` to `ion`, setting its charge when there is only one candidate
+    /// and its possible charges otherwise
+    pub fn assign(ion: &mut SelectedIon, candidates: &[ChargeCandidate]) {
+        match candidates {
+            [] => {}
+            [candidate] => ion.charge = Some(candidate.charge),
+            _ => {
+                let charges: Vec<i32> = candidates.iter().map(|c| c.charge).collect();
+                ion.set_possible_charges(&charges);
+            }
+        }
+    }
+
+    fn annotate_impl<C, D, S, T>(
+        &self,
+        spectrum: &mut S,
+        precursor_spectrum: Option<&T>,
+    ) -> Result<usize, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        T: SpectrumLike<C, D>,
+    {
+        let mut inferred = Vec::new();
+        for (i, precursor) in spectrum.description().precursors.iter().enumerate() {
+            for (j, ion) in precursor.ions.iter().enumerate() {
+                if ion.charge.is_none() {
+                    let candidates = self.infer_impl(ion, spectrum, precursor_spectrum)?;
+                    if !candidates.is_empty() {
+                        inferred.push((i, j, candidates));
+                    }
+                }
+            }
+        }
+        let description = spectrum.description_mut();
+        for (i, j, candidates) in inferred.iter() {
+            Self::assign(&mut description.precursors[*i].ions[*j], candidates);
+        }
+        Ok(inferred.len())
+    }
+
+    /// Infer the charge of every selected ion of `spectrum` which lacks one from the fragment
+    /// peaks of `spectrum`, returning the number of ions assigned a charge or candidates
+    pub fn annotate_spectrum<C, D, S>(
+        &self,
+        spectrum: &mut S,
+    ) -> Result<usize, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        self.annotate_impl::<C, D, S, S>(spectrum, None)
+    }
+
+    /// Infer the charge of every selected ion of `spectrum` which lacks one from the fragment
+    /// peaks of `spectrum` and `precursor_spectrum`, returning the number of ions assigned
+    /// a charge or candidates
+    pub fn annotate_spectrum_with_precursor<C, D, S, T>(
+        &self,
+        spectrum: &mut S,
+        precursor_spectrum: &T,
+    ) -> Result<usize, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        T: SpectrumLike<C, D>,
+    {
+        self.annotate_impl(spectrum, Some(precursor_spectrum))
+    }
+
+    /// Infer the charges of the MSn spectra of `group` using the group's MS1 spectrum if
+    /// present, returning the number of ions assigned a charge or candidates
+    pub fn annotate_group<C, D, S>(
+        &self,
+        group: &mut SpectrumGroup<C, D, S>,
+    ) -> Result<usize, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        let mut annotated = 0;
+        for product in group.products.iter_mut() {
+            annotated += match group.precursor.as_ref() {
+                Some(precursor) => self.annotate_spectrum_with_precursor(product, precursor)?,
+                None => self.annotate_spectrum(product)?,
+            };
+        }
+        Ok(annotated)
+    }
+
+    /// Describe the inference as a list of parameters for a
+    /// [`ProcessingMethod`](crate::meta::ProcessingMethod)
+    pub fn as_params(&self) -> Vec<Param> {
+        vec![
+            DataProcessingAction::ChargeStateCalculation.into(),
+            Param::new_key_value(
+                "charge inference",
+                format!(
+                    "charges {}-{}, fragment tolerance {}",
+                    self.charge_range.0,
+                    self.charge_range.1,
+                    self.fragment_tolerance.to_string()
+                ),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+
+    use crate::io::mgf::{MGFReader, MGFWriter};
+    use crate::prelude::*;
+    use crate::spectrum::{
+        Averagine, CentroidSpectrum, MultiLayerSpectrum, Precursor, SignalContinuity,
+        SpectrumDescription,
+    };
+    use mzpeaks::CentroidPeak;
+
+    const PROTON: f64 = 1.00727646677;
+
+    fn make_msn(mz: f64, fragments: Vec<CentroidPeak>) -> MultiLayerSpectrum {
+        let mut precursor = Precursor::default();
+        precursor.add_ion(SelectedIon {
+            mz,
+            intensity: 1e4,
+            ..Default::default()
+        });
+        let mut descr = SpectrumDescription {
+            id: "scan=2".to_string(),
+            ms_level: 2,
+            signal_continuity: SignalContinuity::Centroid,
+            polarity: ScanPolarity::Positive,
+            ..Default::default()
+        };
+        descr.precursors = vec![precursor];
+        CentroidSpectrum::new(descr, fragments.into_iter().collect())
+            .into_spectrum()
+            .unwrap()
+    }
+
+    /// Fragment pairs whose m/z sum to the precursor mass plus two protons
+    fn complementary_fragments(mass: f64, mzs: &[f64]) -> Vec<CentroidPeak> {
+        mzs.iter()
+            .flat_map(|mz| {
+                [
+                    CentroidPeak::new(*mz, 1000.0, 0),
+                    CentroidPeak::new(mass + 2.0 * PROTON - mz, 1500.0, 0),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_infer_from_fragments() {
+        let inferrer = ChargeInferrer::new(1, 3);
+
+        // Every fragment below the precursor m/z
+        let spectrum = make_msn(
+            800.4,
+            vec![
+                CentroidPeak::new(200.1, 500.0, 0),
+                CentroidPeak::new(450.2, 800.0, 0),
+                CentroidPeak::new(650.3, 300.0, 0),
+                CentroidPeak::new(800.4, 5000.0, 0),
+            ],
+        );
+        let candidates = inferrer
+            .infer(spectrum.precursor().unwrap().ion(), &spectrum)
+            .unwrap();
+        assert_eq!(candidates, [ChargeCandidate::new(1, 1.0)]);
+
+        // Fragments above the precursor m/z without complementary pairs leave 2+ and 3+ tied
+        let spectrum = make_msn(
+            600.3,
+            vec![
+                CentroidPeak::new(300.1, 500.0, 0),
+                CentroidPeak::new(880.4, 800.0, 0),
+                CentroidPeak::new(1150.6, 300.0, 0),
+            ],
+        );
+        let candidates = inferrer
+            .infer(spectrum.precursor().unwrap().ion(), &spectrum)
+            .unwrap();
+        let charges: Vec<i32> = candidates.iter().map(|c| c.charge).collect();
+        assert_eq!(charges, [2, 3]);
+        assert_eq!(candidates[0].score, candidates[1].score);
+
+        // Complementary pairs single out the precursor's charge
+        let mass = neutral_mass(600.3, 3);
+        let spectrum = make_msn(
+            600.3,
+            complementary_fragments(mass, &[300.15, 420.2, 701.4]),
+        );
+        let candidates = inferrer
+            .infer(spectrum.precursor().unwrap().ion(), &spectrum)
+            .unwrap();
+        assert_eq!(candidates[0].charge, 3);
+        assert_eq!(candidates.len(), 1);
+    }
+
+    #[test]
+    fn test_infer_with_precursor_and_write_mgf() {
+        let mz = mass_charge_ratio(1500.7, 2);
+        let ms1_peaks: Vec<CentroidPeak> = Averagine::PEPTIDE
+            .isotopic_cluster(1500.7, 2)
+            .into_iter()
+            .map(|p| CentroidPeak::new(p.mz, p.intensity * 1e5, 0))
+            .collect();
+        let ms1 = CentroidSpectrum::new(
+            SpectrumDescription {
+                id: "scan=1".to_string(),
+                ms_level: 1,
+                signal_continuity: SignalContinuity::Centroid,
+                ..Default::default()
+            },
+            ms1_peaks.into_iter().collect(),
+        )
+        .into_spectrum()
+        .unwrap();
+
+        let fragments = vec![
+            CentroidPeak::new(300.1, 500.0, 0),
+            CentroidPeak::new(900.5, 800.0, 0),
+        ];
+        let inferrer = ChargeInferrer::new(2, 3);
+
+        let mut spectrum = make_msn(mz, fragments.clone());
+        assert_eq!(inferrer.annotate_spectrum(&mut spectrum).unwrap(), 1);
+        let ion = spectrum.precursor().unwrap().ion();
+        assert_eq!(ion.charge, None);
+        assert_eq!(ion.possible_charges(), [2, 3]);
+
+        let mut buffer = Vec::new();
+        {
+            let mut writer = MGFWriter::new(&mut buffer);
+            writer.write(&spectrum).unwrap();
+        }
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert!(text.contains("CHARGE=2+ and 3+\n"));
+        assert!(!text.contains("POSSIBLE_CHARGE_STATE"));
+        let mut reader = MGFReader::new(io::Cursor::new(buffer));
+        let read_back = reader.next().unwrap();
+        assert_eq!(
+            read_back.precursor().unwrap().ion().possible_charges(),
+            [2, 3]
+        );
+
+        let mut spectrum = make_msn(mz, fragments);
+        assert_eq!(
+            inferrer
+                .annotate_spectrum_with_precursor(&mut spectrum, &ms1)
+                .unwrap(),
+            1
+        );
+        let ion = spectrum.precursor().unwrap().ion();
+        assert_eq!(ion.charge, Some(2));
+        assert!(ion.possible_charges().is_empty());
+    }
+}
@@ -1,12 +1,25 @@
 use std::borrow::{Borrow, Cow};
 
-use super::bindata::{ArrayRetrievalError, ArrayType, BinaryArrayMap, ByteArrayView};
-use crate::params::{Param, ParamDescribed};
+use super::bindata::{
+    ArrayRetrievalError, ArrayType, BinaryArrayMap, BinaryDataArrayType, ByteArrayView, DataArray,
+};
+use super::spectrum_types::{CentroidPeakAdapting, DeconvolutedPeakAdapting, SpectrumLike};
+use crate::io::traits::SpectrumSource;
+use crate::params::{Param, ParamDescribed, Unit, CURIE};
 use crate::spectrum::scan_properties::{
-    ChromatogramDescription, ChromatogramType, Precursor, ScanPolarity,
+    ChromatogramDescription, ChromatogramType, IsolationWindow, IsolationWindowState, Precursor,
+    Product, SRMTransition, ScanPolarity,
 };
 use mzpeaks::coordinate::{Time, MZ};
 use mzpeaks::feature::{FeatureView, SimpleFeature, TimeInterval};
+use mzpeaks::Tolerance;
+
+pub mod processing;
+
+use processing::{
+    estimate_baseline, find_peaks, gaussian_smooth, integrate_trapezoid, savitzky_golay_smooth,
+};
+pub use processing::{ChromatogramPeak, PeakDetectionParameters};
 
 #[derive(Debug, Default, Clone)]
 pub struct Chromatogram {
@@ -80,7 +93,6 @@ impl TimeInterval<Time> for Chromatogram {
     }
 }
 
-
 /// Analog of [`SpectrumLike`](crate::spectrum::SpectrumLike) for chromatograms or
 /// other measures over time.
 pub trait ChromatogramLike {
@@ -101,6 +113,18 @@ pub trait ChromatogramLike {
         }
     }
 
+    /// Access the product information, if it exists.
+    #[inline]
+    fn product(&self) -> Option<&Product> {
+        self.description().product.as_ref()
+    }
+
+    /// The Q1/Q3 transition this chromatogram was recorded from, if it is known.
+    #[inline]
+    fn transition(&self) -> Option<SRMTransition> {
+        self.description().transition()
+    }
+
     #[inline]
     fn start_time(&self) -> Option<f64> {
         if let Ok(t) = self.time() {
@@ -194,6 +218,75 @@ impl Chromatogram {
     pub fn area(&self) -> f32 {
         TimeInterval::area(&self)
     }
+
+    /// Create a copy of this chromatogram with its intensity array replaced by `intensity`
+    fn with_intensity(&self, intensity: &[f32]) -> Result<Self, ArrayRetrievalError> {
+        let mut arrays = self.arrays.clone();
+        let template = arrays
+            .get(&ArrayType::IntensityArray)
+            .ok_or(ArrayRetrievalError::NotFound(ArrayType::IntensityArray))?;
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.unit = template.unit;
+        intensity_array.extend(intensity)?;
+        arrays.add(intensity_array);
+        Ok(Self::new(self.description.clone(), arrays))
+    }
+
+    /// Smooth the intensity array with a Savitzky-Golay filter, see [`savitzky_golay_smooth`]
+    pub fn savitzky_golay_smooth(
+        &self,
+        window_length: usize,
+        polynomial_order: usize,
+    ) -> Result<Self, ArrayRetrievalError> {
+        let intensity = self.intensity()?;
+        let smoothed = savitzky_golay_smooth(&intensity, window_length, polynomial_order);
+        self.with_intensity(&smoothed)
+    }
+
+    /// Smooth the intensity array with a Gaussian kernel of width `sigma` in minutes, see
+    /// [`gaussian_smooth`]
+    pub fn gaussian_smooth(&self, sigma: f64) -> Result<Self, ArrayRetrievalError> {
+        let time = self.time()?;
+        let intensity = self.intensity()?;
+        let smoothed = gaussian_smooth(&time, &intensity, sigma);
+        self.with_intensity(&smoothed)
+    }
+
+    /// Estimate the baseline of the intensity array, see [`estimate_baseline`]
+    pub fn baseline(&self, window_length: usize) -> Result<Vec<f32>, ArrayRetrievalError> {
+        let intensity = self.intensity()?;
+        Ok(estimate_baseline(&intensity, window_length))
+    }
+
+    /// Create a copy of this chromatogram with its estimated baseline subtracted
+    pub fn subtract_baseline(&self, window_length: usize) -> Result<Self, ArrayRetrievalError> {
+        let intensity = self.intensity()?;
+        let baseline = estimate_baseline(&intensity, window_length);
+        let corrected: Vec<f32> = intensity
+            .iter()
+            .zip(baseline.iter())
+            .map(|(i, b)| (i - b).max(0.0))
+            .collect();
+        self.with_intensity(&corrected)
+    }
+
+    /// Detect peaks in this chromatogram, see [`find_peaks`]
+    pub fn find_peaks(
+        &self,
+        params: &PeakDetectionParameters,
+    ) -> Result<Vec<ChromatogramPeak>, ArrayRetrievalError> {
+        let time = self.time()?;
+        let intensity = self.intensity()?;
+        Ok(find_peaks(&time, &intensity, params))
+    }
+
+    /// Integrate the intensity between `start_time` and `end_time` by the trapezoid rule
+    pub fn integrate(&self, start_time: f64, end_time: f64) -> Result<f32, ArrayRetrievalError> {
+        let time = self.time()?;
+        let intensity = self.intensity()?;
+        Ok(integrate_trapezoid(&time, &intensity, start_time, end_time))
+    }
 }
 
 impl ChromatogramLike for Chromatogram {
@@ -223,3 +316,308 @@ impl ParamDescribed for Chromatogram {
         self.description.params_mut()
     }
 }
+
+/// The "SRM spectrum" term marking a spectrum as a selected reaction monitoring scan
+const SRM_SPECTRUM: CURIE = curie!(MS:1000583);
+
+#[derive(Debug, Clone)]
+struct SRMTrace {
+    transition: SRMTransition,
+    polarity: ScanPolarity,
+    precursor: Precursor,
+    product: Product,
+    time: Vec<f64>,
+    intensity: Vec<f32>,
+}
+
+impl SRMTrace {
+    fn to_chromatogram(&self, index: usize) -> Chromatogram {
+        self.build_chromatogram(index, self.precursor.clone(), self.product.clone())
+    }
+
+    fn into_chromatogram(mut self, index: usize) -> Chromatogram {
+        let precursor = std::mem::take(&mut self.precursor);
+        let product = std::mem::take(&mut self.product);
+        self.build_chromatogram(index, precursor, product)
+    }
+
+    fn build_chromatogram(
+        &self,
+        index: usize,
+        precursor: Precursor,
+        product: Product,
+    ) -> Chromatogram {
+        let mut description = ChromatogramDescription {
+            id: format!("SRM SIC {}", self.transition),
+            index,
+            ms_level: Some(2),
+            polarity: self.polarity,
+            chromatogram_type: ChromatogramType::SelectedReactionMonitoringChromatogram,
+            precursor: Some(precursor),
+            product: Some(product),
+            ..Default::default()
+        };
+        description.set_transition(self.transition);
+
+        let mut arrays = BinaryArrayMap::default();
+        let mut time_array =
+            DataArray::from_name_and_type(&ArrayType::TimeArray, BinaryDataArrayType::Float64);
+        time_array.unit = Unit::Minute;
+        time_array.extend(&self.time).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.unit = Unit::DetectorCounts;
+        intensity_array.extend(&self.intensity).unwrap();
+        arrays.add(time_array);
+        arrays.add(intensity_array);
+        Chromatogram::new(description, arrays)
+    }
+}
+
+/**
+Collect selected reaction monitoring (SRM) spectra into one [`Chromatogram`] per
+[`SRMTransition`].
+
+Each SRM spectrum is expected to carry the Q1 m/z as its precursor and one data point per
+Q3 m/z, as most vendor converters write them. Transitions are matched using `tolerance`
+on both Q1 and Q3, and are reported in the order they were first observed.
+
+```rust
+use mzpeaks::Tolerance;
+use mzdata::MzMLReader;
+use mzdata::prelude::*;
+use mzdata::spectrum::{ChromatogramLike, SRMChromatogramCollector};
+
+let mut reader = MzMLReader::open_path("./test/data/three_test_scans.mzML").unwrap();
+let collector = SRMChromatogramCollector::from_source(&mut reader, Tolerance::Da(0.01));
+for chromatogram in collector.into_chromatograms() {
+    println!("{}", chromatogram.id());
+}
+```
+*/
+#[derive(Debug, Clone)]
+pub struct SRMChromatogramCollector {
+    tolerance: Tolerance,
+    traces: Vec<SRMTrace>,
+}
+
+impl Default for SRMChromatogramCollector {
+    fn default() -> Self {
+        Self::new(Tolerance::Da(0.01))
+    }
+}
+
+impl SRMChromatogramCollector {
+    pub fn new(tolerance: Tolerance) -> Self {
+        Self {
+            tolerance,
+            traces: Vec::new(),
+        }
+    }
+
+    /// Consume every remaining spectrum in `source`, collecting those that are SRM spectra.
+    pub fn from_source<
+        C: CentroidPeakAdapting,
+        D: DeconvolutedPeakAdapting,
+        S: SpectrumLike<C, D>,
+        R: SpectrumSource<C, D, S>,
+    >(
+        source: &mut R,
+        tolerance: Tolerance,
+    ) -> Self {
+        let mut this = Self::new(tolerance);
+        this.add_spectra(source);
+        this
+    }
+
+    /// Check whether `spectrum` is marked as an SRM spectrum and has a precursor
+    pub fn is_srm_spectrum<C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting>(
+        spectrum: &impl SpectrumLike<C, D>,
+    ) -> bool {
+        spectrum.precursor().is_some()
+            && spectrum
+                .description()
+                .get_param_by_curie(&SRM_SPECTRUM)
+                .is_some()
+    }
+
+    pub fn add_spectra<
+        C: CentroidPeakAdapting,
+        D: DeconvolutedPeakAdapting,
+        S: SpectrumLike<C, D>,
+        I: IntoIterator<Item = S>,
+    >(
+        &mut self,
+        spectra: I,
+    ) {
+        for spectrum in spectra {
+            self.add_spectrum(&spectrum);
+        }
+    }
+
+    /// Add each data point of `spectrum` to the trace of its transition, returning
+    /// `false` if `spectrum` is not an SRM spectrum.
+    pub fn add_spectrum<C: CentroidPeakAdapting, D: DeconvolutedPeakAdapting>(
+        &mut self,
+        spectrum: &impl SpectrumLike<C, D>,
+    ) -> bool {
+        if !Self::is_srm_spectrum(spectrum) {
+            return false;
+        }
+        let precursor = spectrum.precursor().unwrap();
+        let q1 = if precursor.isolation_window.target > 0.0 {
+            precursor.isolation_window.target as f64
+        } else if let Some(ion) = precursor.ions.first() {
+            ion.mz
+        } else {
+            return false;
+        };
+
+        let time = spectrum.start_time();
+        let polarity = spectrum.polarity();
+        let peaks = spectrum.peaks();
+        for point in peaks.iter() {
+            let transition = SRMTransition::new(q1, point.mz);
+            let trace = match self.find_trace(&transition) {
+                Some(i) => &mut self.traces[i],
+                None => {
+                    let mut precursor = precursor.clone();
+                    precursor.precursor_id = None;
+                    precursor.product_id = None;
+                    let product = Product::new(IsolationWindow::new(
+                        point.mz as f32,
+                        point.mz as f32,
+                        point.mz as f32,
+                        IsolationWindowState::Complete,
+                    ));
+                    self.traces.push(SRMTrace {
+                        transition,
+                        polarity,
+                        precursor,
+                        product,
+                        time: Vec::new(),
+                        intensity: Vec::new(),
+                    });
+                    self.traces.last_mut().unwrap()
+                }
+            };
+            trace.time.push(time);
+            trace.intensity.push(point.intensity);
+        }
+        true
+    }
+
+    fn find_trace(&self, transition: &SRMTransition) -> Option<usize> {
+        self.traces.iter().position(|t| {
+            self.tolerance.test(t.transition.q1, transition.q1)
+                && self.tolerance.test(t.transition.q3, transition.q3)
+        })
+    }
+
+    /// Iterate over the transitions observed so far
+    pub fn transitions(&self) -> impl Iterator<Item = SRMTransition> + '_ {
+        self.traces.iter().map(|t| t.transition)
+    }
+
+    pub fn len(&self) -> usize {
+        self.traces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.traces.is_empty()
+    }
+
+    /// Build the chromatogram for the transition matching `transition`, if one was observed
+    pub fn get_chromatogram(&self, transition: &SRMTransition) -> Option<Chromatogram> {
+        self.find_trace(transition)
+            .map(|i| self.traces[i].to_chromatogram(i))
+    }
+
+    pub fn to_chromatograms(&self) -> Vec<Chromatogram> {
+        self.traces
+            .iter()
+            .enumerate()
+            .map(|(i, t)| t.to_chromatogram(i))
+            .collect()
+    }
+
+    pub fn into_chromatograms(self) -> Vec<Chromatogram> {
+        self.traces
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| t.into_chromatogram(i))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::MemorySpectrumSource;
+    use crate::params::ControlledVocabulary;
+    use crate::spectrum::{PrecursorSelection, RawSpectrum, ScanEvent, SpectrumDescription};
+    use mzpeaks::{CentroidPeak, DeconvolutedPeak};
+
+    fn make_srm_spectrum(index: usize, time: f64, q1: f32, q3s: &[f64], scale: f32) -> RawSpectrum {
+        let mut description = SpectrumDescription {
+            id: format!("scan={}", index + 1),
+            index,
+            ms_level: 2,
+            polarity: ScanPolarity::Positive,
+            ..Default::default()
+        };
+        description.params.push(
+            ControlledVocabulary::MS
+                .const_param_ident("SRM spectrum", 1000583)
+                .into(),
+        );
+        description.acquisition.scans.push(ScanEvent {
+            start_time: time,
+            ..Default::default()
+        });
+        let mut precursor = Precursor::default();
+        *precursor.isolation_window_mut() = IsolationWindow::around(q1, 0.35);
+        description.precursors = vec![precursor];
+
+        let mut arrays = BinaryArrayMap::default();
+        let mut mzs =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mzs.extend(q3s).unwrap();
+        let mut ints =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        let intensities: Vec<f32> = (0..q3s.len()).map(|i| (i + 1) as f32 * scale).collect();
+        ints.extend(&intensities).unwrap();
+        arrays.add(mzs);
+        arrays.add(ints);
+        RawSpectrum::new(description, arrays)
+    }
+
+    #[test]
+    fn test_srm_collector() {
+        let spectra = vec![
+            make_srm_spectrum(0, 0.1, 500.25, &[300.1, 400.2], 1.0),
+            make_srm_spectrum(1, 0.2, 600.5, &[350.0], 1.0),
+            make_srm_spectrum(2, 0.3, 500.25, &[300.1, 400.2], 2.0),
+        ];
+        let mut source: MemorySpectrumSource<CentroidPeak, DeconvolutedPeak, RawSpectrum> =
+            MemorySpectrumSource::new(spectra.into());
+        let collector = SRMChromatogramCollector::from_source(&mut source, Tolerance::Da(0.01));
+        assert_eq!(collector.len(), 3);
+
+        let chroms = collector.to_chromatograms();
+        let chrom = &chroms[1];
+        assert!(chrom.description().is_srm());
+        let transition = chrom.transition().unwrap();
+        assert!((transition.q1 - 500.25).abs() < 1e-3);
+        assert!((transition.q3 - 400.2).abs() < 1e-3);
+        assert_eq!(chrom.time().unwrap().as_ref(), &[0.1, 0.3]);
+        assert_eq!(chrom.intensity().unwrap().as_ref(), &[2.0, 4.0]);
+
+        let chrom = collector
+            .get_chromatogram(&SRMTransition::new(600.5, 350.0))
+            .unwrap();
+        assert_eq!(chrom.time().unwrap().len(), 1);
+        let iw = &chrom.precursor().unwrap().isolation_window;
+        assert!((iw.lower_bound - 600.15).abs() < 1e-3);
+    }
+}
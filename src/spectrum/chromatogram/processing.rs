@@ -0,0 +1,524 @@
+//! Signal processing for chromatograms, smoothing, baseline estimation, peak detection
+//! and integration, operating on parallel time and intensity arrays.
+use std::cmp::Ordering;
+
+/// Reflect `index` back into the range `0..n` so that filters can read past the
+/// ends of the signal.
+fn reflect_index(index: isize, n: usize) -> usize {
+    let last = n as isize - 1;
+    if last <= 0 {
+        return 0;
+    }
+    let mut index = index;
+    while index < 0 || index > last {
+        if index < 0 {
+            index = -index;
+        }
+        if index > last {
+            index = 2 * last - index;
+        }
+    }
+    index as usize
+}
+
+/// Solve the linear system `a * x = b` in place by Gaussian elimination with partial pivoting
+fn solve_linear_system(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))
+            .unwrap();
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let diag = a[col][col];
+        for row in (col + 1)..n {
+            let factor = a[row][col] / diag;
+            let (upper, lower) = a.split_at_mut(row);
+            for (x, y) in lower[0][col..].iter_mut().zip(upper[col][col..].iter()) {
+                *x -= factor * y;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let acc: f64 = ((row + 1)..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - acc) / a[row][row];
+    }
+    x
+}
+
+/// Compute the Savitzky-Golay smoothing coefficients for a window of `2 * half_width + 1`
+/// points fitting a polynomial of degree `polynomial_order`.
+pub fn savitzky_golay_coefficients(half_width: usize, polynomial_order: usize) -> Vec<f64> {
+    let window_length = 2 * half_width + 1;
+    let order = polynomial_order.min(window_length - 1);
+    let offsets: Vec<f64> = (0..window_length)
+        .map(|i| i as f64 - half_width as f64)
+        .collect();
+
+    // The normal equations of the least squares fit, (J^T J) x = e_0
+    let mut normal = vec![vec![0.0; order + 1]; order + 1];
+    for (i, row) in normal.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = offsets.iter().map(|z| z.powi((i + j) as i32)).sum();
+        }
+    }
+    let mut unit = vec![0.0; order + 1];
+    unit[0] = 1.0;
+    let solution = solve_linear_system(normal, unit);
+
+    offsets
+        .iter()
+        .map(|z| {
+            solution
+                .iter()
+                .enumerate()
+                .map(|(j, s)| s * z.powi(j as i32))
+                .sum()
+        })
+        .collect()
+}
+
+/// Smooth `intensity` with a Savitzky-Golay filter of `window_length` points and polynomial
+/// degree `polynomial_order`, reflecting the signal at its ends.
+///
+/// The filter assumes the signal is sampled at a uniform rate. An even `window_length` is
+/// rounded up, and the window is shrunk to fit signals shorter than it.
+pub fn savitzky_golay_smooth(
+    intensity: &[f32],
+    window_length: usize,
+    polynomial_order: usize,
+) -> Vec<f32> {
+    let n = intensity.len();
+    if n < 3 || window_length < 3 {
+        return intensity.to_vec();
+    }
+    let mut half_width = window_length / 2;
+    if 2 * half_width + 1 > n {
+        half_width = (n - 1) / 2;
+    }
+    let coefficients = savitzky_golay_coefficients(half_width, polynomial_order);
+    let half_width = half_width as isize;
+    (0..n as isize)
+        .map(|i| {
+            let value: f64 = coefficients
+                .iter()
+                .zip(-half_width..=half_width)
+                .map(|(c, k)| c * intensity[reflect_index(i + k, n)] as f64)
+                .sum();
+            value as f32
+        })
+        .collect()
+}
+
+/// Smooth `intensity` with a Gaussian kernel whose width `sigma` is given in the units of
+/// `time`, so that irregularly sampled signals are weighted by their actual spacing.
+///
+/// The kernel is truncated at three standard deviations and re-normalized at each point. The
+/// result is as long as `intensity`, with any points beyond the end of `time` left unchanged.
+pub fn gaussian_smooth(time: &[f64], intensity: &[f32], sigma: f64) -> Vec<f32> {
+    let n = intensity.len().min(time.len());
+    if sigma <= 0.0 || n < 3 {
+        return intensity.to_vec();
+    }
+    let radius = 3.0 * sigma;
+    let denom = 2.0 * sigma * sigma;
+    let mut result = Vec::with_capacity(n);
+    let mut start = 0;
+    for i in 0..n {
+        let t = time[i];
+        while time[start] < t - radius {
+            start += 1;
+        }
+        let mut acc = 0.0;
+        let mut weight = 0.0;
+        for j in start..n {
+            let delta = time[j] - t;
+            if delta > radius {
+                break;
+            }
+            let w = (-(delta * delta) / denom).exp();
+            acc += w * intensity[j] as f64;
+            weight += w;
+        }
+        result.push((acc / weight) as f32);
+    }
+    result.extend_from_slice(&intensity[n..]);
+    result
+}
+
+fn moving_extremum(values: &[f32], half_width: usize, take_min: bool) -> Vec<f32> {
+    let n = values.len();
+    (0..n)
+        .map(|i| {
+            let lo = i.saturating_sub(half_width);
+            let hi = (i + half_width + 1).min(n);
+            let window = values[lo..hi].iter().copied();
+            if take_min {
+                window.fold(f32::INFINITY, f32::min)
+            } else {
+                window.fold(f32::NEG_INFINITY, f32::max)
+            }
+        })
+        .collect()
+}
+
+fn moving_average(values: &[f32], half_width: usize) -> Vec<f32> {
+    let n = values.len();
+    (0..n)
+        .map(|i| {
+            let lo = i.saturating_sub(half_width);
+            let hi = (i + half_width + 1).min(n);
+            values[lo..hi].iter().sum::<f32>() / (hi - lo) as f32
+        })
+        .collect()
+}
+
+/// Estimate the baseline of `intensity` by a morphological opening (a moving minimum
+/// followed by a moving maximum) over `window_length` points, smoothed by a moving
+/// average of the same width.
+///
+/// `window_length` should be wider than the widest peak in the signal, otherwise peaks
+/// will be absorbed into the baseline.
+pub fn estimate_baseline(intensity: &[f32], window_length: usize) -> Vec<f32> {
+    if intensity.is_empty() {
+        return Vec::new();
+    }
+    let half_width = (window_length / 2).max(1);
+    let eroded = moving_extremum(intensity, half_width, true);
+    let opened = moving_extremum(&eroded, half_width, false);
+    moving_average(&opened, half_width)
+        .into_iter()
+        .zip(intensity.iter())
+        .map(|(b, i)| b.min(*i))
+        .collect()
+}
+
+pub(crate) fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[mid]
+    } else {
+        (values[mid - 1] + values[mid]) / 2.0
+    }
+}
+
+/// Estimate the noise level of `intensity` above `baseline` from the scaled median absolute
+/// deviation of the baseline-subtracted signal, a robust estimate of its standard deviation.
+pub fn estimate_noise(intensity: &[f32], baseline: &[f32]) -> f32 {
+    let mut residuals: Vec<f32> = intensity
+        .iter()
+        .zip(baseline.iter())
+        .map(|(i, b)| i - b)
+        .collect();
+    let center = median(&mut residuals);
+    let mut deviations: Vec<f32> = residuals.iter().map(|r| (r - center).abs()).collect();
+    median(&mut deviations) * 1.4826
+}
+
+/// Integrate `intensity` over `time` between `start_time` and `end_time` by the trapezoid
+/// rule, linearly interpolating the signal at the integration bounds.
+pub fn integrate_trapezoid(time: &[f64], intensity: &[f32], start_time: f64, end_time: f64) -> f32 {
+    let n = time.len().min(intensity.len());
+    if n < 2 || end_time <= start_time {
+        return 0.0;
+    }
+    let interpolate = |i: usize, t: f64| -> f64 {
+        let (t0, t1) = (time[i], time[i + 1]);
+        let (y0, y1) = (intensity[i] as f64, intensity[i + 1] as f64);
+        if t1 == t0 {
+            y0
+        } else {
+            y0 + (y1 - y0) * (t - t0) / (t1 - t0)
+        }
+    };
+    let mut area = 0.0;
+    for i in 0..(n - 1) {
+        let (t0, t1) = (time[i], time[i + 1]);
+        if t1 <= start_time || t0 >= end_time {
+            continue;
+        }
+        let a = t0.max(start_time);
+        let b = t1.min(end_time);
+        area += (b - a) * (interpolate(i, a) + interpolate(i, b)) / 2.0;
+    }
+    area as f32
+}
+
+/// A peak detected in a chromatogram, see [`find_peaks`]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ChromatogramPeak {
+    /// The index of the first point of the peak
+    pub start_index: usize,
+    /// The index of the most intense point of the peak
+    pub apex_index: usize,
+    /// The index of the last point of the peak
+    pub end_index: usize,
+    pub start_time: f64,
+    pub apex_time: f64,
+    pub end_time: f64,
+    /// The intensity at the apex, including the baseline
+    pub apex_intensity: f32,
+    /// The full width at half of the baseline-subtracted apex height, in units of time
+    pub fwhm: f64,
+    /// The area of the baseline-subtracted signal between the peak boundaries
+    pub area: f32,
+    /// The baseline-subtracted apex height divided by the noise level
+    pub signal_to_noise: f32,
+}
+
+impl ChromatogramPeak {
+    pub fn contains_time(&self, time: f64) -> bool {
+        self.start_time <= time && time <= self.end_time
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.end_time - self.start_time
+    }
+}
+
+impl PartialOrd for ChromatogramPeak {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.apex_time.partial_cmp(&other.apex_time)
+    }
+}
+
+/// Parameters controlling [`find_peaks`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakDetectionParameters {
+    /// The minimum signal-to-noise ratio of a peak's apex
+    pub min_signal_to_noise: f32,
+    /// The minimum number of points between a peak's boundaries, inclusive
+    pub min_points: usize,
+    /// The number of points used to estimate the baseline, see [`estimate_baseline`]
+    pub baseline_window: usize,
+}
+
+impl Default for PeakDetectionParameters {
+    fn default() -> Self {
+        Self {
+            min_signal_to_noise: 3.0,
+            min_points: 3,
+            baseline_window: 31,
+        }
+    }
+}
+
+impl PeakDetectionParameters {
+    pub fn new(min_signal_to_noise: f32, min_points: usize, baseline_window: usize) -> Self {
+        Self {
+            min_signal_to_noise,
+            min_points,
+            baseline_window,
+        }
+    }
+}
+
+/// Interpolate the time at which the baseline-subtracted signal crosses `level` between
+/// points `i` and `j`
+fn crossing_time(time: &[f64], corrected: &[f32], i: usize, j: usize, level: f32) -> f64 {
+    let (y0, y1) = (corrected[i], corrected[j]);
+    if y1 == y0 {
+        time[i]
+    } else {
+        time[i] + (time[j] - time[i]) * ((level - y0) / (y1 - y0)) as f64
+    }
+}
+
+/// Detect peaks in `intensity` over `time`.
+///
+/// The baseline and noise level of the signal are estimated with [`estimate_baseline`] and
+/// [`estimate_noise`]. Each local maximum whose baseline-subtracted height passes the
+/// signal-to-noise threshold is extended in both directions until the signal returns to the
+/// baseline or starts rising again into a neighboring peak. Maxima already covered by a more
+/// intense peak are skipped. The peaks are returned in time order.
+///
+/// Smoothing the signal first, e.g. with [`savitzky_golay_smooth`], reduces the number of
+/// spurious maxima in noisy signals.
+pub fn find_peaks(
+    time: &[f64],
+    intensity: &[f32],
+    params: &PeakDetectionParameters,
+) -> Vec<ChromatogramPeak> {
+    let n = time.len().min(intensity.len());
+    if n < 3 {
+        return Vec::new();
+    }
+    let intensity = &intensity[..n];
+    let time = &time[..n];
+    let baseline = estimate_baseline(intensity, params.baseline_window);
+    let corrected: Vec<f32> = intensity
+        .iter()
+        .zip(baseline.iter())
+        .map(|(i, b)| (i - b).max(0.0))
+        .collect();
+    let noise = estimate_noise(intensity, &baseline);
+    let noise_floor = if noise > 0.0 { noise } else { f32::EPSILON };
+
+    let mut maxima: Vec<usize> = (0..n)
+        .filter(|i| {
+            let y = corrected[*i];
+            y > 0.0 && (*i == 0 || corrected[i - 1] <= y) && (*i == n - 1 || corrected[i + 1] < y)
+        })
+        .filter(|i| corrected[*i] / noise_floor >= params.min_signal_to_noise)
+        .collect();
+    maxima.sort_by(|a, b| corrected[*b].total_cmp(&corrected[*a]));
+
+    let mut peaks: Vec<ChromatogramPeak> = Vec::new();
+    for apex in maxima {
+        if peaks
+            .iter()
+            .any(|p| p.start_index <= apex && apex <= p.end_index)
+        {
+            continue;
+        }
+
+        let mut start = apex;
+        while start > 0 && corrected[start - 1] > 0.0 && corrected[start - 1] <= corrected[start] {
+            start -= 1;
+        }
+        if start > 0 && corrected[start - 1] == 0.0 {
+            start -= 1;
+        }
+        let mut end = apex;
+        while end < n - 1 && corrected[end + 1] > 0.0 && corrected[end + 1] <= corrected[end] {
+            end += 1;
+        }
+        if end < n - 1 && corrected[end + 1] == 0.0 {
+            end += 1;
+        }
+
+        // Do not extend into a peak that was already claimed
+        for p in peaks.iter() {
+            if p.end_index >= start && p.end_index < apex {
+                start = p.end_index;
+            }
+            if p.start_index <= end && p.start_index > apex {
+                end = p.start_index;
+            }
+        }
+
+        if end - start + 1 < params.min_points {
+            continue;
+        }
+
+        let height = corrected[apex];
+        let half = height / 2.0;
+        let mut left = apex;
+        while left > start && corrected[left] > half {
+            left -= 1;
+        }
+        let left_time = if corrected[left] <= half && left < apex {
+            crossing_time(time, &corrected, left, left + 1, half)
+        } else {
+            time[start]
+        };
+        let mut right = apex;
+        while right < end && corrected[right] > half {
+            right += 1;
+        }
+        let right_time = if corrected[right] <= half && right > apex {
+            crossing_time(time, &corrected, right - 1, right, half)
+        } else {
+            time[end]
+        };
+
+        peaks.push(ChromatogramPeak {
+            start_index: start,
+            apex_index: apex,
+            end_index: end,
+            start_time: time[start],
+            apex_time: time[apex],
+            end_time: time[end],
+            apex_intensity: intensity[apex],
+            fwhm: right_time - left_time,
+            area: integrate_trapezoid(time, &corrected, time[start], time[end]),
+            signal_to_noise: height / noise_floor,
+        });
+    }
+    peaks.sort_by(|a, b| a.apex_time.total_cmp(&b.apex_time));
+    peaks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gaussian_signal(n: usize, center: f64, width: f64, height: f32) -> (Vec<f64>, Vec<f32>) {
+        let time: Vec<f64> = (0..n).map(|i| i as f64 * 0.1).collect();
+        let intensity = time
+            .iter()
+            .map(|t| {
+                let z = (t - center) / width;
+                height * (-0.5 * z * z).exp() as f32 + 10.0
+            })
+            .collect();
+        (time, intensity)
+    }
+
+    #[test]
+    fn test_savitzky_golay() {
+        let coefs = savitzky_golay_coefficients(2, 2);
+        let expected = [-3.0, 12.0, 17.0, 12.0, -3.0];
+        for (c, e) in coefs.iter().zip(expected) {
+            assert!((c - e / 35.0).abs() < 1e-9, "{c} != {}", e / 35.0);
+        }
+
+        // A quadratic is preserved exactly away from the edges
+        let signal: Vec<f32> = (0..20).map(|i| (i * i) as f32).collect();
+        let smoothed = savitzky_golay_smooth(&signal, 5, 2);
+        for (a, b) in signal.iter().zip(smoothed.iter()).skip(2).take(16) {
+            assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_gaussian_smooth() {
+        let time: Vec<f64> = (0..50).map(|i| i as f64 * 0.1).collect();
+        let flat = vec![5.0f32; 50];
+        let smoothed = gaussian_smooth(&time, &flat, 0.2);
+        assert!(smoothed.iter().all(|v| (v - 5.0).abs() < 1e-4));
+
+        let smoothed = gaussian_smooth(&time[..40], &flat, 0.2);
+        assert_eq!(smoothed.len(), flat.len());
+    }
+
+    #[test]
+    fn test_integrate() {
+        let time = [0.0, 1.0, 2.0, 3.0];
+        let intensity = [0.0f32, 2.0, 2.0, 0.0];
+        assert_eq!(integrate_trapezoid(&time, &intensity, 0.0, 3.0), 4.0);
+        assert_eq!(integrate_trapezoid(&time, &intensity, 0.5, 1.5), 1.75);
+    }
+
+    #[test]
+    fn test_find_peaks() {
+        let (time, mut intensity) = gaussian_signal(200, 10.0, 0.3, 1000.0);
+        let (_, second) = gaussian_signal(200, 15.0, 0.3, 500.0);
+        intensity
+            .iter_mut()
+            .zip(second)
+            .for_each(|(a, b)| *a += b - 10.0);
+        let baseline = estimate_baseline(&intensity, 51);
+        assert!((baseline[0] - 10.0).abs() < 1.0);
+
+        let peaks = find_peaks(&time, &intensity, &PeakDetectionParameters::new(3.0, 3, 51));
+        assert_eq!(peaks.len(), 2);
+        let peak = &peaks[0];
+        assert!((peak.apex_time - 10.0).abs() < 1e-6);
+        let expected_fwhm = 2.0 * (2.0f64 * 2.0f64.ln()).sqrt() * 0.3;
+        assert!((peak.fwhm - expected_fwhm).abs() < 0.02, "{}", peak.fwhm);
+        let expected_area = 1000.0 * 0.3 * (2.0 * std::f64::consts::PI).sqrt();
+        assert!(
+            (peak.area as f64 - expected_area).abs() / expected_area < 0.02,
+            "{}",
+            peak.area
+        );
+        assert!((peaks[1].apex_time - 15.0).abs() < 1e-6);
+    }
+}
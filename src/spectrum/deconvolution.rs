@@ -0,0 +1,786 @@
+//! Deisotoping and charge state deconvolution of centroided spectra.
+//!
+//! A [`Deconvoluter`] groups centroided peaks into isotopic envelopes by fitting them against
+//! theoretical isotopic patterns derived from an [`Averagine`] model, the average elemental
+//! composition per unit mass of a class of molecules. Each accepted [`IsotopicFit`] describes
+//! the envelope's charge, monoisotopic neutral mass and the experimental peaks it explains,
+//! and may be converted into a [`DeconvolutedPeak`].
+//!
+//! This is a greedy algorithm: peaks are visited from most to least intense, every charge
+//! state and isotopic position for the peak is tried, and the best scoring fit claims its
+//! peaks so that they cannot be used by later fits. It does not require `mzsignal`, but it
+//! is not a substitute for a dedicated deconvolution tool on complex, overlapping spectra.
+//!
+//! ```
+//! use mzdata::spectrum::{Averagine, Deconvoluter};
+//! use mzpeaks::{CentroidPeak, Tolerance};
+//!
+//! let averagine = Averagine::PEPTIDE;
+//! let peaks: Vec<CentroidPeak> = averagine
+//!     .isotopic_cluster(1500.0, 2)
+//!     .into_iter()
+//!     .map(|p| CentroidPeak::new(p.mz, p.intensity * 1e4, 0))
+//!     .collect();
+//!
+//! let deconvoluter = Deconvoluter::new(Tolerance::PPM(10.0));
+//! let fits = deconvoluter.fit_envelopes(&peaks, 1);
+//! assert_eq!(fits.len(), 1);
+//! assert_eq!(fits[0].charge, 2);
+//! assert!((fits[0].neutral_mass - 1500.0).abs() < 1e-3);
+//! ```
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use log::warn;
+use mzpeaks::peak::MZPoint;
+use mzpeaks::prelude::*;
+use mzpeaks::{
+    CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak, MassPeakSetType,
+    Tolerance, MZ,
+};
+
+use super::scan_properties::{ScanPolarity, SignalContinuity};
+use super::spectrum_types::{
+    MultiLayerSpectrum, SpectrumConversionError, SpectrumLike, SpectrumProcessingError,
+};
+use super::transforms::AdaptorMetadata;
+use crate::meta::{DataProcessingAction, MSDataFileMetadata};
+use crate::params::Param;
+use crate::utils::{mass_charge_ratio, neutral_mass};
+
+/// The approximate mass difference between successive isotopic peaks of a biomolecule
+const ISOTOPIC_SHIFT: f64 = 1.00235;
+
+/// The isotopes of an element as `(nominal mass offset, monoisotopic mass, abundance)`
+type Isotopes = &'static [(usize, f64, f64)];
+
+const CARBON: Isotopes = &[(0, 12.0, 0.9893), (1, 13.0033548378, 0.0107)];
+const HYDROGEN: Isotopes = &[(0, 1.00782503207, 0.999885), (1, 2.0141017778, 0.000115)];
+const NITROGEN: Isotopes = &[(0, 14.0030740048, 0.99636), (1, 15.0001088982, 0.00364)];
+const OXYGEN: Isotopes = &[
+    (0, 15.99491461956, 0.99757),
+    (1, 16.9991317, 0.00038),
+    (2, 17.999161, 0.00205),
+];
+const SULFUR: Isotopes = &[
+    (0, 31.972071, 0.9499),
+    (1, 32.97145876, 0.0075),
+    (2, 33.9678669, 0.0425),
+    (4, 35.96708076, 0.0001),
+];
+
+/// A discrete isotopic distribution, binned by nominal mass offset from the monoisotopic peak
+#[derive(Debug, Clone)]
+struct IsotopicDistribution {
+    probabilities: Vec<f64>,
+    /// The abundance-weighted mean mass of each bin
+    masses: Vec<f64>,
+}
+
+impl IsotopicDistribution {
+    fn unit() -> Self {
+        Self {
+            probabilities: vec![1.0],
+            masses: vec![0.0],
+        }
+    }
+
+    fn from_isotopes(isotopes: Isotopes) -> Self {
+        let size = isotopes
+            .iter()
+            .map(|(i, _, _)| *i)
+            .max()
+            .unwrap_or_default()
+            + 1;
+        let mut probabilities = vec![0.0; size];
+        let mut masses = vec![0.0; size];
+        for (offset, mass, abundance) in isotopes {
+            probabilities[*offset] = *abundance;
+            masses[*offset] = *mass;
+        }
+        Self {
+            probabilities,
+            masses,
+        }
+    }
+
+    fn convolve(&self, other: &Self, width: usize) -> Self {
+        let size = (self.probabilities.len() + other.probabilities.len() - 1).min(width);
+        let mut probabilities = vec![0.0; size];
+        let mut masses = vec![0.0; size];
+        for (i, (p1, m1)) in self
+            .probabilities
+            .iter()
+            .zip(self.masses.iter())
+            .enumerate()
+        {
+            for (j, (p2, m2)) in other
+                .probabilities
+                .iter()
+                .zip(other.masses.iter())
+                .enumerate()
+            {
+                if i + j >= size {
+                    break;
+                }
+                let p = p1 * p2;
+                probabilities[i + j] += p;
+                masses[i + j] += p * (m1 + m2);
+            }
+        }
+        for (p, m) in probabilities.iter().zip(masses.iter_mut()) {
+            if *p > 0.0 {
+                *m /= p;
+            }
+        }
+        Self {
+            probabilities,
+            masses,
+        }
+    }
+
+    fn power(&self, mut count: u64, width: usize) -> Self {
+        let mut result = Self::unit();
+        let mut base = self.clone();
+        while count > 0 {
+            if count & 1 == 1 {
+                result = result.convolve(&base, width);
+            }
+            count >>= 1;
+            if count > 0 {
+                base = base.convolve(&base, width);
+            }
+        }
+        result
+    }
+}
+
+/// An averagine model, the average elemental composition of a class of molecules per unit,
+/// used to approximate the isotopic pattern of a molecule of unknown composition from its mass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Averagine {
+    pub carbon: f64,
+    pub hydrogen: f64,
+    pub nitrogen: f64,
+    pub oxygen: f64,
+    pub sulfur: f64,
+}
+
+impl Averagine {
+    /// The peptide averagine of Senko et al. (1995)
+    pub const PEPTIDE: Self = Self::new(4.9384, 7.7583, 1.3577, 1.4773, 0.0417);
+    /// An N-glycan averagine
+    pub const GLYCAN: Self = Self::new(7.0, 11.8333, 0.5, 5.16666, 0.0);
+
+    pub const fn new(carbon: f64, hydrogen: f64, nitrogen: f64, oxygen: f64, sulfur: f64) -> Self {
+        Self {
+            carbon,
+            hydrogen,
+            nitrogen,
+            oxygen,
+            sulfur,
+        }
+    }
+
+    /// The monoisotopic mass of a single averagine unit
+    pub fn unit_mass(&self) -> f64 {
+        self.carbon * CARBON[0].1
+            + self.hydrogen * HYDROGEN[0].1
+            + self.nitrogen * NITROGEN[0].1
+            + self.oxygen * OXYGEN[0].1
+            + self.sulfur * SULFUR[0].1
+    }
+
+    /// Scale the model to `neutral_mass`, rounding to a whole number of atoms and making up
+    /// the remaining mass with hydrogen
+    fn composition(&self, neutral_mass: f64) -> [u64; 5] {
+        let units = neutral_mass / self.unit_mass();
+        let carbon = (self.carbon * units).round().max(0.0);
+        let nitrogen = (self.nitrogen * units).round().max(0.0);
+        let oxygen = (self.oxygen * units).round().max(0.0);
+        let sulfur = (self.sulfur * units).round().max(0.0);
+        let remainder = neutral_mass
+            - carbon * CARBON[0].1
+            - nitrogen * NITROGEN[0].1
+            - oxygen * OXYGEN[0].1
+            - sulfur * SULFUR[0].1;
+        let hydrogen = (remainder / HYDROGEN[0].1).round().max(0.0);
+        [
+            carbon as u64,
+            hydrogen as u64,
+            nitrogen as u64,
+            oxygen as u64,
+            sulfur as u64,
+        ]
+    }
+
+    /// The isotopic pattern of `neutral_mass` as `(mass offset from the monoisotopic peak,
+    /// relative abundance)`, truncated once `truncate_after` of the total abundance is covered
+    fn isotopic_shape(&self, neutral_mass: f64, truncate_after: f64) -> Vec<(f64, f64)> {
+        let width = 10 + (neutral_mass / 1000.0) as usize;
+        let counts = self.composition(neutral_mass);
+        let distribution = [CARBON, HYDROGEN, NITROGEN, OXYGEN, SULFUR]
+            .iter()
+            .zip(counts)
+            .fold(IsotopicDistribution::unit(), |acc, (isotopes, count)| {
+                acc.convolve(
+                    &IsotopicDistribution::from_isotopes(isotopes).power(count, width),
+                    width,
+                )
+            });
+
+        let total: f64 = distribution.probabilities.iter().sum();
+        let monoisotopic = distribution.masses[0];
+        let mut shape = Vec::new();
+        let mut cumulative = 0.0;
+        for (p, m) in distribution
+            .probabilities
+            .iter()
+            .zip(distribution.masses.iter())
+        {
+            let p = p / total;
+            shape.push((m - monoisotopic, p));
+            cumulative += p;
+            if cumulative >= truncate_after {
+                break;
+            }
+        }
+        shape.iter_mut().for_each(|(_, p)| *p /= cumulative);
+        shape
+    }
+
+    /// The theoretical isotopic cluster of a molecule with monoisotopic `neutral_mass`
+    /// observed at `charge`, with intensities summing to 1
+    pub fn isotopic_cluster(&self, neutral_mass: f64, charge: i32) -> Vec<MZPoint> {
+        self.isotopic_shape(neutral_mass, 0.95)
+            .into_iter()
+            .map(|(offset, p)| {
+                MZPoint::new(mass_charge_ratio(neutral_mass + offset, charge), p as f32)
+            })
+            .collect()
+    }
+}
+
+impl Default for Averagine {
+    fn default() -> Self {
+        Self::PEPTIDE
+    }
+}
+
+/// An isotopic envelope fit to experimental peaks
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsotopicFit {
+    /// The monoisotopic neutral mass of the envelope
+    pub neutral_mass: f64,
+    pub charge: i32,
+    /// The total intensity of the experimental peaks in the envelope
+    pub intensity: f32,
+    /// The goodness of fit, between 0 and 1
+    pub score: f32,
+    /// The experimental peaks matched to each theoretical isotopic peak. Isotopic peaks
+    /// without a match are given at their theoretical m/z with zero intensity.
+    pub envelope: Vec<MZPoint>,
+}
+
+impl IsotopicFit {
+    /// The monoisotopic m/z of the envelope
+    pub fn monoisotopic_mz(&self) -> f64 {
+        mass_charge_ratio(self.neutral_mass, self.charge)
+    }
+
+    /// Convert the fit into a deconvoluted peak type.
+    ///
+    /// Only the neutral mass, charge and intensity are kept. The [`IsotopicFit::envelope`] has
+    /// no place in a [`DeconvolutedPeak`], so it is dropped.
+    pub fn as_peak<D: From<DeconvolutedPeak>>(&self) -> D {
+        DeconvolutedPeak::new(self.neutral_mass, self.intensity, self.charge, 0).into()
+    }
+}
+
+impl From<&IsotopicFit> for DeconvolutedPeak {
+    fn from(value: &IsotopicFit) -> Self {
+        value.as_peak()
+    }
+}
+
+/// Deisotope and charge state deconvolute centroided peaks by fitting [`Averagine`] isotopic
+/// patterns. See the [module documentation](self) for a description of the algorithm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deconvoluter {
+    pub averagine: Averagine,
+    /// The mass accuracy to match theoretical isotopic peaks with
+    pub tolerance: Tolerance,
+    /// The inclusive range of absolute charge states to try
+    pub charge_range: (i32, i32),
+    /// The minimum score for a fit to be accepted
+    pub minimum_score: f32,
+    /// The minimum number of experimental peaks a fit must explain
+    pub minimum_peaks: usize,
+    /// The number of isotopic positions to the left of each peak to try as the
+    /// monoisotopic peak
+    pub max_isotope_offset: usize,
+    /// The fraction of the total isotopic abundance to include in theoretical patterns
+    pub truncate_after: f64,
+}
+
+impl Default for Deconvoluter {
+    fn default() -> Self {
+        Self {
+            averagine: Averagine::default(),
+            tolerance: Tolerance::PPM(10.0),
+            charge_range: (1, 8),
+            minimum_score: 0.5,
+            minimum_peaks: 2,
+            max_isotope_offset: 3,
+            truncate_after: 0.95,
+        }
+    }
+}
+
+impl Display for Deconvoluter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "averagine deconvolution (tolerance={}, charges={}-{}, minimum score={})",
+            self.tolerance.to_string(),
+            self.charge_range.0,
+            self.charge_range.1,
+            self.minimum_score
+        )
+    }
+}
+
+impl Deconvoluter {
+    pub fn new(tolerance: Tolerance) -> Self {
+        Self {
+            tolerance,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_averagine(mut self, averagine: Averagine) -> Self {
+        self.averagine = averagine;
+        self
+    }
+
+    pub fn with_charge_range(mut self, low: i32, high: i32) -> Self {
+        self.charge_range = (low.abs().min(high.abs()), low.abs().max(high.abs()));
+        self
+    }
+
+    pub fn with_minimum_score(mut self, minimum_score: f32) -> Self {
+        self.minimum_score = minimum_score;
+        self
+    }
+
+    pub fn with_minimum_peaks(mut self, minimum_peaks: usize) -> Self {
+        self.minimum_peaks = minimum_peaks;
+        self
+    }
+
+    /// Fit isotopic envelopes to `peaks`, returning them in order of neutral mass.
+    ///
+    /// `charge_sign` should be negative when the peaks were acquired in negative mode.
+    pub fn fit_envelopes<P: CoordinateLike<MZ> + IntensityMeasurement>(
+        &self,
+        peaks: &[P],
+        charge_sign: i32,
+    ) -> Vec<IsotopicFit> {
+        let points = Self::prepare_points(peaks);
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        order.sort_by(|a, b| points[*b].1.total_cmp(&points[*a].1));
+
+        let mut used = vec![false; points.len()];
+        let mut shapes = HashMap::new();
+        let mut fits = Vec::new();
+        for anchor in order {
+            if used[anchor] {
+                continue;
+            }
+            if let Some((fit, matched)) =
+                self.best_fit(&points, &used, anchor, charge_sign, &mut shapes)
+            {
+                for i in matched {
+                    used[i] = true;
+                }
+                fits.push(fit);
+            }
+        }
+        fits.sort_by(|a, b| a.neutral_mass.total_cmp(&b.neutral_mass));
+        fits
+    }
+
+    /// Fit the best isotopic envelope which includes the most intense peak matching `mz`,
+    /// if one scores at least [`Deconvoluter::minimum_score`].
+    ///
+    /// Unlike [`Deconvoluter::fit_envelopes`], no other envelopes are fit, so the peaks are
+    /// not claimed by more intense neighbouring envelopes first.
+    pub fn fit_peak<P: CoordinateLike<MZ> + IntensityMeasurement>(
+        &self,
+        peaks: &[P],
+        mz: f64,
+        charge_sign: i32,
+    ) -> Option<IsotopicFit> {
+        let points = Self::prepare_points(peaks);
+        let (low, high) = self.tolerance.bounds(mz);
+        let start = points.partition_point(|p| p.0 < low);
+        let anchor = (start..points.len())
+            .take_while(|j| points[*j].0 <= high)
+            .max_by(|a, b| points[*a].1.total_cmp(&points[*b].1))?;
+        let used = vec![false; points.len()];
+        self.best_fit(&points, &used, anchor, charge_sign, &mut HashMap::new())
+            .map(|(fit, _)| fit)
+    }
+
+    fn prepare_points<P: CoordinateLike<MZ> + IntensityMeasurement>(
+        peaks: &[P],
+    ) -> Vec<(f64, f32)> {
+        let mut points: Vec<(f64, f32)> = peaks
+            .iter()
+            .map(|p| (p.coordinate(), p.intensity()))
+            .filter(|(_, i)| *i > 0.0)
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points
+    }
+
+    /// Try every charge state and isotopic position for the peak at `anchor`, returning the
+    /// best scoring fit and the indices of the peaks it matched if it passes the threshold
+    fn best_fit(
+        &self,
+        points: &[(f64, f32)],
+        used: &[bool],
+        anchor: usize,
+        charge_sign: i32,
+        shapes: &mut HashMap<i64, Vec<(f64, f64)>>,
+    ) -> Option<(IsotopicFit, Vec<usize>)> {
+        let sign = if charge_sign < 0 { -1 } else { 1 };
+        let mut best: Option<(IsotopicFit, Vec<usize>)> = None;
+        for z in self.charge_range.0.max(1)..=self.charge_range.1 {
+            for position in 0..=self.max_isotope_offset {
+                let Some(candidate) =
+                    self.fit_candidate(points, used, anchor, z * sign, position, shapes)
+                else {
+                    continue;
+                };
+                let is_better = match &best {
+                    Some((b, _)) => candidate.0.score > b.score,
+                    None => true,
+                };
+                if is_better {
+                    best = Some(candidate);
+                }
+            }
+        }
+        best.filter(|(fit, _)| fit.score >= self.minimum_score)
+    }
+
+    /// Fit an envelope of charge `z` in which the peak at `anchor` is isotopic peak `position`
+    fn fit_candidate(
+        &self,
+        points: &[(f64, f32)],
+        used: &[bool],
+        anchor: usize,
+        z: i32,
+        position: usize,
+        shapes: &mut HashMap<i64, Vec<(f64, f64)>>,
+    ) -> Option<(IsotopicFit, Vec<usize>)> {
+        let anchor_mass = neutral_mass(points[anchor].0, z);
+        let estimate = anchor_mass - position as f64 * ISOTOPIC_SHIFT;
+        if estimate <= 0.0 {
+            return None;
+        }
+        let shape = shapes
+            .entry(estimate.round() as i64)
+            .or_insert_with(|| self.averagine.isotopic_shape(estimate, self.truncate_after));
+        if position >= shape.len() {
+            return None;
+        }
+        let monoisotopic_mass = anchor_mass - shape[position].0;
+
+        let mut envelope = Vec::with_capacity(shape.len());
+        let mut matched = Vec::with_capacity(shape.len());
+        for (i, (offset, _)) in shape.iter().enumerate() {
+            let mz = mass_charge_ratio(monoisotopic_mass + offset, z);
+            let hit = if i == position {
+                Some(anchor)
+            } else {
+                let (low, high) = self.tolerance.bounds(mz);
+                let start = points.partition_point(|p| p.0 < low);
+                (start..points.len())
+                    .take_while(|j| points[*j].0 <= high)
+                    .filter(|j| !used[*j])
+                    .max_by(|a, b| points[*a].1.total_cmp(&points[*b].1))
+            };
+            match hit {
+                Some(j) => {
+                    envelope.push(MZPoint::new(points[j].0, points[j].1));
+                    matched.push(j);
+                }
+                None => envelope.push(MZPoint::new(mz, 0.0)),
+            }
+        }
+        if matched.len() < self.minimum_peaks {
+            return None;
+        }
+
+        let (dot, norm_theoretical, norm_experimental) = shape.iter().zip(envelope.iter()).fold(
+            (0.0, 0.0, 0.0),
+            |(dot, nt, ne), ((_, t), e)| {
+                let e = e.intensity as f64;
+                (dot + t * e, nt + t * t, ne + e * e)
+            },
+        );
+        let cosine = dot / (norm_theoretical.sqrt() * norm_experimental.sqrt());
+
+        // Penalize unexplained signal within the envelope, which indicates the wrong charge state
+        let explained: f64 = matched.iter().map(|j| points[*j].1 as f64).sum();
+        let (low, _) = self.tolerance.bounds(envelope.first().unwrap().mz);
+        let (_, high) = self.tolerance.bounds(envelope.last().unwrap().mz);
+        let start = points.partition_point(|p| p.0 < low);
+        let total: f64 = (start..points.len())
+            .take_while(|j| points[*j].0 <= high)
+            .filter(|j| !used[*j])
+            .map(|j| points[j].1 as f64)
+            .sum();
+        let score = cosine * explained / total.max(explained);
+
+        // Estimate the monoisotopic mass from every matched peak
+        let (weighted_mass, weight) = shape
+            .iter()
+            .zip(envelope.iter())
+            .filter(|(_, e)| e.intensity > 0.0)
+            .fold((0.0, 0.0), |(acc, w), ((offset, _), e)| {
+                let i = e.intensity as f64;
+                (acc + (neutral_mass(e.mz, z) - offset) * i, w + i)
+            });
+
+        let fit = IsotopicFit {
+            neutral_mass: weighted_mass / weight,
+            charge: z,
+            intensity: explained as f32,
+            score: score as f32,
+            envelope,
+        };
+        Some((fit, matched))
+    }
+
+    /// Deconvolute `peaks` into a peak set of neutral masses
+    pub fn deconvolute<P: CoordinateLike<MZ> + IntensityMeasurement, D>(
+        &self,
+        peaks: &[P],
+        charge_sign: i32,
+    ) -> MassPeakSetType<D>
+    where
+        D: DeconvolutedCentroidLike + From<DeconvolutedPeak>,
+    {
+        MassPeakSetType::new(
+            self.fit_envelopes(peaks, charge_sign)
+                .iter()
+                .map(|fit| fit.as_peak())
+                .collect(),
+        )
+    }
+
+    /// Deconvolute the centroided peaks of `spectrum`, storing the result in
+    /// [`MultiLayerSpectrum::deconvoluted_peaks`] and returning the envelopes that were fit.
+    ///
+    /// Spectra acquired in negative mode are fit with negative charge states.
+    pub fn deconvolute_spectrum<C, D>(
+        &self,
+        spectrum: &mut MultiLayerSpectrum<C, D>,
+    ) -> Result<Vec<IsotopicFit>, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default + From<DeconvolutedPeak>,
+    {
+        if matches!(spectrum.signal_continuity(), SignalContinuity::Profile) {
+            return Err(SpectrumConversionError::NotCentroided.into());
+        }
+        let points: Vec<CentroidPeak> = spectrum
+            .peaks()
+            .iter()
+            .map(|p| CentroidPeak::new(p.mz, p.intensity, 0))
+            .collect();
+        let charge_sign = match spectrum.polarity() {
+            ScanPolarity::Negative => -1,
+            _ => 1,
+        };
+        let fits = self.fit_envelopes(&points, charge_sign);
+        spectrum.deconvoluted_peaks = Some(MassPeakSetType::new(
+            fits.iter().map(|fit| fit.as_peak()).collect(),
+        ));
+        Ok(fits)
+    }
+
+    /// Describe the deconvolution as a list of parameters for a
+    /// [`ProcessingMethod`](crate::meta::ProcessingMethod)
+    pub fn as_params(&self) -> Vec<Param> {
+        vec![
+            DataProcessingAction::Deisotoping.into(),
+            DataProcessingAction::ChargeDeconvolution.into(),
+            Param::new_key_value("deconvolution", self.to_string()),
+        ]
+    }
+}
+
+/// Adapt an iterator over centroided spectra by deconvoluting each spectrum with a
+/// [`Deconvoluter`], populating [`MultiLayerSpectrum::deconvoluted_peaks`].
+///
+/// The deconvolution is recorded in the adaptor's [`AdaptorMetadata`]. Spectra which cannot be
+/// deconvoluted, like profile spectra, are passed through unchanged.
+///
+/// The deconvoluted peaks are made with [`IsotopicFit::as_peak`], so the experimental peaks of
+/// each isotopic envelope are not retained. When they are needed, call
+/// [`Deconvoluter::deconvolute_spectrum`] directly, which returns the [`IsotopicFit`]s.
+#[derive(Debug)]
+pub struct DeconvolutingSpectrumSource<
+    R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default + From<DeconvolutedPeak> = DeconvolutedPeak,
+> {
+    source: R,
+    deconvoluter: Deconvoluter,
+    metadata: AdaptorMetadata,
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default + From<DeconvolutedPeak>,
+    > DeconvolutingSpectrumSource<R, C, D>
+{
+    pub fn new(source: R, deconvoluter: Deconvoluter) -> Self
+    where
+        R: MSDataFileMetadata,
+    {
+        let mut metadata = AdaptorMetadata::new(&source);
+        metadata.record_method("deconvolution", deconvoluter.as_params());
+        Self {
+            source,
+            deconvoluter,
+            metadata,
+        }
+    }
+
+    pub fn deconvoluter(&self) -> &Deconvoluter {
+        &self.deconvoluter
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default + From<DeconvolutedPeak>,
+    > MSDataFileMetadata for DeconvolutingSpectrumSource<R, C, D>
+{
+    crate::delegate_impl_metadata_trait!(metadata);
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default + From<DeconvolutedPeak>,
+    > Iterator for DeconvolutingSpectrumSource<R, C, D>
+{
+    type Item = MultiLayerSpectrum<C, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut spectrum = self.source.next()?;
+        if let Err(e) = self.deconvoluter.deconvolute_spectrum(&mut spectrum) {
+            warn!("Failed to deconvolute spectrum {}: {e}", spectrum.id());
+        }
+        Some(spectrum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrum::{CentroidSpectrum, SpectrumDescription};
+
+    fn envelope_peaks(averagine: &Averagine, mass: f64, z: i32, scale: f32) -> Vec<CentroidPeak> {
+        averagine
+            .isotopic_cluster(mass, z)
+            .into_iter()
+            .map(|p| CentroidPeak::new(p.mz, p.intensity * scale, 0))
+            .collect()
+    }
+
+    #[test]
+    fn test_isotopic_cluster() {
+        let cluster = Averagine::PEPTIDE.isotopic_cluster(1000.0, 1);
+        let total: f32 = cluster.iter().map(|p| p.intensity).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+        assert!((cluster[0].mz - 1001.00728).abs() < 1e-4);
+        assert!((cluster[1].mz - cluster[0].mz - 1.003).abs() < 2e-3);
+        assert!(cluster[0].intensity > cluster[1].intensity);
+
+        let cluster = Averagine::PEPTIDE.isotopic_cluster(10000.0, 10);
+        let base_peak = cluster
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.intensity.total_cmp(&b.1.intensity))
+            .unwrap();
+        assert!(base_peak.0 > 3);
+    }
+
+    #[test]
+    fn test_fit_envelopes() {
+        let averagine = Averagine::PEPTIDE;
+        let mut peaks = envelope_peaks(&averagine, 1500.7, 2, 1e5);
+        peaks.extend(envelope_peaks(&averagine, 2400.3, 3, 5e4));
+        peaks.extend(envelope_peaks(&averagine, 920.45, 1, 2e4));
+        peaks.push(CentroidPeak::new(402.17, 500.0, 0));
+
+        let deconvoluter = Deconvoluter::new(Tolerance::PPM(10.0));
+        let fits = deconvoluter.fit_envelopes(&peaks, 1);
+        assert_eq!(fits.len(), 3);
+        for (fit, (mass, z)) in fits.iter().zip([(920.45, 1), (1500.7, 2), (2400.3, 3)]) {
+            assert_eq!(fit.charge, z);
+            assert!((fit.neutral_mass - mass).abs() < 1e-3, "{:?}", fit);
+            assert!(fit.score > 0.95, "{:?}", fit);
+        }
+
+        let fits = deconvoluter.fit_envelopes(&envelope_peaks(&averagine, 1500.7, -2, 1e5), -1);
+        assert_eq!(fits.len(), 1);
+        assert_eq!(fits[0].charge, -2);
+        assert!((fits[0].neutral_mass - 1500.7).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_deconvolute_spectrum() {
+        let mut peaks = envelope_peaks(&Averagine::PEPTIDE, 1500.7, 2, 1e5);
+        peaks.extend(envelope_peaks(&Averagine::PEPTIDE, 950.2, 1, 1e4));
+        let mut descr = SpectrumDescription {
+            ms_level: 2,
+            polarity: ScanPolarity::Positive,
+            signal_continuity: SignalContinuity::Centroid,
+            ..Default::default()
+        };
+        descr.id = "test".to_string();
+        let mut spectrum: MultiLayerSpectrum =
+            CentroidSpectrum::new(descr, peaks.into_iter().collect())
+                .into_spectrum()
+                .unwrap();
+
+        let deconvoluter = Deconvoluter::default();
+        let fits = deconvoluter.deconvolute_spectrum(&mut spectrum).unwrap();
+        assert_eq!(fits.len(), 2);
+        let deconvoluted = spectrum.deconvoluted_peaks.as_ref().unwrap();
+        assert_eq!(deconvoluted.len(), 2);
+        assert_eq!(deconvoluted[1].charge, 2);
+        assert!((deconvoluted[1].neutral_mass - 1500.7).abs() < 1e-3);
+
+        spectrum.description.signal_continuity = SignalContinuity::Profile;
+        assert!(deconvoluter.deconvolute_spectrum(&mut spectrum).is_err());
+    }
+}
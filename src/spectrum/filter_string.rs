@@ -0,0 +1,578 @@
+//! Parse Thermo scan filter strings like `FTMS + p NSI Full ms2 712.37@hcd30.00 [100.00-1435.00]`
+//! into structured metadata.
+//!
+//! Files converted from Thermo RAW files often only carry the filter string, so [`FilterString::backfill`]
+//! can be used to recover precursor, activation and scan window information from it.
+use std::fmt::Display;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::meta::DissociationMethodTerm;
+use crate::params::{ParamDescribed, ParamValue};
+
+use super::scan_properties::{
+    IonMobilityMeasure, IsolationWindow, IsolationWindowState, Precursor, ScanEvent, ScanPolarity, ScanWindow,
+    SelectedIon, SignalContinuity, SpectrumDescription, FILTER_STRING,
+};
+
+/// The mass analyzer named at the start of a filter string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterMassAnalyzer {
+    /// Fourier transform (Orbitrap) mass analyzer
+    FTMS,
+    /// Ion trap mass analyzer
+    ITMS,
+    /// Triple quadrupole mass analyzer
+    TQMS,
+    /// Single quadrupole mass analyzer
+    SQMS,
+    /// Time-of-flight mass analyzer
+    TOFMS,
+    /// Magnetic sector mass analyzer
+    Sector,
+    /// Asymmetric track lossless (Astral) mass analyzer
+    ASTMS,
+}
+
+impl FilterMassAnalyzer {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "FTMS" => Some(Self::FTMS),
+            "ITMS" => Some(Self::ITMS),
+            "TQMS" => Some(Self::TQMS),
+            "SQMS" => Some(Self::SQMS),
+            "TOFMS" => Some(Self::TOFMS),
+            "SECTOR" => Some(Self::Sector),
+            "ASTMS" => Some(Self::ASTMS),
+            _ => None,
+        }
+    }
+}
+
+impl Display for FilterMassAnalyzer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::FTMS => "FTMS",
+            Self::ITMS => "ITMS",
+            Self::TQMS => "TQMS",
+            Self::SQMS => "SQMS",
+            Self::TOFMS => "TOFMS",
+            Self::Sector => "SECTOR",
+            Self::ASTMS => "ASTMS",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The kind of scan described by a filter string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterScanType {
+    #[default]
+    Full,
+    /// Selected ion monitoring
+    SIM,
+    /// Selected reaction monitoring
+    SRM,
+    /// Consecutive reaction monitoring
+    CRM,
+    Q1MS,
+    Q3MS,
+}
+
+impl FilterScanType {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "Full" => Some(Self::Full),
+            "SIM" => Some(Self::SIM),
+            "SRM" => Some(Self::SRM),
+            "CRM" => Some(Self::CRM),
+            "Q1MS" => Some(Self::Q1MS),
+            "Q3MS" => Some(Self::Q3MS),
+            _ => None,
+        }
+    }
+}
+
+/// A single activation step applied to a precursor, like `hcd30.00`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterActivation {
+    /// The lower-case abbreviation of the activation method, e.g. `hcd`, `cid` or `etd`
+    pub method: String,
+    /// The activation energy, or the reaction time for electron-based methods
+    pub energy: f32,
+}
+
+impl FilterActivation {
+    pub fn new(method: String, energy: f32) -> Self {
+        Self { method, energy }
+    }
+
+    /// The dissociation method term for this activation, if it is recognized. When
+    /// `supplemental` is `true`, collisional methods map to their supplemental variants.
+    pub fn dissociation_method(&self, supplemental: bool) -> Option<DissociationMethodTerm> {
+        let term = match (self.method.as_str(), supplemental) {
+            ("cid", false) => DissociationMethodTerm::CollisionInducedDissociation,
+            ("cid", true) => DissociationMethodTerm::SupplementalCollisionInducedDissociation,
+            ("hcd", false) => DissociationMethodTerm::BeamTypeCollisionInducedDissociation,
+            ("hcd", true) => {
+                DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation
+            }
+            ("etd", _) => DissociationMethodTerm::ElectronTransferDissociation,
+            ("ecd", _) => DissociationMethodTerm::ElectronCaptureDissociation,
+            ("netd", _) => DissociationMethodTerm::NegativeElectronTransferDissociation,
+            ("pqd", _) => DissociationMethodTerm::PulsedQDissociation,
+            ("mpd", _) => DissociationMethodTerm::InfraredMultiphotonDissociation,
+            ("uvpd", _) => DissociationMethodTerm::UltravioletPhotodissociation,
+            _ => return None,
+        };
+        Some(term)
+    }
+
+    /// Whether the energy describes a collision energy rather than a reaction time
+    pub fn is_collisional(&self) -> bool {
+        matches!(self.method.as_str(), "cid" | "hcd" | "pqd")
+    }
+}
+
+/// A precursor m/z and the activations applied to it, like `712.37@etd25.00@hcd20.00`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterPrecursor {
+    pub mz: f64,
+    pub activations: Vec<FilterActivation>,
+}
+
+impl FilterPrecursor {
+    pub fn new(mz: f64, activations: Vec<FilterActivation>) -> Self {
+        Self { mz, activations }
+    }
+
+    /// Whether this precursor was activated with more than one method, where
+    /// every step after the first is a supplemental activation
+    pub fn has_supplemental_activation(&self) -> bool {
+        self.activations.len() > 1
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum FilterStringParseError {
+    #[error("The filter string is empty")]
+    Empty,
+    #[error("Could not find an MS level in the filter string")]
+    MissingMSLevel,
+    #[error("Malformed MS level {0}")]
+    MalformedMSLevel(String),
+    #[error("Malformed precursor {0}")]
+    MalformedPrecursor(String),
+    #[error("Malformed scan range {0}")]
+    MalformedScanRange(String),
+    #[error("Malformed compensation voltage {0}")]
+    MalformedCompensationVoltage(String),
+}
+
+/**
+A structured representation of a Thermo scan filter string.
+
+```
+use mzdata::spectrum::{FilterString, FilterMassAnalyzer, ScanPolarity};
+
+let filter: FilterString = "FTMS + p NSI Full ms2 712.37@hcd30.00 [100.00-1435.00]".parse().unwrap();
+assert_eq!(filter.analyzer, Some(FilterMassAnalyzer::FTMS));
+assert_eq!(filter.polarity, ScanPolarity::Positive);
+assert_eq!(filter.ms_level, 2);
+assert_eq!(filter.precursors[0].mz, 712.37);
+assert_eq!(filter.scan_ranges, vec![(100.0, 1435.0)]);
+```
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FilterString {
+    pub analyzer: Option<FilterMassAnalyzer>,
+    pub polarity: ScanPolarity,
+    pub signal_continuity: SignalContinuity,
+    /// The ionization source abbreviation, e.g. `NSI` or `ESI`
+    pub ionization: Option<String>,
+    pub scan_type: FilterScanType,
+    pub ms_level: u8,
+    /// The precursors of each stage of MSn in order, or each multiplexed
+    /// precursor when `multiplex` is set
+    pub precursors: Vec<FilterPrecursor>,
+    /// The scanned m/z ranges, or the SIM/SRM windows
+    pub scan_ranges: Vec<(f64, f64)>,
+    /// The FAIMS compensation voltage, from `cv=`
+    pub compensation_voltage: Option<f64>,
+    /// Whether supplemental activation was enabled, from `sa`
+    pub supplemental_activation: bool,
+    /// Whether the scan was data dependent, from `d`
+    pub data_dependent: bool,
+    /// Whether synchronous precursor selection was used, from `sps`
+    pub sps: bool,
+    /// Whether multiple precursors were isolated together, from `msx`
+    pub multiplex: bool,
+    /// Any tokens that were not recognized
+    pub unrecognized: Vec<String>,
+}
+
+fn parse_precursor(token: &str) -> Result<FilterPrecursor, FilterStringParseError> {
+    let err = || FilterStringParseError::MalformedPrecursor(token.to_string());
+    let mut parts = token.split('@');
+    let mz: f64 = parts.next().ok_or_else(err)?.parse().map_err(|_| err())?;
+    let mut activations = Vec::new();
+    for part in parts {
+        let split = part
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(part.len());
+        let (method, energy) = part.split_at(split);
+        if method.is_empty() {
+            return Err(err());
+        }
+        let energy = if energy.is_empty() {
+            0.0
+        } else {
+            energy.parse().map_err(|_| err())?
+        };
+        activations.push(FilterActivation::new(method.to_lowercase(), energy));
+    }
+    Ok(FilterPrecursor::new(mz, activations))
+}
+
+fn parse_scan_ranges(text: &str) -> Result<Vec<(f64, f64)>, FilterStringParseError> {
+    let mut ranges = Vec::new();
+    for part in text.split(',') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        let err = || FilterStringParseError::MalformedScanRange(part.to_string());
+        let (lo, hi) = part.split_once('-').ok_or_else(err)?;
+        let lo: f64 = lo.trim().parse().map_err(|_| err())?;
+        let hi: f64 = hi.trim().parse().map_err(|_| err())?;
+        ranges.push((lo, hi));
+    }
+    Ok(ranges)
+}
+
+fn parse_ms_level(token: &str) -> Option<Result<u8, FilterStringParseError>> {
+    let rest = token.strip_prefix("ms")?;
+    if rest.is_empty() {
+        Some(Ok(1))
+    } else if rest.chars().all(|c| c.is_ascii_digit()) {
+        Some(
+            rest.parse()
+                .map_err(|_| FilterStringParseError::MalformedMSLevel(token.to_string())),
+        )
+    } else {
+        None
+    }
+}
+
+impl FromStr for FilterString {
+    type Err = FilterStringParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(FilterStringParseError::Empty);
+        }
+        let mut this = Self::default();
+
+        let (head, ranges) = match s.find('[') {
+            Some(i) => {
+                let tail = &s[i + 1..];
+                let end = tail.find(']').unwrap_or(tail.len());
+                (&s[..i], Some(&tail[..end]))
+            }
+            None => (s, None),
+        };
+        if let Some(ranges) = ranges {
+            this.scan_ranges = parse_scan_ranges(ranges)?;
+        }
+
+        let mut seen_ms_level = false;
+        for token in head.split_ascii_whitespace() {
+            if seen_ms_level {
+                this.precursors.push(parse_precursor(token)?);
+                continue;
+            }
+            if let Some(analyzer) = FilterMassAnalyzer::from_token(token) {
+                this.analyzer = Some(analyzer);
+            } else if let Some(scan_type) = FilterScanType::from_token(token) {
+                this.scan_type = scan_type;
+            } else if let Some(level) = parse_ms_level(token) {
+                this.ms_level = level?;
+                seen_ms_level = true;
+            } else if let Some(cv) = token.strip_prefix("cv=") {
+                this.compensation_voltage = Some(cv.parse().map_err(|_| {
+                    FilterStringParseError::MalformedCompensationVoltage(cv.to_string())
+                })?);
+            } else {
+                match token {
+                    "+" => this.polarity = ScanPolarity::Positive,
+                    "-" => this.polarity = ScanPolarity::Negative,
+                    "p" => this.signal_continuity = SignalContinuity::Profile,
+                    "c" => this.signal_continuity = SignalContinuity::Centroid,
+                    "d" => this.data_dependent = true,
+                    "sa" => this.supplemental_activation = true,
+                    "sps" => this.sps = true,
+                    "msx" => this.multiplex = true,
+                    "NSI" | "ESI" | "APCI" | "APPI" | "MALDI" | "EI" | "CI" | "FAB" | "GD"
+                    | "TSP" | "FD" => this.ionization = Some(token.to_string()),
+                    _ => this.unrecognized.push(token.to_string()),
+                }
+            }
+        }
+        if !seen_ms_level {
+            return Err(FilterStringParseError::MissingMSLevel);
+        }
+        Ok(this)
+    }
+}
+
+impl FilterString {
+    /// Find and parse the filter string of a spectrum, looking at its scan events
+    /// and then its own parameters.
+    pub fn from_description(
+        description: &SpectrumDescription,
+    ) -> Option<Result<Self, FilterStringParseError>> {
+        let text = description
+            .acquisition
+            .iter()
+            .find_map(|scan| scan.filter_string())
+            .or_else(|| {
+                description
+                    .get_param_by_curie(&FILTER_STRING)
+                    .map(|p| p.as_str())
+            })?;
+        Some(text.parse())
+    }
+
+    /// The precursors of the spectrum itself. For a multiplexed scan this is every
+    /// listed precursor, otherwise it is the precursor of the last stage.
+    pub fn spectrum_precursors(&self) -> &[FilterPrecursor] {
+        if self.multiplex || self.precursors.is_empty() {
+            &self.precursors
+        } else {
+            &self.precursors[self.precursors.len() - 1..]
+        }
+    }
+
+    fn fill_precursor(&self, fp: &FilterPrecursor, precursor: &mut Precursor) {
+        if precursor.ions.is_empty() {
+            precursor.ions.push(SelectedIon {
+                mz: fp.mz,
+                ..Default::default()
+            });
+        }
+        let iw = &mut precursor.isolation_window;
+        if iw.target == 0.0 && iw.is_empty() {
+            *iw = IsolationWindow::new(fp.mz as f32, 0.0, 0.0, IsolationWindowState::Unknown);
+        }
+        let activation = &mut precursor.activation;
+        if activation.methods().is_empty() {
+            for (i, act) in fp.activations.iter().enumerate() {
+                if let Some(term) = act.dissociation_method(i > 0) {
+                    activation.methods_mut().push(term);
+                }
+            }
+            // `sa` denotes supplemental collisional activation of an electron-based method
+            if self.supplemental_activation
+                && fp.activations.len() == 1
+                && !fp.activations[0].is_collisional()
+            {
+                activation
+                    .methods_mut()
+                    .push(DissociationMethodTerm::SupplementalCollisionInducedDissociation);
+            }
+        }
+        if activation.energy == 0.0 {
+            // The energy of a supplemental activation is not the primary collision energy
+            let primary = if fp.has_supplemental_activation() {
+                &fp.activations[..1]
+            } else {
+                &fp.activations[..]
+            };
+            if let Some(act) = primary.iter().find(|a| a.is_collisional()) {
+                activation.energy = act.energy;
+            }
+        }
+        if activation.reaction_time.is_none() {
+            if let Some(act) = fp.activations.iter().find(|a| !a.is_collisional()) {
+                activation.reaction_time = Some(act.energy);
+            }
+        }
+        if activation.supplemental_energy.is_none() && fp.has_supplemental_activation() {
+            if let Some(act) = fp.activations[1..].iter().find(|a| a.is_collisional()) {
+                activation.supplemental_energy = Some(act.energy);
+            }
+        }
+    }
+
+    /// Fill in any polarity, signal continuity, MS level, precursor, activation, scan window
+    /// and FAIMS compensation voltage information missing from `description`. Information already
+    /// present is left unchanged.
+    pub fn backfill(&self, description: &mut SpectrumDescription) {
+        if description.polarity == ScanPolarity::Unknown {
+            description.polarity = self.polarity;
+        }
+        if description.signal_continuity == SignalContinuity::Unknown {
+            description.signal_continuity = self.signal_continuity;
+        }
+        if description.ms_level == 0 {
+            description.ms_level = self.ms_level;
+        }
+
+        if self.ms_level > 1 {
+            let filter_precursors = self.spectrum_precursors();
+            for (i, fp) in filter_precursors.iter().enumerate() {
+                if description.precursors.len() <= i {
+                    description.precursors.push(Precursor::default());
+                }
+                self.fill_precursor(fp, &mut description.precursors[i]);
+            }
+        }
+
+        if description.acquisition.scans.is_empty() {
+            description.acquisition.scans.push(ScanEvent::default());
+        }
+        let scan = description.acquisition.first_scan_mut().unwrap();
+        if scan.scan_windows.is_empty() {
+            scan.scan_windows.extend(
+                self.scan_ranges
+                    .iter()
+                    .map(|(lo, hi)| ScanWindow::new(*lo as f32, *hi as f32)),
+            );
+        }
+        if let Some(cv) = self.compensation_voltage {
+            if scan.compensation_voltage().is_none() {
+                scan.set_compensation_voltage(cv);
+            }
+        }
+    }
+}
+
+impl SpectrumDescription {
+    /// Parse this spectrum's Thermo filter string, if it has one, and use it to fill in
+    /// missing metadata with [`FilterString::backfill`].
+    pub fn backfill_from_filter_string(
+        &mut self,
+    ) -> Option<Result<FilterString, FilterStringParseError>> {
+        let filter = match FilterString::from_description(self)? {
+            Ok(filter) => filter,
+            Err(e) => return Some(Err(e)),
+        };
+        filter.backfill(self);
+        Some(Ok(filter))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::params::ControlledVocabulary;
+
+    #[test]
+    fn test_parse_filter_strings() {
+        let filter: FilterString = "ITMS - c ESI d Full ms2 445.12@cid35.00 [110.00-2000.00]"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.analyzer, Some(FilterMassAnalyzer::ITMS));
+        assert_eq!(filter.polarity, ScanPolarity::Negative);
+        assert_eq!(filter.signal_continuity, SignalContinuity::Centroid);
+        assert_eq!(filter.ionization.as_deref(), Some("ESI"));
+        assert!(filter.data_dependent);
+        assert_eq!(
+            filter.precursors,
+            vec![FilterPrecursor::new(
+                445.12,
+                vec![FilterActivation::new("cid".into(), 35.0)]
+            )]
+        );
+
+        let filter: FilterString =
+            "FTMS + p NSI sps d Full ms3 712.37@cid35.00 487.23@hcd55.00 [110.00-500.00]"
+                .parse()
+                .unwrap();
+        assert_eq!(filter.ms_level, 3);
+        assert!(filter.sps);
+        assert_eq!(filter.precursors.len(), 2);
+        assert_eq!(filter.spectrum_precursors()[0].mz, 487.23);
+
+        let filter: FilterString = "FTMS + c NSI cv=-45.00 Full ms [350.0000-1500.0000]"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.ms_level, 1);
+        assert_eq!(filter.compensation_voltage, Some(-45.0));
+
+        let filter: FilterString =
+            "+ c ESI SRM ms2 500.300@cid25.00 [300.100-300.200, 400.100-400.200]"
+                .parse()
+                .unwrap();
+        assert_eq!(filter.scan_type, FilterScanType::SRM);
+        assert_eq!(filter.scan_ranges.len(), 2);
+
+        let filter: FilterString =
+            "FTMS + p NSI Full ms2 712.37@etd25.00@hcd20.00 [120.00-2000.00]"
+                .parse()
+                .unwrap();
+        assert!(filter.precursors[0].has_supplemental_activation());
+
+        assert!("FTMS + p NSI Full [100.00-200.00]"
+            .parse::<FilterString>()
+            .is_err());
+        assert!("FTMS + p NSI Full ms2 abc@hcd30.00"
+            .parse::<FilterString>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_backfill() {
+        let mut descr = SpectrumDescription::default();
+        let mut scan = ScanEvent::default();
+        scan.add_param(ControlledVocabulary::MS.param_val(
+            1000512,
+            "filter string",
+            "FTMS + p NSI cv=-60.00 Full ms2 712.37@etd25.00@hcd20.00 [120.00-2000.00]",
+        ));
+        descr.acquisition.scans.push(scan);
+
+        let filter = descr.backfill_from_filter_string().unwrap().unwrap();
+        assert_eq!(filter.ms_level, 2);
+        assert_eq!(descr.ms_level, 2);
+        assert_eq!(descr.polarity, ScanPolarity::Positive);
+        assert_eq!(descr.signal_continuity, SignalContinuity::Profile);
+        let prec = descr.precursors.first().unwrap();
+        assert_eq!(prec.ions[0].mz, 712.37);
+        assert_eq!(prec.isolation_window.target, 712.37f32);
+        assert_eq!(
+            prec.activation.methods(),
+            &[
+                DissociationMethodTerm::ElectronTransferDissociation,
+                DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation
+            ]
+        );
+        assert_eq!(prec.activation.energy, 0.0);
+        assert_eq!(prec.activation.reaction_time, Some(25.0));
+        assert_eq!(prec.activation.supplemental_energy, Some(20.0));
+        assert!(prec.activation.is_ethcd());
+        let scan = descr.acquisition.first_scan().unwrap();
+        assert_eq!(scan.scan_windows, vec![ScanWindow::new(120.0, 2000.0)]);
+        assert_eq!(
+            scan.compensation_voltage().unwrap(),
+            -60.0
+        );
+
+        // Existing information is not overwritten
+        let mut descr2 = descr.clone();
+        descr2.precursors[0].activation.energy = 25.0;
+        filter.backfill(&mut descr2);
+        assert_eq!(descr2.precursors[0].activation.energy, 25.0);
+        assert_eq!(descr2.precursors[0].activation.methods().len(), 2);
+
+        let filter: FilterString = "FTMS + p NSI Full ms2 712.37@hcd30.00 [120.00-2000.00]"
+            .parse()
+            .unwrap();
+        let mut descr3 = SpectrumDescription::default();
+        filter.backfill(&mut descr3);
+        assert_eq!(descr3.precursors[0].activation.energy, 30.0);
+        assert_eq!(descr3.precursors[0].activation.supplemental_energy, None);
+    }
+}
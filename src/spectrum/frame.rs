@@ -5,10 +5,11 @@ use mzpeaks::{
     coordinate::{IonMobility, Mass, MZ},
     feature::{ChargedFeature, Feature, FeatureLike},
     feature_map::FeatureMap,
+    Tolerance,
 };
 
 use super::{
-    bindata::{ArrayRetrievalError, BinaryArrayMap3D, BinaryCompressionType, BinaryDataArrayType, BuildArrayMap3DFrom}, Acquisition, ArrayType, BinaryArrayMap, CentroidPeakAdapting, DeconvolutedPeakAdapting, Precursor, ScanPolarity, SignalContinuity, SpectrumDescription
+    bindata::{ArrayRetrievalError, BinaryArrayMap3D, BinaryCompressionType, BinaryDataArrayType, BuildArrayMap3DFrom, BuildArrayMapFrom, BuildFromArrayMap}, Acquisition, ArrayType, BinaryArrayMap, CentroidPeakAdapting, DeconvolutedPeakAdapting, Precursor, ScanPolarity, SignalContinuity, SpectrumDescription
 };
 use super::{scan_properties::SCAN_TITLE, MultiLayerSpectrum};
 use crate::{prelude::*, RawSpectrum};
@@ -66,8 +67,9 @@ pub struct IonMobilityFrameDescription {
     /// A description of how the spectrum was acquired including time, scan windows, and more
     pub acquisition: Acquisition,
 
-    /// The parent ion or ions and their isolation and activation description
-    pub precursor: Option<Precursor>,
+    /// The parent ion or ions and their isolation and activation description. The first
+    /// entry is treated as the primary precursor.
+    pub precursors: Vec<Precursor>,
 }
 
 impl_param_described!(IonMobilityFrameDescription);
@@ -82,7 +84,7 @@ impl IonMobilityFrameDescription {
         signal_continuity: SignalContinuity,
         params: ParamList,
         acquisition: Acquisition,
-        precursor: Option<Precursor>,
+        precursors: Vec<Precursor>,
     ) -> Self {
         Self {
             id,
@@ -92,13 +94,31 @@ impl IonMobilityFrameDescription {
             signal_continuity,
             params,
             acquisition,
-            precursor,
+            precursors,
         }
     }
 
     pub fn title(&self) -> Option<Cow<'_, str>> {
         self.get_param_by_curie(&SCAN_TITLE).map(|p| p.as_str())
     }
+
+    /// The primary precursor of the spectrum, if any
+    #[deprecated(
+        since = "0.34.0",
+        note = "the `precursor` field became the `precursors` list, use `precursors.first()`"
+    )]
+    pub fn precursor(&self) -> Option<&Precursor> {
+        self.precursors.first()
+    }
+
+    /// Mutably access the primary precursor of the spectrum, if any
+    #[deprecated(
+        since = "0.34.0",
+        note = "the `precursor` field became the `precursors` list, use `precursors.first_mut()`"
+    )]
+    pub fn precursor_mut(&mut self) -> Option<&mut Precursor> {
+        self.precursors.first_mut()
+    }
 }
 
 impl From<SpectrumDescription> for IonMobilityFrameDescription {
@@ -111,7 +131,7 @@ impl From<SpectrumDescription> for IonMobilityFrameDescription {
             value.signal_continuity,
             value.params,
             value.acquisition,
-            value.precursor,
+            value.precursors,
         )
     }
 }
@@ -126,7 +146,7 @@ impl From<IonMobilityFrameDescription> for SpectrumDescription {
             value.signal_continuity,
             value.params,
             value.acquisition,
-            value.precursor,
+            value.precursors,
         )
     }
 }
@@ -150,37 +170,44 @@ pub trait IonMobilityFrameLike<
         &self.description().acquisition
     }
 
-    /// Access the precursor information, if it exists.
+    /// Access the primary precursor information, if it exists.
+    ///
+    /// When more than one precursor is present, this is the first one.
+    /// See [`precursors`](Self::precursors) for the complete list.
     #[inline]
     fn precursor(&self) -> Option<&Precursor> {
         let desc = self.description();
-        if let Some(precursor) = &desc.precursor {
-            Some(precursor)
-        } else {
-            None
-        }
+        desc.precursors.first()
+    }
+
+    /// Access all of the precursors of the spectrum, in the order they were listed
+    #[inline]
+    fn precursors(&self) -> &[Precursor] {
+        &self.description().precursors
     }
 
     /// Iterate over all precursors of the spectrum
     fn precursor_iter(&self) -> impl Iterator<Item = &Precursor> {
         let desc = self.description();
-        desc.precursor.iter()
+        desc.precursors.iter()
     }
 
-    /// Mutably access the precursor information, if it exists
+    /// Mutably access the primary precursor information, if it exists
     fn precursor_mut(&mut self) -> Option<&mut Precursor> {
         let desc = self.description_mut();
-        if let Some(precursor) = desc.precursor.as_mut() {
-            Some(precursor)
-        } else {
-            None
-        }
+        desc.precursors.first_mut()
     }
 
     /// Iterate over all precursors of the spectrum mutably
     fn precursor_iter_mut(&mut self) -> impl Iterator<Item = &mut Precursor> {
         let desc = self.description_mut();
-        desc.precursor.iter_mut()
+        desc.precursors.iter_mut()
+    }
+
+    /// Add another precursor to the spectrum. If there was no precursor
+    /// already, this becomes the primary precursor.
+    fn add_precursor(&mut self, precursor: Precursor) {
+        self.description_mut().precursors.push(precursor);
     }
 
     /// A shortcut method to retrieve the scan start time of a spectrum
@@ -262,6 +289,37 @@ impl<C: FeatureLike<MZ, IonMobility>, D: FeatureLike<Mass, IonMobility> + KnownC
             description,
         }
     }
+
+    /// Collapse the ion mobility dimension of this frame's [`BinaryArrayMap3D`] into a
+    /// [`MultiLayerSpectrum`] by summing the signal within `ion_mobility_range` on a common
+    /// m/z axis.
+    ///
+    /// See [`BinaryArrayMap3D::collapse`] for how `ion_mobility_range` and `mz_tolerance` are used.
+    ///
+    /// # Errors
+    /// If the frame does not have raw arrays, [`ArrayRetrievalError::NotFound`] is returned.
+    /// Otherwise, any error that occurs while decoding the arrays is returned.
+    pub fn collapse<
+        CP: CentroidPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+        DP: DeconvolutedPeakAdapting + BuildFromArrayMap + BuildArrayMapFrom,
+    >(
+        &self,
+        ion_mobility_range: Option<(f64, f64)>,
+        mz_tolerance: Option<Tolerance>,
+    ) -> Result<MultiLayerSpectrum<CP, DP>, ArrayRetrievalError> {
+        let arrays = self
+            .arrays
+            .as_ref()
+            .ok_or(ArrayRetrievalError::NotFound(ArrayType::IonMobilityArray))?
+            .collapse(ion_mobility_range, mz_tolerance)?;
+        let description: SpectrumDescription = self.description.clone().into();
+        Ok(MultiLayerSpectrum::new(
+            description,
+            Some(arrays),
+            None,
+            None,
+        ))
+    }
 }
 
 impl<C: FeatureLike<MZ, IonMobility>, D: FeatureLike<Mass, IonMobility> + KnownCharge>
@@ -529,4 +587,184 @@ mod test {
         });
         Ok(())
     }
+
+    #[test]
+    fn test_collapse_source() -> io::Result<()> {
+        use crate::io::{CollapsedIonMobilitySpectrumSource, MzMLReader, MzMLWriter};
+        use crate::spectrum::{BinaryDataArrayType, DataArray};
+        use mzpeaks::{CentroidPeak, DeconvolutedPeak};
+
+        let mut arrays = BinaryArrayMap::default();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array.extend(&[200.0f64, 300.0, 200.0, 400.0]).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array
+            .extend(&[10.0f32, 20.0, 30.0, 40.0])
+            .unwrap();
+        let mut im_array = DataArray::from_name_and_type(
+            &ArrayType::MeanIonMobilityArray,
+            BinaryDataArrayType::Float64,
+        );
+        im_array.extend(&[0.8f64, 0.8, 0.9, 1.0]).unwrap();
+        arrays.add(mz_array);
+        arrays.add(intensity_array);
+        arrays.add(im_array);
+        let descr = IonMobilityFrameDescription {
+            id: "frame=1".to_string(),
+            ms_level: 1,
+            ..Default::default()
+        };
+        let frame: MultiLayerIonMobilityFrame = MultiLayerIonMobilityFrame::new(
+            Some(BinaryArrayMap3D::stack(&arrays)?),
+            None,
+            None,
+            descr,
+        );
+
+        let spectrum: MultiLayerSpectrum = frame.collapse(Some((0.85, 0.95)), None)?;
+        assert_eq!(spectrum.id(), "frame=1");
+        assert_eq!(&*spectrum.arrays.as_ref().unwrap().mzs()?, &[200.0]);
+
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("frames.mzML");
+        let mut writer = MzMLWriter::new(std::fs::File::create(&path)?);
+        writer.write_frame(&frame)?;
+        writer.close()?;
+
+        let reader = MzMLReader::open_path(&path)?;
+        let frames: Generic3DIonMobilityFrameSource<_, _, _> =
+            Generic3DIonMobilityFrameSource::new(reader);
+        let mut source: CollapsedIonMobilitySpectrumSource<CentroidPeak, DeconvolutedPeak, _> =
+            CollapsedIonMobilitySpectrumSource::new(frames, None, None);
+
+        let dp = source.data_processings().first().unwrap();
+        let method = dp.methods.last().unwrap();
+        assert!(source
+            .softwares()
+            .iter()
+            .any(|sw| sw.id == method.software_reference));
+        assert!(method
+            .get_param_by_name("collapse ion mobility dimension")
+            .is_some());
+
+        let spectrum = source.get_spectrum_by_index(0).unwrap();
+        assert_eq!(
+            &*spectrum.arrays.as_ref().unwrap().mzs()?,
+            &[200.0, 300.0, 400.0]
+        );
+        assert_eq!(
+            &*spectrum.arrays.as_ref().unwrap().intensities()?,
+            &[40.0, 20.0, 40.0]
+        );
+        assert_eq!(source.count(), 1);
+        Ok(())
+    }
+
+    /// A minimal in-memory [`IonMobilityFrameSource`](crate::io::IonMobilityFrameSource)
+    #[derive(Default)]
+    struct FrameVec {
+        frames: Vec<MultiLayerIonMobilityFrame>,
+        position: usize,
+        index: crate::io::OffsetIndex,
+        file_description: crate::meta::FileDescription,
+        instrument_configurations:
+            std::collections::HashMap<u32, crate::meta::InstrumentConfiguration>,
+        softwares: Vec<crate::meta::Software>,
+        samples: Vec<crate::meta::Sample>,
+        data_processings: Vec<crate::meta::DataProcessing>,
+    }
+
+    impl MSDataFileMetadata for FrameVec {
+        crate::impl_metadata_trait!();
+    }
+
+    impl Iterator for FrameVec {
+        type Item = MultiLayerIonMobilityFrame;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let frame = self.frames.get(self.position).cloned();
+            self.position += 1;
+            frame
+        }
+    }
+
+    impl crate::io::IonMobilityFrameSource for FrameVec {
+        fn reset(&mut self) {
+            self.position = 0;
+        }
+
+        fn get_frame_by_id(&mut self, id: &str) -> Option<MultiLayerIonMobilityFrame> {
+            self.frames.iter().find(|f| f.id() == id).cloned()
+        }
+
+        fn get_frame_by_index(&mut self, index: usize) -> Option<MultiLayerIonMobilityFrame> {
+            self.frames.get(index).cloned()
+        }
+
+        fn get_index(&self) -> &crate::io::OffsetIndex {
+            &self.index
+        }
+
+        fn set_index(&mut self, index: crate::io::OffsetIndex) {
+            self.index = index;
+        }
+    }
+
+    #[test]
+    fn test_collapse_source_skips_failures() -> io::Result<()> {
+        use crate::io::CollapsedIonMobilitySpectrumSource;
+        use crate::spectrum::{BinaryDataArrayType, DataArray};
+        use mzpeaks::{CentroidPeak, DeconvolutedPeak};
+
+        let mut arrays = BinaryArrayMap::default();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array.extend(&[200.0f64, 300.0]).unwrap();
+        let mut intensity_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        intensity_array.extend(&[10.0f32, 20.0]).unwrap();
+        let mut im_array = DataArray::from_name_and_type(
+            &ArrayType::MeanIonMobilityArray,
+            BinaryDataArrayType::Float64,
+        );
+        im_array.extend(&[0.8f64, 0.9]).unwrap();
+        arrays.add(mz_array);
+        arrays.add(intensity_array);
+        arrays.add(im_array);
+
+        let empty = MultiLayerIonMobilityFrame::new(
+            None,
+            None,
+            None,
+            IonMobilityFrameDescription {
+                id: "frame=1".to_string(),
+                ms_level: 1,
+                ..Default::default()
+            },
+        );
+        let valid = MultiLayerIonMobilityFrame::new(
+            Some(BinaryArrayMap3D::stack(&arrays)?),
+            None,
+            None,
+            IonMobilityFrameDescription {
+                id: "frame=2".to_string(),
+                index: 1,
+                ms_level: 1,
+                ..Default::default()
+            },
+        );
+        let frames = FrameVec {
+            frames: vec![empty, valid],
+            ..Default::default()
+        };
+
+        let source: CollapsedIonMobilitySpectrumSource<CentroidPeak, DeconvolutedPeak, _> =
+            CollapsedIonMobilitySpectrumSource::new(frames, None, None);
+        let spectra: Vec<_> = source.collect();
+        assert_eq!(spectra.len(), 1);
+        assert_eq!(spectra[0].id(), "frame=2");
+        Ok(())
+    }
 }
@@ -18,10 +18,12 @@ use crate::{
 
 use super::{IonMobilityFrameLike, MultiLayerIonMobilityFrame, MultiLayerSpectrum};
 
+mod dia;
 mod frame;
 mod spectrum;
 mod util;
 
+pub use dia::{DIACycle, DIACycleIterator, DIAWindowTable, DEFAULT_WINDOW_TOLERANCE};
 pub use frame::{IonMobilityFrameGroup, IonMobilityFrameGroupIntoIter, IonMobilityFrameGroupIter};
 pub use spectrum::{SpectrumGroup, SpectrumGroupIntoIter, SpectrumGroupIter};
 pub(crate) use util::GenerationTracker;
@@ -0,0 +1,443 @@
+use std::{marker::PhantomData, mem};
+
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
+
+use crate::io::SpectrumGrouping;
+use crate::spectrum::IsolationWindow;
+
+use super::super::{MultiLayerSpectrum, SpectrumLike};
+
+/// The default absolute m/z tolerance used to decide whether two isolation windows are the same
+pub const DEFAULT_WINDOW_TOLERANCE: f32 = 0.05;
+
+/**
+The table of distinct isolation windows observed in a data independent acquisition (DIA)
+run, in the order they were first observed.
+
+Windows may overlap, as in staggered or overlapping window schemes, so an m/z may
+belong to more than one window.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct DIAWindowTable {
+    windows: Vec<IsolationWindow>,
+    /// The absolute m/z tolerance used to decide whether two windows are the same
+    pub tolerance: f32,
+}
+
+impl Default for DIAWindowTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW_TOLERANCE)
+    }
+}
+
+impl DIAWindowTable {
+    pub fn new(tolerance: f32) -> Self {
+        Self {
+            windows: Vec::new(),
+            tolerance,
+        }
+    }
+
+    fn same_window(&self, a: &IsolationWindow, b: &IsolationWindow) -> bool {
+        (a.lower_bound - b.lower_bound).abs() <= self.tolerance
+            && (a.upper_bound - b.upper_bound).abs() <= self.tolerance
+    }
+
+    /// Find the index of the window matching `window`, if it is in the table
+    pub fn find(&self, window: &IsolationWindow) -> Option<usize> {
+        self.windows
+            .iter()
+            .position(|w| self.same_window(w, window))
+    }
+
+    /// Find the index of the window matching `window`, adding it to the table if
+    /// it is not already present
+    pub fn find_or_insert(&mut self, window: &IsolationWindow) -> usize {
+        match self.find(window) {
+            Some(i) => i,
+            None => {
+                self.windows.push(window.clone());
+                self.windows.len() - 1
+            }
+        }
+    }
+
+    /// Get the window at `index`
+    pub fn get(&self, index: usize) -> Option<&IsolationWindow> {
+        self.windows.get(index)
+    }
+
+    /// The windows in the order they were first observed
+    pub fn windows(&self) -> &[IsolationWindow] {
+        &self.windows
+    }
+
+    /// Iterate over the window indices and windows, ordered by their lower bound
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (usize, &IsolationWindow)> {
+        let mut entries: Vec<_> = self.windows.iter().enumerate().collect();
+        entries.sort_by(|(_, a), (_, b)| {
+            a.lower_bound
+                .total_cmp(&b.lower_bound)
+                .then(a.upper_bound.total_cmp(&b.upper_bound))
+        });
+        entries.into_iter()
+    }
+
+    /// Iterate over the indices of all windows that contain `mz`
+    pub fn containing(&self, mz: f64) -> impl Iterator<Item = usize> + '_ {
+        self.windows
+            .iter()
+            .enumerate()
+            .filter(move |(_, w)| w.contains(mz))
+            .map(|(i, _)| i)
+    }
+
+    /// Whether any two windows in the table overlap by more than the table's tolerance,
+    /// as in staggered or overlapping window schemes
+    pub fn has_overlaps(&self) -> bool {
+        let windows: Vec<_> = self.iter_sorted().map(|(_, w)| w).collect();
+        windows
+            .windows(2)
+            .any(|pair| pair[1].lower_bound + self.tolerance < pair[0].upper_bound)
+    }
+
+    /// The lowest and highest m/z covered by any window
+    pub fn mz_range(&self) -> Option<(f32, f32)> {
+        self.windows.iter().fold(None, |acc, w| match acc {
+            Some((lo, hi)) => Some((w.lower_bound.min(lo), w.upper_bound.max(hi))),
+            None => Some((w.lower_bound, w.upper_bound)),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+}
+
+/**
+A single acquisition cycle of a DIA run, an optional MS1 spectrum followed by the MSn spectra
+acquired over the isolation window scheme.
+*/
+#[derive(Debug, Clone)]
+pub struct DIACycle<C = CentroidPeak, D = DeconvolutedPeak, S = MultiLayerSpectrum<C, D>>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    /// The MS1 spectrum of the cycle. This may be absent if the MS1 spectrum is missing
+    /// or the source does not contain any MS1 spectra
+    pub precursor: Option<S>,
+    /// The MSn spectra of the cycle, in acquisition order
+    pub products: Vec<S>,
+    /// The index into the [`DIAWindowTable`] of the isolation window of each entry in
+    /// `products`, or `None` if the product did not have an isolation window
+    pub windows: Vec<Option<usize>>,
+    /// The ordinal of this cycle in the run
+    pub cycle_index: usize,
+    centroid_type: PhantomData<C>,
+    deconvoluted_type: PhantomData<D>,
+}
+
+impl<C, D, S> Default for DIACycle<C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<C, D, S> DIACycle<C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    pub fn new(cycle_index: usize) -> Self {
+        Self {
+            precursor: None,
+            products: Vec::new(),
+            windows: Vec::new(),
+            cycle_index,
+            centroid_type: PhantomData,
+            deconvoluted_type: PhantomData,
+        }
+    }
+
+    /// Add a product spectrum with the index of its isolation window
+    pub fn add_product(&mut self, spectrum: S, window: Option<usize>) {
+        self.products.push(spectrum);
+        self.windows.push(window);
+    }
+
+    /// Iterate over the product spectra paired with the index of their isolation window
+    pub fn products_with_windows(&self) -> impl Iterator<Item = (&S, Option<usize>)> {
+        self.products.iter().zip(self.windows.iter().copied())
+    }
+
+    /// Find the product spectrum acquired with the isolation window `window_index`
+    pub fn product_for_window(&self, window_index: usize) -> Option<&S> {
+        self.products_with_windows()
+            .find(|(_, w)| *w == Some(window_index))
+            .map(|(s, _)| s)
+    }
+
+    /// Whether the cycle already contains a product spectrum for `window_index`
+    pub fn has_window(&self, window_index: usize) -> bool {
+        self.windows.contains(&Some(window_index))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.precursor.is_none() && self.products.is_empty()
+    }
+}
+
+impl<C, D, S> SpectrumGrouping<C, D, S> for DIACycle<C, D, S>
+where
+    C: CentroidLike + Default,
+    D: DeconvolutedCentroidLike + Default,
+    S: SpectrumLike<C, D>,
+{
+    fn precursor(&self) -> Option<&S> {
+        self.precursor.as_ref()
+    }
+
+    fn precursor_mut(&mut self) -> Option<&mut S> {
+        self.precursor.as_mut()
+    }
+
+    fn set_precursor(&mut self, prec: S) {
+        self.precursor = Some(prec)
+    }
+
+    fn products(&self) -> &[S] {
+        &self.products
+    }
+
+    /// Note that adding or removing products through this method does not update
+    /// [`DIACycle::windows`].
+    fn products_mut(&mut self) -> &mut Vec<S> {
+        &mut self.products
+    }
+
+    fn into_parts(self) -> (Option<S>, Vec<S>) {
+        (self.precursor, self.products)
+    }
+}
+
+/**
+A wrapper for [`Iterator`]-implementors that groups the spectra of a data independent acquisition
+(DIA) run into [`DIACycle`]s, one per repetition of the isolation window scheme.
+
+A new cycle starts at each MS1 spectrum, or when a product spectrum's isolation window was already
+acquired in the current cycle, so runs with missing or no MS1 spectra are still split into cycles.
+When MS1 spectra are absent, a staggered scheme which alternates between two window layouts will be
+grouped into cycles covering both layouts, as that is the repeating unit.
+
+The isolation windows are collected into a [`DIAWindowTable`] as they are encountered, available
+from [`DIACycleIterator::window_table`].
+*/
+#[derive(Debug)]
+pub struct DIACycleIterator<
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> = MultiLayerSpectrum<C, D>,
+> {
+    pub source: R,
+    window_table: DIAWindowTable,
+    current: DIACycle<C, D, S>,
+    cycle_index: usize,
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > DIACycleIterator<R, C, D, S>
+{
+    /// Construct a new [`DIACycleIterator`] around an [`Iterator`] using the
+    /// [`DEFAULT_WINDOW_TOLERANCE`] to match isolation windows
+    pub fn new(source: R) -> Self {
+        Self::with_window_tolerance(source, DEFAULT_WINDOW_TOLERANCE)
+    }
+
+    /// Construct a new [`DIACycleIterator`] around an [`Iterator`], treating isolation
+    /// windows whose bounds are within `tolerance` m/z as the same window
+    pub fn with_window_tolerance(source: R, tolerance: f32) -> Self {
+        Self {
+            source,
+            window_table: DIAWindowTable::new(tolerance),
+            current: DIACycle::new(0),
+            cycle_index: 0,
+        }
+    }
+
+    /// The isolation windows observed so far
+    pub fn window_table(&self) -> &DIAWindowTable {
+        &self.window_table
+    }
+
+    /// Consume the iterator, returning the isolation windows observed
+    pub fn into_window_table(self) -> DIAWindowTable {
+        self.window_table
+    }
+
+    fn take_current(&mut self) -> Option<DIACycle<C, D, S>> {
+        if self.current.is_empty() {
+            return None;
+        }
+        self.cycle_index += 1;
+        let cycle = mem::replace(&mut self.current, DIACycle::new(self.cycle_index));
+        Some(cycle)
+    }
+
+    fn window_of(&mut self, spectrum: &S) -> Option<usize> {
+        let window = &spectrum.precursor()?.isolation_window;
+        if window.is_empty() {
+            None
+        } else {
+            Some(self.window_table.find_or_insert(window))
+        }
+    }
+
+    fn push(&mut self, spectrum: S) -> Option<DIACycle<C, D, S>> {
+        if spectrum.ms_level() == 1 {
+            let done = self.take_current();
+            self.current.precursor = Some(spectrum);
+            done
+        } else {
+            let window = self.window_of(&spectrum);
+            let done = match window {
+                Some(i) if self.current.has_window(i) => self.take_current(),
+                _ => None,
+            };
+            self.current.add_product(spectrum, window);
+            done
+        }
+    }
+
+    /// Retrieve the next cycle or `None` if the source is exhausted
+    pub fn next_cycle(&mut self) -> Option<DIACycle<C, D, S>> {
+        loop {
+            match self.source.next() {
+                Some(spectrum) => {
+                    if let Some(cycle) = self.push(spectrum) {
+                        return Some(cycle);
+                    }
+                }
+                None => return self.take_current(),
+            }
+        }
+    }
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    > Iterator for DIACycleIterator<R, C, D, S>
+{
+    type Item = DIACycle<C, D, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_cycle()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrum::{Precursor, RawSpectrum, SpectrumDescription};
+
+    fn make_spectrum(index: usize, ms_level: u8, window: Option<(f32, f32)>) -> RawSpectrum {
+        let mut descr = SpectrumDescription {
+            id: format!("scan={}", index + 1),
+            index,
+            ms_level,
+            ..Default::default()
+        };
+        if let Some((lo, hi)) = window {
+            descr.precursors.push(Precursor {
+                isolation_window: IsolationWindow::around((lo + hi) / 2.0, (hi - lo) / 2.0),
+                ..Default::default()
+            });
+        }
+        RawSpectrum::new(descr, Default::default())
+    }
+
+    fn make_run(windows: &[&[(f32, f32)]], with_ms1: &[bool]) -> Vec<RawSpectrum> {
+        let mut spectra = Vec::new();
+        for (layout, ms1) in windows.iter().zip(with_ms1) {
+            if *ms1 {
+                spectra.push(make_spectrum(spectra.len(), 1, None));
+            }
+            for w in layout.iter() {
+                spectra.push(make_spectrum(spectra.len(), 2, Some(*w)));
+            }
+        }
+        spectra
+    }
+
+    #[test]
+    fn test_dia_cycles() {
+        let layout: &[(f32, f32)] = &[(400.0, 425.0), (425.0, 450.0), (450.0, 475.0)];
+        let spectra = make_run(&[layout, layout, layout], &[true, false, true]);
+        let mut iter: DIACycleIterator<_, CentroidPeak, DeconvolutedPeak, RawSpectrum> =
+            DIACycleIterator::new(spectra.into_iter());
+        let cycles: Vec<_> = iter.by_ref().collect();
+        assert_eq!(cycles.len(), 3);
+        assert!(cycles[0].precursor.is_some());
+        assert!(cycles[1].precursor.is_none());
+        assert!(cycles[2].precursor.is_some());
+        for (i, cycle) in cycles.iter().enumerate() {
+            assert_eq!(cycle.cycle_index, i);
+            assert_eq!(cycle.products.len(), 3);
+            assert_eq!(cycle.windows, vec![Some(0), Some(1), Some(2)]);
+        }
+        let table = iter.window_table();
+        assert_eq!(table.len(), 3);
+        assert!(!table.has_overlaps());
+        assert_eq!(table.mz_range(), Some((400.0, 475.0)));
+        assert_eq!(table.containing(430.0).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            cycles[1].product_for_window(2).map(|s| s.description.index),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn test_dia_cycles_staggered() {
+        let a: &[(f32, f32)] = &[(400.0, 420.0), (420.0, 440.0)];
+        let b: &[(f32, f32)] = &[(410.0, 430.0), (430.0, 450.0)];
+        let spectra = make_run(&[a, b, a, b], &[true, true, true, true]);
+        let mut iter: DIACycleIterator<_, CentroidPeak, DeconvolutedPeak, RawSpectrum> =
+            DIACycleIterator::new(spectra.into_iter());
+        let cycles: Vec<_> = iter.by_ref().collect();
+        assert_eq!(cycles.len(), 4);
+        assert_eq!(cycles[1].windows, vec![Some(2), Some(3)]);
+        assert_eq!(cycles[3].windows, vec![Some(2), Some(3)]);
+        let table = iter.window_table();
+        assert_eq!(table.len(), 4);
+        assert!(table.has_overlaps());
+        assert_eq!(table.containing(425.0).collect::<Vec<_>>(), vec![1, 2]);
+
+        let spectra = make_run(&[a, b, a, b], &[false, false, false, false]);
+        let cycles: Vec<_> =
+            DIACycleIterator::<_, CentroidPeak, DeconvolutedPeak, RawSpectrum>::new(
+                spectra.into_iter(),
+            )
+            .collect();
+        assert_eq!(cycles.len(), 2);
+        assert_eq!(cycles[0].products.len(), 4);
+    }
+}
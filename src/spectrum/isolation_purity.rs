@@ -0,0 +1,566 @@
+//! Estimate how much of the signal co-isolated with a precursor ion belongs to it.
+//!
+//! Isobaric labeling quantification is distorted by other ions isolated alongside the selected
+//! precursor, so MSn spectra are usually filtered by *precursor isolation purity*, the fraction
+//! of MS1 intensity within the [`IsolationWindow`] attributable to the selected ion's isotopic
+//! envelope. A [`PurityEstimator`] fits that envelope with a [`Deconvoluter`] and compares it
+//! to the total signal in the window, optionally interpolating between the MS1 scans before and
+//! after the MSn spectrum since the precursor's abundance changes between them.
+//!
+//! The estimate is stored on the [`SelectedIon`] and can be read back with
+//! [`SelectedIon::isolation_purity`]. As a parameter of the selected ion, it is written to
+//! MGF as a `PRECURSOR_ISOLATION_PURITY` header entry and to mzML as a user parameter.
+use std::collections::VecDeque;
+
+use log::warn;
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak};
+
+use super::deconvolution::Deconvoluter;
+use super::precursor_correction::{is_isolated_from, PrecursorCorrector};
+use super::scan_properties::{IsolationWindow, ScanPolarity, SelectedIon};
+use super::spectrum_types::{MultiLayerSpectrum, SpectrumLike, SpectrumProcessingError};
+use super::transforms::AdaptorMetadata;
+use crate::meta::MSDataFileMetadata;
+use crate::params::Param;
+
+/// The isolation purity of a selected ion in a single MS1 spectrum, or interpolated
+/// between two of them
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PurityEstimate {
+    /// The intensity within the isolation window attributed to the selected ion
+    pub precursor_intensity: f32,
+    /// The total intensity within the isolation window
+    pub total_intensity: f32,
+}
+
+impl PurityEstimate {
+    pub fn new(precursor_intensity: f32, total_intensity: f32) -> Self {
+        Self {
+            precursor_intensity,
+            total_intensity,
+        }
+    }
+
+    /// The fraction of the isolated signal attributed to the selected ion
+    pub fn purity(&self) -> f32 {
+        if self.total_intensity > 0.0 {
+            (self.precursor_intensity / self.total_intensity).min(1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// The fraction of the isolated signal from other ions
+    pub fn interference(&self) -> f32 {
+        1.0 - self.purity()
+    }
+
+    /// Linearly interpolate between `self` and `other`, where `weight` is the distance from
+    /// `self` to `other` between 0 and 1
+    pub fn interpolate(&self, other: &Self, weight: f32) -> Self {
+        let weight = weight.clamp(0.0, 1.0);
+        Self {
+            precursor_intensity: self.precursor_intensity
+                + (other.precursor_intensity - self.precursor_intensity) * weight,
+            total_intensity: self.total_intensity
+                + (other.total_intensity - self.total_intensity) * weight,
+        }
+    }
+}
+
+/// Estimate the isolation purity of selected ions from MS1 spectra
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurityEstimator {
+    /// The deconvoluter used to fit the selected ion's isotopic envelope
+    pub deconvoluter: Deconvoluter,
+    /// The m/z distance around the selected ion to treat as isolated when the isolation
+    /// window is not known
+    pub default_half_width: f64,
+    /// The m/z distance beyond the isolation window to include, so that isotopic peaks of the
+    /// selected ion outside the window may still be fit
+    pub margin: f64,
+    /// Whether to only fit the charge state reported for the ion when one is present
+    pub respect_reported_charge: bool,
+}
+
+impl Default for PurityEstimator {
+    fn default() -> Self {
+        Self {
+            deconvoluter: Deconvoluter::default(),
+            default_half_width: 1.0,
+            margin: 3.5,
+            respect_reported_charge: true,
+        }
+    }
+}
+
+impl PurityEstimator {
+    pub fn new(deconvoluter: Deconvoluter) -> Self {
+        Self {
+            deconvoluter,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_default_half_width(mut self, default_half_width: f64) -> Self {
+        self.default_half_width = default_half_width;
+        self
+    }
+
+    pub fn with_margin(mut self, margin: f64) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn with_respect_reported_charge(mut self, respect_reported_charge: bool) -> Self {
+        self.respect_reported_charge = respect_reported_charge;
+        self
+    }
+
+    /// Estimate the purity of `ion` isolated by `window` in the MS1 spectrum `ms1`.
+    ///
+    /// When the ion's isotopic envelope cannot be fit, only the peak matching the ion's m/z
+    /// is attributed to it.
+    pub fn estimate<C, D, S>(
+        &self,
+        ion: &SelectedIon,
+        window: &IsolationWindow,
+        ms1: &S,
+    ) -> Result<PurityEstimate, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        let (low, high) = if window.is_empty() {
+            (
+                ion.mz - self.default_half_width,
+                ion.mz + self.default_half_width,
+            )
+        } else {
+            (window.lower_bound as f64, window.upper_bound as f64)
+        };
+        // Include the peaks around the window so that the envelope can be fit from
+        // isotopic peaks which were not isolated
+        let peaks = PrecursorCorrector::peaks_between(ms1, low - self.margin, high + self.margin)?;
+        let in_window = |mz: f64| low <= mz && mz <= high;
+
+        let total_intensity: f32 = peaks
+            .iter()
+            .filter(|p| in_window(p.mz))
+            .map(|p| p.intensity)
+            .sum();
+
+        let charge_sign = match ms1.polarity() {
+            ScanPolarity::Negative => -1,
+            _ => 1,
+        };
+        let fit = match ion.charge.filter(|_| self.respect_reported_charge) {
+            Some(z) => self.deconvoluter.clone().with_charge_range(z, z).fit_peak(
+                &peaks,
+                ion.mz,
+                charge_sign,
+            ),
+            None => self.deconvoluter.fit_peak(&peaks, ion.mz, charge_sign),
+        };
+        let precursor_intensity = match fit {
+            Some(fit) => fit
+                .envelope
+                .iter()
+                .filter(|p| in_window(p.mz))
+                .map(|p| p.intensity)
+                .sum(),
+            None => peaks
+                .iter()
+                .filter(|p| self.deconvoluter.tolerance.test(p.mz, ion.mz))
+                .map(|p| p.intensity)
+                .fold(0.0, f32::max),
+        };
+        Ok(PurityEstimate::new(precursor_intensity, total_intensity))
+    }
+
+    /// Estimate the purity of `ion` at `time` by interpolating between the MS1 spectra
+    /// `before` and `after` it. If only one is given, its estimate is used directly.
+    pub fn estimate_interpolated<C, D, S>(
+        &self,
+        ion: &SelectedIon,
+        window: &IsolationWindow,
+        time: f64,
+        before: Option<&S>,
+        after: Option<&S>,
+    ) -> Result<Option<PurityEstimate>, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        let estimate = match (before, after) {
+            (Some(before), Some(after)) => {
+                let start = self.estimate(ion, window, before)?;
+                let end = self.estimate(ion, window, after)?;
+                let span = after.start_time() - before.start_time();
+                let weight = if span > 0.0 {
+                    ((time - before.start_time()) / span) as f32
+                } else {
+                    0.0
+                };
+                Some(start.interpolate(&end, weight))
+            }
+            (Some(ms1), None) | (None, Some(ms1)) => Some(self.estimate(ion, window, ms1)?),
+            (None, None) => None,
+        };
+        Ok(estimate)
+    }
+
+    /// Estimate the isolation purity of the selected ions of `spectrum` from the flanking MS1
+    /// spectra, storing it with [`SelectedIon::set_isolation_purity`].
+    ///
+    /// Only the precursors isolated from one of the flanking MS1 spectra are annotated: those
+    /// whose [`Precursor::precursor_id`](crate::spectrum::Precursor::precursor_id) names one of
+    /// them, or, for an MS2 spectrum, those without an identifier. The precursors of later
+    /// stages of an MSn experiment, isolated from a product ion spectrum, are left unchanged.
+    pub fn annotate_spectrum<C, D, S, T>(
+        &self,
+        spectrum: &mut T,
+        before: Option<&S>,
+        after: Option<&S>,
+    ) -> Result<(), SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        T: SpectrumLike<C, D>,
+    {
+        let time = spectrum.start_time();
+        let ms_level = spectrum.ms_level();
+        let ms1_ids: Vec<&str> = [before, after]
+            .iter()
+            .flatten()
+            .filter(|s| s.ms_level() == 1)
+            .map(|s| s.id())
+            .collect();
+        for precursor in spectrum
+            .description_mut()
+            .precursors
+            .iter_mut()
+            .filter(|p| ms1_ids.iter().any(|id| is_isolated_from(p, ms_level, id)))
+        {
+            let window = precursor.isolation_window.clone();
+            for ion in precursor.ions.iter_mut() {
+                if let Some(estimate) =
+                    self.estimate_interpolated(ion, &window, time, before, after)?
+                {
+                    ion.set_isolation_purity(estimate.purity());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Describe the estimation as a list of parameters for a
+    /// [`ProcessingMethod`](crate::meta::ProcessingMethod)
+    pub fn as_params(&self) -> Vec<Param> {
+        vec![Param::new_key_value(
+            "precursor isolation purity estimation",
+            self.deconvoluter.to_string(),
+        )]
+    }
+}
+
+/// Adapt an iterator over spectra by estimating the isolation purity of each MSn spectrum's
+/// selected ions with a [`PurityEstimator`].
+///
+/// When interpolating, MSn spectra are held back until the next MS1 spectrum is read, and are
+/// then produced in their original order.
+///
+/// The estimation is recorded in the adaptor's [`AdaptorMetadata`].
+#[derive(Debug)]
+pub struct PurityAnnotatingSource<
+    R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+> {
+    source: R,
+    estimator: PurityEstimator,
+    interpolate: bool,
+    previous_ms1: Option<MultiLayerSpectrum<C, D>>,
+    pending: VecDeque<MultiLayerSpectrum<C, D>>,
+    ready: VecDeque<MultiLayerSpectrum<C, D>>,
+    metadata: AdaptorMetadata,
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default + Clone,
+        D: DeconvolutedCentroidLike + Default + Clone,
+    > PurityAnnotatingSource<R, C, D>
+{
+    pub fn new(source: R, estimator: PurityEstimator, interpolate: bool) -> Self
+    where
+        R: MSDataFileMetadata,
+    {
+        let mut metadata = AdaptorMetadata::new(&source);
+        let mut params = estimator.as_params();
+        params.push(Param::new_key_value(
+            "interpolate flanking MS1",
+            interpolate.to_string(),
+        ));
+        metadata.record_method("isolation_purity", params);
+        Self {
+            source,
+            estimator,
+            interpolate,
+            previous_ms1: None,
+            pending: VecDeque::new(),
+            ready: VecDeque::new(),
+            metadata,
+        }
+    }
+
+    pub fn estimator(&self) -> &PurityEstimator {
+        &self.estimator
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    fn annotate(
+        &self,
+        mut spectrum: MultiLayerSpectrum<C, D>,
+        after: Option<&MultiLayerSpectrum<C, D>>,
+    ) -> MultiLayerSpectrum<C, D> {
+        let before = self.previous_ms1.as_ref();
+        if let Err(e) = self
+            .estimator
+            .annotate_spectrum(&mut spectrum, before, after)
+        {
+            warn!(
+                "Failed to estimate the isolation purity of {}: {e}",
+                spectrum.id()
+            );
+        }
+        spectrum
+    }
+
+    fn flush_pending(&mut self, after: Option<&MultiLayerSpectrum<C, D>>) {
+        while let Some(spectrum) = self.pending.pop_front() {
+            let spectrum = self.annotate(spectrum, after);
+            self.ready.push_back(spectrum);
+        }
+    }
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+    > MSDataFileMetadata for PurityAnnotatingSource<R, C, D>
+{
+    crate::delegate_impl_metadata_trait!(metadata);
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default + Clone,
+        D: DeconvolutedCentroidLike + Default + Clone,
+    > Iterator for PurityAnnotatingSource<R, C, D>
+{
+    type Item = MultiLayerSpectrum<C, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(spectrum) = self.ready.pop_front() {
+                return Some(spectrum);
+            }
+            match self.source.next() {
+                Some(spectrum) if spectrum.ms_level() == 1 => {
+                    self.flush_pending(Some(&spectrum));
+                    self.previous_ms1 = Some(spectrum.clone());
+                    self.ready.push_back(spectrum);
+                }
+                Some(spectrum) if self.interpolate => {
+                    self.pending.push_back(spectrum);
+                }
+                Some(spectrum) => {
+                    let spectrum = self.annotate(spectrum, None);
+                    self.ready.push_back(spectrum);
+                }
+                None => {
+                    self.flush_pending(None);
+                    return self.ready.pop_front();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mgf::MGFWriter;
+    use crate::prelude::*;
+    use crate::spectrum::test_utils::{centroid_spectrum, isotopic_envelope, precursor};
+    use crate::spectrum::Averagine;
+
+    /// A doubly charged envelope with a co-isolated interfering peak of `interference`
+    fn ms1_peaks(interference: f32) -> Vec<(f64, f32)> {
+        let mut peaks = isotopic_envelope(1500.7, 2, 1e5);
+        if interference > 0.0 {
+            peaks.push((751.1, interference));
+        }
+        peaks
+    }
+
+    /// The m/z of the monoisotopic peak of the envelope in [`ms1_peaks`]
+    fn precursor_mz() -> f64 {
+        Averagine::PEPTIDE.isotopic_cluster(1500.7, 2)[0].mz
+    }
+
+    #[test]
+    fn test_estimate() {
+        let estimator = PurityEstimator::default();
+        let msn = centroid_spectrum(
+            "msn",
+            1.0,
+            2,
+            &[(126.127, 100.0)],
+            vec![precursor(precursor_mz(), Some(2), None)],
+        );
+        let precursor = msn.precursor().unwrap();
+
+        let pure = centroid_spectrum("ms1", 0.9, 1, &ms1_peaks(0.0), vec![]);
+        let estimate = estimator
+            .estimate(precursor.ion(), &precursor.isolation_window, &pure)
+            .unwrap();
+        assert!((estimate.purity() - 1.0).abs() < 1e-6, "{:?}", estimate);
+
+        let mixed = centroid_spectrum("ms1", 1.1, 1, &ms1_peaks(1e5), vec![]);
+        let estimate = estimator
+            .estimate(precursor.ion(), &precursor.isolation_window, &mixed)
+            .unwrap();
+        assert!(
+            (estimate.interference() - 1e5 / estimate.total_intensity).abs() < 1e-3,
+            "{:?}",
+            estimate
+        );
+
+        let estimate = estimator
+            .estimate_interpolated(
+                precursor.ion(),
+                &precursor.isolation_window,
+                1.0,
+                Some(&pure),
+                Some(&mixed),
+            )
+            .unwrap()
+            .unwrap();
+        let expected = PurityEstimate::new(
+            estimate.precursor_intensity,
+            estimate.precursor_intensity + 0.5e5,
+        );
+        assert!((estimate.purity() - expected.purity()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_annotate_ms3() {
+        let estimator = PurityEstimator::default();
+        let ms1 = centroid_spectrum("ms1", 0.9, 1, &ms1_peaks(1e5), vec![]);
+
+        // The MS2 stage's fragment falls within the MS1 envelope, but was isolated from
+        // the MS2 spectrum
+        let mut ms3 = centroid_spectrum(
+            "ms3",
+            1.0,
+            3,
+            &[(126.127, 100.0)],
+            vec![
+                precursor(precursor_mz(), Some(2), Some("ms1")),
+                precursor(precursor_mz(), Some(2), Some("msn")),
+            ],
+        );
+        estimator
+            .annotate_spectrum(&mut ms3, Some(&ms1), None)
+            .unwrap();
+        let precursors = &ms3.description().precursors;
+        assert!(precursors[0].ion().isolation_purity().unwrap() < 1.0);
+        assert!(precursors[1].ion().isolation_purity().is_none());
+
+        // Nor is an MS3 precursor without an identifier assumed to come from the MS1 spectrum
+        let mut ms3 = centroid_spectrum(
+            "ms3",
+            1.0,
+            3,
+            &[(126.127, 100.0)],
+            vec![precursor(precursor_mz(), Some(2), None)],
+        );
+        estimator
+            .annotate_spectrum(&mut ms3, Some(&ms1), None)
+            .unwrap();
+        assert!(ms3.precursor().unwrap().ion().isolation_purity().is_none());
+    }
+
+    #[test]
+    fn test_annotating_source() {
+        let spectra = vec![
+            centroid_spectrum("ms1-1", 0.9, 1, &ms1_peaks(0.0), vec![]),
+            centroid_spectrum(
+                "msn-1",
+                1.0,
+                2,
+                &[(126.127, 100.0)],
+                vec![precursor(precursor_mz(), Some(2), None)],
+            ),
+            centroid_spectrum(
+                "msn-2",
+                1.05,
+                2,
+                &[(126.127, 100.0)],
+                vec![precursor(precursor_mz(), Some(2), None)],
+            ),
+            centroid_spectrum("ms1-2", 1.1, 1, &ms1_peaks(1e5), vec![]),
+            centroid_spectrum(
+                "msn-3",
+                1.2,
+                2,
+                &[(126.127, 100.0)],
+                vec![precursor(precursor_mz(), Some(2), None)],
+            ),
+        ];
+
+        let mut source = PurityAnnotatingSource {
+            source: spectra.into_iter(),
+            estimator: PurityEstimator::default(),
+            interpolate: true,
+            previous_ms1: None,
+            pending: VecDeque::new(),
+            ready: VecDeque::new(),
+            metadata: Default::default(),
+        };
+        let spectra: Vec<_> = source.by_ref().collect();
+        let ids: Vec<_> = spectra.iter().map(|s| s.id()).collect();
+        assert_eq!(ids, ["ms1-1", "msn-1", "msn-2", "ms1-2", "msn-3"]);
+
+        let purities: Vec<f32> = spectra
+            .iter()
+            .filter_map(|s| s.precursor())
+            .map(|p| p.ion().isolation_purity().unwrap())
+            .collect();
+        assert_eq!(purities.len(), 3);
+        assert!(purities[0] > purities[1]);
+        assert!(purities[1] > purities[2]);
+        assert!(purities[2] < 1.0);
+
+        let mut buffer = Vec::new();
+        let mut writer = MGFWriter::new(&mut buffer);
+        writer.write(&spectra[1]).unwrap();
+        writer.close().unwrap();
+        drop(writer);
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.contains("PRECURSOR_ISOLATION_PURITY="), "{}", text);
+    }
+}
@@ -0,0 +1,949 @@
+//! Parse and apply msconvert-style `--filter` expressions.
+//!
+//! ProteoWizard's `msconvert` describes spectrum processing as a list of filter expressions
+//! like `msLevel 2-` or `threshold count 150 most-intense`. [`MSConvertFilter`] parses the common
+//! subset of these expressions, and [`MSConvertFilterChain`] applies them in order to each spectrum
+//! of a source, using mzdata's own signal processing and metadata. Expressions naming msconvert
+//! filters that are not supported produce an [`MSConvertFilterError`] rather than being ignored.
+//!
+//! Supported filters:
+//! - `msLevel <levels>`
+//! - `scanTime [<start>,<end>]`, in seconds
+//! - `polarity <positive|negative>`
+//! - `activation <CID|HCD|ETD|ECD|IRMPD|BIRD|PD|PSD|PQD|SID|SA|any>`
+//! - `mzWindow [<low>,<high>]`
+//! - `threshold <count|absolute|bpi-relative|tic-relative> <value> most-intense [<levels>]`
+//! - `zeroSamples removeExtra [<levels>]`
+//! - `peakPicking [vendor|cwt] [snr=<value>] [msLevel=<levels>]`, which uses `mzsignal`'s peak
+//!   picker for both methods, keeps each peak's signal-to-noise ratio as a signal-to-noise
+//!   array, and requires the `mzsignal` feature
+//!
+//! ```
+//! use mzdata::spectrum::MSConvertFilterChain;
+//!
+//! let chain = MSConvertFilterChain::parse(["msLevel 2-", "threshold count 150 most-intense"]).unwrap();
+//! assert_eq!(chain.len(), 2);
+//!
+//! let err = MSConvertFilterChain::parse(["titleMaker <RunId>.<ScanNumber>"]).unwrap_err();
+//! assert_eq!(err.to_string(), "The msconvert filter `titleMaker` is not supported");
+//! ```
+use std::fmt::Display;
+use std::str::FromStr;
+
+use log::warn;
+use mzpeaks::prelude::*;
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike};
+use thiserror::Error;
+
+use super::scan_properties::ScanPolarity;
+use super::spectrum_types::{MultiLayerSpectrum, SpectrumLike, SpectrumProcessingError};
+use super::transforms::{
+    append_processing_method, AdaptorMetadata, ClipMzRange, IntensityThreshold, RemoveExtraZeros,
+    TransformChain,
+};
+use super::CentroidPeakAdapting;
+use crate::meta::{
+    DataProcessing, DataProcessingAction, DissociationMethodTerm, MSDataFileMetadata,
+};
+use crate::params::Param;
+
+/// msconvert filters which are recognized but not supported
+const UNSUPPORTED_FILTERS: &[&str] = &[
+    "index",
+    "id",
+    "scanNumber",
+    "scanEvent",
+    "sortByScanTime",
+    "stripIT",
+    "metadataFixer",
+    "titleMaker",
+    "chargeStatePredictor",
+    "turbocharger",
+    "chargeFromIsotope",
+    "defaultArrayLength",
+    "collisionEnergy",
+    "analyzer",
+    "analyzerType",
+    "mzPrecursors",
+    "mzPresent",
+    "mzShift",
+    "mzRefiner",
+    "lockmassRefiner",
+    "precursorRecalculation",
+    "precursorRefine",
+    "scanSummer",
+    "MS2Denoise",
+    "MS2Deisotope",
+    "ETDFilter",
+    "demultiplex",
+    "diaUmpire",
+    "thermoScanFilter",
+];
+
+/// Errors that may occur while parsing an msconvert filter expression
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MSConvertFilterError {
+    #[error("The filter expression is empty")]
+    Empty,
+    #[error("Unknown filter `{0}`")]
+    UnknownFilter(String),
+    #[error("The msconvert filter `{0}` is not supported")]
+    UnsupportedFilter(String),
+    #[error("The `{filter}` filter does not support `{option}`")]
+    UnsupportedOption { filter: String, option: String },
+    #[error("Invalid argument for the `{filter}` filter: {message}")]
+    InvalidArgument { filter: String, message: String },
+    #[error("The `{filter}` filter requires mzdata to be built with the `{feature}` feature")]
+    RequiresFeature { filter: String, feature: String },
+}
+
+impl MSConvertFilterError {
+    fn invalid(filter: &str, message: impl Into<String>) -> Self {
+        Self::InvalidArgument {
+            filter: filter.to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn unsupported_option(filter: &str, option: &str) -> Self {
+        Self::UnsupportedOption {
+            filter: filter.to_string(),
+            option: option.to_string(),
+        }
+    }
+}
+
+/// A set of MS levels, written as whitespace separated levels or ranges like `1 3-5` or `2-`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MSLevelSet {
+    ranges: Vec<(u8, u8)>,
+}
+
+impl MSLevelSet {
+    /// A set including every MS level
+    pub fn all() -> Self {
+        Self {
+            ranges: vec![(1, u8::MAX)],
+        }
+    }
+
+    pub fn contains(&self, ms_level: u8) -> bool {
+        self.ranges
+            .iter()
+            .any(|(low, high)| *low <= ms_level && ms_level <= *high)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    fn parse_level(token: &str) -> Result<u8, String> {
+        token
+            .trim()
+            .parse()
+            .map_err(|_| format!("`{token}` is not a valid MS level"))
+    }
+}
+
+impl FromStr for MSLevelSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = Vec::new();
+        for token in s.split_whitespace() {
+            let range =
+                if let Some(interval) = token.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                    let (low, high) = interval
+                        .split_once(',')
+                        .ok_or_else(|| format!("`{token}` is not a valid MS level interval"))?;
+                    (Self::parse_level(low)?, Self::parse_level(high)?)
+                } else if let Some((low, high)) = token.split_once('-') {
+                    let low = if low.is_empty() {
+                        1
+                    } else {
+                        Self::parse_level(low)?
+                    };
+                    let high = if high.is_empty() {
+                        u8::MAX
+                    } else {
+                        Self::parse_level(high)?
+                    };
+                    (low, high)
+                } else {
+                    let level = Self::parse_level(token)?;
+                    (level, level)
+                };
+            ranges.push(range);
+        }
+        if ranges.is_empty() {
+            return Err("no MS levels were given".to_string());
+        }
+        Ok(Self { ranges })
+    }
+}
+
+impl Display for MSLevelSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|(low, high)| {
+                if low == high {
+                    low.to_string()
+                } else if *high == u8::MAX {
+                    format!("{low}-")
+                } else {
+                    format!("{low}-{high}")
+                }
+            })
+            .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// The precursor activation types recognized by msconvert's `activation` filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActivationType {
+    CID,
+    HCD,
+    ETD,
+    ECD,
+    IRMPD,
+    BIRD,
+    PD,
+    PSD,
+    PQD,
+    SID,
+    /// Any supplemental activation, like the supplemental collisions of EThcD
+    SA,
+    Any,
+}
+
+impl ActivationType {
+    const HCD_TERMS: &'static [DissociationMethodTerm] = &[
+        DissociationMethodTerm::BeamTypeCollisionInducedDissociation,
+        DissociationMethodTerm::HigherEnergyBeamTypeCollisionInducedDissociation,
+    ];
+
+    fn terms(&self) -> &'static [DissociationMethodTerm] {
+        match self {
+            Self::CID => &[
+                DissociationMethodTerm::CollisionInducedDissociation,
+                DissociationMethodTerm::TrapTypeCollisionInducedDissociation,
+                DissociationMethodTerm::LowEnergyCollisionInducedDissociation,
+            ],
+            Self::HCD => Self::HCD_TERMS,
+            Self::ETD => &[DissociationMethodTerm::ElectronTransferDissociation],
+            Self::ECD => &[DissociationMethodTerm::ElectronCaptureDissociation],
+            Self::IRMPD => &[DissociationMethodTerm::InfraredMultiphotonDissociation],
+            Self::BIRD => &[DissociationMethodTerm::BlackbodyInfraredRadiativeDissociation],
+            Self::PD => &[
+                DissociationMethodTerm::Photodissociation,
+                DissociationMethodTerm::UltravioletPhotodissociation,
+            ],
+            Self::PSD => &[DissociationMethodTerm::PostSourceDecay],
+            Self::PQD => &[DissociationMethodTerm::PulsedQDissociation],
+            Self::SID => &[DissociationMethodTerm::SurfaceInducedDissociation],
+            Self::SA | Self::Any => &[],
+        }
+    }
+
+    /// Test whether `spectrum` was produced by this kind of activation. Spectra without a
+    /// precursor always match, as in msconvert.
+    pub fn matches<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>(
+        &self,
+        spectrum: &MultiLayerSpectrum<C, D>,
+    ) -> bool {
+        let precursor = match spectrum.description.precursors.first() {
+            Some(precursor) => precursor,
+            None => return true,
+        };
+        let activation = &precursor.activation;
+        match self {
+            Self::Any => true,
+            Self::SA => activation.has_supplemental_activation(),
+            // Beam-type CID is reported as HCD, not CID
+            Self::CID => {
+                self.terms().iter().any(|t| activation.has_method(t))
+                    && !Self::HCD_TERMS.iter().any(|t| activation.has_method(t))
+            }
+            _ => self.terms().iter().any(|t| activation.has_method(t)),
+        }
+    }
+}
+
+impl FromStr for ActivationType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = match s.to_ascii_uppercase().as_str() {
+            "CID" => Self::CID,
+            "HCD" => Self::HCD,
+            "ETD" => Self::ETD,
+            "ECD" => Self::ECD,
+            "IRMPD" => Self::IRMPD,
+            "BIRD" => Self::BIRD,
+            "PD" => Self::PD,
+            "PSD" => Self::PSD,
+            "PQD" => Self::PQD,
+            "SID" => Self::SID,
+            "SA" => Self::SA,
+            "ANY" => Self::Any,
+            _ => return Err(format!("`{s}` is not a recognized activation type")),
+        };
+        Ok(value)
+    }
+}
+
+/// A single parsed msconvert filter expression
+#[derive(Debug)]
+pub enum MSConvertFilter {
+    /// Keep spectra whose MS level is in the set
+    MSLevel(MSLevelSet),
+    /// Keep spectra whose scan start time in seconds is within the inclusive interval
+    ScanTime { start: f64, end: f64 },
+    /// Keep spectra with the given polarity
+    Polarity(ScanPolarity),
+    /// Keep MSn spectra produced by the given activation type
+    Activation(ActivationType),
+    /// Apply peak transforms to spectra whose MS level is in the set
+    Transform {
+        chain: TransformChain,
+        ms_levels: MSLevelSet,
+    },
+    /// Pick peaks from profile spectra whose MS level is in the set
+    PeakPicking {
+        signal_to_noise: f32,
+        ms_levels: MSLevelSet,
+    },
+}
+
+impl MSConvertFilter {
+    fn parse_interval(filter: &str, args: &str) -> Result<(f64, f64), MSConvertFilterError> {
+        let interval = args
+            .trim()
+            .strip_prefix('[')
+            .and_then(|t| t.strip_suffix(']'))
+            .ok_or_else(|| {
+                MSConvertFilterError::invalid(
+                    filter,
+                    format!("expected `[start,end]`, got `{args}`"),
+                )
+            })?;
+        let (low, high) = interval.split_once(',').ok_or_else(|| {
+            MSConvertFilterError::invalid(filter, format!("expected `[start,end]`, got `{args}`"))
+        })?;
+        let parse = |v: &str| {
+            v.trim().parse::<f64>().map_err(|_| {
+                MSConvertFilterError::invalid(filter, format!("`{v}` is not a number"))
+            })
+        };
+        let (low, high) = (parse(low)?, parse(high)?);
+        if low > high {
+            return Err(MSConvertFilterError::invalid(
+                filter,
+                format!("the interval start {low} is after its end {high}"),
+            ));
+        }
+        Ok((low, high))
+    }
+
+    fn parse_levels(filter: &str, args: &str) -> Result<MSLevelSet, MSConvertFilterError> {
+        if args.trim().is_empty() {
+            Ok(MSLevelSet::all())
+        } else {
+            args.parse()
+                .map_err(|e: String| MSConvertFilterError::invalid(filter, e))
+        }
+    }
+
+    fn parse_threshold(filter: &str, args: &str) -> Result<Self, MSConvertFilterError> {
+        let mut tokens = args.split_whitespace();
+        let (kind, value, orientation) = match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some(kind), Some(value), Some(orientation)) => (kind, value, orientation),
+            _ => {
+                return Err(MSConvertFilterError::invalid(
+                    filter,
+                    "expected `<type> <threshold> <orientation>`",
+                ))
+            }
+        };
+        if orientation != "most-intense" {
+            return Err(MSConvertFilterError::unsupported_option(
+                filter,
+                orientation,
+            ));
+        }
+        let invalid_value =
+            || MSConvertFilterError::invalid(filter, format!("`{value}` is not a valid threshold"));
+        let threshold = match kind {
+            "count" => IntensityThreshold::Count(value.parse().map_err(|_| invalid_value())?),
+            "absolute" => IntensityThreshold::Absolute(value.parse().map_err(|_| invalid_value())?),
+            "bpi-relative" => {
+                IntensityThreshold::Relative(value.parse().map_err(|_| invalid_value())?)
+            }
+            "tic-relative" => {
+                IntensityThreshold::RelativeToTotal(value.parse().map_err(|_| invalid_value())?)
+            }
+            _ => return Err(MSConvertFilterError::unsupported_option(filter, kind)),
+        };
+        let rest: Vec<&str> = tokens.collect();
+        Ok(Self::Transform {
+            chain: TransformChain::new().then(threshold),
+            ms_levels: Self::parse_levels(filter, &rest.join(" "))?,
+        })
+    }
+
+    fn parse_zero_samples(filter: &str, args: &str) -> Result<Self, MSConvertFilterError> {
+        let mut tokens = args.split_whitespace();
+        match tokens.next() {
+            Some("removeExtra") => {}
+            Some(mode) => return Err(MSConvertFilterError::unsupported_option(filter, mode)),
+            None => {
+                return Err(MSConvertFilterError::invalid(
+                    filter,
+                    "expected `removeExtra [<levels>]`",
+                ))
+            }
+        }
+        let rest: Vec<&str> = tokens.collect();
+        Ok(Self::Transform {
+            chain: TransformChain::new().then(RemoveExtraZeros),
+            ms_levels: Self::parse_levels(filter, &rest.join(" "))?,
+        })
+    }
+
+    fn parse_peak_picking(filter: &str, args: &str) -> Result<Self, MSConvertFilterError> {
+        let mut signal_to_noise = 1.0;
+        let mut ms_levels = MSLevelSet::all();
+        let mut tokens = args.split_whitespace().peekable();
+        match tokens.peek().copied() {
+            // The older `peakPicking <prefer vendor> <levels>` form
+            Some("true") | Some("false") => {
+                tokens.next();
+                let rest: Vec<&str> = tokens.collect();
+                ms_levels = Self::parse_levels(filter, &rest.join(" "))?;
+            }
+            _ => {
+                if let Some("vendor") | Some("cwt") = tokens.peek().copied() {
+                    tokens.next();
+                }
+                for token in tokens {
+                    match token.split_once('=') {
+                        Some(("snr", value)) => {
+                            signal_to_noise = value.parse().map_err(|_| {
+                                MSConvertFilterError::invalid(
+                                    filter,
+                                    format!("`{value}` is not a valid signal-to-noise ratio"),
+                                )
+                            })?;
+                        }
+                        Some(("msLevel", value)) => {
+                            ms_levels = Self::parse_levels(filter, value)?;
+                        }
+                        _ => return Err(MSConvertFilterError::unsupported_option(filter, token)),
+                    }
+                }
+            }
+        }
+        if cfg!(feature = "mzsignal") {
+            Ok(Self::PeakPicking {
+                signal_to_noise,
+                ms_levels,
+            })
+        } else {
+            Err(MSConvertFilterError::RequiresFeature {
+                filter: filter.to_string(),
+                feature: "mzsignal".to_string(),
+            })
+        }
+    }
+
+    /// The kind of processing this filter performs, recorded in the
+    /// [`ProcessingMethod`](crate::meta::ProcessingMethod)
+    pub fn action(&self) -> DataProcessingAction {
+        match self {
+            Self::Transform { chain, .. } => chain
+                .iter()
+                .next()
+                .map(|t| t.action())
+                .unwrap_or(DataProcessingAction::DataFiltering),
+            Self::PeakPicking { .. } => DataProcessingAction::PeakPicking,
+            _ => DataProcessingAction::DataFiltering,
+        }
+    }
+
+    /// A human readable description of this filter and its parameters
+    pub fn describe(&self) -> String {
+        match self {
+            Self::MSLevel(levels) => format!("keep MS levels {levels}"),
+            Self::ScanTime { start, end } => {
+                format!("keep scan start times from {start} to {end} seconds")
+            }
+            Self::Polarity(polarity) => format!("keep {polarity:?} polarity scans"),
+            Self::Activation(activation) => format!("keep {activation:?} activation scans"),
+            Self::Transform { chain, ms_levels } => {
+                let steps: Vec<String> = chain.iter().map(|t| t.describe()).collect();
+                format!("{} for MS levels {ms_levels}", steps.join(", "))
+            }
+            Self::PeakPicking {
+                signal_to_noise,
+                ms_levels,
+            } => format!(
+                "pick peaks with signal-to-noise threshold {signal_to_noise} for MS levels {ms_levels}"
+            ),
+        }
+    }
+
+    /// Apply the filter to `spectrum`, returning whether the spectrum should be kept
+    pub fn apply<C, D>(
+        &self,
+        spectrum: &mut MultiLayerSpectrum<C, D>,
+    ) -> Result<bool, SpectrumProcessingError>
+    where
+        C: CentroidPeakAdapting + IntensityMeasurementMut,
+        D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+    {
+        let keep = match self {
+            Self::MSLevel(levels) => levels.contains(spectrum.ms_level()),
+            Self::ScanTime { start, end } => {
+                let time = spectrum.start_time() * 60.0;
+                *start <= time && time <= *end
+            }
+            Self::Polarity(polarity) => spectrum.polarity() == *polarity,
+            Self::Activation(activation) => activation.matches(spectrum),
+            Self::Transform { chain, ms_levels } => {
+                if ms_levels.contains(spectrum.ms_level()) {
+                    chain.transform(spectrum)?;
+                }
+                true
+            }
+            Self::PeakPicking {
+                signal_to_noise,
+                ms_levels,
+            } => {
+                if ms_levels.contains(spectrum.ms_level()) {
+                    pick_peaks(spectrum, *signal_to_noise)?;
+                }
+                true
+            }
+        };
+        Ok(keep)
+    }
+
+    /// Whether this filter may remove whole spectra
+    pub fn is_selection(&self) -> bool {
+        matches!(
+            self,
+            Self::MSLevel(_) | Self::ScanTime { .. } | Self::Polarity(_) | Self::Activation(_)
+        )
+    }
+}
+
+#[cfg(feature = "mzsignal")]
+fn pick_peaks<C: CentroidPeakAdapting, D: DeconvolutedCentroidLike + Default>(
+    spectrum: &mut MultiLayerSpectrum<C, D>,
+    signal_to_noise: f32,
+) -> Result<(), SpectrumProcessingError> {
+    use mzsignal::peak_picker::{PeakFitType, PeakPicker};
+
+    if spectrum.arrays.is_none() {
+        return Ok(());
+    }
+    let peak_picker = PeakPicker {
+        fit_type: PeakFitType::Quadratic,
+        signal_to_noise_threshold: signal_to_noise,
+        ..Default::default()
+    };
+    spectrum.pick_peaks_into_arrays(&peak_picker)
+}
+
+#[cfg(not(feature = "mzsignal"))]
+fn pick_peaks<C: CentroidPeakAdapting, D: DeconvolutedCentroidLike + Default>(
+    _spectrum: &mut MultiLayerSpectrum<C, D>,
+    _signal_to_noise: f32,
+) -> Result<(), SpectrumProcessingError> {
+    Err(SpectrumProcessingError::RequiresFeature(
+        "mzsignal".to_string(),
+    ))
+}
+
+impl FromStr for MSConvertFilter {
+    type Err = MSConvertFilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, args) = s
+            .split_once(char::is_whitespace)
+            .map(|(name, args)| (name, args.trim()))
+            .unwrap_or((s, ""));
+        if name.is_empty() {
+            return Err(MSConvertFilterError::Empty);
+        }
+        match name {
+            "msLevel" => {
+                if args.is_empty() {
+                    return Err(MSConvertFilterError::invalid(
+                        name,
+                        "no MS levels were given",
+                    ));
+                }
+                Ok(Self::MSLevel(Self::parse_levels(name, args)?))
+            }
+            "scanTime" => {
+                let (start, end) = Self::parse_interval(name, args)?;
+                Ok(Self::ScanTime { start, end })
+            }
+            "polarity" => match args {
+                "positive" | "+" => Ok(Self::Polarity(ScanPolarity::Positive)),
+                "negative" | "-" => Ok(Self::Polarity(ScanPolarity::Negative)),
+                _ => Err(MSConvertFilterError::invalid(
+                    name,
+                    format!("expected `positive` or `negative`, got `{args}`"),
+                )),
+            },
+            "activation" => {
+                Ok(Self::Activation(args.parse().map_err(|e: String| {
+                    MSConvertFilterError::invalid(name, e)
+                })?))
+            }
+            "mzWindow" => {
+                let (low, high) = Self::parse_interval(name, args)?;
+                Ok(Self::Transform {
+                    chain: TransformChain::new().then(ClipMzRange::new(low, high)),
+                    ms_levels: MSLevelSet::all(),
+                })
+            }
+            "threshold" => Self::parse_threshold(name, args),
+            "zeroSamples" => Self::parse_zero_samples(name, args),
+            "peakPicking" => Self::parse_peak_picking(name, args),
+            _ if UNSUPPORTED_FILTERS.contains(&name) => {
+                Err(MSConvertFilterError::UnsupportedFilter(name.to_string()))
+            }
+            _ => Err(MSConvertFilterError::UnknownFilter(name.to_string())),
+        }
+    }
+}
+
+/// An ordered list of [`MSConvertFilter`]s, applied to each spectrum in turn as msconvert does
+#[derive(Debug, Default)]
+pub struct MSConvertFilterChain {
+    filters: Vec<MSConvertFilter>,
+}
+
+impl MSConvertFilterChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse each of `expressions` as an [`MSConvertFilter`], stopping at the first error
+    pub fn parse<I, T>(expressions: I) -> Result<Self, MSConvertFilterError>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let filters = expressions
+            .into_iter()
+            .map(|expr| expr.as_ref().parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { filters })
+    }
+
+    pub fn push(&mut self, filter: MSConvertFilter) {
+        self.filters.push(filter);
+    }
+
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, MSConvertFilter> {
+        self.filters.iter()
+    }
+
+    /// Apply each filter in turn to `spectrum`, returning whether the spectrum should be kept.
+    /// Once a filter rejects the spectrum, the remaining filters are skipped.
+    pub fn apply<C, D>(
+        &self,
+        spectrum: &mut MultiLayerSpectrum<C, D>,
+    ) -> Result<bool, SpectrumProcessingError>
+    where
+        C: CentroidPeakAdapting + IntensityMeasurementMut,
+        D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+    {
+        for filter in self.iter() {
+            if !filter.apply(spectrum)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Append a [`ProcessingMethod`](crate::meta::ProcessingMethod) for each filter in the chain
+    /// to `data_processing`, attributed to the [`Software`](crate::meta::Software) with id
+    /// `software_reference`
+    pub fn record_processing(
+        &self,
+        data_processing: &mut DataProcessing,
+        software_reference: &str,
+    ) {
+        for filter in self.iter() {
+            append_processing_method(
+                data_processing,
+                software_reference,
+                [
+                    filter.action().into(),
+                    Param::new_key_value("msconvert filter", filter.describe()),
+                ],
+            );
+        }
+    }
+
+    /// Wrap `source` in an [`MSConvertFilteredSource`] which applies this chain to each spectrum
+    pub fn filter_source<R, C, D>(self, source: R) -> MSConvertFilteredSource<R, C, D>
+    where
+        R: Iterator<Item = MultiLayerSpectrum<C, D>> + MSDataFileMetadata,
+        C: CentroidPeakAdapting + IntensityMeasurementMut,
+        D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+    {
+        MSConvertFilteredSource::new(source, self)
+    }
+}
+
+/// Adapt an iterator over spectra by applying an [`MSConvertFilterChain`] to each spectrum,
+/// skipping those which are rejected.
+///
+/// Each filter is recorded in the adaptor's [`AdaptorMetadata`]. Spectra which fail to be
+/// processed are logged and dropped, as they may not have passed the chain's selection filters.
+#[derive(Debug)]
+pub struct MSConvertFilteredSource<
+    R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+    C: CentroidPeakAdapting + IntensityMeasurementMut,
+    D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+> {
+    source: R,
+    chain: MSConvertFilterChain,
+    metadata: AdaptorMetadata,
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidPeakAdapting + IntensityMeasurementMut,
+        D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+    > MSConvertFilteredSource<R, C, D>
+{
+    pub fn new(source: R, chain: MSConvertFilterChain) -> Self
+    where
+        R: MSDataFileMetadata,
+    {
+        let mut metadata = AdaptorMetadata::new(&source);
+        // Once spectra may be skipped, the source's count is no longer meaningful
+        if chain.iter().any(|f| f.is_selection()) {
+            metadata.set_spectrum_count_hint(None);
+        }
+        if !chain.is_empty() {
+            metadata.record_processing("msconvert_filters", |dp, software_reference| {
+                chain.record_processing(dp, software_reference)
+            });
+        }
+        Self {
+            source,
+            chain,
+            metadata,
+        }
+    }
+
+    pub fn chain(&self) -> &MSConvertFilterChain {
+        &self.chain
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidPeakAdapting + IntensityMeasurementMut,
+        D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+    > MSDataFileMetadata for MSConvertFilteredSource<R, C, D>
+{
+    crate::delegate_impl_metadata_trait!(metadata);
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidPeakAdapting + IntensityMeasurementMut,
+        D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+    > Iterator for MSConvertFilteredSource<R, C, D>
+{
+    type Item = MultiLayerSpectrum<C, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for mut spectrum in self.source.by_ref() {
+            match self.chain.apply(&mut spectrum) {
+                Ok(true) => return Some(spectrum),
+                Ok(false) => {}
+                Err(e) => {
+                    warn!(
+                        "Failed to apply filters to spectrum {}, dropping it: {e}",
+                        spectrum.id()
+                    );
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use crate::MzMLReader;
+
+    #[test]
+    fn test_parse_levels() {
+        let levels: MSLevelSet = "1 3-4 6-".parse().unwrap();
+        assert!(levels.contains(1));
+        assert!(!levels.contains(2));
+        assert!(levels.contains(4));
+        assert!(!levels.contains(5));
+        assert!(levels.contains(10));
+        assert_eq!(levels.to_string(), "1 3-4 6-");
+
+        let levels: MSLevelSet = "[2,3]".parse().unwrap();
+        assert!(levels.contains(3) && !levels.contains(1));
+        assert!("two".parse::<MSLevelSet>().is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = "frobnicate 1".parse::<MSConvertFilter>().unwrap_err();
+        assert_eq!(
+            err,
+            MSConvertFilterError::UnknownFilter("frobnicate".into())
+        );
+
+        let err = "threshold count 150 least-intense"
+            .parse::<MSConvertFilter>()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            MSConvertFilterError::UnsupportedOption { .. }
+        ));
+
+        let err = "zeroSamples addMissing"
+            .parse::<MSConvertFilter>()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            MSConvertFilterError::UnsupportedOption { .. }
+        ));
+
+        let err = "scanTime 600 1200".parse::<MSConvertFilter>().unwrap_err();
+        assert!(matches!(err, MSConvertFilterError::InvalidArgument { .. }));
+
+        let err = "polarity sideways".parse::<MSConvertFilter>().unwrap_err();
+        assert!(matches!(err, MSConvertFilterError::InvalidArgument { .. }));
+
+        let err = "peakPicking vendor msLevel=1-".parse::<MSConvertFilter>();
+        if cfg!(feature = "mzsignal") {
+            assert!(matches!(err, Ok(MSConvertFilter::PeakPicking { .. })));
+        } else {
+            assert!(matches!(
+                err,
+                Err(MSConvertFilterError::RequiresFeature { .. })
+            ));
+        }
+
+        assert_eq!(
+            "".parse::<MSConvertFilter>().unwrap_err(),
+            MSConvertFilterError::Empty
+        );
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        let chain = MSConvertFilterChain::parse([
+            "msLevel 2-",
+            "scanTime [600, 1200]",
+            "threshold count 150 most-intense",
+            "activation HCD",
+            "polarity positive",
+            "zeroSamples removeExtra 1",
+            "mzWindow [100,2000]",
+        ])
+        .unwrap();
+        let filters: Vec<_> = chain.iter().collect();
+        assert!(matches!(filters[0], MSConvertFilter::MSLevel(levels) if !levels.contains(1)));
+        assert!(matches!(
+            filters[1],
+            MSConvertFilter::ScanTime { start, end } if *start == 600.0 && *end == 1200.0
+        ));
+        assert!(matches!(
+            filters[2],
+            MSConvertFilter::Transform { ms_levels, .. } if ms_levels.contains(1)
+        ));
+        assert!(matches!(
+            filters[3],
+            MSConvertFilter::Activation(ActivationType::HCD)
+        ));
+        assert!(matches!(
+            filters[4],
+            MSConvertFilter::Polarity(ScanPolarity::Positive)
+        ));
+        assert!(matches!(
+            filters[5],
+            MSConvertFilter::Transform { ms_levels, .. } if !ms_levels.contains(2)
+        ));
+        assert_eq!(
+            filters[2].describe(),
+            "keep 150 most intense peaks for MS levels 1-"
+        );
+    }
+
+    #[test]
+    fn test_filter_source() {
+        let reader = MzMLReader::open_path("./test/data/three_test_scans.mzML").unwrap();
+        let n_methods: Vec<_> = reader
+            .data_processings()
+            .iter()
+            .map(|dp| dp.methods.len())
+            .collect();
+        let chain =
+            MSConvertFilterChain::parse(["msLevel 2-", "threshold count 10 most-intense"]).unwrap();
+        let source = chain.filter_source(reader);
+        assert_eq!(source.spectrum_count_hint(), None);
+        for (dp, n) in source.data_processings().iter().zip(n_methods) {
+            assert_eq!(dp.methods.len(), n + 2);
+        }
+
+        let spectra: Vec<_> = source.collect();
+        assert!(!spectra.is_empty());
+        for spectrum in spectra {
+            assert!(spectrum.ms_level() > 1);
+            assert!(spectrum.peaks().len() <= 10);
+        }
+    }
+
+    #[cfg(not(feature = "mzsignal"))]
+    #[test]
+    fn test_filter_source_drops_failures() {
+        let reader = MzMLReader::open_path("./test/data/three_test_scans.mzML").unwrap();
+        let mut chain = MSConvertFilterChain::new();
+        chain.push(MSConvertFilter::PeakPicking {
+            signal_to_noise: 1.0,
+            ms_levels: MSLevelSet::all(),
+        });
+        assert_eq!(chain.filter_source(reader).count(), 0);
+    }
+}
@@ -0,0 +1,410 @@
+//! Estimate the noise level of spectra and the signal-to-noise ratio of their points.
+//!
+//! Few instrument vendors report a noise level for each point. A [`NoiseEstimator`] divides
+//! the m/z range of a spectrum into windows and, in each window, repeatedly excludes the
+//! intensities more than [`NoiseEstimator::clip_threshold`] scaled median absolute deviations
+//! above the median until only the noise remains:
+//!
+//! - For profile data, the median of the remaining intensities is the baseline and their scaled
+//!   median absolute deviation is the noise level.
+//! - For centroid data, which has no baseline, the median of the remaining peak intensities is
+//!   the noise level.
+//!
+//! The estimates of neighbouring windows are linearly interpolated to each point. They may be
+//! stored alongside the signal in a [`BinaryArrayMap`] as [`ArrayType::BaselineArray`],
+//! [`ArrayType::NoiseArray`] and [`ArrayType::SignalToNoiseArray`]. When the `mzsignal` feature
+//! is enabled, the peak picking methods of [`MultiLayerSpectrum`] give each picked peak the
+//! signal-to-noise ratio stored at its apex, and [`MultiLayerSpectrum::pick_peaks_into_arrays`]
+//! keeps those ratios in the picked peaks' own [`ArrayType::SignalToNoiseArray`].
+use mzpeaks::prelude::*;
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike, MZ};
+
+use super::bindata::{
+    ArrayRetrievalError, ArrayType, BinaryArrayMap, BinaryDataArrayType, DataArray,
+};
+use super::chromatogram::processing::median;
+use super::scan_properties::SignalContinuity;
+use super::spectrum_types::{MultiLayerSpectrum, SpectrumConversionError, SpectrumProcessingError};
+
+/// The ratio of the standard deviation of normally distributed values to their median
+/// absolute deviation
+const MAD_SCALE: f32 = 1.4826;
+
+/// The baseline-subtracted `intensity` divided by the `noise` level, never negative
+pub fn signal_to_noise(intensity: f32, baseline: f32, noise: f32) -> f32 {
+    ((intensity - baseline) / noise.max(f32::EPSILON)).max(0.0)
+}
+
+/// The baseline and noise level at each point of a spectrum
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NoiseEstimate {
+    pub baseline: Vec<f32>,
+    pub noise: Vec<f32>,
+}
+
+impl NoiseEstimate {
+    pub fn len(&self) -> usize {
+        self.noise.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.noise.is_empty()
+    }
+
+    /// The signal-to-noise ratio of each of the `intensities` the estimate was made from
+    pub fn signal_to_noise(&self, intensities: &[f32]) -> Vec<f32> {
+        intensities
+            .iter()
+            .zip(self.baseline.iter().zip(self.noise.iter()))
+            .map(|(i, (b, n))| signal_to_noise(*i, *b, *n))
+            .collect()
+    }
+}
+
+/// Estimate the noise level of spectra by iterative median absolute deviation clipping in
+/// m/z windows. See the [module documentation](self) for a description of the algorithm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseEstimator {
+    /// The width of each m/z window
+    pub window_width: f64,
+    /// The number of scaled median absolute deviations above the median beyond which an
+    /// intensity is considered signal
+    pub clip_threshold: f32,
+    /// The maximum number of clipping rounds in each window
+    pub max_iterations: usize,
+    /// The minimum number of non-zero intensities a window needs to be estimated on its own
+    pub min_points: usize,
+}
+
+impl Default for NoiseEstimator {
+    fn default() -> Self {
+        Self {
+            window_width: 100.0,
+            clip_threshold: 3.0,
+            max_iterations: 10,
+            min_points: 5,
+        }
+    }
+}
+
+impl NoiseEstimator {
+    pub fn new(window_width: f64) -> Self {
+        Self {
+            window_width,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_clip_threshold(mut self, clip_threshold: f32) -> Self {
+        self.clip_threshold = clip_threshold;
+        self
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn with_min_points(mut self, min_points: usize) -> Self {
+        self.min_points = min_points;
+        self
+    }
+
+    /// The median and scaled median absolute deviation of `values`
+    fn median_and_deviation(values: &[f32]) -> (f32, f32) {
+        let center = median(&mut values.to_vec());
+        let mut deviations: Vec<f32> = values.iter().map(|v| (v - center).abs()).collect();
+        (center, median(&mut deviations) * MAD_SCALE)
+    }
+
+    /// Clip the signal out of `values`, returning the baseline and noise level of the rest
+    fn window_level(
+        &self,
+        mut values: Vec<f32>,
+        continuity: SignalContinuity,
+        min_points: usize,
+    ) -> Option<(f32, f32)> {
+        values.retain(|v| *v > 0.0);
+        if values.is_empty() || values.len() < min_points {
+            return None;
+        }
+        let (mut center, mut deviation) = Self::median_and_deviation(&values);
+        for _ in 0..self.max_iterations {
+            let limit = center + self.clip_threshold * deviation;
+            let kept: Vec<f32> = values.iter().copied().filter(|v| *v <= limit).collect();
+            if kept.len() == values.len() || kept.len() < min_points.max(1) {
+                break;
+            }
+            values = kept;
+            (center, deviation) = Self::median_and_deviation(&values);
+        }
+        match continuity {
+            SignalContinuity::Profile => Some((center, deviation)),
+            _ => Some((0.0, center)),
+        }
+    }
+
+    /// Estimate the baseline and noise level at each point of a spectrum
+    pub fn estimate(
+        &self,
+        mzs: &[f64],
+        intensities: &[f32],
+        continuity: SignalContinuity,
+    ) -> NoiseEstimate {
+        let n = mzs.len().min(intensities.len());
+        if n == 0 {
+            return NoiseEstimate::default();
+        }
+        let first = mzs[0];
+        let width = self.window_width.max(f64::EPSILON);
+
+        // The m/z at the middle of each window and its baseline and noise level
+        let mut knots: Vec<(f64, f32, f32)> = Vec::new();
+        let mut start = 0;
+        while start < n {
+            let window = ((mzs[start] - first) / width).floor();
+            let end = start
+                + mzs[start..n].partition_point(|mz| ((mz - first) / width).floor() <= window);
+            let values = intensities[start..end].to_vec();
+            if let Some((baseline, noise)) = self.window_level(values, continuity, self.min_points)
+            {
+                knots.push((first + (window + 0.5) * width, baseline, noise));
+            }
+            start = end;
+        }
+        if knots.is_empty() {
+            // Too little signal to estimate any window on its own, so pool all of it
+            let (baseline, noise) = self
+                .window_level(intensities[..n].to_vec(), continuity, 1)
+                .unwrap_or_default();
+            return NoiseEstimate {
+                baseline: vec![baseline; n],
+                noise: vec![noise; n],
+            };
+        }
+
+        let mut estimate = NoiseEstimate {
+            baseline: Vec::with_capacity(n),
+            noise: Vec::with_capacity(n),
+        };
+        for mz in mzs[..n].iter().copied() {
+            let i = knots.partition_point(|k| k.0 < mz);
+            let (baseline, noise) = if i == 0 {
+                (knots[0].1, knots[0].2)
+            } else if i == knots.len() {
+                (knots[i - 1].1, knots[i - 1].2)
+            } else {
+                let (x0, b0, n0) = knots[i - 1];
+                let (x1, b1, n1) = knots[i];
+                let t = ((mz - x0) / (x1 - x0)) as f32;
+                (b0 + (b1 - b0) * t, n0 + (n1 - n0) * t)
+            };
+            estimate.baseline.push(baseline);
+            estimate.noise.push(noise);
+        }
+        estimate
+    }
+
+    /// Estimate the noise level at each of a list of centroided `peaks`
+    pub fn estimate_peaks<P: CoordinateLike<MZ> + IntensityMeasurement>(
+        &self,
+        peaks: &[P],
+    ) -> NoiseEstimate {
+        let mzs: Vec<f64> = peaks.iter().map(|p| p.coordinate()).collect();
+        let intensities: Vec<f32> = peaks.iter().map(|p| p.intensity()).collect();
+        self.estimate(&mzs, &intensities, SignalContinuity::Centroid)
+    }
+
+    /// Estimate the noise level of the signal in `arrays`, storing the estimate as
+    /// [`ArrayType::BaselineArray`], [`ArrayType::NoiseArray`] and [`ArrayType::SignalToNoiseArray`]
+    /// arrays and replacing any already present.
+    pub fn annotate_arrays(
+        &self,
+        arrays: &mut BinaryArrayMap,
+        continuity: SignalContinuity,
+    ) -> Result<NoiseEstimate, ArrayRetrievalError> {
+        let (estimate, snr) = {
+            let mzs = arrays.mzs()?;
+            let intensities = arrays.intensities()?;
+            if mzs.len() != intensities.len() {
+                return Err(ArrayRetrievalError::DataTypeSizeMismatch);
+            }
+            let estimate = self.estimate(&mzs, &intensities, continuity);
+            let snr = estimate.signal_to_noise(&intensities);
+            (estimate, snr)
+        };
+        let annotations = vec![
+            (ArrayType::BaselineArray, &estimate.baseline),
+            (ArrayType::NoiseArray, &estimate.noise),
+            (ArrayType::SignalToNoiseArray, &snr),
+        ];
+        for (array_type, values) in annotations {
+            let mut array =
+                DataArray::from_name_and_type(&array_type, BinaryDataArrayType::Float32);
+            array.extend(values)?;
+            arrays.add(array);
+        }
+        Ok(estimate)
+    }
+
+    /// Estimate the noise level of `spectrum`, annotating its data arrays if it has them with
+    /// [`NoiseEstimator::annotate_arrays`]. Otherwise the estimate is made from its centroid
+    /// peaks, which have nowhere to store it, so it is only returned.
+    pub fn annotate_spectrum<C, D>(
+        &self,
+        spectrum: &mut MultiLayerSpectrum<C, D>,
+    ) -> Result<NoiseEstimate, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+    {
+        let continuity = spectrum.description.signal_continuity;
+        if let Some(arrays) = spectrum
+            .arrays
+            .as_mut()
+            .filter(|a| a.has_array(&ArrayType::MZArray))
+        {
+            Ok(self.annotate_arrays(arrays, continuity)?)
+        } else if let Some(peaks) = spectrum.peaks.as_ref() {
+            let mzs: Vec<f64> = peaks.iter().map(|p| p.mz()).collect();
+            let intensities: Vec<f32> = peaks.iter().map(|p| p.intensity()).collect();
+            Ok(self.estimate(&mzs, &intensities, SignalContinuity::Centroid))
+        } else {
+            Err(SpectrumConversionError::NoPeakData.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use crate::spectrum::{CentroidSpectrum, SpectrumDescription};
+    use mzpeaks::CentroidPeak;
+
+    /// A deterministic sawtooth between `level - amplitude` and `level + amplitude`
+    fn noise_at(i: usize, level: f32, amplitude: f32) -> f32 {
+        level + amplitude * (((i * 7) % 11) as f32 / 5.0 - 1.0)
+    }
+
+    fn make_profile() -> (Vec<f64>, Vec<f32>) {
+        let mzs: Vec<f64> = (0..4000).map(|i| 200.0 + i as f64 * 0.05).collect();
+        let mut intensities: Vec<f32> = (0..4000)
+            .map(|i| noise_at(i, 100.0 + i as f32 * 0.05, 10.0))
+            .collect();
+        for center in [500, 1500, 3000] {
+            for offset in 0..5 {
+                intensities[center + offset] += [500.0, 2000.0, 5000.0, 2000.0, 500.0][offset];
+            }
+        }
+        (mzs, intensities)
+    }
+
+    #[test]
+    fn test_estimate_profile() {
+        let (mzs, intensities) = make_profile();
+        let estimator = NoiseEstimator::new(25.0);
+        let estimate = estimator.estimate(&mzs, &intensities, SignalContinuity::Profile);
+        assert_eq!(estimate.len(), mzs.len());
+
+        // The estimate follows the rising baseline
+        assert!(
+            (estimate.baseline[200] - 110.0).abs() < 5.0,
+            "{}",
+            estimate.baseline[200]
+        );
+        assert!(
+            (estimate.baseline[3800] - 290.0).abs() < 5.0,
+            "{}",
+            estimate.baseline[3800]
+        );
+        assert!(
+            estimate.noise[200] > 1.0 && estimate.noise[200] < 20.0,
+            "{}",
+            estimate.noise[200]
+        );
+
+        let snr = estimate.signal_to_noise(&intensities);
+        assert!(snr[1502] > 100.0, "{}", snr[1502]);
+        assert!(snr[3002] > 100.0, "{}", snr[3002]);
+        let noisy = (0..4000).filter(|i| snr[*i] > 5.0).count();
+        assert_eq!(noisy, 15);
+    }
+
+    #[test]
+    fn test_annotate_spectrum() {
+        let (mzs, intensities) = make_profile();
+        let mut arrays = BinaryArrayMap::new();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array.extend(&mzs).unwrap();
+        let mut int_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        int_array.extend(&intensities).unwrap();
+        arrays.add(mz_array);
+        arrays.add(int_array);
+
+        let mut spectrum = MultiLayerSpectrum::<CentroidPeak, mzpeaks::DeconvolutedPeak>::default();
+        spectrum.description.signal_continuity = SignalContinuity::Profile;
+        spectrum.arrays = Some(arrays);
+        let estimator = NoiseEstimator::new(25.0);
+        let estimate = estimator.annotate_spectrum(&mut spectrum).unwrap();
+
+        let arrays = spectrum.raw_arrays().unwrap();
+        assert_eq!(arrays.noise().unwrap().as_ref(), estimate.noise.as_slice());
+        assert_eq!(
+            arrays.baseline().unwrap().as_ref(),
+            estimate.baseline.as_slice()
+        );
+        assert_eq!(arrays.signal_to_noise().unwrap().len(), mzs.len());
+
+        // The annotations can be written to and read from mzML
+        let mut buffer = Vec::new();
+        {
+            let mut writer = crate::io::mzml::MzMLWriter::new(&mut buffer);
+            writer.write(&spectrum).unwrap();
+            writer.close().unwrap();
+        }
+        let mut reader = crate::io::mzml::MzMLReader::new(std::io::Cursor::new(buffer));
+        let read_back = reader.next().unwrap();
+        let arrays = read_back.raw_arrays().unwrap();
+        assert_eq!(arrays.noise().unwrap().as_ref(), estimate.noise.as_slice());
+        assert_eq!(
+            arrays.baseline().unwrap().as_ref(),
+            estimate.baseline.as_slice()
+        );
+        assert!(arrays.signal_to_noise().is_ok());
+    }
+
+    #[test]
+    fn test_estimate_centroids() {
+        let mut peaks: Vec<CentroidPeak> = (0..200)
+            .map(|i| CentroidPeak::new(300.0 + i as f64 * 2.5, noise_at(i, 50.0, 20.0), 0))
+            .collect();
+        peaks.push(CentroidPeak::new(555.55, 5000.0, 0));
+        let descr = SpectrumDescription {
+            signal_continuity: SignalContinuity::Centroid,
+            ..Default::default()
+        };
+        let mut spectrum: MultiLayerSpectrum =
+            CentroidSpectrum::new(descr, peaks.into_iter().collect())
+                .into_spectrum()
+                .unwrap();
+        let estimator = NoiseEstimator::default();
+        let estimate = estimator.annotate_spectrum(&mut spectrum).unwrap();
+        assert!(estimate.baseline.iter().all(|b| *b == 0.0));
+        assert!(estimate.noise.iter().all(|n| (*n - 50.0).abs() < 10.0));
+
+        let intensities: Vec<f32> = spectrum
+            .peaks
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|p| p.intensity)
+            .collect();
+        let snr = estimate.signal_to_noise(&intensities);
+        let strong: Vec<usize> = (0..snr.len()).filter(|i| snr[*i] > 10.0).collect();
+        assert_eq!(strong.len(), 1);
+        assert_eq!(spectrum.peaks.as_ref().unwrap()[strong[0]].mz, 555.55);
+    }
+}
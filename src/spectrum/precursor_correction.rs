@@ -0,0 +1,547 @@
+//! Correct the selected ion m/z and charge of MSn spectra from their precursor MS1 spectrum.
+//!
+//! Instruments frequently select an isotopic peak other than the monoisotopic peak of a
+//! precursor ion and may not report its charge state at all. A [`PrecursorCorrector`] examines
+//! the MS1 spectrum around each [`SelectedIon`], fits the isotopic envelope the selected peak
+//! belongs to with a [`Deconvoluter`], and rewrites the ion's m/z to the monoisotopic m/z of that
+//! envelope and its charge to the envelope's charge. The values originally reported are kept
+//! as parameters, see [`SelectedIon::original_mz`] and [`SelectedIon::original_charge`].
+//!
+//! The precursor MS1 spectrum can be given directly with [`PrecursorCorrector::correct_spectrum`],
+//! taken from a [`SpectrumGroup`] with [`PrecursorCorrector::correct_group`], or tracked while
+//! streaming with [`PrecursorCorrectingSource`].
+use std::collections::VecDeque;
+
+use log::warn;
+use mzpeaks::peak::MZPoint;
+use mzpeaks::prelude::*;
+use mzpeaks::{CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak, Tolerance};
+
+use super::bindata::ArrayRetrievalError;
+use super::deconvolution::{Deconvoluter, IsotopicFit};
+use super::group::SpectrumGroup;
+use super::peaks::RefPeakDataLevel;
+use super::scan_properties::{Precursor, ScanPolarity, SelectedIon, SignalContinuity};
+use super::spectrum_types::{
+    MultiLayerSpectrum, SpectrumConversionError, SpectrumLike, SpectrumProcessingError,
+};
+use super::transforms::AdaptorMetadata;
+use crate::meta::{DataProcessingAction, MSDataFileMetadata};
+use crate::params::Param;
+
+/// Whether `precursor` of an MSn spectrum of `ms_level` was isolated from the spectrum `id`.
+///
+/// A precursor without an identifier is assumed to be isolated from the MS1 spectrum when
+/// the spectrum is an MS2 spectrum.
+pub(crate) fn is_isolated_from(precursor: &Precursor, ms_level: u8, id: &str) -> bool {
+    match precursor.precursor_id.as_deref() {
+        Some(precursor_id) => precursor_id == id,
+        None => ms_level == 2,
+    }
+}
+
+/// Determine the monoisotopic m/z and charge of selected ions from their precursor MS1 spectrum
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrecursorCorrector {
+    /// The deconvoluter used to fit the precursor's isotopic envelope
+    pub deconvoluter: Deconvoluter,
+    /// The m/z distance around the selected ion to search when the isolation window is not known
+    pub default_half_width: f64,
+    /// The m/z distance beyond the isolation window to include, so that isotopic peaks of the
+    /// precursor outside the window may still be fit
+    pub margin: f64,
+    /// Whether to only fit the charge state reported for the ion when one is present
+    pub respect_reported_charge: bool,
+}
+
+impl Default for PrecursorCorrector {
+    fn default() -> Self {
+        Self {
+            deconvoluter: Deconvoluter::default(),
+            default_half_width: 1.5,
+            margin: 3.5,
+            respect_reported_charge: false,
+        }
+    }
+}
+
+impl PrecursorCorrector {
+    pub fn new(deconvoluter: Deconvoluter) -> Self {
+        Self {
+            deconvoluter,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.deconvoluter.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_margin(mut self, margin: f64) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn with_respect_reported_charge(mut self, respect_reported_charge: bool) -> Self {
+        self.respect_reported_charge = respect_reported_charge;
+        self
+    }
+
+    /// Collect the centroided peaks of `spectrum` between `low` and `high`
+    pub(crate) fn peaks_between<C, D, S>(
+        spectrum: &S,
+        low: f64,
+        high: f64,
+    ) -> Result<Vec<MZPoint>, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        let in_range = |p: &MZPoint| low <= p.mz && p.mz <= high;
+        match spectrum.peaks() {
+            RefPeakDataLevel::Missing => Err(SpectrumConversionError::NoPeakData.into()),
+            RefPeakDataLevel::RawData(arrays) => {
+                if matches!(spectrum.signal_continuity(), SignalContinuity::Profile) {
+                    return Err(SpectrumConversionError::NotCentroided.into());
+                }
+                let mzs = arrays.mzs()?;
+                let intensities = arrays.intensities()?;
+                if mzs.len() != intensities.len() {
+                    return Err(ArrayRetrievalError::DataTypeSizeMismatch.into());
+                }
+                let start = mzs.partition_point(|mz| *mz < low);
+                Ok(mzs[start..]
+                    .iter()
+                    .zip(intensities[start..].iter())
+                    .map(|(mz, i)| MZPoint::new(*mz, *i))
+                    .take_while(in_range)
+                    .collect())
+            }
+            RefPeakDataLevel::Centroid(peaks) => Ok(peaks
+                .between(low, high, Tolerance::PPM(0.0))
+                .iter()
+                .map(|p| MZPoint::new(p.mz(), p.intensity()))
+                .collect()),
+            RefPeakDataLevel::Deconvoluted(_) => Err(SpectrumConversionError::NotCentroided.into()),
+        }
+    }
+
+    /// Fit the isotopic envelope of `ion` in `precursor_spectrum`, isolated by `window`
+    /// bounds when they are known, without modifying it.
+    pub fn fit_ion<C, D, S>(
+        &self,
+        ion: &SelectedIon,
+        window: Option<(f64, f64)>,
+        precursor_spectrum: &S,
+    ) -> Result<Option<IsotopicFit>, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        let (low, high) = window.unwrap_or((
+            ion.mz - self.default_half_width,
+            ion.mz + self.default_half_width,
+        ));
+        let peaks = Self::peaks_between(
+            precursor_spectrum,
+            low.min(ion.mz) - self.margin,
+            high.max(ion.mz) + self.margin,
+        )?;
+
+        let charge_sign = match precursor_spectrum.polarity() {
+            ScanPolarity::Negative => -1,
+            _ => 1,
+        };
+        let fit = match ion.charge.filter(|_| self.respect_reported_charge) {
+            Some(z) => self.deconvoluter.clone().with_charge_range(z, z).fit_peak(
+                &peaks,
+                ion.mz,
+                charge_sign,
+            ),
+            None => self.deconvoluter.fit_peak(&peaks, ion.mz, charge_sign),
+        };
+        Ok(fit)
+    }
+
+    /// Correct the selected ions of `spectrum` isolated from `precursor_spectrum`, returning the
+    /// number of ions that were corrected. Ions whose envelope cannot be fit are left unchanged.
+    ///
+    /// Only MS1 precursor spectra are used, and only the precursors whose
+    /// [`Precursor::precursor_id`](crate::spectrum::Precursor::precursor_id) names
+    /// `precursor_spectrum` are corrected. Precursors without an identifier are assumed to be
+    /// isolated from it when `spectrum` is an MS2 spectrum. The precursors of later stages of an
+    /// MSn experiment, isolated from a product ion spectrum, are left unchanged.
+    pub fn correct_spectrum<C, D, S, T>(
+        &self,
+        spectrum: &mut T,
+        precursor_spectrum: &S,
+    ) -> Result<usize, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        T: SpectrumLike<C, D>,
+    {
+        if precursor_spectrum.ms_level() != 1 {
+            return Ok(0);
+        }
+        let ms_level = spectrum.ms_level();
+        let mut corrected = 0;
+        for precursor in spectrum
+            .description_mut()
+            .precursors
+            .iter_mut()
+            .filter(|p| is_isolated_from(p, ms_level, precursor_spectrum.id()))
+        {
+            let window = &precursor.isolation_window;
+            let window = (!window.is_empty())
+                .then_some((window.lower_bound as f64, window.upper_bound as f64));
+            for ion in precursor.ions.iter_mut() {
+                if let Some(fit) = self.fit_ion(ion, window, precursor_spectrum)? {
+                    ion.replace_mz_and_charge(fit.monoisotopic_mz(), Some(fit.charge));
+                    corrected += 1;
+                }
+            }
+        }
+        Ok(corrected)
+    }
+
+    /// Correct the MSn spectra of `group` using the group's MS1 spectrum, returning the number
+    /// of ions that were corrected
+    pub fn correct_group<C, D, S>(
+        &self,
+        group: &mut SpectrumGroup<C, D, S>,
+    ) -> Result<usize, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        let Some(precursor) = group.precursor.as_ref() else {
+            return Ok(0);
+        };
+        let mut corrected = 0;
+        for product in group.products.iter_mut() {
+            corrected += self.correct_spectrum(product, precursor)?;
+        }
+        Ok(corrected)
+    }
+
+    /// Describe the correction as a list of parameters for a
+    /// [`ProcessingMethod`](crate::meta::ProcessingMethod)
+    pub fn as_params(&self) -> Vec<Param> {
+        vec![
+            DataProcessingAction::PrecursorRecalculation.into(),
+            DataProcessingAction::ChargeStateCalculation.into(),
+            Param::new_key_value("precursor correction", self.deconvoluter.to_string()),
+        ]
+    }
+}
+
+/// Adapt an iterator over spectra by correcting the selected ions of each MSn spectrum with
+/// a [`PrecursorCorrector`].
+///
+/// The most recent MS1 spectra are retained so that each MSn spectrum can be matched to its
+/// precursor scan by [`Precursor::precursor_id`](crate::spectrum::Precursor::precursor_id).
+/// An MS2 spectrum without any identifier is matched to the most recent MS1 spectrum, while a
+/// spectrum whose precursor scan is no longer retained is left unchanged. Only precursors
+/// isolated from an MS1 spectrum are corrected, see [`PrecursorCorrector::correct_spectrum`].
+///
+/// The correction is recorded in the adaptor's [`AdaptorMetadata`].
+#[derive(Debug)]
+pub struct PrecursorCorrectingSource<
+    R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+> {
+    source: R,
+    corrector: PrecursorCorrector,
+    recent_ms1: VecDeque<MultiLayerSpectrum<C, D>>,
+    history: usize,
+    metadata: AdaptorMetadata,
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default + Clone,
+        D: DeconvolutedCentroidLike + Default + Clone,
+    > PrecursorCorrectingSource<R, C, D>
+{
+    pub fn new(source: R, corrector: PrecursorCorrector) -> Self
+    where
+        R: MSDataFileMetadata,
+    {
+        let mut metadata = AdaptorMetadata::new(&source);
+        metadata.record_method("precursor_correction", corrector.as_params());
+        Self {
+            source,
+            corrector,
+            recent_ms1: VecDeque::new(),
+            history: 2,
+            metadata,
+        }
+    }
+
+    /// Set the number of recent MS1 spectra to retain to match against
+    pub fn with_history(mut self, history: usize) -> Self {
+        self.history = history.max(1);
+        self
+    }
+
+    pub fn corrector(&self) -> &PrecursorCorrector {
+        &self.corrector
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    fn find_precursor_spectrum(
+        &self,
+        spectrum: &MultiLayerSpectrum<C, D>,
+    ) -> Option<&MultiLayerSpectrum<C, D>> {
+        let precursors = &spectrum.description().precursors;
+        if precursors.iter().all(|p| p.precursor_id.is_none()) {
+            return if spectrum.ms_level() == 2 {
+                self.recent_ms1.back()
+            } else {
+                None
+            };
+        }
+        precursors
+            .iter()
+            .filter_map(|p| p.precursor_id.as_deref())
+            .find_map(|id| self.recent_ms1.iter().find(|s| s.id() == id))
+    }
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+    > MSDataFileMetadata for PrecursorCorrectingSource<R, C, D>
+{
+    crate::delegate_impl_metadata_trait!(metadata);
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default + Clone,
+        D: DeconvolutedCentroidLike + Default + Clone,
+    > Iterator for PrecursorCorrectingSource<R, C, D>
+{
+    type Item = MultiLayerSpectrum<C, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut spectrum = self.source.next()?;
+        if spectrum.ms_level() == 1 {
+            if self.recent_ms1.len() >= self.history {
+                self.recent_ms1.pop_front();
+            }
+            self.recent_ms1.push_back(spectrum.clone());
+        } else if let Some(precursor_spectrum) = self.find_precursor_spectrum(&spectrum) {
+            if let Err(e) = self
+                .corrector
+                .correct_spectrum(&mut spectrum, precursor_spectrum)
+            {
+                warn!(
+                    "Failed to correct the precursor of {} from {}: {e}",
+                    spectrum.id(),
+                    precursor_spectrum.id()
+                );
+            }
+        }
+        Some(spectrum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use crate::spectrum::test_utils::{centroid_spectrum, isotopic_envelope, precursor};
+    use crate::spectrum::Averagine;
+
+    /// A doubly and a triply charged envelope beside an unrelated peak
+    fn ms1_peaks() -> Vec<(f64, f32)> {
+        [
+            isotopic_envelope(1500.7, 2, 1e5),
+            isotopic_envelope(1503.2, 3, 1e5),
+            vec![(749.9, 2e4)],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_correct_spectrum() {
+        let ms1 = centroid_spectrum("ms1", 0.0, 1, &ms1_peaks(), vec![]);
+        let cluster = Averagine::PEPTIDE.isotopic_cluster(1500.7, 2);
+        let mut msn = centroid_spectrum(
+            "msn",
+            0.0,
+            2,
+            &[],
+            vec![precursor(cluster[1].mz, None, Some("ms1"))],
+        );
+
+        let corrector = PrecursorCorrector::default();
+        assert_eq!(corrector.correct_spectrum(&mut msn, &ms1).unwrap(), 1);
+
+        let ion = &msn.precursor().unwrap().ions[0];
+        assert!((ion.mz - cluster[0].mz).abs() < 1e-3, "{:?}", ion);
+        assert_eq!(ion.charge, Some(2));
+        assert!((ion.original_mz().unwrap() - cluster[1].mz).abs() < 1e-6);
+        assert_eq!(ion.original_charge(), None);
+
+        // A second correction preserves the instrument's values
+        assert_eq!(corrector.correct_spectrum(&mut msn, &ms1).unwrap(), 1);
+        let ion = &msn.precursor().unwrap().ions[0];
+        assert!((ion.original_mz().unwrap() - cluster[1].mz).abs() < 1e-6);
+
+        // The triply charged envelope overlapping the window
+        let cluster = Averagine::PEPTIDE.isotopic_cluster(1503.2, 3);
+        let mut msn = centroid_spectrum(
+            "msn",
+            0.0,
+            2,
+            &[],
+            vec![precursor(cluster[2].mz, None, Some("ms1"))],
+        );
+        assert_eq!(corrector.correct_spectrum(&mut msn, &ms1).unwrap(), 1);
+        let ion = &msn.precursor().unwrap().ions[0];
+        assert_eq!(ion.charge, Some(3));
+        assert!((ion.mz - cluster[0].mz).abs() < 1e-3, "{:?}", ion);
+
+        // Nothing to fit
+        let mut msn = centroid_spectrum(
+            "msn",
+            0.0,
+            2,
+            &[],
+            vec![precursor(900.0, None, Some("ms1"))],
+        );
+        assert_eq!(corrector.correct_spectrum(&mut msn, &ms1).unwrap(), 0);
+        assert_eq!(msn.precursor().unwrap().ions[0].mz, 900.0);
+        assert!(msn.precursor().unwrap().ions[0].original_mz().is_none());
+
+        // Isolated from another MS1 spectrum
+        let cluster = Averagine::PEPTIDE.isotopic_cluster(1500.7, 2);
+        let mut msn = centroid_spectrum(
+            "msn",
+            0.0,
+            2,
+            &[],
+            vec![precursor(cluster[1].mz, None, Some("ms0"))],
+        );
+        assert_eq!(corrector.correct_spectrum(&mut msn, &ms1).unwrap(), 0);
+        assert_eq!(msn.precursor().unwrap().ions[0].mz, cluster[1].mz);
+
+        // Only the MS1 stage of an MS3 spectrum is corrected, even when the MS2 stage's
+        // fragment falls on an envelope of the MS1 spectrum
+        let other = Averagine::PEPTIDE.isotopic_cluster(1503.2, 3);
+        let ms3_precursors = vec![
+            precursor(cluster[1].mz, None, Some("ms1")),
+            precursor(other[1].mz, None, Some("msn")),
+        ];
+        let mut ms3 = centroid_spectrum("ms3", 0.0, 3, &[], ms3_precursors.clone());
+        assert_eq!(corrector.correct_spectrum(&mut ms3, &ms1).unwrap(), 1);
+        let precursors = &ms3.description().precursors;
+        assert!((precursors[0].ions[0].mz - cluster[0].mz).abs() < 1e-3);
+        assert_eq!(precursors[1].ions[0].mz, other[1].mz);
+        assert!(precursors[1].ions[0].original_mz().is_none());
+
+        // Product ion spectra are never used as the precursor spectrum
+        let mut ms3 = centroid_spectrum("ms3", 0.0, 3, &[], ms3_precursors);
+        let ms2 = centroid_spectrum(
+            "msn",
+            0.0,
+            2,
+            &ms1_peaks(),
+            vec![precursor(cluster[1].mz, None, Some("ms1"))],
+        );
+        assert_eq!(corrector.correct_spectrum(&mut ms3, &ms2).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_correcting_source() {
+        let cluster = Averagine::PEPTIDE.isotopic_cluster(1500.7, 2);
+        let spectra = vec![
+            centroid_spectrum("ms1", 0.0, 1, &ms1_peaks(), vec![]),
+            centroid_spectrum(
+                "msn1",
+                0.1,
+                2,
+                &[],
+                vec![precursor(cluster[1].mz, None, Some("ms1"))],
+            ),
+            centroid_spectrum(
+                "msn2",
+                0.2,
+                2,
+                &[],
+                vec![precursor(cluster[2].mz, None, None)],
+            ),
+            centroid_spectrum(
+                "ms3",
+                0.3,
+                3,
+                &[],
+                vec![
+                    precursor(cluster[1].mz, None, Some("ms1")),
+                    precursor(cluster[2].mz, None, Some("msn1")),
+                ],
+            ),
+            centroid_spectrum("ms1b", 1.0, 1, &ms1_peaks(), vec![]),
+            centroid_spectrum("ms1c", 2.0, 1, &ms1_peaks(), vec![]),
+            centroid_spectrum(
+                "msn3",
+                2.1,
+                2,
+                &[],
+                vec![precursor(cluster[1].mz, None, Some("ms1"))],
+            ),
+        ];
+
+        let reader = crate::MzMLReader::open_path("./test/data/three_test_scans.mzML").unwrap();
+        let source = PrecursorCorrectingSource::new(reader, PrecursorCorrector::default());
+        for dp in source.data_processings() {
+            let method = dp.methods.last().unwrap();
+            assert!(method
+                .params()
+                .iter()
+                .any(|p| p.name == "precursor recalculation"));
+        }
+
+        let mut source = PrecursorCorrectingSource {
+            source: spectra.into_iter(),
+            corrector: PrecursorCorrector::default(),
+            recent_ms1: VecDeque::new(),
+            history: 2,
+            metadata: Default::default(),
+        };
+        let spectra: Vec<_> = source.by_ref().collect();
+        assert_eq!(spectra.len(), 7);
+        for msn in spectra[1..4].iter() {
+            let ion = &msn.precursor().unwrap().ions[0];
+            assert!((ion.mz - cluster[0].mz).abs() < 1e-3, "{:?}", ion);
+            assert_eq!(ion.charge, Some(2));
+        }
+
+        // The MS2 stage of the MS3 spectrum is left as reported
+        let ion = &spectra[3].description().precursors[1].ions[0];
+        assert_eq!(ion.mz, cluster[2].mz);
+        assert_eq!(ion.charge, None);
+
+        // The precursor scan was evicted, so no other MS1 spectrum is substituted
+        let ion = &spectra[6].precursor().unwrap().ions[0];
+        assert_eq!(ion.mz, cluster[1].mz);
+        assert!(ion.original_mz().is_none());
+    }
+}
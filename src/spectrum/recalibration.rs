@@ -0,0 +1,645 @@
+//! Correct systematic m/z measurement error.
+//!
+//! A [`MassRecalibrator`] locates known reference ions, like lock masses or background
+//! contaminants, in MS1 spectra and fits a [`CalibrationCurve`] describing the m/z error
+//! in parts-per-million as a function of m/z and optionally retention time. The curve can then
+//! be applied to the m/z arrays, peak lists and precursor ions of each spectrum.
+//!
+//! Calibration can be done globally, fitting a single curve over every reference ion in the run
+//! with [`MassRecalibrator::fit_source`], or per scan, re-fitting the curve from the reference ions
+//! of each MS1 spectrum as lock mass correction does. Both are available as a streaming adaptor
+//! with [`RecalibratedSpectrumSource`].
+//!
+//! ```
+//! use mzdata::spectrum::{CalibrationModel, CalibrationPoint, MassRecalibrator};
+//! use mzpeaks::Tolerance;
+//!
+//! let recalibrator = MassRecalibrator::new(vec![445.12003], Tolerance::PPM(20.0))
+//!     .with_model(CalibrationModel::Offset);
+//! let curve = recalibrator
+//!     .fit(&[CalibrationPoint::new(445.12003, 445.12448, 10.0, 1e6)])
+//!     .unwrap();
+//! assert!((curve.ppm_error(500.0, 10.0) - 10.0).abs() < 0.01);
+//! assert!((curve.correct_mz(445.12448, 10.0) - 445.12003).abs() < 1e-6);
+//! ```
+use std::fmt::Display;
+
+use log::warn;
+use mzpeaks::prelude::*;
+use mzpeaks::{
+    CentroidLike, CentroidPeak, CoordinateLikeMut, DeconvolutedCentroidLike, DeconvolutedPeak,
+    Mass, Tolerance, MZ,
+};
+use thiserror::Error;
+
+use super::bindata::{ArrayRetrievalError, ArrayType};
+use super::spectrum_types::{MultiLayerSpectrum, SpectrumLike};
+use super::transforms::AdaptorMetadata;
+use crate::meta::{DataProcessingAction, MSDataFileMetadata};
+use crate::params::Param;
+use crate::utils::{mass_charge_ratio, neutral_mass, solve_linear_system};
+
+/// The functional form of a [`CalibrationCurve`], describing the m/z error in ppm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CalibrationModel {
+    /// A constant ppm error, `a`
+    Offset,
+    /// A ppm error linear in m/z, `a + b * mz`
+    #[default]
+    Linear,
+    /// A ppm error quadratic in m/z, `a + b * mz + c * mz ^ 2`
+    Quadratic,
+    /// A ppm error linear in m/z and retention time in minutes, `a + b * mz + c * time`
+    TimeDependent,
+}
+
+impl CalibrationModel {
+    /// The minimum number of points needed to fit the model
+    pub const fn parameter_count(&self) -> usize {
+        match self {
+            Self::Offset => 1,
+            Self::Linear => 2,
+            Self::Quadratic => 3,
+            Self::TimeDependent => 3,
+        }
+    }
+
+    fn features(&self, mz: f64, time: f64) -> Vec<f64> {
+        match self {
+            Self::Offset => vec![1.0],
+            Self::Linear => vec![1.0, mz],
+            Self::Quadratic => vec![1.0, mz, mz * mz],
+            Self::TimeDependent => vec![1.0, mz, time],
+        }
+    }
+}
+
+impl Display for CalibrationModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Offset => "offset",
+            Self::Linear => "linear",
+            Self::Quadratic => "quadratic",
+            Self::TimeDependent => "time-dependent linear",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Errors that may occur while fitting a [`CalibrationCurve`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CalibrationError {
+    #[error("The {model} calibration model requires {required} reference points, but only {found} were found")]
+    InsufficientPoints {
+        model: CalibrationModel,
+        required: usize,
+        found: usize,
+    },
+    #[error("The reference points do not constrain the {0} calibration model")]
+    Underdetermined(CalibrationModel),
+}
+
+/// An observation of a reference ion with a known m/z
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationPoint {
+    /// The known m/z of the reference ion
+    pub reference_mz: f64,
+    /// The m/z the reference ion was observed at
+    pub observed_mz: f64,
+    /// The scan start time of the spectrum the reference ion was observed in, in minutes
+    pub time: f64,
+    pub intensity: f32,
+}
+
+impl CalibrationPoint {
+    pub fn new(reference_mz: f64, observed_mz: f64, time: f64, intensity: f32) -> Self {
+        Self {
+            reference_mz,
+            observed_mz,
+            time,
+            intensity,
+        }
+    }
+
+    /// The error of the observed m/z in parts-per-million
+    pub fn ppm_error(&self) -> f64 {
+        (self.observed_mz - self.reference_mz) / self.reference_mz * 1e6
+    }
+}
+
+/// Solve the least squares problem for `rows` of features and `targets` with the normal equations
+fn least_squares(rows: &[Vec<f64>], targets: &[f64]) -> Option<Vec<f64>> {
+    let k = rows.first()?.len();
+    let mut system = vec![vec![0.0; k + 1]; k];
+    for (row, y) in rows.iter().zip(targets.iter()) {
+        for i in 0..k {
+            for j in 0..k {
+                system[i][j] += row[i] * row[j];
+            }
+            system[i][k] += row[i] * y;
+        }
+    }
+
+    solve_linear_system(system)
+}
+
+/// A fitted model of m/z error in parts-per-million
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationCurve {
+    pub model: CalibrationModel,
+    /// The model coefficients, in the order of the terms listed for [`CalibrationModel`]
+    pub coefficients: Vec<f64>,
+    /// The number of reference points the curve was fit with
+    pub point_count: usize,
+    /// The root mean squared error of the fit, in ppm
+    pub rmse_ppm: f64,
+}
+
+impl CalibrationCurve {
+    /// Fit a curve of `model` to `points` by least squares
+    pub fn fit(
+        model: CalibrationModel,
+        points: &[CalibrationPoint],
+    ) -> Result<Self, CalibrationError> {
+        let required = model.parameter_count();
+        if points.len() < required {
+            return Err(CalibrationError::InsufficientPoints {
+                model,
+                required,
+                found: points.len(),
+            });
+        }
+        let rows: Vec<Vec<f64>> = points
+            .iter()
+            .map(|p| model.features(p.observed_mz, p.time))
+            .collect();
+        let targets: Vec<f64> = points.iter().map(|p| p.ppm_error()).collect();
+        let coefficients =
+            least_squares(&rows, &targets).ok_or(CalibrationError::Underdetermined(model))?;
+
+        let mut curve = Self {
+            model,
+            coefficients,
+            point_count: points.len(),
+            rmse_ppm: 0.0,
+        };
+        let sse: f64 = points
+            .iter()
+            .map(|p| (curve.ppm_error(p.observed_mz, p.time) - p.ppm_error()).powi(2))
+            .sum();
+        curve.rmse_ppm = (sse / points.len() as f64).sqrt();
+        Ok(curve)
+    }
+
+    /// The predicted error in ppm of an m/z observed at `time` minutes
+    pub fn ppm_error(&self, mz: f64, time: f64) -> f64 {
+        self.model
+            .features(mz, time)
+            .iter()
+            .zip(self.coefficients.iter())
+            .map(|(x, c)| x * c)
+            .sum()
+    }
+
+    /// Correct an m/z observed at `time` minutes
+    pub fn correct_mz(&self, mz: f64, time: f64) -> f64 {
+        mz / (1.0 + self.ppm_error(mz, time) * 1e-6)
+    }
+
+    /// Correct the m/z array, peak lists and precursor ions of `spectrum`
+    pub fn correct_spectrum<C, D>(
+        &self,
+        spectrum: &mut MultiLayerSpectrum<C, D>,
+    ) -> Result<(), ArrayRetrievalError>
+    where
+        C: CentroidLike + Default + CoordinateLikeMut<MZ>,
+        D: DeconvolutedCentroidLike + Default + CoordinateLikeMut<Mass>,
+    {
+        let time = spectrum.start_time();
+        if let Some(arrays) = spectrum.arrays.as_mut() {
+            if arrays.has_array(&ArrayType::MZArray) {
+                arrays
+                    .mzs_mut()?
+                    .iter_mut()
+                    .for_each(|mz| *mz = self.correct_mz(*mz, time));
+            }
+        }
+        if let Some(peaks) = spectrum.peaks.as_mut() {
+            for p in peaks.iter_mut() {
+                let mz = self.correct_mz(p.mz(), time);
+                *CoordinateLikeMut::<MZ>::coordinate_mut(p) = mz;
+            }
+            peaks.sort();
+        }
+        if let Some(peaks) = spectrum.deconvoluted_peaks.as_mut() {
+            for p in peaks.iter_mut() {
+                let z = p.charge();
+                let mz = self.correct_mz(mass_charge_ratio(p.neutral_mass(), z), time);
+                *CoordinateLikeMut::<Mass>::coordinate_mut(p) = neutral_mass(mz, z);
+            }
+            peaks.sort();
+        }
+        for precursor in spectrum.description.precursors.iter_mut() {
+            for ion in precursor.ions.iter_mut() {
+                ion.mz = self.correct_mz(ion.mz, time);
+                if let Some(mz) = ion.monoisotopic_mz() {
+                    ion.set_monoisotopic_mz(self.correct_mz(mz, time));
+                }
+            }
+        }
+        spectrum.update_summaries();
+        Ok(())
+    }
+
+    /// Describe the curve as a list of parameters for a
+    /// [`ProcessingMethod`](crate::meta::ProcessingMethod)
+    pub fn as_params(&self) -> Vec<Param> {
+        let coefficients: Vec<String> = self.coefficients.iter().map(|c| c.to_string()).collect();
+        vec![
+            DataProcessingAction::MZCalibration.into(),
+            Param::new_key_value("calibration model", self.model.to_string()),
+            Param::new_key_value("calibration coefficients (ppm)", coefficients.join(",")),
+            Param::new_key_value("calibration RMSE (ppm)", self.rmse_ppm.to_string()),
+            Param::new_key_value("calibration reference points", self.point_count.to_string()),
+        ]
+    }
+}
+
+/// Locate reference ions in MS1 spectra and fit [`CalibrationCurve`]s from them
+#[derive(Debug, Clone, PartialEq)]
+pub struct MassRecalibrator {
+    /// The known m/z of each reference ion
+    pub reference_mzs: Vec<f64>,
+    /// The tolerance to search for each reference ion within
+    pub tolerance: Tolerance,
+    pub model: CalibrationModel,
+    /// The minimum intensity of a peak to be used as a reference ion
+    pub min_intensity: f32,
+}
+
+impl MassRecalibrator {
+    pub fn new(reference_mzs: Vec<f64>, tolerance: Tolerance) -> Self {
+        Self {
+            reference_mzs,
+            tolerance,
+            model: CalibrationModel::default(),
+            min_intensity: 0.0,
+        }
+    }
+
+    pub fn with_model(mut self, model: CalibrationModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_min_intensity(mut self, min_intensity: f32) -> Self {
+        self.min_intensity = min_intensity;
+        self
+    }
+
+    /// Find the most intense peak matching each reference ion in `spectrum`. Only MS1
+    /// mass spectra are searched.
+    pub fn find_reference_ions<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    >(
+        &self,
+        spectrum: &S,
+    ) -> Vec<CalibrationPoint> {
+        if spectrum.ms_level() != 1 || !spectrum.spectrum_type().is_mass_spectrum() {
+            return Vec::new();
+        }
+        let time = spectrum.start_time();
+        let mut best: Vec<Option<CalibrationPoint>> = vec![None; self.reference_mzs.len()];
+        for peak in spectrum.peaks().iter() {
+            if peak.intensity < self.min_intensity {
+                continue;
+            }
+            for (ref_mz, slot) in self.reference_mzs.iter().zip(best.iter_mut()) {
+                let is_more_intense = match slot {
+                    Some(p) => p.intensity < peak.intensity,
+                    None => true,
+                };
+                if is_more_intense && self.tolerance.test(peak.mz, *ref_mz) {
+                    *slot = Some(CalibrationPoint::new(
+                        *ref_mz,
+                        peak.mz,
+                        time,
+                        peak.intensity,
+                    ));
+                }
+            }
+        }
+        best.into_iter().flatten().collect()
+    }
+
+    /// Fit a [`CalibrationCurve`] of [`MassRecalibrator::model`] to `points`
+    pub fn fit(&self, points: &[CalibrationPoint]) -> Result<CalibrationCurve, CalibrationError> {
+        CalibrationCurve::fit(self.model, points)
+    }
+
+    /// Read every spectrum from `source` and fit a single [`CalibrationCurve`] from the
+    /// reference ions found in its MS1 spectra
+    pub fn fit_source<
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        I: Iterator<Item = S>,
+    >(
+        &self,
+        source: I,
+    ) -> Result<CalibrationCurve, CalibrationError> {
+        let points: Vec<CalibrationPoint> = source
+            .flat_map(|spectrum| self.find_reference_ions(&spectrum))
+            .collect();
+        self.fit(&points)
+    }
+
+    /// Describe the lock mass search as a list of parameters for a
+    /// [`ProcessingMethod`](crate::meta::ProcessingMethod)
+    fn as_params(&self) -> Vec<Param> {
+        let reference_mzs: Vec<String> =
+            self.reference_mzs.iter().map(|mz| mz.to_string()).collect();
+        vec![
+            DataProcessingAction::MZCalibration.into(),
+            Param::new_key_value("calibration model", self.model.to_string()),
+            Param::new_key_value("lock mass", reference_mzs.join(",")),
+            Param::new_key_value("lock mass tolerance", self.tolerance.to_string()),
+        ]
+    }
+}
+
+/// How a [`RecalibratedSpectrumSource`] calibrates the spectra it reads
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalibrationStrategy {
+    /// Apply the same pre-fit curve to every spectrum
+    Global(CalibrationCurve),
+    /// Fit a new curve from the reference ions of each MS1 spectrum, applying it to that spectrum
+    /// and the MSn spectra that follow it. When too few reference ions are found to fit the
+    /// model, a constant offset is fit instead, and if none are found the previous curve is used.
+    LockMass(MassRecalibrator),
+}
+
+/// Adapt an iterator over spectra by correcting the m/z of each spectrum with a
+/// [`CalibrationStrategy`], which is recorded in the adaptor's [`AdaptorMetadata`].
+#[derive(Debug)]
+pub struct RecalibratedSpectrumSource<
+    R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+    C: CentroidLike + Default + CoordinateLikeMut<MZ> = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default + CoordinateLikeMut<Mass> = DeconvolutedPeak,
+> {
+    source: R,
+    strategy: CalibrationStrategy,
+    current: Option<CalibrationCurve>,
+    metadata: AdaptorMetadata,
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default + CoordinateLikeMut<MZ>,
+        D: DeconvolutedCentroidLike + Default + CoordinateLikeMut<Mass>,
+    > RecalibratedSpectrumSource<R, C, D>
+{
+    pub fn new(source: R, strategy: CalibrationStrategy) -> Self
+    where
+        R: MSDataFileMetadata,
+    {
+        let current = match &strategy {
+            CalibrationStrategy::Global(curve) => Some(curve.clone()),
+            CalibrationStrategy::LockMass(_) => None,
+        };
+        let mut metadata = AdaptorMetadata::new(&source);
+        let params = match &strategy {
+            CalibrationStrategy::Global(curve) => curve.as_params(),
+            CalibrationStrategy::LockMass(recalibrator) => recalibrator.as_params(),
+        };
+        metadata.record_method("mz_calibration", params);
+        Self {
+            source,
+            strategy,
+            current,
+            metadata,
+        }
+    }
+
+    pub fn strategy(&self) -> &CalibrationStrategy {
+        &self.strategy
+    }
+
+    /// The calibration curve most recently applied
+    pub fn current_curve(&self) -> Option<&CalibrationCurve> {
+        self.current.as_ref()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    fn update_lock_mass(&mut self, spectrum: &MultiLayerSpectrum<C, D>) {
+        if let CalibrationStrategy::LockMass(recalibrator) = &self.strategy {
+            let points = recalibrator.find_reference_ions(spectrum);
+            if points.is_empty() {
+                return;
+            }
+            let curve = recalibrator
+                .fit(&points)
+                .or_else(|_| CalibrationCurve::fit(CalibrationModel::Offset, &points));
+            match curve {
+                Ok(curve) => self.current = Some(curve),
+                Err(e) => warn!(
+                    "Failed to fit lock mass calibration for {}: {e}",
+                    spectrum.id()
+                ),
+            }
+        }
+    }
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default + CoordinateLikeMut<MZ>,
+        D: DeconvolutedCentroidLike + Default + CoordinateLikeMut<Mass>,
+    > MSDataFileMetadata for RecalibratedSpectrumSource<R, C, D>
+{
+    crate::delegate_impl_metadata_trait!(metadata);
+}
+
+impl<
+        R: Iterator<Item = MultiLayerSpectrum<C, D>>,
+        C: CentroidLike + Default + CoordinateLikeMut<MZ>,
+        D: DeconvolutedCentroidLike + Default + CoordinateLikeMut<Mass>,
+    > Iterator for RecalibratedSpectrumSource<R, C, D>
+{
+    type Item = MultiLayerSpectrum<C, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut spectrum = self.source.next()?;
+        if spectrum.ms_level() == 1 {
+            self.update_lock_mass(&spectrum);
+        }
+        if let Some(curve) = self.current.as_ref() {
+            if let Err(e) = curve.correct_spectrum(&mut spectrum) {
+                warn!("Failed to recalibrate spectrum {}: {e}", spectrum.id());
+            }
+        }
+        Some(spectrum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use crate::spectrum::test_utils::{centroid_spectrum, precursor};
+    use crate::MzMLReader;
+
+    /// Shift `mzs` by `ppm`, as an instrument's calibration drift would
+    fn drift(mzs: &[f64], ppm: f64) -> Vec<(f64, f32)> {
+        mzs.iter()
+            .map(|mz| (mz * (1.0 + ppm * 1e-6), 1000.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_models() {
+        let points: Vec<_> = [200.0, 400.0, 800.0, 1200.0]
+            .iter()
+            .enumerate()
+            .map(|(i, mz)| {
+                let ppm = 3.0 + 0.005 * mz;
+                CalibrationPoint::new(*mz, mz * (1.0 + ppm * 1e-6), i as f64, 1.0)
+            })
+            .collect();
+
+        let curve = CalibrationCurve::fit(CalibrationModel::Linear, &points).unwrap();
+        assert!((curve.coefficients[0] - 3.0).abs() < 1e-3);
+        assert!((curve.coefficients[1] - 0.005).abs() < 1e-6);
+        assert!(curve.rmse_ppm < 1e-3);
+        for p in points.iter() {
+            assert!((curve.correct_mz(p.observed_mz, p.time) - p.reference_mz).abs() < 1e-6);
+        }
+
+        let curve = CalibrationCurve::fit(CalibrationModel::Quadratic, &points).unwrap();
+        assert!(curve.coefficients[2].abs() < 1e-6);
+
+        let err = CalibrationCurve::fit(CalibrationModel::Quadratic, &points[..2]).unwrap_err();
+        assert_eq!(
+            err,
+            CalibrationError::InsufficientPoints {
+                model: CalibrationModel::Quadratic,
+                required: 3,
+                found: 2
+            }
+        );
+
+        let same_time: Vec<_> = points
+            .iter()
+            .map(|p| CalibrationPoint { time: 1.0, ..*p })
+            .collect();
+        let err = CalibrationCurve::fit(CalibrationModel::TimeDependent, &same_time).unwrap_err();
+        assert_eq!(
+            err,
+            CalibrationError::Underdetermined(CalibrationModel::TimeDependent)
+        );
+    }
+
+    #[test]
+    fn test_lock_mass_stream() {
+        let reader = MzMLReader::open_path("./test/data/three_test_scans.mzML").unwrap();
+        let n_methods: Vec<_> = reader
+            .data_processings()
+            .iter()
+            .map(|dp| dp.methods.len())
+            .collect();
+        let recalibrator = MassRecalibrator::new(vec![445.12003], Tolerance::PPM(20.0));
+        let mut source = RecalibratedSpectrumSource::new(
+            reader,
+            CalibrationStrategy::LockMass(recalibrator.clone()),
+        );
+        for (dp, n) in source.data_processings().iter().zip(n_methods) {
+            assert_eq!(dp.methods.len(), n + 1);
+            let method = dp.methods.last().unwrap();
+            assert!(method.params().iter().any(|p| p.name == "m/z calibration"));
+        }
+        assert!(source.next().is_some());
+
+        // Drive the per-scan calibration with synthetic spectra
+        let spectra = vec![
+            centroid_spectrum(
+                "ms1-1",
+                1.0,
+                1,
+                &drift(&[300.0, 445.12003, 900.0], 5.0),
+                vec![],
+            ),
+            centroid_spectrum(
+                "ms2-1",
+                1.1,
+                2,
+                &drift(&[150.0, 250.0], 5.0),
+                vec![precursor(600.0 * (1.0 + 5e-6), Some(2), Some("ms1-1"))],
+            ),
+            centroid_spectrum("ms1-2", 2.0, 1, &drift(&[300.0, 445.12003], -2.0), vec![]),
+        ];
+        let mut source = RecalibratedSpectrumSource {
+            source: spectra.into_iter(),
+            strategy: CalibrationStrategy::LockMass(recalibrator),
+            current: None,
+            metadata: Default::default(),
+        };
+
+        let ms1 = source.next().unwrap();
+        let peaks = ms1.peaks.as_ref().unwrap();
+        assert!((peaks[1].mz - 445.12003).abs() < 1e-6);
+        assert!((peaks[2].mz - 900.0).abs() < 1e-6);
+        assert!(source.current_curve().unwrap().model == CalibrationModel::Offset);
+
+        let ms2 = source.next().unwrap();
+        assert!((ms2.peaks.as_ref().unwrap()[0].mz - 150.0).abs() < 1e-6);
+        assert!((ms2.precursor().unwrap().ions[0].mz - 600.0).abs() < 1e-6);
+
+        let ms1 = source.next().unwrap();
+        assert!((ms1.peaks.as_ref().unwrap()[0].mz - 300.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fit_source() {
+        let spectra = [
+            centroid_spectrum(
+                "ms1-1",
+                1.0,
+                1,
+                &drift(&[300.0, 445.12003, 900.0], 4.0),
+                vec![],
+            ),
+            centroid_spectrum(
+                "ms2-1",
+                1.1,
+                2,
+                &drift(&[150.0, 250.0], 4.0),
+                vec![precursor(600.0 * (1.0 + 4e-6), Some(2), Some("ms1-1"))],
+            ),
+            centroid_spectrum(
+                "ms1-2",
+                2.0,
+                1,
+                &drift(&[300.0, 445.12003, 900.0], 4.0),
+                vec![],
+            ),
+        ];
+        let recalibrator =
+            MassRecalibrator::new(vec![300.0, 445.12003, 900.0], Tolerance::PPM(10.0));
+        let curve = recalibrator.fit_source(spectra.iter().cloned()).unwrap();
+        assert_eq!(curve.point_count, 6);
+        assert!((curve.ppm_error(600.0, 1.5) - 4.0).abs() < 1e-3);
+
+        let mut spectrum = spectra[1].clone();
+        curve.correct_spectrum(&mut spectrum).unwrap();
+        assert!((spectrum.peaks.as_ref().unwrap()[1].mz - 250.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,792 @@
+//! Extract isobaric labeling reporter ion intensities from MSn spectra.
+//!
+//! The reporter ions of the common TMT and iTRAQ reagents are built in as [`IsobaricReagent`]s.
+//! A [`ReporterQuantifier`] matches each reporter ion's m/z within a [`Tolerance`] in a spectrum's
+//! centroids, profile arrays or deconvoluted peaks, optionally corrects the intensities for the
+//! reagent lots' isotopic impurities with an [`ImpurityMatrix`], and either returns them for
+//! collection in a [`ReporterTable`] or records them as parameters of the spectrum. They are
+//! not stored as data arrays, since every array of a spectrum must match its m/z array's length.
+//!
+//! ```
+//! use mzdata::spectrum::{IsobaricReagent, ReporterQuantifier};
+//! use mzpeaks::Tolerance;
+//!
+//! let quantifier = ReporterQuantifier::new(IsobaricReagent::TMT10, Tolerance::PPM(20.0));
+//! let names: Vec<_> = quantifier.reagent.channels().iter().map(|c| c.name).collect();
+//! assert_eq!(names[..3], ["126", "127N", "127C"]);
+//! ```
+use std::fmt::Display;
+use std::io;
+use std::str::FromStr;
+
+use mzpeaks::prelude::*;
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike, Tolerance};
+use thiserror::Error;
+
+use super::bindata::ArrayRetrievalError;
+use super::peaks::RefPeakDataLevel;
+use super::scan_properties::SignalContinuity;
+use super::spectrum_types::{SpectrumLike, SpectrumProcessingError};
+use crate::params::{Param, ParamDescribed, ParamValue};
+use crate::utils::{mass_charge_ratio, solve_linear_system};
+
+/// The mass difference between carbon 13 and carbon 12, the most common isotopic impurity
+const C13_SHIFT: f64 = 1.0033548378;
+
+/// A reporter ion of an isobaric labeling reagent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReporterChannel {
+    pub name: &'static str,
+    pub mz: f64,
+}
+
+impl ReporterChannel {
+    pub const fn new(name: &'static str, mz: f64) -> Self {
+        Self { name, mz }
+    }
+}
+
+const TMT126: ReporterChannel = ReporterChannel::new("126", 126.127726);
+const TMT127N: ReporterChannel = ReporterChannel::new("127N", 127.124761);
+const TMT127C: ReporterChannel = ReporterChannel::new("127C", 127.131081);
+const TMT128N: ReporterChannel = ReporterChannel::new("128N", 128.128116);
+const TMT128C: ReporterChannel = ReporterChannel::new("128C", 128.134436);
+const TMT129N: ReporterChannel = ReporterChannel::new("129N", 129.131471);
+const TMT129C: ReporterChannel = ReporterChannel::new("129C", 129.137790);
+const TMT130N: ReporterChannel = ReporterChannel::new("130N", 130.134825);
+const TMT130C: ReporterChannel = ReporterChannel::new("130C", 130.141145);
+const TMT131N: ReporterChannel = ReporterChannel::new("131N", 131.138180);
+const TMT131C: ReporterChannel = ReporterChannel::new("131C", 131.144499);
+const TMT132N: ReporterChannel = ReporterChannel::new("132N", 132.141535);
+const TMT132C: ReporterChannel = ReporterChannel::new("132C", 132.147855);
+const TMT133N: ReporterChannel = ReporterChannel::new("133N", 133.144890);
+const TMT133C: ReporterChannel = ReporterChannel::new("133C", 133.151210);
+const TMT134N: ReporterChannel = ReporterChannel::new("134N", 134.148245);
+const TMT134C: ReporterChannel = ReporterChannel::new("134C", 134.154565);
+const TMT135N: ReporterChannel = ReporterChannel::new("135N", 135.151600);
+
+const TMT6_CHANNELS: &[ReporterChannel] = &[
+    ReporterChannel::new("126", TMT126.mz),
+    ReporterChannel::new("127", TMT127N.mz),
+    ReporterChannel::new("128", TMT128C.mz),
+    ReporterChannel::new("129", TMT129N.mz),
+    ReporterChannel::new("130", TMT130C.mz),
+    ReporterChannel::new("131", TMT131N.mz),
+];
+
+const TMT11_CHANNELS: &[ReporterChannel] = &[
+    TMT126, TMT127N, TMT127C, TMT128N, TMT128C, TMT129N, TMT129C, TMT130N, TMT130C, TMT131N,
+    TMT131C,
+];
+
+const TMT18_CHANNELS: &[ReporterChannel] = &[
+    TMT126, TMT127N, TMT127C, TMT128N, TMT128C, TMT129N, TMT129C, TMT130N, TMT130C, TMT131N,
+    TMT131C, TMT132N, TMT132C, TMT133N, TMT133C, TMT134N, TMT134C, TMT135N,
+];
+
+const ITRAQ4_CHANNELS: &[ReporterChannel] = &[
+    ReporterChannel::new("114", 114.110680),
+    ReporterChannel::new("115", 115.107715),
+    ReporterChannel::new("116", 116.111069),
+    ReporterChannel::new("117", 117.114424),
+];
+
+const ITRAQ8_CHANNELS: &[ReporterChannel] = &[
+    ReporterChannel::new("113", 113.107325),
+    ReporterChannel::new("114", 114.110680),
+    ReporterChannel::new("115", 115.107715),
+    ReporterChannel::new("116", 116.111069),
+    ReporterChannel::new("117", 117.114424),
+    ReporterChannel::new("118", 118.111459),
+    ReporterChannel::new("119", 119.114814),
+    ReporterChannel::new("121", 121.121524),
+];
+
+/// An isobaric labeling reagent and its set of reporter ions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IsobaricReagent {
+    TMT6,
+    TMT10,
+    TMT11,
+    /// TMTpro 16-plex
+    TMT16,
+    /// TMTpro 18-plex
+    TMT18,
+    ITRAQ4,
+    ITRAQ8,
+}
+
+impl IsobaricReagent {
+    /// The reporter ions of the reagent in order of increasing m/z
+    pub fn channels(&self) -> &'static [ReporterChannel] {
+        match self {
+            Self::TMT6 => TMT6_CHANNELS,
+            Self::TMT10 => &TMT11_CHANNELS[..10],
+            Self::TMT11 => TMT11_CHANNELS,
+            Self::TMT16 => &TMT18_CHANNELS[..16],
+            Self::TMT18 => TMT18_CHANNELS,
+            Self::ITRAQ4 => ITRAQ4_CHANNELS,
+            Self::ITRAQ8 => ITRAQ8_CHANNELS,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.channels().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels().is_empty()
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::TMT6 => "TMT6plex",
+            Self::TMT10 => "TMT10plex",
+            Self::TMT11 => "TMT11plex",
+            Self::TMT16 => "TMTpro16plex",
+            Self::TMT18 => "TMTpro18plex",
+            Self::ITRAQ4 => "iTRAQ4plex",
+            Self::ITRAQ8 => "iTRAQ8plex",
+        }
+    }
+}
+
+impl Display for IsobaricReagent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for IsobaricReagent {
+    type Err = ReporterQuantificationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.to_lowercase().replace(['-', '_', ' '], "");
+        let reagent = match normalized.as_str() {
+            "tmt6" | "tmt6plex" => Self::TMT6,
+            "tmt10" | "tmt10plex" => Self::TMT10,
+            "tmt11" | "tmt11plex" => Self::TMT11,
+            "tmt16" | "tmt16plex" | "tmtpro" | "tmtpro16" | "tmtpro16plex" => Self::TMT16,
+            "tmt18" | "tmt18plex" | "tmtpro18" | "tmtpro18plex" => Self::TMT18,
+            "itraq4" | "itraq4plex" => Self::ITRAQ4,
+            "itraq8" | "itraq8plex" => Self::ITRAQ8,
+            _ => return Err(ReporterQuantificationError::UnknownReagent(s.to_string())),
+        };
+        Ok(reagent)
+    }
+}
+
+/// Errors that may occur while configuring reporter ion quantification
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ReporterQuantificationError {
+    #[error("Unknown isobaric reagent {0}")]
+    UnknownReagent(String),
+    #[error(
+        "The impurity matrix has {found} rows or columns, but the reagent has {expected} channels"
+    )]
+    DimensionMismatch { expected: usize, found: usize },
+    #[error("The impurity matrix cannot be inverted")]
+    SingularMatrix,
+}
+
+/// How to choose between several peaks matching a reporter ion's m/z
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ReporterMatching {
+    /// Use the most intense peak within the tolerance
+    #[default]
+    MostIntense,
+    /// Use the peak closest to the reporter ion's m/z within the tolerance
+    Nearest,
+}
+
+/// Describes how the signal of each reporter ion is spread over the reporter ion channels
+/// by the isotopic impurities of the reagents.
+///
+/// Entry `[i][j]` is the fraction of the signal of channel `i`'s reagent observed in channel `j`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImpurityMatrix {
+    matrix: Vec<Vec<f64>>,
+}
+
+impl ImpurityMatrix {
+    /// Create an impurity matrix from its rows, one per channel of `reagent`
+    pub fn new(
+        reagent: IsobaricReagent,
+        matrix: Vec<Vec<f64>>,
+    ) -> Result<Self, ReporterQuantificationError> {
+        let expected = reagent.len();
+        if matrix.len() != expected {
+            return Err(ReporterQuantificationError::DimensionMismatch {
+                expected,
+                found: matrix.len(),
+            });
+        }
+        if let Some(row) = matrix.iter().find(|row| row.len() != expected) {
+            return Err(ReporterQuantificationError::DimensionMismatch {
+                expected,
+                found: row.len(),
+            });
+        }
+        let this = Self { matrix };
+        this.solve(&vec![1.0; expected])
+            .ok_or(ReporterQuantificationError::SingularMatrix)?;
+        Ok(this)
+    }
+
+    /// Create an impurity matrix from the percentages of each reagent's signal shifted by
+    /// -2, -1, +1 and +2 carbon 13 isotopes, as listed on reagent product data sheets.
+    ///
+    /// Shifted signal is assigned to the channel nearest the shifted m/z, so reagents whose
+    /// channels differ by a 15N label like TMT6 still receive it. Signal shifted to an m/z
+    /// that is not within 0.01 Da of a channel of the reagent is lost.
+    pub fn from_isotope_impurities(
+        reagent: IsobaricReagent,
+        impurities: &[[f64; 4]],
+    ) -> Result<Self, ReporterQuantificationError> {
+        let channels = reagent.channels();
+        if impurities.len() != channels.len() {
+            return Err(ReporterQuantificationError::DimensionMismatch {
+                expected: channels.len(),
+                found: impurities.len(),
+            });
+        }
+        let mut matrix = vec![vec![0.0; channels.len()]; channels.len()];
+        for (i, (channel, row)) in channels.iter().zip(impurities.iter()).enumerate() {
+            matrix[i][i] = 1.0 - row.iter().sum::<f64>() / 100.0;
+            for (shift, percent) in [-2.0, -1.0, 1.0, 2.0].iter().zip(row.iter()) {
+                let mz = channel.mz + shift * C13_SHIFT;
+                let nearest = channels
+                    .iter()
+                    .map(|c| (c.mz - mz).abs())
+                    .enumerate()
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((j, _)) = nearest.filter(|(_, err)| *err < 0.01) {
+                    matrix[i][j] += percent / 100.0;
+                }
+            }
+        }
+        Self::new(reagent, matrix)
+    }
+
+    pub fn matrix(&self) -> &[Vec<f64>] {
+        &self.matrix
+    }
+
+    /// Find the true intensities which produce the `observed` intensities
+    fn solve(&self, observed: &[f64]) -> Option<Vec<f64>> {
+        let k = self.matrix.len();
+        let system = (0..k)
+            .map(|j| {
+                let mut row: Vec<f64> = self.matrix.iter().map(|r| r[j]).collect();
+                row.push(observed[j]);
+                row
+            })
+            .collect();
+        solve_linear_system(system)
+    }
+
+    /// Correct `intensities` for the isotopic impurities, clamping negative values to zero
+    pub fn correct(&self, intensities: &mut [f32]) {
+        let observed: Vec<f64> = intensities.iter().map(|i| *i as f64).collect();
+        if let Some(corrected) = self.solve(&observed) {
+            for (i, c) in intensities.iter_mut().zip(corrected) {
+                *i = c.max(0.0) as f32;
+            }
+        }
+    }
+}
+
+/// The reporter ion intensities extracted from a single spectrum
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReporterIntensities {
+    pub reagent: IsobaricReagent,
+    /// The intensity of each channel, zero when no peak matched
+    pub intensities: Vec<f32>,
+    /// The observed m/z of each channel, if a peak matched
+    pub mzs: Vec<Option<f64>>,
+}
+
+impl ReporterIntensities {
+    pub fn iter(&self) -> impl Iterator<Item = (&'static ReporterChannel, f32)> + '_ {
+        self.reagent
+            .channels()
+            .iter()
+            .zip(self.intensities.iter().copied())
+    }
+
+    /// The total intensity over all channels
+    pub fn total(&self) -> f32 {
+        self.intensities.iter().sum()
+    }
+
+    /// The number of channels where a peak matched
+    pub fn matched_count(&self) -> usize {
+        self.mzs.iter().filter(|mz| mz.is_some()).count()
+    }
+}
+
+/// The name of the parameter storing a reporter ion channel's intensity
+fn reporter_param_name(channel: &ReporterChannel) -> String {
+    format!("reporter ion intensity {}", channel.name)
+}
+
+/// Extract reporter ion intensities from spectra
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReporterQuantifier {
+    pub reagent: IsobaricReagent,
+    pub tolerance: Tolerance,
+    pub matching: ReporterMatching,
+    pub impurities: Option<ImpurityMatrix>,
+}
+
+impl ReporterQuantifier {
+    pub fn new(reagent: IsobaricReagent, tolerance: Tolerance) -> Self {
+        Self {
+            reagent,
+            tolerance,
+            matching: ReporterMatching::default(),
+            impurities: None,
+        }
+    }
+
+    pub fn with_matching(mut self, matching: ReporterMatching) -> Self {
+        self.matching = matching;
+        self
+    }
+
+    pub fn with_impurities(mut self, impurities: ImpurityMatrix) -> Self {
+        self.impurities = Some(impurities);
+        self
+    }
+
+    /// Choose among `(mz, intensity)` candidates for the reporter ion at `target`
+    fn choose(
+        &self,
+        candidates: impl Iterator<Item = (f64, f32)>,
+        target: f64,
+    ) -> Option<(f64, f32)> {
+        match self.matching {
+            ReporterMatching::MostIntense => candidates.max_by(|a, b| a.1.total_cmp(&b.1)),
+            ReporterMatching::Nearest => {
+                candidates.min_by(|a, b| (a.0 - target).abs().total_cmp(&(b.0 - target).abs()))
+            }
+        }
+    }
+
+    /// Extract the reporter ion intensities from a level of peak data. Profile data arrays are
+    /// matched by their local maxima.
+    pub fn quantify_peak_data<C: CentroidLike, D: DeconvolutedCentroidLike>(
+        &self,
+        peaks: &RefPeakDataLevel<'_, C, D>,
+        continuity: SignalContinuity,
+    ) -> Result<ReporterIntensities, SpectrumProcessingError> {
+        let channels = self.reagent.channels();
+        let mut matches = Vec::with_capacity(channels.len());
+        match peaks {
+            RefPeakDataLevel::Missing => matches.resize(channels.len(), None),
+            RefPeakDataLevel::RawData(arrays) => {
+                let mzs = arrays.mzs()?;
+                let intensities = arrays.intensities()?;
+                if mzs.len() != intensities.len() {
+                    return Err(ArrayRetrievalError::DataTypeSizeMismatch.into());
+                }
+                let profile = matches!(continuity, SignalContinuity::Profile);
+                for channel in channels {
+                    let (low, high) = self.tolerance.bounds(channel.mz);
+                    let start = mzs.partition_point(|mz| *mz < low);
+                    let candidates = (start..mzs.len())
+                        .take_while(|i| mzs[*i] <= high)
+                        .filter(|i| {
+                            // In profile mode only the apex of each peak is a candidate
+                            !profile
+                                || ((*i == 0 || intensities[i - 1] <= intensities[*i])
+                                    && (i + 1 == intensities.len()
+                                        || intensities[i + 1] <= intensities[*i]))
+                        })
+                        .map(|i| (mzs[i], intensities[i]));
+                    matches.push(self.choose(candidates, channel.mz));
+                }
+            }
+            RefPeakDataLevel::Centroid(peaks) => {
+                for channel in channels {
+                    let candidates = peaks
+                        .all_peaks_for(channel.mz, self.tolerance)
+                        .iter()
+                        .map(|p| (p.mz(), p.intensity()));
+                    matches.push(self.choose(candidates, channel.mz));
+                }
+            }
+            RefPeakDataLevel::Deconvoluted(peaks) => {
+                for channel in channels {
+                    let candidates = peaks
+                        .iter()
+                        .filter(|p| p.charge().abs() == 1)
+                        .map(|p| {
+                            (
+                                mass_charge_ratio(p.neutral_mass(), p.charge()),
+                                p.intensity(),
+                            )
+                        })
+                        .filter(|(mz, _)| self.tolerance.test(*mz, channel.mz));
+                    matches.push(self.choose(candidates, channel.mz));
+                }
+            }
+        }
+
+        let mut intensities: Vec<f32> = matches
+            .iter()
+            .map(|m| m.map(|(_, i)| i).unwrap_or_default())
+            .collect();
+        if let Some(impurities) = self.impurities.as_ref() {
+            impurities.correct(&mut intensities);
+        }
+        Ok(ReporterIntensities {
+            reagent: self.reagent,
+            intensities,
+            mzs: matches.iter().map(|m| m.map(|(mz, _)| mz)).collect(),
+        })
+    }
+
+    /// Extract the reporter ion intensities from the most refined peak data of `spectrum`
+    pub fn quantify<C, D, S>(
+        &self,
+        spectrum: &S,
+    ) -> Result<ReporterIntensities, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        self.quantify_peak_data(&spectrum.peaks(), spectrum.signal_continuity())
+    }
+
+    /// Extract the reporter ion intensities from `spectrum` and store them as parameters of
+    /// the spectrum, one per channel, replacing any stored previously. Read them back with
+    /// [`ReporterQuantifier::read_annotations`].
+    pub fn annotate_spectrum<C, D, S>(
+        &self,
+        spectrum: &mut S,
+    ) -> Result<ReporterIntensities, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        let reporters = self.quantify(spectrum)?;
+        let description = spectrum.description_mut();
+        for (channel, intensity) in reporters.iter() {
+            let name = reporter_param_name(channel);
+            if let Some(p) = description.params_mut().iter_mut().find(|p| p.name == name) {
+                p.value = intensity.into();
+            } else {
+                description.add_param(Param::new_key_value(name, intensity));
+            }
+        }
+        Ok(reporters)
+    }
+
+    /// Read the reporter ion intensities stored by [`ReporterQuantifier::annotate_spectrum`],
+    /// if every channel is present
+    pub fn read_annotations<C, D, S>(&self, spectrum: &S) -> Option<Vec<f32>>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        self.reagent
+            .channels()
+            .iter()
+            .map(|channel| {
+                spectrum
+                    .description()
+                    .get_param_by_name(&reporter_param_name(channel))
+                    .and_then(|p| p.to_f32().ok())
+            })
+            .collect()
+    }
+
+    /// Quantify every spectrum of `source` at `ms_level`, collecting the results in a table
+    pub fn quantify_source<C, D, S, I>(
+        &self,
+        source: I,
+        ms_level: u8,
+    ) -> Result<ReporterTable, SpectrumProcessingError>
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+        I: Iterator<Item = S>,
+    {
+        let mut table = ReporterTable::new(self.reagent);
+        for spectrum in source.filter(|s| s.ms_level() == ms_level) {
+            let reporters = self.quantify(&spectrum)?;
+            table.push(&spectrum, reporters);
+        }
+        Ok(table)
+    }
+}
+
+/// A row of a [`ReporterTable`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReporterRow {
+    pub id: String,
+    pub index: usize,
+    pub ms_level: u8,
+    /// The scan start time in minutes
+    pub time: f64,
+    pub precursor_mz: Option<f64>,
+    pub precursor_charge: Option<i32>,
+    pub intensities: Vec<f32>,
+}
+
+/// Reporter ion intensities for many spectra
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReporterTable {
+    pub reagent: IsobaricReagent,
+    pub rows: Vec<ReporterRow>,
+}
+
+impl ReporterTable {
+    pub fn new(reagent: IsobaricReagent) -> Self {
+        Self {
+            reagent,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add the reporter ion intensities of `spectrum` to the table
+    pub fn push<C, D, S>(&mut self, spectrum: &S, reporters: ReporterIntensities)
+    where
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D>,
+    {
+        let ion = spectrum.precursor().and_then(|p| p.ions.first());
+        self.rows.push(ReporterRow {
+            id: spectrum.id().to_string(),
+            index: spectrum.index(),
+            ms_level: spectrum.ms_level(),
+            time: spectrum.start_time(),
+            precursor_mz: ion.map(|ion| ion.mz),
+            precursor_charge: ion.and_then(|ion| ion.charge),
+            intensities: reporters.intensities,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, ReporterRow> {
+        self.rows.iter()
+    }
+
+    /// Write the table as tab-separated values with a header line
+    pub fn write_tsv<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(
+            writer,
+            "id\tindex\tms_level\tscan_start_time\tprecursor_mz\tprecursor_charge"
+        )?;
+        for channel in self.reagent.channels() {
+            write!(writer, "\t{}", channel.name)?;
+        }
+        writeln!(writer)?;
+        for row in self.rows.iter() {
+            write!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                row.id,
+                row.index,
+                row.ms_level,
+                row.time,
+                row.precursor_mz
+                    .map(|mz| mz.to_string())
+                    .unwrap_or_default(),
+                row.precursor_charge
+                    .map(|z| z.to_string())
+                    .unwrap_or_default()
+            )?;
+            for intensity in row.intensities.iter() {
+                write!(writer, "\t{intensity}")?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrum::{
+        ArrayType, BinaryArrayMap, BinaryDataArrayType, CentroidSpectrum, DataArray,
+        MultiLayerSpectrum, SpectrumDescription,
+    };
+    use mzpeaks::CentroidPeak;
+
+    fn make_centroid_spectrum(peaks: Vec<CentroidPeak>) -> MultiLayerSpectrum {
+        let descr = SpectrumDescription {
+            id: "scan=1".to_string(),
+            ms_level: 2,
+            signal_continuity: SignalContinuity::Centroid,
+            ..Default::default()
+        };
+        CentroidSpectrum::new(descr, peaks.into())
+            .into_spectrum()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reagents() {
+        for reagent in [
+            IsobaricReagent::TMT6,
+            IsobaricReagent::TMT10,
+            IsobaricReagent::TMT11,
+            IsobaricReagent::TMT16,
+            IsobaricReagent::TMT18,
+            IsobaricReagent::ITRAQ4,
+            IsobaricReagent::ITRAQ8,
+        ] {
+            let n: usize = reagent
+                .name()
+                .trim_end_matches("plex")
+                .trim_start_matches(char::is_alphabetic)
+                .parse()
+                .unwrap();
+            assert_eq!(reagent.len(), n);
+            assert!(reagent.channels().windows(2).all(|w| w[0].mz < w[1].mz));
+            assert_eq!(reagent.name().parse::<IsobaricReagent>().unwrap(), reagent);
+        }
+        assert_eq!(
+            "tmtpro".parse::<IsobaricReagent>().unwrap(),
+            IsobaricReagent::TMT16
+        );
+        assert!("silac".parse::<IsobaricReagent>().is_err());
+    }
+
+    #[test]
+    fn test_quantify_centroids() {
+        let spectrum = make_centroid_spectrum(vec![
+            CentroidPeak::new(126.1277, 1000.0, 0),
+            CentroidPeak::new(127.1248, 2000.0, 0),
+            CentroidPeak::new(127.1280, 5000.0, 0),
+            CentroidPeak::new(128.1344, 3000.0, 0),
+            CentroidPeak::new(131.1382, 500.0, 0),
+            CentroidPeak::new(500.0, 1e5, 0),
+        ]);
+
+        let quantifier = ReporterQuantifier::new(IsobaricReagent::TMT6, Tolerance::Da(0.003));
+        let reporters = quantifier.quantify(&spectrum).unwrap();
+        assert_eq!(
+            reporters.intensities,
+            [1000.0, 2000.0, 3000.0, 0.0, 0.0, 500.0]
+        );
+        assert_eq!(reporters.matched_count(), 4);
+
+        let quantifier = ReporterQuantifier::new(IsobaricReagent::TMT6, Tolerance::Da(0.01));
+        let reporters = quantifier.quantify(&spectrum).unwrap();
+        assert_eq!(reporters.intensities[1], 5000.0);
+        let quantifier = quantifier.with_matching(ReporterMatching::Nearest);
+        let reporters = quantifier.quantify(&spectrum).unwrap();
+        assert_eq!(reporters.intensities[1], 2000.0);
+    }
+
+    #[test]
+    fn test_quantify_profile() {
+        let mut mzs = Vec::new();
+        let mut intensities = Vec::new();
+        for (center, height) in [(126.127726, 100.0f32), (127.124761, 300.0)] {
+            for i in -5..=5 {
+                let offset = i as f64 * 0.001;
+                mzs.push(center + offset);
+                intensities.push(height * (-(offset / 0.002).powi(2)).exp() as f32);
+            }
+        }
+        let mut arrays = BinaryArrayMap::new();
+        let mut mz_array =
+            DataArray::from_name_and_type(&ArrayType::MZArray, BinaryDataArrayType::Float64);
+        mz_array.extend(&mzs).unwrap();
+        let mut int_array =
+            DataArray::from_name_and_type(&ArrayType::IntensityArray, BinaryDataArrayType::Float32);
+        int_array.extend(&intensities).unwrap();
+        arrays.add(mz_array);
+        arrays.add(int_array);
+
+        let quantifier = ReporterQuantifier::new(IsobaricReagent::TMT6, Tolerance::PPM(20.0));
+        let level = RefPeakDataLevel::<CentroidPeak, mzpeaks::DeconvolutedPeak>::RawData(&arrays);
+        let reporters = quantifier
+            .quantify_peak_data(&level, SignalContinuity::Profile)
+            .unwrap();
+        assert!((reporters.intensities[0] - 100.0).abs() < 1e-3);
+        assert!((reporters.intensities[1] - 300.0).abs() < 1e-3);
+        assert!((reporters.mzs[1].unwrap() - 127.124761).abs() < 1e-6);
+        assert_eq!(reporters.intensities[2], 0.0);
+    }
+
+    #[test]
+    fn test_impurity_correction() {
+        let mut impurities = [[0.0; 4]; 6];
+        impurities[0] = [0.0, 0.0, 10.0, 0.0];
+        let matrix =
+            ImpurityMatrix::from_isotope_impurities(IsobaricReagent::TMT6, &impurities).unwrap();
+        assert!((matrix.matrix()[0][0] - 0.9).abs() < 1e-9);
+        assert!((matrix.matrix()[0][1] - 0.1).abs() < 1e-9);
+
+        // 126 contributes 10% of its signal to 127
+        let mut intensities = [900.0, 600.0, 0.0, 0.0, 0.0, 0.0];
+        matrix.correct(&mut intensities);
+        assert!((intensities[0] - 1000.0).abs() < 1e-2);
+        assert!((intensities[1] - 500.0).abs() < 1e-2);
+
+        assert!(matches!(
+            ImpurityMatrix::new(IsobaricReagent::TMT6, vec![vec![1.0; 6]; 5]),
+            Err(ReporterQuantificationError::DimensionMismatch {
+                expected: 6,
+                found: 5
+            })
+        ));
+        assert_eq!(
+            ImpurityMatrix::new(IsobaricReagent::ITRAQ4, vec![vec![0.25; 4]; 4]),
+            Err(ReporterQuantificationError::SingularMatrix)
+        );
+    }
+
+    #[test]
+    fn test_annotate_and_table() {
+        let mut spectrum = make_centroid_spectrum(vec![
+            CentroidPeak::new(114.1107, 10.0, 0),
+            CentroidPeak::new(117.1144, 40.0, 0),
+        ]);
+        let quantifier = ReporterQuantifier::new(IsobaricReagent::ITRAQ4, Tolerance::PPM(20.0));
+        quantifier.annotate_spectrum(&mut spectrum).unwrap();
+        quantifier.annotate_spectrum(&mut spectrum).unwrap();
+        assert_eq!(
+            quantifier.read_annotations(&spectrum).unwrap(),
+            [10.0, 0.0, 0.0, 40.0]
+        );
+        assert_eq!(
+            spectrum
+                .description()
+                .params()
+                .iter()
+                .filter(|p| p.name.starts_with("reporter ion intensity"))
+                .count(),
+            4
+        );
+
+        let table = quantifier
+            .quantify_source(vec![spectrum.clone(), spectrum].into_iter(), 2)
+            .unwrap();
+        assert_eq!(table.len(), 2);
+        let mut buffer = Vec::new();
+        table.write_tsv(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let mut lines = text.lines();
+        assert!(lines.next().unwrap().ends_with("\t114\t115\t116\t117"));
+        assert!(lines.next().unwrap().ends_with("\t10\t0\t0\t40"));
+    }
+}
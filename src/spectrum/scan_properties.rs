@@ -7,7 +7,8 @@ use num_traits::Float;
 use super::spectrum_types::{CentroidPeakAdapting, DeconvolutedPeakAdapting, SpectrumLike};
 use crate::io::traits::SpectrumSource;
 use crate::params::{
-    ControlledVocabulary, Param, ParamDescribed, ParamLike, ParamValue, Unit, ValueRef, CURIE,
+    ControlledVocabulary, Param, ParamCow, ParamDescribed, ParamLike, ParamValue, Unit, ValueRef,
+    CURIE,
 };
 use crate::meta::DissociationMethodTerm;
 use crate::{curie, impl_param_described, ParamList};
@@ -80,12 +81,29 @@ impl IsolationWindow {
 
     pub fn contains<F: Float>(&self, point: F) -> bool {
         let point = point.to_f32().unwrap();
-        self.lower_bound <= point && self.upper_bound <= point
+        self.lower_bound <= point && point <= self.upper_bound
     }
 
     pub fn is_empty(&self) -> bool {
         self.lower_bound == 0.0 && self.upper_bound == 0.0
     }
+
+    /// The distance from the target m/z to the lower bound, the "isolation window lower offset"
+    pub fn lower_offset(&self) -> f32 {
+        self.target - self.lower_bound
+    }
+
+    /// The distance from the target m/z to the upper bound, the "isolation window upper offset"
+    pub fn upper_offset(&self) -> f32 {
+        self.upper_bound - self.target
+    }
+
+    /// Set the bounds of the window relative to the current target m/z
+    pub fn set_offsets(&mut self, lower_offset: f32, upper_offset: f32) {
+        self.lower_bound = self.target - lower_offset;
+        self.upper_bound = self.target + upper_offset;
+        self.flags = IsolationWindowState::Complete;
+    }
 }
 
 impl PartialEq for IsolationWindow {
@@ -115,7 +133,7 @@ impl ScanWindow {
 
     pub fn contains<F: Float>(&self, point: F) -> bool {
         let point = point.to_f32().unwrap();
-        self.lower_bound <= point && self.upper_bound <= point
+        self.lower_bound <= point && point <= self.upper_bound
     }
 
     pub fn is_empty(&self) -> bool {
@@ -136,11 +154,12 @@ pub struct ScanEvent {
     pub params: Option<Box<ParamList>>,
 }
 
+pub(crate) const ION_MOBILITY_DRIFT_TIME: CURIE = curie!(MS:1002476);
+pub(crate) const INVERSE_REDUCED_ION_MOBILITY: CURIE = curie!(MS:1002815);
+
 pub(crate) const ION_MOBILITY_SCAN_TERMS: [CURIE; 4] = [
-    // ion mobility drift time
-    curie!(MS:1002476),
-    // inverse reduced ion mobility drift time
-    curie!(MS:1002815),
+    ION_MOBILITY_DRIFT_TIME,
+    INVERSE_REDUCED_ION_MOBILITY,
     // FAIMS compensation voltage
     curie!(MS:1001581),
     // SELEXION compensation voltage
@@ -163,12 +182,91 @@ pub trait IonMobilityMeasure: ParamDescribed {
     fn has_ion_mobility(&self) -> bool {
         self.ion_mobility().is_some()
     }
+
+    /// Get the FAIMS or SelexION compensation voltage, if one is present.
+    ///
+    /// Unlike [`IonMobilityMeasure::ion_mobility`], this ignores drift time
+    /// measures, which vary within a run rather than selecting a subset of it.
+    fn compensation_voltage(&self) -> Option<f64> {
+        for u in &ION_MOBILITY_SCAN_TERMS[2..] {
+            if let Some(v) = self.get_param_by_curie(u).map(|p| p.value()) {
+                return v.to_f64().map(Some).unwrap_or_else(|e| {
+                    warn!("Failed to parse compensation voltage {u} value {v}: {e}");
+                    None
+                });
+            }
+        }
+        None
+    }
+
+    /// Get the collisional cross section in square angstroms, if one is present
+    fn collisional_cross_section(&self) -> Option<f64> {
+        self.get_param_by_curie(&COLLISIONAL_CROSS_SECTION)
+            .and_then(|p| p.to_f64().ok())
+    }
 }
 
+pub(crate) const COLLISIONAL_CROSS_SECTION: CURIE = curie!(MS:1002954);
 pub(crate) const PRESET_SCAN_CONFIGURATION: CURIE = curie!(MS:1000616);
 pub(crate) const MASS_RESOLUTION: CURIE = curie!(MS:1000011);
 pub(crate) const FILTER_STRING: CURIE = curie!(MS:1000512);
 pub(crate) const SCAN_TITLE: CURIE = curie!(MS:1000499);
+pub(crate) const FAIMS_COMPENSATION_VOLTAGE: CURIE = curie!(MS:1001581);
+pub(crate) const TOTAL_ION_CURRENT: CURIE = curie!(MS:1000285);
+pub(crate) const BASE_PEAK_MZ: CURIE = curie!(MS:1000504);
+pub(crate) const BASE_PEAK_INTENSITY: CURIE = curie!(MS:1000505);
+pub(crate) const LOWEST_OBSERVED_MZ: CURIE = curie!(MS:1000528);
+pub(crate) const HIGHEST_OBSERVED_MZ: CURIE = curie!(MS:1000527);
+pub(crate) const MONOISOTOPIC_MZ: CURIE = curie!(MS:1003208);
+pub(crate) const NORMALIZED_COLLISION_ENERGY: CURIE = curie!(MS:1000138);
+pub(crate) const SUPPLEMENTAL_COLLISION_ENERGY: CURIE = curie!(MS:1002680);
+pub(crate) const POSSIBLE_CHARGE_STATE: CURIE = curie!(MS:1000633);
+
+/// There are no controlled vocabulary terms for stepped collision energies or ion/ion
+/// reaction times, so they are stored as user parameters with these names
+pub(crate) const STEPPED_COLLISION_ENERGY_NAME: &str = "stepped collision energy";
+pub(crate) const REACTION_TIME_NAME: &str = "reaction time";
+
+/// There is no controlled vocabulary term for the automatic gain control target, so
+/// it is stored as a user parameter with this name
+pub(crate) const AGC_TARGET_NAME: &str = "AGC target";
+
+/// The selected ion m/z and charge reported by the instrument, preserved as user parameters
+/// when they are replaced by a precursor correction
+pub(crate) const ORIGINAL_MZ_NAME: &str = "original selected ion m/z";
+pub(crate) const ORIGINAL_CHARGE_NAME: &str = "original charge state";
+
+/// The fraction of the isolated signal attributed to the selected ion, stored as a user parameter
+pub(crate) const ISOLATION_PURITY_NAME: &str = "precursor isolation purity";
+
+/// Read the value of the parameter identified by `curie` as a float
+fn param_f64<P: ParamDescribed>(source: &P, curie: &CURIE) -> Option<f64> {
+    source
+        .get_param_by_curie(curie)
+        .and_then(|p| p.to_f64().ok())
+}
+
+/// Set the value of the parameter identified by `curie`, adding it with `name` and `unit`
+/// if it is not already present
+fn set_param_f64<P: ParamDescribed>(
+    target: &mut P,
+    curie: CURIE,
+    name: &str,
+    value: f64,
+    unit: Unit,
+) {
+    if let Some(p) = target.params_mut().iter_mut().find(|p| **p == curie) {
+        p.value = value.into();
+        p.unit = unit;
+    } else {
+        target.add_param(
+            curie
+                .controlled_vocabulary
+                .param_val(curie.accession, name, value)
+                .with_unit_t(&unit),
+        );
+    }
+}
 
 impl ScanEvent {
     pub fn new(
@@ -195,6 +293,51 @@ impl ScanEvent {
         self.get_param_by_curie(&MASS_RESOLUTION).map(|p| p.value())
     }
 
+    /// The mass resolution of the analyzer for this scan, as a number
+    pub fn mass_resolution(&self) -> Option<f64> {
+        param_f64(self, &MASS_RESOLUTION)
+    }
+
+    pub fn set_mass_resolution(&mut self, resolution: f64) {
+        set_param_f64(
+            self,
+            MASS_RESOLUTION,
+            "mass resolution",
+            resolution,
+            Unit::Unknown,
+        )
+    }
+
+    /// The automatic gain control target, the number of charges the instrument tried to accumulate
+    pub fn agc_target(&self) -> Option<f64> {
+        self.get_param_by_name(AGC_TARGET_NAME)
+            .and_then(|p| p.to_f64().ok())
+    }
+
+    pub fn set_agc_target(&mut self, target: f64) {
+        if let Some(p) = self
+            .params_mut()
+            .iter_mut()
+            .find(|p| p.name == AGC_TARGET_NAME)
+        {
+            p.value = target.into();
+        } else {
+            self.add_param(Param::new_key_value(AGC_TARGET_NAME, target));
+        }
+    }
+
+    /// Set the FAIMS compensation voltage in volts. It is read with
+    /// [`IonMobilityMeasure::compensation_voltage`].
+    pub fn set_compensation_voltage(&mut self, voltage: f64) {
+        set_param_f64(
+            self,
+            FAIMS_COMPENSATION_VOLTAGE,
+            "FAIMS compensation voltage",
+            voltage,
+            Unit::Volt,
+        )
+    }
+
     pub fn scan_configuration(&self) -> Option<ValueRef> {
         self.get_param_by_curie(&PRESET_SCAN_CONFIGURATION)
             .map(|p| p.value())
@@ -371,12 +514,127 @@ impl IonProperties for SelectedIon {
 
 impl IonMobilityMeasure for SelectedIon {}
 
+impl SelectedIon {
+    /// The monoisotopic m/z of the ion, if it was determined separately from [`SelectedIon::mz`]
+    pub fn monoisotopic_mz(&self) -> Option<f64> {
+        param_f64(self, &MONOISOTOPIC_MZ)
+    }
+
+    pub fn set_monoisotopic_mz(&mut self, mz: f64) {
+        set_param_f64(
+            self,
+            MONOISOTOPIC_MZ,
+            "experimental precursor monoisotopic m/z",
+            mz,
+            Unit::MZ,
+        )
+    }
+
+    /// The m/z reported for the ion before it was replaced with [`SelectedIon::replace_mz_and_charge`]
+    pub fn original_mz(&self) -> Option<f64> {
+        self.get_param_by_name(ORIGINAL_MZ_NAME)
+            .and_then(|p| p.to_f64().ok())
+    }
+
+    /// The charge reported for the ion before it was replaced with [`SelectedIon::replace_mz_and_charge`],
+    /// if one was reported
+    pub fn original_charge(&self) -> Option<i32> {
+        self.get_param_by_name(ORIGINAL_CHARGE_NAME)
+            .and_then(|p| p.to_i32().ok())
+    }
+
+    /// The fraction of the MS1 signal in the isolation window attributed to this ion, between 0 and 1
+    pub fn isolation_purity(&self) -> Option<f32> {
+        self.get_param_by_name(ISOLATION_PURITY_NAME)
+            .and_then(|p| p.to_f32().ok())
+    }
+
+    pub fn set_isolation_purity(&mut self, purity: f32) {
+        if let Some(p) = self
+            .params_mut()
+            .iter_mut()
+            .find(|p| p.name == ISOLATION_PURITY_NAME)
+        {
+            p.value = purity.into();
+        } else {
+            self.add_param(Param::new_key_value(ISOLATION_PURITY_NAME, purity));
+        }
+    }
+
+    /// Set the collisional cross section in square angstroms, read with
+    /// [`IonMobilityMeasure::collisional_cross_section`]
+    pub fn set_collisional_cross_section(&mut self, ccs: f64) {
+        set_param_f64(
+            self,
+            COLLISIONAL_CROSS_SECTION,
+            "collisional cross sectional area",
+            ccs,
+            Unit::SquareAngstrom,
+        )
+    }
+
+    /// The candidate charge states of an ion whose charge could not be determined, ordered from
+    /// most to least likely. Empty if none were recorded.
+    pub fn possible_charges(&self) -> Vec<i32> {
+        self.params()
+            .iter()
+            .filter(|p| **p == POSSIBLE_CHARGE_STATE)
+            .filter_map(|p| p.to_i32().ok())
+            .collect()
+    }
+
+    /// Replace the candidate charge states of the ion, ordered from most to least likely
+    pub fn set_possible_charges(&mut self, charges: &[i32]) {
+        if let Some(params) = self.params.as_mut() {
+            params.retain(|p| *p != POSSIBLE_CHARGE_STATE);
+        }
+        for z in charges {
+            self.add_param(POSSIBLE_CHARGE_STATE.controlled_vocabulary.param_val(
+                POSSIBLE_CHARGE_STATE.accession,
+                "possible charge state",
+                *z,
+            ));
+        }
+    }
+
+    /// Replace the ion's m/z and charge, preserving the originally reported values as parameters.
+    ///
+    /// Only the first replacement is preserved, so repeated corrections still refer back to the
+    /// values reported by the instrument. A separately recorded [`SelectedIon::monoisotopic_mz`]
+    /// is updated to match.
+    pub fn replace_mz_and_charge(&mut self, mz: f64, charge: Option<i32>) {
+        if self.original_mz().is_none() {
+            self.add_param(Param::new_key_value(ORIGINAL_MZ_NAME, self.mz).with_unit_t(&Unit::MZ));
+            if let Some(z) = self.charge {
+                self.add_param(Param::new_key_value(ORIGINAL_CHARGE_NAME, z));
+            }
+        }
+        if self.monoisotopic_mz().is_some() {
+            self.set_monoisotopic_mz(mz);
+        }
+        self.mz = mz;
+        self.charge = charge;
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq)]
-/// Describes the activation method used to dissociate the precursor ion
+/// Describes the activation method used to dissociate the precursor ion.
+///
+/// Combined activations like EThcD or ETciD list the primary method first, followed by
+/// a supplemental method. The primary collision energy is [`Activation::energy`], the
+/// supplemental method's energy is [`Activation::supplemental_energy`] and the electron-based
+/// method's duration is [`Activation::reaction_time`].
 pub struct Activation {
     _methods: Vec<DissociationMethodTerm>,
     pub energy: f32,
     pub params: ParamList,
+    /// The collision energy of the supplemental activation, if any
+    pub supplemental_energy: Option<f32>,
+    /// The collision energies used when the energy was stepped. When this is not empty,
+    /// [`Activation::energy`] is the central energy.
+    pub stepped_energies: Vec<f32>,
+    /// The ion/ion reaction time in milliseconds for electron-based methods like ETD
+    pub reaction_time: Option<f32>,
 }
 
 impl Activation {
@@ -420,6 +678,162 @@ impl Activation {
         DissociationMethodTerm::from_accession(accession).is_some()
     }
 
+    /// The first method that is not a supplemental activation
+    pub fn primary_method(&self) -> Option<&DissociationMethodTerm> {
+        self._methods.iter().find(|m| !m.is_supplemental())
+    }
+
+    /// Iterate over the methods that were used to supplement the primary method
+    pub fn supplemental_methods(&self) -> impl Iterator<Item = &DissociationMethodTerm> {
+        self._methods.iter().filter(|m| m.is_supplemental())
+    }
+
+    /// Check if any supplemental activation was applied
+    pub fn has_supplemental_activation(&self) -> bool {
+        self._methods.iter().any(|m| m.is_supplemental())
+    }
+
+    /// Check if the collision energy was stepped
+    pub fn is_stepped(&self) -> bool {
+        !self.stepped_energies.is_empty()
+    }
+
+    /// Check if electron transfer dissociation was used with supplemental beam-type collisional
+    /// activation
+    pub fn is_ethcd(&self) -> bool {
+        self.is_combination_of(
+            DissociationMethodTerm::ElectronTransferDissociation,
+            DissociationMethodTerm::SupplementalBeamTypeCollisionInducedDissociation,
+        )
+    }
+
+    /// Check if electron transfer dissociation was used with supplemental collisional activation
+    pub fn is_etcid(&self) -> bool {
+        self.is_combination_of(
+            DissociationMethodTerm::ElectronTransferDissociation,
+            DissociationMethodTerm::SupplementalCollisionInducedDissociation,
+        )
+    }
+
+    /// Check if electron capture dissociation was used with any supplemental activation
+    pub fn is_supplemental_ecd(&self) -> bool {
+        self.has_method(&DissociationMethodTerm::ElectronCaptureDissociation)
+            && self.has_supplemental_activation()
+    }
+
+    fn is_combination_of(
+        &self,
+        primary: DissociationMethodTerm,
+        supplemental: DissociationMethodTerm,
+    ) -> bool {
+        self.has_method(&primary) && self.has_method(&supplemental)
+    }
+
+    /// Check if `method` was used
+    pub fn has_method(&self, method: &DissociationMethodTerm) -> bool {
+        self._methods.contains(method)
+    }
+
+    /// Get the collision energy of `method`. Electron-based methods have no collision energy,
+    /// see [`Activation::reaction_time_of`] instead.
+    pub fn energy_of(&self, method: &DissociationMethodTerm) -> Option<f32> {
+        if !self.has_method(method) || method.is_electronic() {
+            None
+        } else if method.is_supplemental() {
+            self.supplemental_energy
+        } else if self.energy != 0.0 {
+            Some(self.energy)
+        } else {
+            None
+        }
+    }
+
+    /// Get the reaction time of `method` in milliseconds, if it is an electron-based method
+    pub fn reaction_time_of(&self, method: &DissociationMethodTerm) -> Option<f32> {
+        if self.has_method(method) && method.is_electronic() {
+            self.reaction_time
+        } else {
+            None
+        }
+    }
+
+    /// Move the supplemental energy, stepped energies, reaction time and any additional
+    /// dissociation methods stored in [`Activation::params`] into their structured fields.
+    pub fn _extract_energies_from_params(&mut self) {
+        let mut rest = Vec::with_capacity(self.params.len());
+        for p in self.params.drain(..) {
+            if Self::is_param_activation(&p) {
+                let method: DissociationMethodTerm = p.into();
+                if !self._methods.contains(&method) {
+                    self._methods.push(method);
+                }
+            } else if p == SUPPLEMENTAL_COLLISION_ENERGY {
+                match p.to_f32() {
+                    Ok(v) => self.supplemental_energy = Some(v),
+                    Err(_) => rest.push(p),
+                }
+            } else if p.name == STEPPED_COLLISION_ENERGY_NAME {
+                let steps: Result<Vec<f32>, _> = p
+                    .value
+                    .to_string()
+                    .split(',')
+                    .map(|s| s.trim().parse::<f32>())
+                    .collect();
+                match steps {
+                    Ok(steps) => self.stepped_energies = steps,
+                    Err(_) => rest.push(p),
+                }
+            } else if p.name == REACTION_TIME_NAME {
+                let value = p.to_f64().ok().map(|v| match p.unit {
+                    Unit::Second => v * 1000.0,
+                    Unit::Minute => v * 60000.0,
+                    _ => v,
+                });
+                match value {
+                    Some(v) => self.reaction_time = Some(v as f32),
+                    None => rest.push(p),
+                }
+            } else {
+                rest.push(p)
+            }
+        }
+        self.params = rest;
+    }
+
+    /// Convert the supplemental energy, stepped energies and reaction time into parameters
+    /// for writing. The dissociation methods and [`Activation::energy`] are not included.
+    pub fn structured_params(&self) -> Vec<Param> {
+        let mut params = Vec::new();
+        if let Some(energy) = self.supplemental_energy {
+            params.push(
+                ControlledVocabulary::MS
+                    .param_val(
+                        SUPPLEMENTAL_COLLISION_ENERGY.accession,
+                        "supplemental collision energy",
+                        energy,
+                    )
+                    .with_unit_t(&Unit::Electronvolt),
+            );
+        }
+        if !self.stepped_energies.is_empty() {
+            let steps: Vec<String> = self
+                .stepped_energies
+                .iter()
+                .map(|e| e.to_string())
+                .collect();
+            params.push(Param::new_key_value(
+                STEPPED_COLLISION_ENERGY_NAME,
+                steps.join(","),
+            ));
+        }
+        if let Some(time) = self.reaction_time {
+            params.push(
+                Param::new_key_value(REACTION_TIME_NAME, time).with_unit_t(&Unit::Millisecond),
+            );
+        }
+        params
+    }
+
     pub fn _extract_methods_from_params(&mut self) {
         let mut methods = Vec::with_capacity(1);
         let mut rest = Vec::with_capacity(self.params.len());
@@ -433,6 +847,21 @@ impl Activation {
         self.params = rest;
         self._methods = methods;
     }
+
+    /// The collision energy as a percentage of an instrument-specific reference energy
+    pub fn normalized_collision_energy(&self) -> Option<f64> {
+        param_f64(self, &NORMALIZED_COLLISION_ENERGY)
+    }
+
+    pub fn set_normalized_collision_energy(&mut self, energy: f64) {
+        set_param_f64(
+            self,
+            NORMALIZED_COLLISION_ENERGY,
+            "normalized collision energy",
+            energy,
+            Unit::PercentElectronVolt,
+        )
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -485,6 +914,22 @@ impl Precursor {
     }
 }
 
+/// Describes the product ion isolation of a chromatogram, such as the third quadrupole
+/// (Q3) of a selected reaction monitoring transition.
+///
+/// See <https://peptideatlas.org/tmp/mzML1.1.0.html#product>
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Product {
+    /// Describes the isolation window around the product ion
+    pub isolation_window: IsolationWindow,
+}
+
+impl Product {
+    pub fn new(isolation_window: IsolationWindow) -> Self {
+        Self { isolation_window }
+    }
+}
+
 /**
 A trait for abstracting over how a precursor ion is described, immutably.
 */
@@ -597,7 +1042,7 @@ Describes the polarity of a mass spectrum. A spectrum is either `Positive` (1+),
 or `Unknown` (0). The `Unknown` state is the default.
 */
 #[repr(i8)]
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Hash, Default)]
 pub enum ScanPolarity {
     #[default]
     /// The polarity of the spectrum is unknown
@@ -655,6 +1100,74 @@ impl Display for SignalContinuity {
     }
 }
 
+/// The kind of measurement a spectrum records. Most spectra are mass spectra, but a run
+/// may also include the UV/PDA traces of the chromatographic system, which measure absorbance
+/// or emission over wavelength instead of intensity over m/z.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Hash, Eq)]
+pub enum SpectrumType {
+    /// A mass spectrum, either MS1 or MSn depending upon the MS level
+    #[default]
+    MassSpectrum,
+    /// A spectrum of electromagnetic radiation of unspecified kind
+    ElectromagneticRadiationSpectrum,
+    /// A spectrum of the absorption of electromagnetic radiation by the sample
+    AbsorptionSpectrum,
+    /// A spectrum of the electromagnetic radiation emitted by the sample
+    EmissionSpectrum,
+}
+
+impl SpectrumType {
+    pub fn from_accession(accession: u32) -> Option<Self> {
+        let tp = match accession {
+            1000579 | 1000580 | 1000294 => Self::MassSpectrum,
+            1000804 => Self::ElectromagneticRadiationSpectrum,
+            1000806 => Self::AbsorptionSpectrum,
+            1000805 => Self::EmissionSpectrum,
+            _ => return None,
+        };
+        Some(tp)
+    }
+
+    /// Whether this spectrum measures electromagnetic radiation over wavelength
+    pub fn is_electromagnetic_radiation(&self) -> bool {
+        !self.is_mass_spectrum()
+    }
+
+    pub fn is_mass_spectrum(&self) -> bool {
+        matches!(self, Self::MassSpectrum)
+    }
+
+    /// The controlled vocabulary term for non-mass spectra. Mass spectra are described
+    /// by `MS1 spectrum` or `MSn spectrum` according to their MS level instead.
+    pub fn to_curie(&self) -> Option<CURIE> {
+        match self {
+            Self::MassSpectrum => None,
+            Self::ElectromagneticRadiationSpectrum => {
+                Some(CURIE::new(ControlledVocabulary::MS, 1000804))
+            }
+            Self::AbsorptionSpectrum => Some(CURIE::new(ControlledVocabulary::MS, 1000806)),
+            Self::EmissionSpectrum => Some(CURIE::new(ControlledVocabulary::MS, 1000805)),
+        }
+    }
+
+    pub fn to_param(&self) -> Option<Param> {
+        let name = match self {
+            Self::MassSpectrum => return None,
+            Self::ElectromagneticRadiationSpectrum => "electromagnetic radiation spectrum",
+            Self::AbsorptionSpectrum => "absorption spectrum",
+            Self::EmissionSpectrum => "emission spectrum",
+        };
+        self.to_curie()
+            .map(|c| c.controlled_vocabulary.param(c.accession, name))
+    }
+}
+
+impl Display for SpectrumType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 /**
 The set of descriptive metadata that give context for how a mass spectrum was acquired
 within a particular run. This forms the basis for a large portion of the [`SpectrumDescription`]
@@ -676,14 +1189,20 @@ pub struct SpectrumDescription {
     /// profile
     pub signal_continuity: SignalContinuity,
 
+    /// Whether the spectrum is a mass spectrum or some other kind of spectrum like
+    /// an absorption spectrum
+    pub spectrum_type: SpectrumType,
+
     /// A set of controlled or uncontrolled descriptors of the spectrum not already
     /// covered by fields
     pub params: ParamList,
 
     /// A description of how the spectrum was acquired including time, scan windows, and more
     pub acquisition: Acquisition,
-    /// The parent ion or ions and their isolation and activation description
-    pub precursor: Option<Precursor>,
+    /// The parent ion or ions and their isolation and activation description. Most spectra
+    /// have at most one, but synchronous precursor selection (SPS) MS3 and multiplexed (MSX)
+    /// acquisitions may isolate several. The first entry is treated as the primary precursor.
+    pub precursors: Vec<Precursor>,
 }
 
 impl SpectrumDescription {
@@ -696,7 +1215,7 @@ impl SpectrumDescription {
         signal_continuity: SignalContinuity,
         params: ParamList,
         acquisition: Acquisition,
-        precursor: Option<Precursor>,
+        precursors: Vec<Precursor>,
     ) -> Self {
         Self {
             id,
@@ -706,13 +1225,101 @@ impl SpectrumDescription {
             signal_continuity,
             params,
             acquisition,
-            precursor,
+            precursors,
+            ..Default::default()
         }
     }
 
+    /// The primary precursor of the spectrum, if any
+    #[deprecated(
+        since = "0.34.0",
+        note = "the `precursor` field became the `precursors` list, use `precursors.first()`"
+    )]
+    pub fn precursor(&self) -> Option<&Precursor> {
+        self.precursors.first()
+    }
+
+    /// Mutably access the primary precursor of the spectrum, if any
+    #[deprecated(
+        since = "0.34.0",
+        note = "the `precursor` field became the `precursors` list, use `precursors.first_mut()`"
+    )]
+    pub fn precursor_mut(&mut self) -> Option<&mut Precursor> {
+        self.precursors.first_mut()
+    }
+
+    /// Whether the spectrum measures electromagnetic radiation rather than ions
+    pub fn is_electromagnetic_radiation(&self) -> bool {
+        self.spectrum_type.is_electromagnetic_radiation()
+    }
+
     pub fn title(&self) -> Option<Cow<'_, str>> {
         self.get_param_by_curie(&SCAN_TITLE).map(|p| p.as_str())
     }
+
+    pub fn total_ion_current(&self) -> Option<f64> {
+        param_f64(self, &TOTAL_ION_CURRENT)
+    }
+
+    pub fn set_total_ion_current(&mut self, tic: f64) {
+        set_param_f64(
+            self,
+            TOTAL_ION_CURRENT,
+            "total ion current",
+            tic,
+            Unit::DetectorCounts,
+        )
+    }
+
+    pub fn base_peak_mz(&self) -> Option<f64> {
+        param_f64(self, &BASE_PEAK_MZ)
+    }
+
+    pub fn set_base_peak_mz(&mut self, mz: f64) {
+        set_param_f64(self, BASE_PEAK_MZ, "base peak m/z", mz, Unit::MZ)
+    }
+
+    pub fn base_peak_intensity(&self) -> Option<f64> {
+        param_f64(self, &BASE_PEAK_INTENSITY)
+    }
+
+    pub fn set_base_peak_intensity(&mut self, intensity: f64) {
+        set_param_f64(
+            self,
+            BASE_PEAK_INTENSITY,
+            "base peak intensity",
+            intensity,
+            Unit::DetectorCounts,
+        )
+    }
+
+    pub fn lowest_observed_mz(&self) -> Option<f64> {
+        param_f64(self, &LOWEST_OBSERVED_MZ)
+    }
+
+    pub fn set_lowest_observed_mz(&mut self, mz: f64) {
+        set_param_f64(
+            self,
+            LOWEST_OBSERVED_MZ,
+            "lowest observed m/z",
+            mz,
+            Unit::MZ,
+        )
+    }
+
+    pub fn highest_observed_mz(&self) -> Option<f64> {
+        param_f64(self, &HIGHEST_OBSERVED_MZ)
+    }
+
+    pub fn set_highest_observed_mz(&mut self, mz: f64) {
+        set_param_f64(
+            self,
+            HIGHEST_OBSERVED_MZ,
+            "highest observed m/z",
+            mz,
+            Unit::MZ,
+        )
+    }
 }
 
 impl_param_described!(Activation, SpectrumDescription);
@@ -795,9 +1402,57 @@ impl ChromatogramType {
             Self::EmissionChromatogram => CURIE::new(ControlledVocabulary::MS, 1000813),
             Self::FlowRateChromatogram => CURIE::new(ControlledVocabulary::MS, 1003020),
             Self::PressureChromatogram => CURIE::new(ControlledVocabulary::MS, 1003019),
-            Self::Unknown => CURIE::new(ControlledVocabulary::MS, 100626),
+            Self::Unknown => CURIE::new(ControlledVocabulary::MS, 1000626),
         }
     }
+
+    pub const fn to_param(&self) -> ParamCow<'static> {
+        const CV: ControlledVocabulary = ControlledVocabulary::MS;
+        match self {
+            Self::TotalIonCurrentChromatogram => {
+                CV.const_param_ident("total ion current chromatogram", 1000235)
+            }
+            Self::BasePeakChromatogram => CV.const_param_ident("basepeak chromatogram", 1000628),
+            Self::SelectedIonCurrentChromatogram => {
+                CV.const_param_ident("selected ion current chromatogram", 1000627)
+            }
+            Self::SelectedIonMonitoringChromatogram => {
+                CV.const_param_ident("selected ion monitoring chromatogram", 1000472)
+            }
+            Self::SelectedReactionMonitoringChromatogram => {
+                CV.const_param_ident("selected reaction monitoring chromatogram", 1000473)
+            }
+            Self::AbsorptionChromatogram => {
+                CV.const_param_ident("absorption chromatogram", 1000812)
+            }
+            Self::EmissionChromatogram => CV.const_param_ident("emission chromatogram", 1000813),
+            Self::FlowRateChromatogram => CV.const_param_ident("flow rate chromatogram", 1003020),
+            Self::PressureChromatogram => CV.const_param_ident("pressure chromatogram", 1003019),
+            Self::Unknown => CV.const_param_ident("chromatogram type", 1000626),
+        }
+    }
+}
+
+/// A selected reaction monitoring transition, the pair of m/z values isolated by the
+/// first (Q1) and third (Q3) quadrupoles of a triple quadrupole instrument.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct SRMTransition {
+    /// The precursor ion m/z isolated in Q1
+    pub q1: f64,
+    /// The product ion m/z isolated in Q3
+    pub q3: f64,
+}
+
+impl SRMTransition {
+    pub fn new(q1: f64, q3: f64) -> Self {
+        Self { q1, q3 }
+    }
+}
+
+impl Display for SRMTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Q1={} Q3={}", self.q1, self.q3)
+    }
 }
 
 /// The set of descriptive metadata that give context for how a chromatogram was
@@ -812,9 +1467,64 @@ pub struct ChromatogramDescription {
 
     pub params: ParamList,
     pub precursor: Option<Precursor>,
+    pub product: Option<Product>,
 }
 
 impl ChromatogramDescription {
+    /// The precursor m/z isolated in Q1, read from the precursor isolation window target
+    /// or, failing that, the first selected ion.
+    pub fn q1(&self) -> Option<f64> {
+        let precursor = self.precursor.as_ref()?;
+        let iw = &precursor.isolation_window;
+        if iw.target > 0.0 {
+            Some(iw.target as f64)
+        } else {
+            precursor.ions.first().map(|ion| ion.mz)
+        }
+    }
+
+    /// The product m/z isolated in Q3, read from the product isolation window target
+    pub fn q3(&self) -> Option<f64> {
+        self.product
+            .as_ref()
+            .map(|p| p.isolation_window.target as f64)
+            .filter(|mz| *mz > 0.0)
+    }
+
+    /// The Q1/Q3 transition this chromatogram was recorded from, if both
+    /// the precursor and product are known.
+    pub fn transition(&self) -> Option<SRMTransition> {
+        Some(SRMTransition::new(self.q1()?, self.q3()?))
+    }
+
+    /// Set the precursor and product isolation windows from `transition`, creating the
+    /// [`Precursor`] and [`Product`] if they are missing. The existing window widths are
+    /// retained.
+    pub fn set_transition(&mut self, transition: SRMTransition) {
+        let q1 = transition.q1 as f32;
+        let q3 = transition.q3 as f32;
+
+        let precursor = self.precursor.get_or_insert_with(Precursor::default);
+        let iw = &mut precursor.isolation_window;
+        let (lower, upper) = (iw.lower_offset(), iw.upper_offset());
+        *iw = IsolationWindow::new(q1, q1 - lower, q1 + upper, IsolationWindowState::Complete);
+        if let Some(ion) = precursor.ions.first_mut() {
+            ion.mz = transition.q1;
+        }
+
+        let product = self.product.get_or_insert_with(Product::default);
+        let iw = &mut product.isolation_window;
+        let (lower, upper) = (iw.lower_offset(), iw.upper_offset());
+        *iw = IsolationWindow::new(q3, q3 - lower, q3 + upper, IsolationWindowState::Complete);
+    }
+
+    pub fn is_srm(&self) -> bool {
+        matches!(
+            self.chromatogram_type,
+            ChromatogramType::SelectedReactionMonitoringChromatogram
+        )
+    }
+
     pub fn is_aggregate(&self) -> bool {
         self.chromatogram_type.is_aggregate()
     }
@@ -0,0 +1,552 @@
+//! Score the similarity of two spectra.
+//!
+//! [`SpectralSimilarity`] matches the peaks of two [`SpectrumLike`] values within a
+//! [`Tolerance`] and scores the matches with one of several [`SimilarityMethod`]s, after
+//! optionally re-weighting their intensities with an [`IntensityTransform`].
+//!
+//! Centroided spectra are compared by m/z, and deconvoluted spectra are compared by neutral mass.
+//! Profile spectra are compared point by point, which is rarely meaningful, so it is best to pick
+//! peaks first.
+//!
+//! ```
+//! use mzpeaks::{CentroidPeak, Tolerance};
+//! use mzdata::spectrum::{CentroidSpectrum, SimilarityMethod, SpectralSimilarity, SpectrumDescription};
+//!
+//! let peaks: Vec<CentroidPeak> = vec![
+//!     CentroidPeak::new(100.0, 50.0, 0),
+//!     CentroidPeak::new(200.0, 100.0, 1),
+//! ];
+//! let a = CentroidSpectrum::new(SpectrumDescription::default(), peaks.into_iter().collect());
+//! let b = a.clone();
+//!
+//! let scorer = SpectralSimilarity::new(SimilarityMethod::Cosine, Tolerance::Da(0.02));
+//! let score = scorer.score(&a, &b);
+//! assert!((score.score - 1.0).abs() < 1e-6);
+//! assert_eq!(score.matched_peaks, 2);
+//! ```
+use std::cmp::Ordering;
+use std::f64::consts::LN_2;
+
+use mzpeaks::prelude::*;
+use mzpeaks::{CentroidLike, DeconvolutedCentroidLike, Tolerance};
+
+#[cfg(feature = "parallelism")]
+use rayon::prelude::*;
+
+use super::peaks::RefPeakDataLevel;
+use super::scan_properties::{IonProperties, PrecursorSelection};
+use super::spectrum_types::SpectrumLike;
+use crate::utils::mass_charge_ratio;
+
+/// A transformation applied to peak intensities before scoring.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum IntensityTransform {
+    /// Use the intensities as they are
+    #[default]
+    Identity,
+    /// Take the square root of each intensity, damping the influence of the most intense peaks
+    Sqrt,
+    /// Take `ln(1 + intensity)` of each intensity
+    Log1p,
+    /// Raise each intensity to a power
+    Power(f64),
+    /// Weight each peak by `mz ^ mz_power * intensity ^ intensity_power`, as in the weighted
+    /// dot product of Stein and Scott (1994)
+    MzWeighted { mz_power: f64, intensity_power: f64 },
+}
+
+impl IntensityTransform {
+    /// Apply the transform to a peak at `mz` with `intensity`
+    pub fn apply(&self, mz: f64, intensity: f64) -> f64 {
+        match self {
+            Self::Identity => intensity,
+            Self::Sqrt => intensity.sqrt(),
+            Self::Log1p => intensity.ln_1p(),
+            Self::Power(power) => intensity.powf(*power),
+            Self::MzWeighted {
+                mz_power,
+                intensity_power,
+            } => mz.powf(*mz_power) * intensity.powf(*intensity_power),
+        }
+    }
+}
+
+/// The ways two spectra may be scored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SimilarityMethod {
+    /// The cosine of the angle between the two intensity vectors, also known as the
+    /// normalized dot product
+    #[default]
+    Cosine,
+    /// The cosine similarity where peaks may also match after shifting one spectrum by the
+    /// difference between the two precursors, for comparing related compounds
+    ModifiedCosine,
+    /// The spectral entropy similarity of Li et al. (2021)
+    Entropy,
+    /// The spectral entropy similarity where low-entropy spectra have their intensities
+    /// re-weighted to increase the influence of minor peaks
+    WeightedEntropy,
+}
+
+/// The result of comparing two spectra
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SimilarityScore {
+    /// The similarity, between 0 and 1
+    pub score: f64,
+    /// The number of peak pairs that contributed to the score
+    pub matched_peaks: usize,
+}
+
+impl SimilarityScore {
+    pub fn new(score: f64, matched_peaks: usize) -> Self {
+        Self {
+            score,
+            matched_peaks,
+        }
+    }
+}
+
+/// A peak reduced to the coordinate it is matched on and its transformed intensity
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScoringPeak {
+    coordinate: f64,
+    intensity: f64,
+}
+
+/// Scores the similarity of pairs of spectra.
+///
+/// Peaks are matched one-to-one, with the pairs contributing the most to the score assigned first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpectralSimilarity {
+    pub method: SimilarityMethod,
+    /// The tolerance used to decide if two peaks match
+    pub tolerance: Tolerance,
+    /// The transform applied to intensities before scoring. The entropy methods apply their own
+    /// normalization after this transform.
+    pub transform: IntensityTransform,
+    /// Ignore peaks whose intensity is below this fraction of the base peak, after transforming
+    pub min_relative_intensity: f64,
+}
+
+impl SpectralSimilarity {
+    pub fn new(method: SimilarityMethod, tolerance: Tolerance) -> Self {
+        Self {
+            method,
+            tolerance,
+            transform: IntensityTransform::default(),
+            min_relative_intensity: 0.0,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: IntensityTransform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_min_relative_intensity(mut self, min_relative_intensity: f64) -> Self {
+        self.min_relative_intensity = min_relative_intensity;
+        self
+    }
+
+    /// Score the similarity of `query` and `reference`. Spectra without peaks score 0.
+    pub fn score<
+        C1: CentroidLike,
+        D1: DeconvolutedCentroidLike,
+        S1: SpectrumLike<C1, D1>,
+        C2: CentroidLike,
+        D2: DeconvolutedCentroidLike,
+        S2: SpectrumLike<C2, D2>,
+    >(
+        &self,
+        query: &S1,
+        reference: &S2,
+    ) -> SimilarityScore {
+        let query_peaks = self.prepare_peaks(&query.peaks());
+        let reference_peaks = self.prepare_peaks(&reference.peaks());
+        let shift = match self.method {
+            SimilarityMethod::ModifiedCosine => precursor_shift(query, reference),
+            _ => None,
+        };
+        self.score_peaks(&query_peaks, &reference_peaks, shift)
+    }
+
+    /// Score `query` against each spectrum in `references`, in order
+    #[cfg(not(feature = "parallelism"))]
+    pub fn score_many<
+        C1: CentroidLike,
+        D1: DeconvolutedCentroidLike,
+        S1: SpectrumLike<C1, D1>,
+        C2: CentroidLike,
+        D2: DeconvolutedCentroidLike,
+        S2: SpectrumLike<C2, D2>,
+    >(
+        &self,
+        query: &S1,
+        references: &[S2],
+    ) -> Vec<SimilarityScore> {
+        let query_peaks = self.prepare_peaks(&query.peaks());
+        references
+            .iter()
+            .map(|reference| self.score_prepared(query, &query_peaks, reference))
+            .collect()
+    }
+
+    /// Score `query` against each spectrum in `references`, in order. The references are
+    /// scored in parallel.
+    #[cfg(feature = "parallelism")]
+    pub fn score_many<
+        C1: CentroidLike,
+        D1: DeconvolutedCentroidLike,
+        S1: SpectrumLike<C1, D1> + Sync,
+        C2: CentroidLike,
+        D2: DeconvolutedCentroidLike,
+        S2: SpectrumLike<C2, D2> + Sync,
+    >(
+        &self,
+        query: &S1,
+        references: &[S2],
+    ) -> Vec<SimilarityScore> {
+        let query_peaks = self.prepare_peaks(&query.peaks());
+        references
+            .par_iter()
+            .map(|reference| self.score_prepared(query, &query_peaks, reference))
+            .collect()
+    }
+
+    fn score_prepared<
+        C1: CentroidLike,
+        D1: DeconvolutedCentroidLike,
+        S1: SpectrumLike<C1, D1>,
+        C2: CentroidLike,
+        D2: DeconvolutedCentroidLike,
+        S2: SpectrumLike<C2, D2>,
+    >(
+        &self,
+        query: &S1,
+        query_peaks: &[ScoringPeak],
+        reference: &S2,
+    ) -> SimilarityScore {
+        let reference_peaks = self.prepare_peaks(&reference.peaks());
+        let shift = match self.method {
+            SimilarityMethod::ModifiedCosine => precursor_shift(query, reference),
+            _ => None,
+        };
+        self.score_peaks(query_peaks, &reference_peaks, shift)
+    }
+
+    /// Extract, transform and filter the peaks of a spectrum, sorted by coordinate
+    fn prepare_peaks<C: CentroidLike, D: DeconvolutedCentroidLike>(
+        &self,
+        peaks: &RefPeakDataLevel<'_, C, D>,
+    ) -> Vec<ScoringPeak> {
+        let mut result: Vec<ScoringPeak> = match peaks {
+            RefPeakDataLevel::Missing => Vec::new(),
+            RefPeakDataLevel::Deconvoluted(peaks) => peaks
+                .iter()
+                .map(|p| ScoringPeak {
+                    coordinate: p.neutral_mass(),
+                    intensity: self.transform.apply(
+                        mass_charge_ratio(p.neutral_mass(), p.charge()),
+                        p.intensity() as f64,
+                    ),
+                })
+                .collect(),
+            _ => peaks
+                .iter()
+                .map(|p| ScoringPeak {
+                    coordinate: p.mz,
+                    intensity: self.transform.apply(p.mz, p.intensity as f64),
+                })
+                .collect(),
+        };
+        result.retain(|p| p.intensity > 0.0 && p.intensity.is_finite());
+        if self.min_relative_intensity > 0.0 {
+            let base = result.iter().map(|p| p.intensity).fold(0.0, f64::max);
+            let threshold = base * self.min_relative_intensity;
+            result.retain(|p| p.intensity >= threshold);
+        }
+        result.sort_by(|a, b| {
+            a.coordinate
+                .partial_cmp(&b.coordinate)
+                .unwrap_or(Ordering::Equal)
+        });
+        result
+    }
+
+    fn score_peaks(
+        &self,
+        query: &[ScoringPeak],
+        reference: &[ScoringPeak],
+        shift: Option<f64>,
+    ) -> SimilarityScore {
+        if query.is_empty() || reference.is_empty() {
+            return SimilarityScore::default();
+        }
+        match self.method {
+            SimilarityMethod::Cosine | SimilarityMethod::ModifiedCosine => {
+                self.cosine(query, reference, shift)
+            }
+            SimilarityMethod::Entropy => self.entropy(query, reference, false),
+            SimilarityMethod::WeightedEntropy => self.entropy(query, reference, true),
+        }
+    }
+
+    fn cosine(
+        &self,
+        query: &[ScoringPeak],
+        reference: &[ScoringPeak],
+        shift: Option<f64>,
+    ) -> SimilarityScore {
+        let query_norm = query
+            .iter()
+            .map(|p| p.intensity.powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let reference_norm = reference
+            .iter()
+            .map(|p| p.intensity.powi(2))
+            .sum::<f64>()
+            .sqrt();
+
+        let mut candidates = candidate_pairs(query, reference, self.tolerance, 0.0, |a, b| a * b);
+        if let Some(shift) = shift.filter(|s| *s != 0.0) {
+            candidates.extend(candidate_pairs(
+                query,
+                reference,
+                self.tolerance,
+                shift,
+                |a, b| a * b,
+            ));
+        }
+        let matches = assign_pairs(candidates, query.len(), reference.len());
+        let dot: f64 = matches.iter().map(|m| m.2).sum();
+        SimilarityScore::new(dot / (query_norm * reference_norm), matches.len())
+    }
+
+    fn entropy(
+        &self,
+        query: &[ScoringPeak],
+        reference: &[ScoringPeak],
+        weighted: bool,
+    ) -> SimilarityScore {
+        let query = normalize_for_entropy(query, weighted);
+        let reference = normalize_for_entropy(reference, weighted);
+
+        // A matched pair contributes (a + b) ln(a + b) - a ln(a) - b ln(b) to the
+        // entropy difference between the merged spectrum and its parts.
+        let contribution = |a: f64, b: f64| (a + b) * (a + b).ln() - a * a.ln() - b * b.ln();
+        let candidates = candidate_pairs(&query, &reference, self.tolerance, 0.0, contribution);
+        let matches = assign_pairs(candidates, query.len(), reference.len());
+        let total: f64 = matches.iter().map(|m| m.2).sum();
+        SimilarityScore::new((total / (2.0 * LN_2)).clamp(0.0, 1.0), matches.len())
+    }
+}
+
+/// The difference between the precursor m/z (or neutral mass, for deconvoluted spectra) of
+/// `query` and `reference`
+fn precursor_shift<
+    C1: CentroidLike,
+    D1: DeconvolutedCentroidLike,
+    S1: SpectrumLike<C1, D1>,
+    C2: CentroidLike,
+    D2: DeconvolutedCentroidLike,
+    S2: SpectrumLike<C2, D2>,
+>(
+    query: &S1,
+    reference: &S2,
+) -> Option<f64> {
+    let a = query.precursor()?.ion();
+    let b = reference.precursor()?.ion();
+    match (query.peaks(), reference.peaks()) {
+        (RefPeakDataLevel::Deconvoluted(_), RefPeakDataLevel::Deconvoluted(_)) => {
+            Some(a.neutral_mass() - b.neutral_mass())
+        }
+        _ => Some(a.mz() - b.mz()),
+    }
+}
+
+/// Scale intensities to sum to 1, re-weighting low entropy spectra if requested
+fn normalize_for_entropy(peaks: &[ScoringPeak], weighted: bool) -> Vec<ScoringPeak> {
+    let mut peaks = normalize_sum(peaks);
+    if weighted {
+        let entropy = -peaks
+            .iter()
+            .map(|p| p.intensity * p.intensity.ln())
+            .sum::<f64>();
+        if entropy < 3.0 {
+            let power = 0.25 + entropy * 0.25;
+            peaks
+                .iter_mut()
+                .for_each(|p| p.intensity = p.intensity.powf(power));
+            peaks = normalize_sum(&peaks);
+        }
+    }
+    peaks
+}
+
+fn normalize_sum(peaks: &[ScoringPeak]) -> Vec<ScoringPeak> {
+    let total: f64 = peaks.iter().map(|p| p.intensity).sum();
+    peaks
+        .iter()
+        .map(|p| ScoringPeak {
+            coordinate: p.coordinate,
+            intensity: p.intensity / total,
+        })
+        .collect()
+}
+
+/// Find every pair of peaks where `reference + shift` matches `query` within `tolerance`,
+/// scoring each with `weight`. Both peak lists must be sorted by coordinate.
+fn candidate_pairs<F: Fn(f64, f64) -> f64>(
+    query: &[ScoringPeak],
+    reference: &[ScoringPeak],
+    tolerance: Tolerance,
+    shift: f64,
+    weight: F,
+) -> Vec<(usize, usize, f64)> {
+    let mut pairs = Vec::new();
+    let mut start = 0;
+    for (i, q) in query.iter().enumerate() {
+        let (lower, upper) = tolerance.bounds(q.coordinate);
+        while start < reference.len() && reference[start].coordinate + shift < lower {
+            start += 1;
+        }
+        for (j, r) in reference.iter().enumerate().skip(start) {
+            if r.coordinate + shift > upper {
+                break;
+            }
+            pairs.push((i, j, weight(q.intensity, r.intensity)));
+        }
+    }
+    pairs
+}
+
+/// Greedily pick the highest scoring pairs so that each peak is used at most once
+fn assign_pairs(
+    mut candidates: Vec<(usize, usize, f64)>,
+    query_len: usize,
+    reference_len: usize,
+) -> Vec<(usize, usize, f64)> {
+    candidates.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal));
+    let mut used_query = vec![false; query_len];
+    let mut used_reference = vec![false; reference_len];
+    let mut matches = Vec::new();
+    for (i, j, score) in candidates {
+        if used_query[i] || used_reference[j] {
+            continue;
+        }
+        used_query[i] = true;
+        used_reference[j] = true;
+        matches.push((i, j, score));
+    }
+    matches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrum::test_utils::{centroid_spectrum, precursor};
+
+    #[test]
+    fn test_cosine() {
+        let a = centroid_spectrum(
+            "a",
+            0.0,
+            2,
+            &[(100.0, 10.0), (150.0, 20.0), (200.0, 30.0)],
+            vec![precursor(300.0, Some(1), None)],
+        );
+        let b = centroid_spectrum(
+            "b",
+            0.0,
+            2,
+            &[(100.005, 10.0), (150.0, 20.0), (250.0, 30.0)],
+            vec![precursor(300.0, Some(1), None)],
+        );
+        let scorer = SpectralSimilarity::new(SimilarityMethod::Cosine, Tolerance::Da(0.01));
+
+        let score = scorer.score(&a, &a);
+        assert!((score.score - 1.0).abs() < 1e-9);
+        assert_eq!(score.matched_peaks, 3);
+
+        let score = scorer.score(&a, &b);
+        assert_eq!(score.matched_peaks, 2);
+        let expected = (100.0 + 400.0) / 1400.0;
+        assert!((score.score - expected).abs() < 1e-9, "{:?}", score);
+
+        let sqrt = scorer
+            .clone()
+            .with_transform(IntensityTransform::Sqrt)
+            .score(&a, &b);
+        assert!(sqrt.score > score.score);
+
+        let empty = centroid_spectrum("empty", 0.0, 2, &[], vec![precursor(300.0, Some(1), None)]);
+        assert_eq!(scorer.score(&a, &empty), SimilarityScore::default());
+    }
+
+    #[test]
+    fn test_modified_cosine() {
+        let a = centroid_spectrum(
+            "a",
+            0.0,
+            2,
+            &[(100.0, 10.0), (150.0, 20.0), (200.0, 30.0)],
+            vec![precursor(300.0, Some(1), None)],
+        );
+        // The same compound with a 14 Da modification carried by the heavier fragments
+        let b = centroid_spectrum(
+            "b",
+            0.0,
+            2,
+            &[(100.0, 10.0), (164.0, 20.0), (214.0, 30.0)],
+            vec![precursor(314.0, Some(1), None)],
+        );
+
+        let cosine = SpectralSimilarity::new(SimilarityMethod::Cosine, Tolerance::Da(0.01));
+        let modified =
+            SpectralSimilarity::new(SimilarityMethod::ModifiedCosine, Tolerance::Da(0.01));
+        assert_eq!(cosine.score(&a, &b).matched_peaks, 1);
+        let score = modified.score(&a, &b);
+        assert_eq!(score.matched_peaks, 3);
+        assert!((score.score - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_entropy() {
+        let a = centroid_spectrum(
+            "a",
+            0.0,
+            2,
+            &[(100.0, 10.0), (150.0, 20.0), (200.0, 30.0)],
+            vec![precursor(300.0, Some(1), None)],
+        );
+        let b = centroid_spectrum(
+            "b",
+            0.0,
+            2,
+            &[(100.0, 10.0), (150.0, 20.0), (250.0, 30.0)],
+            vec![precursor(300.0, Some(1), None)],
+        );
+        let c = centroid_spectrum(
+            "c",
+            0.0,
+            2,
+            &[(110.0, 10.0), (160.0, 20.0)],
+            vec![precursor(300.0, Some(1), None)],
+        );
+
+        for method in [SimilarityMethod::Entropy, SimilarityMethod::WeightedEntropy] {
+            let scorer = SpectralSimilarity::new(method, Tolerance::PPM(20.0));
+            assert!((scorer.score(&a, &a).score - 1.0).abs() < 1e-9);
+            let partial = scorer.score(&a, &b).score;
+            assert!(partial > 0.0 && partial < 1.0);
+            assert_eq!(scorer.score(&a, &c).score, 0.0);
+        }
+
+        let scores = SpectralSimilarity::new(SimilarityMethod::Entropy, Tolerance::PPM(20.0))
+            .score_many(&a, &[a.clone(), b.clone(), c.clone()]);
+        assert_eq!(scores.len(), 3);
+        assert!(scores[0].score > scores[1].score);
+        assert!(scores[1].score > scores[2].score);
+    }
+}
@@ -17,12 +17,13 @@ use mzsignal::{
     FittedPeak,
 };
 
-use crate::params::{ParamDescribed, ParamList, Unit, Value};
+use crate::params::{ParamDescribed, ParamList};
 #[allow(unused)]
 use crate::spectrum::bindata::{ArrayType, BinaryArrayMap, BinaryDataArrayType};
 use crate::spectrum::peaks::{PeakDataLevel, RefPeakDataLevel, SpectrumSummary};
 use crate::spectrum::scan_properties::{
-    Acquisition, IonMobilityMeasure, Precursor, ScanPolarity, SignalContinuity, SpectrumDescription,
+    Acquisition, IonMobilityMeasure, Precursor, ScanPolarity, SignalContinuity,
+    SpectrumDescription, SpectrumType,
 };
 
 use super::bindata::{ArrayRetrievalError, ArraysAvailable, BuildArrayMapFrom, BuildFromArrayMap};
@@ -62,37 +63,44 @@ pub trait SpectrumLike<
         &self.description().acquisition
     }
 
-    /// Access the precursor information, if it exists.
+    /// Access the primary precursor information, if it exists.
+    ///
+    /// When more than one precursor is present, this is the first one.
+    /// See [`precursors`](Self::precursors) for the complete list.
     #[inline]
     fn precursor(&self) -> Option<&Precursor> {
         let desc = self.description();
-        if let Some(precursor) = &desc.precursor {
-            Some(precursor)
-        } else {
-            None
-        }
+        desc.precursors.first()
+    }
+
+    /// Access all of the precursors of the spectrum, in the order they were listed
+    #[inline]
+    fn precursors(&self) -> &[Precursor] {
+        &self.description().precursors
     }
 
     /// Iterate over all precursors of the spectrum
     fn precursor_iter(&self) -> impl Iterator<Item = &Precursor> {
         let desc = self.description();
-        desc.precursor.iter()
+        desc.precursors.iter()
     }
 
-    /// Mutably access the precursor information, if it exists
+    /// Mutably access the primary precursor information, if it exists
     fn precursor_mut(&mut self) -> Option<&mut Precursor> {
         let desc = self.description_mut();
-        if let Some(precursor) = desc.precursor.as_mut() {
-            Some(precursor)
-        } else {
-            None
-        }
+        desc.precursors.first_mut()
     }
 
     /// Iterate over all precursors of the spectrum mutably
     fn precursor_iter_mut(&mut self) -> impl Iterator<Item = &mut Precursor> {
         let desc = self.description_mut();
-        desc.precursor.iter_mut()
+        desc.precursors.iter_mut()
+    }
+
+    /// Add another precursor to the spectrum. If there was no precursor
+    /// already, this becomes the primary precursor.
+    fn add_precursor(&mut self, precursor: Precursor) {
+        self.description_mut().precursors.push(precursor);
     }
 
     /// A shortcut method to retrieve the scan start time of a spectrum
@@ -135,6 +143,13 @@ pub trait SpectrumLike<
         self.description().polarity
     }
 
+    /// Access whether the spectrum is a mass spectrum or some other kind of spectrum,
+    /// like a UV absorption spectrum
+    #[inline]
+    fn spectrum_type(&self) -> SpectrumType {
+        self.description().spectrum_type
+    }
+
     #[inline]
     fn params(&self) -> &ParamList {
         &self.description().params
@@ -176,62 +191,11 @@ pub trait SpectrumLike<
         } = self.peaks().fetch_summaries();
 
         let desc = self.description_mut();
-        let params = desc.params_mut();
-        let tic_curie = curie!(MS:1000285);
-
-        if let Some(p) = params.iter_mut().find(|p| **p == tic_curie) {
-            p.value = Value::Float(tic as f64);
-        } else {
-            let mut p = tic_curie.as_param();
-            p.name = "total ion current".to_string();
-            p.value = Value::Float(tic as f64);
-            p.unit = Unit::DetectorCounts;
-            params.push(p)
-        }
-
-        let bpmz_curie = curie!(MS:1000504);
-        if let Some(p) = params.iter_mut().find(|p| **p == bpmz_curie) {
-            p.value = Value::Float(bp.mz());
-        } else {
-            let mut p = bpmz_curie.as_param();
-            p.name = "base peak m/z".to_string();
-            p.value = Value::Float(bp.mz());
-            p.unit = Unit::MZ;
-            params.push(p);
-        }
-
-        let lowest_mz_curie = curie!(MS:1000528);
-        if let Some(p) = params.iter_mut().find(|p| **p == lowest_mz_curie) {
-            p.value = Value::Float(mz_range.0);
-        } else {
-            let mut p = lowest_mz_curie.as_param();
-            p.name = "lowest observed m/z".to_string();
-            p.value = Value::Float(mz_range.0);
-            p.unit = Unit::MZ;
-            params.push(p);
-        }
-
-        let highest_mz_curie = curie!(MS:1000527);
-        if let Some(p) = params.iter_mut().find(|p| **p == highest_mz_curie) {
-            p.value = Value::Float(mz_range.0);
-        } else {
-            let mut p = highest_mz_curie.as_param();
-            p.name = "highest observed m/z".to_string();
-            p.value = Value::Float(mz_range.1);
-            p.unit = Unit::MZ;
-            params.push(p);
-        }
-
-        let bpint_curie = curie!(MS:1000505);
-        if let Some(p) = params.iter_mut().find(|p| **p == bpint_curie) {
-            p.value = Value::Float(bp.intensity() as f64);
-        } else {
-            let mut p = bpint_curie.as_param();
-            p.name = "base peak intensity".to_string();
-            p.value = Value::Float(bp.intensity() as f64);
-            p.unit = Unit::DetectorCounts;
-            params.push(p);
-        }
+        desc.set_total_ion_current(tic as f64);
+        desc.set_base_peak_mz(bp.mz());
+        desc.set_lowest_observed_mz(mz_range.0);
+        desc.set_highest_observed_mz(mz_range.1);
+        desc.set_base_peak_intensity(bp.intensity() as f64);
     }
 }
 
@@ -308,6 +272,9 @@ pub enum SpectrumProcessingError {
         #[source]
         ArrayRetrievalError,
     ),
+
+    #[error("This operation requires mzdata to be built with the `{0}` feature")]
+    RequiresFeature(String),
 }
 
 impl<'transient, 'lifespan: 'transient> RawSpectrum {
@@ -1100,6 +1067,33 @@ where
     }
 }
 
+/// Replace the signal-to-noise ratio the peak picker estimated for each of `peaks` with the one
+/// in `arrays`'s [`ArrayType::SignalToNoiseArray`] at the peak's apex, if there is one
+#[cfg(feature = "mzsignal")]
+fn propagate_signal_to_noise(arrays: &BinaryArrayMap, peaks: &mut [FittedPeak]) {
+    if let Ok(signal_to_noise) = arrays.signal_to_noise() {
+        for peak in peaks.iter_mut() {
+            if let Some(snr) = signal_to_noise.get(peak.index as usize) {
+                peak.signal_to_noise = *snr;
+            }
+        }
+    }
+}
+
+/// Build the data arrays of `peaks`, including an [`ArrayType::SignalToNoiseArray`] holding each
+/// peak's signal-to-noise ratio, which peak types like [`CentroidPeak`] have no room for
+#[cfg(feature = "mzsignal")]
+fn fitted_peak_arrays(peaks: &[FittedPeak]) -> Result<BinaryArrayMap, ArrayRetrievalError> {
+    let centroids: Vec<CentroidPeak> = peaks.iter().map(|p| p.as_centroid()).collect();
+    let mut arrays = CentroidPeak::as_arrays(&centroids);
+    let signal_to_noise: Vec<f32> = peaks.iter().map(|p| p.signal_to_noise).collect();
+    let mut array =
+        DataArray::from_name_and_type(&ArrayType::SignalToNoiseArray, BinaryDataArrayType::Float32);
+    array.extend(&signal_to_noise)?;
+    arrays.add(array);
+    Ok(arrays)
+}
+
 /// When [`mzsignal`] is available, [`MultiLayerSpectrum`] supports in-place signal processing operations.
 ///
 /// The peak picking steps need to convert an [`mzsignal::FittedPeak`] into `C`. This is trivial for [`CentroidPeak`]
@@ -1114,6 +1108,12 @@ impl<C: CentroidLike + Default + From<FittedPeak>, D: DeconvolutedCentroidLike +
     /// If [`SpectrumLike::signal_continuity`] returns [`SignalContinuity::Centroid`], then no filtering is
     /// performed and all points are directly converted into picked peaks.
     ///
+    /// If `arrays` has an [`ArrayType::SignalToNoiseArray`], such as one added by
+    /// [`NoiseEstimator::annotate_arrays`](crate::spectrum::NoiseEstimator::annotate_arrays),
+    /// each picked peak's signal-to-noise ratio is taken from it at the peak's apex instead of
+    /// the peak picker's local estimate. Peak types which cannot hold a signal-to-noise ratio,
+    /// like [`CentroidPeak`], lose it; use [`MultiLayerSpectrum::pick_peaks_into_arrays`] to keep it.
+    ///
     /// **NOTE**: This does not modify [`SpectrumLike::signal_continuity`], and if it is desired
     /// you should update this manually to [`SignalContinuity::Centroid`].
     pub fn pick_peaks_with(
@@ -1125,10 +1125,18 @@ impl<C: CentroidLike + Default + From<FittedPeak>, D: DeconvolutedCentroidLike +
             let intensity_array = arrays.intensities()?;
 
             if matches!(self.signal_continuity(), SignalContinuity::Centroid) {
+                let signal_to_noise = arrays.signal_to_noise().ok();
                 let mut peaks: MZPeakSetType<C> = mz_array
                     .iter()
                     .zip(intensity_array.iter())
-                    .map(|(mz, inten)| FittedPeak::new(*mz, *inten, 0, 0.0, 0.0).into())
+                    .enumerate()
+                    .map(|(i, (mz, inten))| {
+                        let snr = signal_to_noise
+                            .as_ref()
+                            .and_then(|s| s.get(i).copied())
+                            .unwrap_or_default();
+                        FittedPeak::new(*mz, *inten, 0, snr, 0.0).into()
+                    })
                     .collect();
                 peaks.sort();
                 self.peaks = Some(peaks);
@@ -1137,6 +1145,7 @@ impl<C: CentroidLike + Default + From<FittedPeak>, D: DeconvolutedCentroidLike +
                 let mut acc = Vec::new();
                 match peak_picker.discover_peaks(&mz_array, &intensity_array, &mut acc) {
                     Ok(_) => {
+                        propagate_signal_to_noise(arrays, &mut acc);
                         let peaks: MZPeakSetType<C> = acc.into_iter().map(|p| C::from(p)).collect();
                         self.peaks = Some(peaks);
                         Ok(())
@@ -1219,6 +1228,7 @@ impl<C: CentroidLike + Default + From<FittedPeak>, D: DeconvolutedCentroidLike +
                     Err(err) => return Err(SpectrumProcessingError::PeakPickerError(err)),
                 }
             }
+            propagate_signal_to_noise(arrays, &mut acc);
             let peaks: MZPeakSetType<C> = acc.into_iter().map(|p| C::from(p)).collect();
             self.peaks = Some(peaks);
             return Ok(());
@@ -1230,6 +1240,41 @@ impl<C: CentroidLike + Default + From<FittedPeak>, D: DeconvolutedCentroidLike +
     }
 }
 
+#[cfg(feature = "mzsignal")]
+impl<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default> MultiLayerSpectrum<C, D> {
+    /// Using a pre-configured [`mzsignal::PeakPicker`](mzsignal::peak_picker::PeakPicker) to pick
+    /// peaks from `arrays`'s profile signal, replacing `arrays` with the picked peaks' m/z,
+    /// intensity and [`ArrayType::SignalToNoiseArray`] arrays and marking the spectrum as
+    /// [`SignalContinuity::Centroid`].
+    ///
+    /// Each peak's signal-to-noise ratio is chosen as in [`MultiLayerSpectrum::pick_peaks_with`],
+    /// but is kept in the arrays whatever the peak type, so writers include it. `peaks` is
+    /// cleared so it cannot shadow the new arrays, and can be rebuilt from them with
+    /// [`MultiLayerSpectrum::pick_peaks_with`].
+    ///
+    /// If the spectrum is already centroided, it is left unchanged.
+    pub fn pick_peaks_into_arrays(
+        &mut self,
+        peak_picker: &PeakPicker,
+    ) -> Result<(), SpectrumProcessingError> {
+        if matches!(self.signal_continuity(), SignalContinuity::Centroid) {
+            return Ok(());
+        }
+        let arrays = self
+            .arrays
+            .as_ref()
+            .ok_or(SpectrumConversionError::NoPeakData)?;
+        let mut acc = Vec::new();
+        peak_picker.discover_peaks(&arrays.mzs()?, &arrays.intensities()?, &mut acc)?;
+        propagate_signal_to_noise(arrays, &mut acc);
+        self.arrays = Some(fitted_peak_arrays(&acc)?);
+        self.peaks = None;
+        self.description.signal_continuity = SignalContinuity::Centroid;
+        self.update_summaries();
+        Ok(())
+    }
+}
+
 impl<C: CentroidLike + Default, D: DeconvolutedCentroidLike + Default>
     TryFrom<MultiLayerSpectrum<C, D>> for CentroidSpectrumType<C>
 where
@@ -1374,6 +1419,34 @@ mod test {
         Ok(())
     }
 
+    #[test_log::test]
+    fn test_update_summaries() -> io::Result<()> {
+        let mut reader = MzMLReader::open_path("./test/data/three_test_scans.mzML")?;
+        let mut spec = reader.get_spectrum_by_index(0).unwrap();
+        let tic = spec.description().total_ion_current().unwrap();
+        let bp_int = spec.description().base_peak_intensity().unwrap();
+        assert!(tic > 0.0);
+
+        spec.update_summaries();
+        let summary = spec.peaks().fetch_summaries();
+        let desc = spec.description();
+        assert!((desc.total_ion_current().unwrap() - summary.tic as f64).abs() < 1e-3);
+        assert!((desc.base_peak_intensity().unwrap() - bp_int).abs() / bp_int < 1e-3);
+        assert_eq!(desc.lowest_observed_mz(), Some(summary.mz_range.0));
+        assert_eq!(desc.highest_observed_mz(), Some(summary.mz_range.1));
+        assert_eq!(
+            desc.params()
+                .iter()
+                .filter(|p| p.name == "total ion current")
+                .count(),
+            1
+        );
+
+        let event = spec.acquisition().first_scan().unwrap();
+        assert!(event.injection_time > 0.0);
+        Ok(())
+    }
+
     #[cfg(feature = "mzsignal")]
     #[test_log::test]
     fn test_profile_read() {
@@ -1410,4 +1483,61 @@ mod test {
             assert!((p.mz() - 563.739).abs() < 1e-3)
         }
     }
+
+    #[cfg(feature = "mzsignal")]
+    #[test]
+    fn test_pick_peaks_signal_to_noise() {
+        use crate::spectrum::NoiseEstimator;
+
+        let mut reader = MzMLReader::open_path("./test/data/three_test_scans.mzML")
+            .expect("Failed to open test file");
+        let mut scan = reader.next().expect("Failed to read spectrum");
+        assert_eq!(scan.signal_continuity(), SignalContinuity::Profile);
+
+        let peak_picker = PeakPicker {
+            fit_type: PeakFitType::Quadratic,
+            signal_to_noise_threshold: 1.0,
+            ..Default::default()
+        };
+        let (profile_snr, fitted) = {
+            let arrays = scan.arrays.as_mut().unwrap();
+            NoiseEstimator::default()
+                .annotate_arrays(arrays, SignalContinuity::Profile)
+                .unwrap();
+            let mut fitted = Vec::new();
+            peak_picker
+                .discover_peaks(
+                    &arrays.mzs().unwrap(),
+                    &arrays.intensities().unwrap(),
+                    &mut fitted,
+                )
+                .unwrap();
+            (arrays.signal_to_noise().unwrap().into_owned(), fitted)
+        };
+        assert!(!fitted.is_empty());
+
+        scan.pick_peaks_into_arrays(&peak_picker).unwrap();
+        assert_eq!(scan.signal_continuity(), SignalContinuity::Centroid);
+        assert!(scan.peaks.is_none());
+
+        let arrays = scan.arrays.as_ref().unwrap();
+        let snr = arrays.signal_to_noise().unwrap();
+        assert_eq!(arrays.mzs().unwrap().len(), fitted.len());
+        assert_eq!(snr.len(), fitted.len());
+        for (s, p) in snr.iter().zip(fitted.iter()) {
+            assert_eq!(*s, profile_snr[p.index as usize]);
+        }
+
+        // Rebuilding the peaks from the centroided arrays keeps the stored ratios alongside them
+        let mut rebuilt: MultiLayerSpectrum<CentroidPeak> =
+            MultiLayerSpectrum::new(scan.description.clone(), scan.arrays.clone(), None, None);
+        rebuilt.pick_peaks_with(&peak_picker).unwrap();
+        let peaks = rebuilt.peaks.as_ref().unwrap();
+        let rebuilt_snr = rebuilt.arrays.as_ref().unwrap().signal_to_noise().unwrap();
+        assert_eq!(peaks.len(), snr.len());
+        assert_eq!(rebuilt_snr, snr);
+        for (p, f) in peaks.iter().zip(fitted.iter()) {
+            assert!((p.mz - f.mz).abs() < 1e-6);
+        }
+    }
 }
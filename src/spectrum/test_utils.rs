@@ -0,0 +1,60 @@
+//! Fixtures shared by the tests of the spectrum processing modules
+use mzpeaks::CentroidPeak;
+
+use super::deconvolution::Averagine;
+use super::scan_properties::{
+    IsolationWindow, Precursor, PrecursorSelection, SelectedIon, SignalContinuity,
+    SpectrumDescription,
+};
+use super::spectrum_types::MultiLayerSpectrum;
+
+/// Build a centroid spectrum of `ms_level` identified by `id` and acquired at `time` from
+/// `(m/z, intensity)` pairs, isolated from the given `precursors`
+pub(crate) fn centroid_spectrum(
+    id: &str,
+    time: f64,
+    ms_level: u8,
+    peaks: &[(f64, f32)],
+    precursors: Vec<Precursor>,
+) -> MultiLayerSpectrum {
+    let mut descr = SpectrumDescription {
+        id: id.to_string(),
+        ms_level,
+        signal_continuity: SignalContinuity::Centroid,
+        precursors,
+        ..Default::default()
+    };
+    descr.acquisition.first_scan_mut().unwrap().start_time = time;
+    let peaks = peaks
+        .iter()
+        .enumerate()
+        .map(|(i, (mz, intensity))| CentroidPeak::new(*mz, *intensity, i as u32))
+        .collect();
+    MultiLayerSpectrum::new(descr, None, Some(peaks), None)
+}
+
+/// A precursor with a single selected ion of `mz` and `charge`, isolated within 1 m/z of it
+/// from the spectrum `precursor_id` if it is known
+pub(crate) fn precursor(mz: f64, charge: Option<i32>, precursor_id: Option<&str>) -> Precursor {
+    let mut precursor = Precursor {
+        precursor_id: precursor_id.map(|id| id.to_string()),
+        isolation_window: IsolationWindow::around(mz as f32, 1.0),
+        ..Default::default()
+    };
+    precursor.add_ion(SelectedIon {
+        mz,
+        charge,
+        ..Default::default()
+    });
+    precursor
+}
+
+/// The peptide averagine isotopic envelope of `neutral_mass` at `charge` as `(m/z, intensity)`
+/// pairs, with the relative abundances scaled by `scale`
+pub(crate) fn isotopic_envelope(neutral_mass: f64, charge: i32, scale: f32) -> Vec<(f64, f32)> {
+    Averagine::PEPTIDE
+        .isotopic_cluster(neutral_mass, charge)
+        .into_iter()
+        .map(|p| (p.mz, p.intensity * scale))
+        .collect()
+}
@@ -0,0 +1,1032 @@
+//! Composable peak list transformations.
+//!
+//! A [`SpectrumTransform`] filters or re-scales a peak list, like removing low intensity peaks or
+//! square-root scaling intensities. Transforms are composed into a [`TransformChain`] which applies
+//! them in order to every peak layer of a [`MultiLayerSpectrum`]: the raw data arrays, the centroid
+//! peaks and the deconvoluted peaks. Deconvoluted peaks are compared by their m/z, not their neutral mass.
+//!
+//! [`TransformedSpectrumSource`] applies a chain to every spectrum read from a [`SpectrumSource`],
+//! and records each transform as a [`ProcessingMethod`] in the source's [`DataProcessing`] so the
+//! provenance of the spectra is retained when they are written out.
+//!
+//! ```
+//! use mzpeaks::CentroidPeak;
+//! use mzdata::prelude::*;
+//! use mzdata::spectrum::{
+//!     CentroidSpectrum, IntensityThreshold, MultiLayerSpectrum, NormalizeIntensity,
+//!     SpectrumDescription, TransformChain,
+//! };
+//!
+//! let peaks: Vec<CentroidPeak> = vec![
+//!     CentroidPeak::new(100.0, 5.0, 0),
+//!     CentroidPeak::new(200.0, 100.0, 1),
+//!     CentroidPeak::new(300.0, 50.0, 2),
+//! ];
+//! let mut spectrum: MultiLayerSpectrum = CentroidSpectrum::new(
+//!     SpectrumDescription::default(),
+//!     peaks.into_iter().collect(),
+//! )
+//! .into_spectrum()
+//! .unwrap();
+//!
+//! let chain = TransformChain::new()
+//!     .then(IntensityThreshold::Relative(0.1))
+//!     .then(NormalizeIntensity::BasePeak(1.0));
+//! chain.transform(&mut spectrum).unwrap();
+//!
+//! let peaks = spectrum.peaks.as_ref().unwrap();
+//! assert_eq!(peaks.len(), 2);
+//! assert_eq!(peaks[0].intensity, 1.0);
+//! assert_eq!(peaks[1].intensity, 0.5);
+//! ```
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::mem;
+
+use log::warn;
+use mzpeaks::prelude::*;
+use mzpeaks::{
+    CentroidLike, CentroidPeak, DeconvolutedCentroidLike, DeconvolutedPeak, MZPeakSetType,
+    MassPeakSetType, Tolerance,
+};
+
+use super::bindata::ByteArrayView;
+use super::bindata::{ArrayRetrievalError, ArrayType, BinaryArrayMap, BinaryDataArrayType};
+use super::peaks::PeakDataLevel;
+use super::scan_properties::SpectrumDescription;
+use super::spectrum_types::{
+    CentroidSpectrumType, DeconvolutedSpectrumType, MultiLayerSpectrum, RawSpectrum, SpectrumLike,
+    SpectrumProcessingError,
+};
+use crate::io::{OffsetIndex, RandomAccessSpectrumIterator, SpectrumAccessError, SpectrumSource};
+use crate::meta::{
+    custom_software_name, DataProcessing, DataProcessingAction, FileDescription,
+    InstrumentConfiguration, MSDataFileMetadata, MassSpectrometryRun, ProcessingMethod, Sample,
+    Software,
+};
+use crate::params::{Param, ParamDescribed};
+use crate::utils::mass_charge_ratio;
+
+/// A single step of peak list processing.
+///
+/// A transform operates on the m/z and intensity values of a peak list, which may be the points of
+/// a raw data array or the peaks of a centroid or deconvoluted peak list. It may re-scale the
+/// intensities in place, and remove entries by setting their position in `keep` to `false`.
+///
+/// `keep` is all `true` when a transform is called, entries removed by earlier transforms in a
+/// [`TransformChain`] are not shown to later ones.
+pub trait SpectrumTransform: Debug + Send + Sync {
+    /// Transform the peak list with m/z values `mzs` and intensities `intensities` from the
+    /// spectrum described by `description`
+    fn apply(
+        &self,
+        mzs: &[f64],
+        intensities: &mut [f32],
+        keep: &mut [bool],
+        description: &SpectrumDescription,
+    );
+
+    /// The kind of processing this transform performs, recorded in the [`ProcessingMethod`]
+    fn action(&self) -> DataProcessingAction {
+        DataProcessingAction::DataFiltering
+    }
+
+    /// A human readable description of this transform and its parameters
+    fn describe(&self) -> String;
+}
+
+/// Remove peaks below an intensity threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntensityThreshold {
+    /// Remove peaks whose intensity is below this value
+    Absolute(f32),
+    /// Remove peaks whose intensity is below this fraction of the base peak's intensity
+    Relative(f32),
+    /// Remove peaks whose intensity is below this fraction of the total ion current
+    RelativeToTotal(f32),
+    /// Keep only this many of the most intense peaks
+    Count(usize),
+}
+
+impl SpectrumTransform for IntensityThreshold {
+    fn apply(
+        &self,
+        _mzs: &[f64],
+        intensities: &mut [f32],
+        keep: &mut [bool],
+        _description: &SpectrumDescription,
+    ) {
+        let threshold = match self {
+            Self::Absolute(threshold) => *threshold,
+            Self::Relative(fraction) => {
+                intensities.iter().copied().fold(0.0f32, f32::max) * *fraction
+            }
+            Self::RelativeToTotal(fraction) => intensities.iter().sum::<f32>() * *fraction,
+            Self::Count(n) => {
+                let mut order: Vec<usize> = (0..intensities.len()).collect();
+                order.sort_by(|a, b| intensities[*b].total_cmp(&intensities[*a]));
+                for (rank, i) in order.into_iter().enumerate() {
+                    keep[i] = rank < *n;
+                }
+                return;
+            }
+        };
+        for (k, inten) in keep.iter_mut().zip(intensities.iter()) {
+            *k = *inten >= threshold;
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Absolute(threshold) => format!("intensity threshold {threshold}"),
+            Self::Relative(fraction) => {
+                format!("intensity threshold {fraction} relative to base peak")
+            }
+            Self::RelativeToTotal(fraction) => {
+                format!("intensity threshold {fraction} relative to total ion current")
+            }
+            Self::Count(n) => format!("keep {n} most intense peaks"),
+        }
+    }
+}
+
+/// Keep only the `n` most intense peaks within each m/z window of `window_width`.
+///
+/// Windows are aligned to multiples of `window_width`, so that the same windows are used for
+/// every spectrum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TopNPerWindow {
+    pub n: usize,
+    pub window_width: f64,
+}
+
+impl TopNPerWindow {
+    pub fn new(n: usize, window_width: f64) -> Self {
+        Self { n, window_width }
+    }
+}
+
+impl SpectrumTransform for TopNPerWindow {
+    fn apply(
+        &self,
+        mzs: &[f64],
+        intensities: &mut [f32],
+        keep: &mut [bool],
+        _description: &SpectrumDescription,
+    ) {
+        let mut order: Vec<(i64, usize)> = mzs
+            .iter()
+            .enumerate()
+            .map(|(i, mz)| ((mz / self.window_width).floor() as i64, i))
+            .collect();
+        order.sort_by(|(bin_a, a), (bin_b, b)| {
+            bin_a
+                .cmp(bin_b)
+                .then_with(|| intensities[*b].total_cmp(&intensities[*a]))
+        });
+
+        let mut current_bin = None;
+        let mut count = 0;
+        for (bin, i) in order {
+            if current_bin != Some(bin) {
+                current_bin = Some(bin);
+                count = 0;
+            }
+            count += 1;
+            keep[i] = count <= self.n;
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("top {} peaks per {} m/z window", self.n, self.window_width)
+    }
+}
+
+/// Remove peaks matching the m/z of a selected precursor ion within a [`Tolerance`].
+///
+/// Spectra without a precursor are unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RemovePrecursor {
+    pub tolerance: Tolerance,
+}
+
+impl RemovePrecursor {
+    pub fn new(tolerance: Tolerance) -> Self {
+        Self { tolerance }
+    }
+}
+
+impl SpectrumTransform for RemovePrecursor {
+    fn apply(
+        &self,
+        mzs: &[f64],
+        _intensities: &mut [f32],
+        keep: &mut [bool],
+        description: &SpectrumDescription,
+    ) {
+        let precursor_mzs: Vec<f64> = description
+            .precursors
+            .iter()
+            .flat_map(|prec| prec.ions.iter().map(|ion| ion.mz))
+            .collect();
+        if precursor_mzs.is_empty() {
+            return;
+        }
+        for (k, mz) in keep.iter_mut().zip(mzs.iter()) {
+            *k = !precursor_mzs
+                .iter()
+                .any(|prec_mz| self.tolerance.test(*mz, *prec_mz));
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "remove precursor peaks within {}",
+            self.tolerance.to_string()
+        )
+    }
+}
+
+/// Remove peaks outside of an inclusive m/z range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipMzRange {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl ClipMzRange {
+    pub fn new(low: f64, high: f64) -> Self {
+        Self { low, high }
+    }
+}
+
+impl SpectrumTransform for ClipMzRange {
+    fn apply(
+        &self,
+        mzs: &[f64],
+        _intensities: &mut [f32],
+        keep: &mut [bool],
+        _description: &SpectrumDescription,
+    ) {
+        for (k, mz) in keep.iter_mut().zip(mzs.iter()) {
+            *k = *mz >= self.low && *mz <= self.high;
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("clip m/z range {} to {}", self.low, self.high)
+    }
+}
+
+/// Scale intensities so that a summary of the peak list equals a target value.
+///
+/// Empty peak lists, or those whose intensities are all zero, are unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalizeIntensity {
+    /// Scale intensities so that the base peak has this intensity
+    BasePeak(f32),
+    /// Scale intensities so that they sum to this value
+    TotalIonCurrent(f32),
+}
+
+impl SpectrumTransform for NormalizeIntensity {
+    fn apply(
+        &self,
+        _mzs: &[f64],
+        intensities: &mut [f32],
+        _keep: &mut [bool],
+        _description: &SpectrumDescription,
+    ) {
+        let (reference, target) = match self {
+            Self::BasePeak(target) => (intensities.iter().copied().fold(0.0f32, f32::max), *target),
+            Self::TotalIonCurrent(target) => (intensities.iter().sum(), *target),
+        };
+        if reference <= 0.0 {
+            return;
+        }
+        let scale = target / reference;
+        intensities.iter_mut().for_each(|i| *i *= scale);
+    }
+
+    fn action(&self) -> DataProcessingAction {
+        DataProcessingAction::IntensityNormalization
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::BasePeak(target) => format!("normalize base peak intensity to {target}"),
+            Self::TotalIonCurrent(target) => format!("normalize total ion current to {target}"),
+        }
+    }
+}
+
+/// Replace each intensity with its square root, damping the influence of the most intense peaks.
+///
+/// Negative intensities are clamped to zero.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SqrtIntensity;
+
+impl SpectrumTransform for SqrtIntensity {
+    fn apply(
+        &self,
+        _mzs: &[f64],
+        intensities: &mut [f32],
+        _keep: &mut [bool],
+        _description: &SpectrumDescription,
+    ) {
+        intensities.iter_mut().for_each(|i| *i = i.max(0.0).sqrt());
+    }
+
+    fn action(&self) -> DataProcessingAction {
+        DataProcessingAction::IntensityNormalization
+    }
+
+    fn describe(&self) -> String {
+        "square root intensity scaling".to_string()
+    }
+}
+
+/// Remove zero intensity points which are not adjacent to a non-zero intensity point.
+///
+/// Profile spectra often pad their signal with long runs of zeros. The zeros flanking each
+/// stretch of signal are kept so that the profile peak shapes are preserved. This assumes
+/// the points are sorted by m/z.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RemoveExtraZeros;
+
+impl SpectrumTransform for RemoveExtraZeros {
+    fn apply(
+        &self,
+        _mzs: &[f64],
+        intensities: &mut [f32],
+        keep: &mut [bool],
+        _description: &SpectrumDescription,
+    ) {
+        let n = intensities.len();
+        for i in 0..n {
+            keep[i] = intensities[i] != 0.0
+                || (i > 0 && intensities[i - 1] != 0.0)
+                || (i + 1 < n && intensities[i + 1] != 0.0);
+        }
+    }
+
+    fn describe(&self) -> String {
+        "remove extra zero samples".to_string()
+    }
+}
+
+fn retain_by_mask<T>(values: &mut Vec<T>, keep: &[bool]) {
+    let mut it = keep.iter();
+    values.retain(|_| *it.next().unwrap());
+}
+
+/// An ordered sequence of [`SpectrumTransform`]s applied to each peak layer of a spectrum
+#[derive(Debug, Default)]
+pub struct TransformChain {
+    transforms: Vec<Box<dyn SpectrumTransform>>,
+}
+
+impl TransformChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `transform` to the end of the chain
+    pub fn push<T: SpectrumTransform + 'static>(&mut self, transform: T) {
+        self.transforms.push(Box::new(transform));
+    }
+
+    /// Add `transform` to the end of the chain, returning the chain
+    pub fn then<T: SpectrumTransform + 'static>(mut self, transform: T) -> Self {
+        self.push(transform);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Box<dyn SpectrumTransform>> {
+        self.transforms.iter()
+    }
+
+    /// Apply each transform in turn to the peak list with m/z values `mzs` and intensities
+    /// `intensities`, returning the indices of the entries kept and their new intensities
+    fn run(
+        &self,
+        mzs: &[f64],
+        intensities: &[f32],
+        description: &SpectrumDescription,
+    ) -> (Vec<usize>, Vec<f32>) {
+        let mut indices: Vec<usize> = (0..mzs.len()).collect();
+        let mut mzs = mzs.to_vec();
+        let mut intensities = intensities.to_vec();
+        for transform in self.iter() {
+            let mut keep = vec![true; indices.len()];
+            transform.apply(&mzs, &mut intensities, &mut keep, description);
+            if keep.iter().all(|k| *k) {
+                continue;
+            }
+            retain_by_mask(&mut indices, &keep);
+            retain_by_mask(&mut mzs, &keep);
+            retain_by_mask(&mut intensities, &keep);
+        }
+        (indices, intensities)
+    }
+
+    /// Apply the chain to the m/z and intensity arrays of `arrays`. When points are removed, they
+    /// are removed from every other array of the same length too.
+    pub fn transform_arrays(
+        &self,
+        arrays: &mut BinaryArrayMap,
+        description: &SpectrumDescription,
+    ) -> Result<(), ArrayRetrievalError> {
+        let mzs = arrays.mzs()?.into_owned();
+        let intensities = arrays.intensities()?.into_owned();
+        let n = mzs.len();
+        let (indices, intensities) = self.run(&mzs, &intensities, description);
+
+        if indices.len() != n {
+            let mut keep = vec![false; n];
+            indices.iter().for_each(|i| keep[*i] = true);
+            for (_, array) in arrays.iter_mut() {
+                if array.data_len()? == n {
+                    array.retain_by_mask(&keep)?;
+                }
+            }
+        }
+
+        let view = arrays
+            .get_mut(&ArrayType::IntensityArray)
+            .ok_or(ArrayRetrievalError::NotFound(ArrayType::IntensityArray))?;
+        view.store_as(BinaryDataArrayType::Float32)?;
+        view.update_buffer(&intensities)?;
+        Ok(())
+    }
+
+    /// Apply the chain to a centroid peak list
+    pub fn transform_peaks<C: CentroidLike + IntensityMeasurementMut>(
+        &self,
+        peaks: &mut MZPeakSetType<C>,
+        description: &SpectrumDescription,
+    ) {
+        let mzs: Vec<f64> = peaks.iter().map(|p| p.mz()).collect();
+        let intensities: Vec<f32> = peaks.iter().map(|p| p.intensity()).collect();
+        let (indices, intensities) = self.run(&mzs, &intensities, description);
+        if indices.len() == peaks.len() {
+            for (p, inten) in peaks.iter_mut().zip(intensities) {
+                *p.intensity_mut() = inten;
+            }
+        } else {
+            let mut slots: Vec<Option<C>> = mem::replace(peaks, MZPeakSetType::empty())
+                .into_iter()
+                .map(Some)
+                .collect();
+            let kept = indices
+                .into_iter()
+                .zip(intensities)
+                .filter_map(|(i, inten)| {
+                    let mut p = slots[i].take()?;
+                    *p.intensity_mut() = inten;
+                    Some(p)
+                })
+                .collect();
+            *peaks = MZPeakSetType::new(kept);
+        }
+    }
+
+    /// Apply the chain to a deconvoluted peak list, using each peak's m/z
+    pub fn transform_deconvoluted_peaks<D: DeconvolutedCentroidLike + IntensityMeasurementMut>(
+        &self,
+        peaks: &mut MassPeakSetType<D>,
+        description: &SpectrumDescription,
+    ) {
+        let mzs: Vec<f64> = peaks
+            .iter()
+            .map(|p| mass_charge_ratio(p.neutral_mass(), p.charge()))
+            .collect();
+        let intensities: Vec<f32> = peaks.iter().map(|p| p.intensity()).collect();
+        let (indices, intensities) = self.run(&mzs, &intensities, description);
+        if indices.len() == peaks.len() {
+            for (p, inten) in peaks.iter_mut().zip(intensities) {
+                *p.intensity_mut() = inten;
+            }
+        } else {
+            let mut slots: Vec<Option<D>> = mem::replace(peaks, MassPeakSetType::empty())
+                .into_iter()
+                .map(Some)
+                .collect();
+            let kept = indices
+                .into_iter()
+                .zip(intensities)
+                .filter_map(|(i, inten)| {
+                    let mut p = slots[i].take()?;
+                    *p.intensity_mut() = inten;
+                    Some(p)
+                })
+                .collect();
+            *peaks = MassPeakSetType::new(kept);
+        }
+    }
+
+    /// Apply the chain to a single [`PeakDataLevel`]
+    pub fn transform_peak_data<C, D>(
+        &self,
+        peaks: &mut PeakDataLevel<C, D>,
+        description: &SpectrumDescription,
+    ) -> Result<(), SpectrumProcessingError>
+    where
+        C: CentroidLike + IntensityMeasurementMut,
+        D: DeconvolutedCentroidLike + IntensityMeasurementMut,
+    {
+        match peaks {
+            PeakDataLevel::Missing => {}
+            PeakDataLevel::RawData(arrays) => self.transform_arrays(arrays, description)?,
+            PeakDataLevel::Centroid(peaks) => self.transform_peaks(peaks, description),
+            PeakDataLevel::Deconvoluted(peaks) => {
+                self.transform_deconvoluted_peaks(peaks, description)
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the chain to every peak layer of `spectrum` and update its summary statistics.
+    ///
+    /// See [`TransformableSpectrum`] for how each spectrum type is handled.
+    pub fn transform<S: TransformableSpectrum>(
+        &self,
+        spectrum: &mut S,
+    ) -> Result<(), SpectrumProcessingError> {
+        spectrum.apply_transforms(self)
+    }
+
+    /// Append a [`ProcessingMethod`] for each transform in the chain to `data_processing`,
+    /// attributed to the [`Software`] with id `software_reference`
+    pub fn record_processing(
+        &self,
+        data_processing: &mut DataProcessing,
+        software_reference: &str,
+    ) {
+        for transform in self.iter() {
+            append_processing_method(
+                data_processing,
+                software_reference,
+                [
+                    transform.action().into(),
+                    Param::new_key_value("spectrum transform", transform.describe()),
+                ],
+            );
+        }
+    }
+}
+
+/// Append a [`ProcessingMethod`] with `params` to the end of `data_processing`, attributed to
+/// the [`Software`] with id `software_reference`
+pub(crate) fn append_processing_method(
+    data_processing: &mut DataProcessing,
+    software_reference: &str,
+    params: impl IntoIterator<Item = Param>,
+) {
+    let mut method = ProcessingMethod {
+        order: data_processing.highest_order() + 1,
+        software_reference: software_reference.to_string(),
+        ..Default::default()
+    };
+    for param in params {
+        method.add_param(param);
+    }
+    data_processing.push(method);
+}
+
+/// A copy of a source's [`MSDataFileMetadata`] held by an adaptor which processes the
+/// source's spectra, so the adaptor can record that processing without altering the source.
+///
+/// The spectrum processing adaptors, like [`TransformedSpectrumSource`], report this copy as
+/// their own metadata. Recording processing adds this version of mzdata to the [`Software`]
+/// list and appends a [`ProcessingMethod`] attributed to it to each [`DataProcessing`], so
+/// the provenance of the spectra is retained when they are written out.
+#[derive(Debug, Default, Clone)]
+pub struct AdaptorMetadata {
+    file_description: FileDescription,
+    instrument_configurations: HashMap<u32, InstrumentConfiguration>,
+    softwares: Vec<Software>,
+    samples: Vec<Sample>,
+    data_processings: Vec<DataProcessing>,
+    run: Option<MassSpectrometryRun>,
+    num_spectra: Option<u64>,
+}
+
+impl AdaptorMetadata {
+    pub fn new(source: &impl MSDataFileMetadata) -> Self {
+        Self {
+            file_description: source.file_description().clone(),
+            instrument_configurations: source.instrument_configurations().clone(),
+            softwares: source.softwares().clone(),
+            samples: source.samples().clone(),
+            data_processings: source.data_processings().clone(),
+            run: source.run_description().cloned(),
+            num_spectra: source.spectrum_count_hint(),
+        }
+    }
+
+    pub fn set_spectrum_count_hint(&mut self, num_spectra: Option<u64>) {
+        self.num_spectra = num_spectra;
+    }
+
+    /// Add this version of mzdata to the [`Software`] list and call `record` with each
+    /// [`DataProcessing`] and the new software's id. If there is no [`DataProcessing`], one
+    /// with id `processing_id` is created first.
+    pub fn record_processing(
+        &mut self,
+        processing_id: &str,
+        mut record: impl FnMut(&mut DataProcessing, &str),
+    ) {
+        let mut software = Software::new(
+            Software::find_unique_id("mzdata", self.softwares.iter()),
+            env!("CARGO_PKG_VERSION").to_string(),
+            Vec::new(),
+        );
+        software.add_param(custom_software_name("mzdata"));
+
+        if self.data_processings.is_empty() {
+            self.data_processings.push(DataProcessing {
+                id: processing_id.to_string(),
+                ..Default::default()
+            });
+        }
+        for dp in self.data_processings.iter_mut() {
+            record(dp, &software.id);
+        }
+        self.softwares.push(software);
+    }
+
+    /// Record a single [`ProcessingMethod`] with `params`, as in [`AdaptorMetadata::record_processing`]
+    pub fn record_method(&mut self, processing_id: &str, params: Vec<Param>) {
+        self.record_processing(processing_id, |dp, software_reference| {
+            append_processing_method(dp, software_reference, params.iter().cloned())
+        });
+    }
+}
+
+impl MSDataFileMetadata for AdaptorMetadata {
+    crate::impl_metadata_trait!();
+
+    fn spectrum_count_hint(&self) -> Option<u64> {
+        self.num_spectra
+    }
+
+    fn run_description(&self) -> Option<&MassSpectrometryRun> {
+        self.run.as_ref()
+    }
+
+    fn run_description_mut(&mut self) -> Option<&mut MassSpectrometryRun> {
+        self.run.as_mut()
+    }
+}
+
+/// A spectrum type whose peak data a [`TransformChain`] can be applied to
+pub trait TransformableSpectrum {
+    /// Apply `chain` to each peak layer of this spectrum and update its summary statistics
+    fn apply_transforms(&mut self, chain: &TransformChain) -> Result<(), SpectrumProcessingError>;
+}
+
+/// Raw data arrays without an m/z array, like those of electromagnetic radiation spectra,
+/// are left unchanged.
+impl<C, D> TransformableSpectrum for MultiLayerSpectrum<C, D>
+where
+    C: CentroidLike + Default + IntensityMeasurementMut,
+    D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+{
+    fn apply_transforms(&mut self, chain: &TransformChain) -> Result<(), SpectrumProcessingError> {
+        if let Some(arrays) = self.arrays.as_mut() {
+            if arrays.has_array(&ArrayType::MZArray) {
+                chain.transform_arrays(arrays, &self.description)?;
+            }
+        }
+        if let Some(peaks) = self.peaks.as_mut() {
+            chain.transform_peaks(peaks, &self.description);
+        }
+        if let Some(peaks) = self.deconvoluted_peaks.as_mut() {
+            chain.transform_deconvoluted_peaks(peaks, &self.description);
+        }
+        self.update_summaries();
+        Ok(())
+    }
+}
+
+/// Raw data arrays without an m/z array, like those of electromagnetic radiation spectra,
+/// are left unchanged.
+impl TransformableSpectrum for RawSpectrum {
+    fn apply_transforms(&mut self, chain: &TransformChain) -> Result<(), SpectrumProcessingError> {
+        if self.arrays.has_array(&ArrayType::MZArray) {
+            chain.transform_arrays(&mut self.arrays, &self.description)?;
+            <Self as SpectrumLike>::update_summaries(self);
+        }
+        Ok(())
+    }
+}
+
+impl<C> TransformableSpectrum for CentroidSpectrumType<C>
+where
+    C: CentroidLike + Default + IntensityMeasurementMut,
+{
+    fn apply_transforms(&mut self, chain: &TransformChain) -> Result<(), SpectrumProcessingError> {
+        chain.transform_peaks(&mut self.peaks, &self.description);
+        <Self as SpectrumLike<C>>::update_summaries(self);
+        Ok(())
+    }
+}
+
+impl<D> TransformableSpectrum for DeconvolutedSpectrumType<D>
+where
+    D: DeconvolutedCentroidLike + Default + IntensityMeasurementMut,
+{
+    fn apply_transforms(&mut self, chain: &TransformChain) -> Result<(), SpectrumProcessingError> {
+        chain.transform_deconvoluted_peaks(&mut self.deconvoluted_peaks, &self.description);
+        <Self as SpectrumLike<CentroidPeak, D>>::update_summaries(self);
+        Ok(())
+    }
+}
+
+/// Adapt an iterator over spectra by applying a [`TransformChain`] to each spectrum. When the
+/// source is a [`SpectrumSource`], so is the adaptor, and spectra read by random access are
+/// transformed too.
+///
+/// Each transform is recorded in the adaptor's [`AdaptorMetadata`]. Spectra which fail to be
+/// transformed are logged and passed through unchanged.
+#[derive(Debug)]
+pub struct TransformedSpectrumSource<
+    R: Iterator<Item = S>,
+    C: CentroidLike + Default = CentroidPeak,
+    D: DeconvolutedCentroidLike + Default = DeconvolutedPeak,
+    S: SpectrumLike<C, D> + TransformableSpectrum = MultiLayerSpectrum<C, D>,
+> {
+    source: R,
+    chain: TransformChain,
+    metadata: AdaptorMetadata,
+    _c: PhantomData<C>,
+    _d: PhantomData<D>,
+    _s: PhantomData<S>,
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D> + TransformableSpectrum,
+    > TransformedSpectrumSource<R, C, D, S>
+{
+    pub fn new(source: R, chain: TransformChain) -> Self
+    where
+        R: MSDataFileMetadata,
+    {
+        let mut metadata = AdaptorMetadata::new(&source);
+        if !chain.is_empty() {
+            metadata.record_processing("spectrum_transforms", |dp, software_reference| {
+                chain.record_processing(dp, software_reference)
+            });
+        }
+        Self {
+            source,
+            chain,
+            metadata,
+            _c: PhantomData,
+            _d: PhantomData,
+            _s: PhantomData,
+        }
+    }
+
+    pub fn chain(&self) -> &TransformChain {
+        &self.chain
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    fn transform_spectrum(&self, mut spectrum: S) -> S {
+        if let Err(e) = spectrum.apply_transforms(&self.chain) {
+            warn!("Failed to transform spectrum {}: {e}", spectrum.id());
+        }
+        spectrum
+    }
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D> + TransformableSpectrum,
+    > MSDataFileMetadata for TransformedSpectrumSource<R, C, D, S>
+{
+    crate::delegate_impl_metadata_trait!(metadata);
+}
+
+impl<
+        R: Iterator<Item = S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D> + TransformableSpectrum,
+    > Iterator for TransformedSpectrumSource<R, C, D, S>
+{
+    type Item = S;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let spectrum = self.source.next()?;
+        Some(self.transform_spectrum(spectrum))
+    }
+}
+
+impl<
+        R: SpectrumSource<C, D, S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D> + TransformableSpectrum,
+    > SpectrumSource<C, D, S> for TransformedSpectrumSource<R, C, D, S>
+{
+    fn reset(&mut self) {
+        self.source.reset()
+    }
+
+    fn get_spectrum_by_id(&mut self, id: &str) -> Option<S> {
+        let spectrum = self.source.get_spectrum_by_id(id)?;
+        Some(self.transform_spectrum(spectrum))
+    }
+
+    fn get_spectrum_by_index(&mut self, index: usize) -> Option<S> {
+        let spectrum = self.source.get_spectrum_by_index(index)?;
+        Some(self.transform_spectrum(spectrum))
+    }
+
+    fn get_spectrum_by_time(&mut self, time: f64) -> Option<S> {
+        let spectrum = self.source.get_spectrum_by_time(time)?;
+        Some(self.transform_spectrum(spectrum))
+    }
+
+    fn get_index(&self) -> &OffsetIndex {
+        self.source.get_index()
+    }
+
+    fn set_index(&mut self, index: OffsetIndex) {
+        self.source.set_index(index)
+    }
+}
+
+impl<
+        R: SpectrumSource<C, D, S>,
+        C: CentroidLike + Default,
+        D: DeconvolutedCentroidLike + Default,
+        S: SpectrumLike<C, D> + TransformableSpectrum,
+    > RandomAccessSpectrumIterator<C, D, S> for TransformedSpectrumSource<R, C, D, S>
+where
+    R: RandomAccessSpectrumIterator<C, D, S>,
+{
+    fn start_from_id(&mut self, id: &str) -> Result<&mut Self, SpectrumAccessError> {
+        match self.source.start_from_id(id) {
+            Ok(_) => Ok(self),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn start_from_index(&mut self, index: usize) -> Result<&mut Self, SpectrumAccessError> {
+        match self.source.start_from_index(index) {
+            Ok(_) => Ok(self),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn start_from_time(&mut self, time: f64) -> Result<&mut Self, SpectrumAccessError> {
+        match self.source.start_from_time(time) {
+            Ok(_) => Ok(self),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use crate::spectrum::test_utils::{centroid_spectrum, precursor};
+    use crate::MzMLReader;
+
+    #[test]
+    fn test_chain_on_peaks() {
+        let mut spectrum = centroid_spectrum(
+            "scan=1",
+            0.0,
+            2,
+            &[
+                (100.0, 4.0),
+                (101.0, 9.0),
+                (102.0, 16.0),
+                (250.0, 100.0),
+                (300.0, 1.0),
+                (500.0, 25.0),
+                (900.0, 64.0),
+            ],
+            vec![precursor(250.001, Some(2), None)],
+        );
+        let chain = TransformChain::new()
+            .then(ClipMzRange::new(50.0, 600.0))
+            .then(RemovePrecursor::new(Tolerance::PPM(20.0)))
+            .then(IntensityThreshold::Absolute(2.0))
+            .then(TopNPerWindow::new(2, 100.0))
+            .then(SqrtIntensity)
+            .then(NormalizeIntensity::BasePeak(100.0));
+        chain.transform(&mut spectrum).unwrap();
+
+        let peaks = spectrum.peaks.as_ref().unwrap();
+        let mzs: Vec<f64> = peaks.iter().map(|p| p.mz).collect();
+        assert_eq!(mzs, vec![101.0, 102.0, 500.0]);
+        let intensities: Vec<f32> = peaks.iter().map(|p| p.intensity).collect();
+        assert_eq!(intensities, vec![60.0, 80.0, 100.0]);
+        assert_eq!(peaks[2].index, 2);
+        assert_eq!(spectrum.description.base_peak_mz(), Some(500.0));
+    }
+
+    #[test]
+    fn test_chain_on_arrays() {
+        let spectrum = centroid_spectrum(
+            "scan=1",
+            0.0,
+            2,
+            &[(100.0, 10.0), (200.0, 50.0), (300.0, 40.0)],
+            vec![precursor(400.0, Some(2), None)],
+        );
+        let mut spectrum = spectrum.into_raw().unwrap();
+        let chain = TransformChain::new()
+            .then(IntensityThreshold::Relative(0.5))
+            .then(NormalizeIntensity::TotalIonCurrent(1.0));
+        chain
+            .transform_arrays(&mut spectrum.arrays, &spectrum.description)
+            .unwrap();
+
+        assert_eq!(spectrum.arrays.mzs().unwrap().as_ref(), &[200.0, 300.0]);
+        let intensities = spectrum.arrays.intensities().unwrap();
+        assert!((intensities[0] - 50.0 / 90.0).abs() < 1e-6);
+        assert!((intensities[1] - 40.0 / 90.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_transformed_source() {
+        let reader = MzMLReader::open_path("./test/data/three_test_scans.mzML").unwrap();
+        let n_processing_methods: Vec<_> = reader
+            .data_processings()
+            .iter()
+            .map(|dp| dp.methods.len())
+            .collect();
+        let chain = TransformChain::new()
+            .then(IntensityThreshold::Absolute(1000.0))
+            .then(SqrtIntensity);
+        let mut source = reader.into_transformed(chain);
+
+        for (dp, n) in source.data_processings().iter().zip(n_processing_methods) {
+            assert_eq!(dp.methods.len(), n + 2);
+            let last = dp.methods.last().unwrap();
+            assert!(last
+                .params()
+                .iter()
+                .any(|p| p.name == "intensity normalization"));
+        }
+        assert!(source
+            .softwares()
+            .iter()
+            .any(|sw| sw.id.starts_with("mzdata")));
+
+        let originals: Vec<_> = MzMLReader::open_path("./test/data/three_test_scans.mzML")
+            .unwrap()
+            .collect();
+        let spectra: Vec<_> = source.by_ref().collect();
+        assert_eq!(spectra.len(), 3);
+        for (spectrum, original) in spectra.iter().zip(originals.iter()) {
+            let bp = spectrum.peaks().base_peak();
+            let original_bp = original.peaks().base_peak();
+            assert!((bp.intensity - original_bp.intensity.sqrt()).abs() < 1e-3);
+            assert!(spectrum
+                .peaks()
+                .iter()
+                .all(|p| p.intensity >= 1000.0f32.sqrt()));
+        }
+
+        let spectrum = source.get_spectrum_by_index(0).unwrap();
+        assert!(spectrum
+            .peaks()
+            .iter()
+            .all(|p| p.intensity >= 1000.0f32.sqrt()));
+
+        let reader = MzMLReader::open_path("./test/data/three_test_scans.mzML").unwrap();
+        let expected = reader.data_processings().clone();
+        let source = reader.into_transformed(TransformChain::new());
+        assert_eq!(source.data_processings(), &expected);
+    }
+}
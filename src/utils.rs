@@ -9,3 +9,33 @@ pub fn mass_charge_ratio(mass: f64, z: i32) -> f64 {
 pub fn neutral_mass(mz: f64, z: i32) -> f64 {
     (mz * z.abs() as f64) - z as f64 * PROTON
 }
+
+/// Solve the square linear system given as an augmented matrix, where each row holds the
+/// coefficients followed by the right hand side, by Gaussian elimination with partial pivoting.
+///
+/// Returns `None` if the system is singular.
+pub(crate) fn solve_linear_system(mut system: Vec<Vec<f64>>) -> Option<Vec<f64>> {
+    let k = system.len();
+    for col in 0..k {
+        let pivot =
+            (col..k).max_by(|a, b| system[*a][col].abs().total_cmp(&system[*b][col].abs()))?;
+        let scale = system.iter().map(|r| r[col].abs()).fold(0.0, f64::max);
+        if system[pivot][col].abs() <= scale * 1e-12 {
+            return None;
+        }
+        system.swap(col, pivot);
+        for row in (col + 1)..k {
+            let factor = system[row][col] / system[col][col];
+            let (upper, lower) = system.split_at_mut(row);
+            for (x, y) in lower[0][col..].iter_mut().zip(upper[col][col..].iter()) {
+                *x -= factor * y;
+            }
+        }
+    }
+    let mut solution = vec![0.0; k];
+    for i in (0..k).rev() {
+        let tail: f64 = ((i + 1)..k).map(|j| system[i][j] * solution[j]).sum();
+        solution[i] = (system[i][k] - tail) / system[i][i];
+    }
+    Some(solution)
+}